@@ -0,0 +1,148 @@
+// Retrying lookups refused by a provider's rate limit
+
+use super::client::{is_rate_limited, Asn};
+use super::types::AsnInfo;
+use crate::metrics::Metrics;
+use std::io::Error;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Wraps a provider and retries lookups it refuses because of its rate limit,
+/// waiting twice as long before each retry
+///
+/// Lookups still refused after the last retry fail with the rate-limit error.
+/// Other errors are returned at once.
+pub struct Backoff {
+    name: String,
+    inner: Box<dyn Asn>,
+    delay: Duration,
+    retries: u32,
+    metrics: Option<Arc<Metrics>>,
+}
+
+impl Backoff {
+    pub const DEFAULT_DELAY: Duration = Duration::from_secs(1);
+    pub const DEFAULT_RETRIES: u32 = 3;
+
+    /// Retries the lookups of `inner`, reporting its waits under the provider label `name`
+    pub fn new(name: impl Into<String>, inner: Box<dyn Asn>) -> Self {
+        Backoff {
+            name: name.into(),
+            inner,
+            delay: Self::DEFAULT_DELAY,
+            retries: Self::DEFAULT_RETRIES,
+            metrics: None,
+        }
+    }
+
+    /// Sets how long to wait before the first retry
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Sets how many times a refused lookup is retried
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Records the time spent waiting in `metrics`
+    pub fn metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    fn retry<T>(&self, lookup: impl Fn() -> Result<T, Error>) -> Result<T, Error> {
+        let mut delay = self.delay;
+        for _ in 0..self.retries {
+            match lookup() {
+                Err(e) if is_rate_limited(&e) => {
+                    tracing::debug!(provider = %self.name, "rate limited, retrying in {:?}", delay);
+                    thread::sleep(delay);
+                    if let Some(metrics) = &self.metrics {
+                        metrics.record_rate_limit_wait(&self.name, delay);
+                    }
+                    delay *= 2;
+                }
+                result => return result,
+            }
+        }
+        lookup()
+    }
+}
+
+impl Asn for Backoff {
+    fn lookup_asn(&self, ip: IpAddr) -> Result<Vec<AsnInfo>, Error> {
+        self.retry(|| self.inner.lookup_asn(ip))
+    }
+
+    fn lookup_as(&self, asn: u32) -> Result<Vec<AsnInfo>, Error> {
+        self.retry(|| self.inner.lookup_as(asn))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asn::client::rate_limited;
+    use std::io::ErrorKind;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Refuses the first `refusals` lookups, then answers
+    struct Limited {
+        refusals: u32,
+        calls: AtomicU32,
+    }
+
+    impl Asn for Limited {
+        fn lookup_asn(&self, ip: IpAddr) -> Result<Vec<AsnInfo>, Error> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) < self.refusals {
+                return Err(rate_limited("429"));
+            }
+            if ip.is_ipv6() {
+                return Err(Error::new(ErrorKind::TimedOut, "timed out"));
+            }
+            Ok(vec![AsnInfo::new("64496", "EXAMPLE")])
+        }
+    }
+
+    fn backoff(refusals: u32, metrics: &Arc<Metrics>) -> Backoff {
+        let limited = Limited {
+            refusals,
+            calls: AtomicU32::new(0),
+        };
+        Backoff::new("ripe", Box::new(limited))
+            .delay(Duration::from_millis(1))
+            .metrics(Arc::clone(metrics))
+    }
+
+    #[test]
+    fn test_retries_until_answered() {
+        let metrics = Arc::new(Metrics::new());
+        let results = backoff(2, &metrics)
+            .lookup_asn("192.0.2.1".parse().unwrap())
+            .unwrap();
+        assert_eq!(results[0].asn, "64496");
+        assert!(metrics
+            .render()
+            .contains("asn_fetcher_rate_limit_wait_seconds{provider=\"ripe\"} 0.003\n"));
+    }
+
+    #[test]
+    fn test_gives_up_after_retries() {
+        let metrics = Arc::new(Metrics::new());
+        let err = backoff(10, &metrics)
+            .retries(2)
+            .lookup_asn("192.0.2.1".parse().unwrap())
+            .unwrap_err();
+        assert!(is_rate_limited(&err));
+
+        let err = backoff(0, &metrics)
+            .lookup_asn("2001:db8::1".parse().unwrap())
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TimedOut);
+    }
+}
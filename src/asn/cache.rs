@@ -0,0 +1,277 @@
+// Cache for lookup results
+
+use super::client::Asn;
+use super::types::AsnInfo;
+use crate::metrics::Metrics;
+use std::collections::HashMap;
+use std::io::Error;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A cached result and the time it stops being served
+#[derive(Debug, Clone)]
+struct Entry {
+    results: Vec<AsnInfo>,
+    expires: Instant,
+}
+
+impl Entry {
+    fn fresh(&self, now: Instant) -> Option<Vec<AsnInfo>> {
+        (now < self.expires).then(|| self.results.clone())
+    }
+}
+
+#[derive(Default)]
+struct Tables {
+    addresses: HashMap<IpAddr, Entry>,
+    asns: HashMap<u32, Entry>,
+}
+
+impl Tables {
+    fn len(&self) -> usize {
+        self.addresses.len() + self.asns.len()
+    }
+}
+
+/// Wraps a provider and remembers its results for a fixed time
+///
+/// Results are stored per address: a result naming a covering prefix says
+/// nothing about more-specific routes inside it, and enrichers may compute
+/// per-address fields. Errors are never cached. When the cache grows past its
+/// capacity it is emptied and refilled on demand.
+pub struct LookupCache {
+    inner: Box<dyn Asn>,
+    ttl: Duration,
+    capacity: usize,
+    tables: Mutex<Tables>,
+    metrics: Option<Arc<Metrics>>,
+}
+
+impl LookupCache {
+    pub const DEFAULT_CAPACITY: usize = 100_000;
+
+    /// Creates a cache that serves results for `ttl` after they were looked up
+    pub fn new(inner: Box<dyn Asn>, ttl: Duration) -> Self {
+        LookupCache {
+            inner,
+            ttl,
+            capacity: Self::DEFAULT_CAPACITY,
+            tables: Mutex::new(Tables::default()),
+            metrics: None,
+        }
+    }
+
+    /// Sets the number of entries kept before the cache is cleared
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Records hits, misses and the number of entries in `metrics`
+    pub fn metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Number of cached entries, including expired ones not yet replaced
+    pub fn len(&self) -> usize {
+        self.tables().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn tables(&self) -> std::sync::MutexGuard<'_, Tables> {
+        // A panic while holding the lock cannot leave the tables inconsistent
+        self.tables.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn entry(&self, results: &[AsnInfo]) -> Entry {
+        Entry {
+            results: results.to_vec(),
+            expires: Instant::now() + self.ttl,
+        }
+    }
+
+    fn record(&self, hit: bool) {
+        if let Some(metrics) = &self.metrics {
+            metrics.record_cache(hit);
+        }
+    }
+
+    fn record_entries(&self, tables: &Tables) {
+        if let Some(metrics) = &self.metrics {
+            metrics.set_cache_entries(tables.len());
+        }
+    }
+
+    /// Makes room for one more entry
+    fn reserve(&self, tables: &mut Tables) {
+        if tables.len() >= self.capacity {
+            *tables = Tables::default();
+        }
+    }
+}
+
+impl Asn for LookupCache {
+    fn lookup_asn(&self, ip: IpAddr) -> Result<Vec<AsnInfo>, Error> {
+        let now = Instant::now();
+        {
+            let tables = self.tables();
+            let cached = tables.addresses.get(&ip).and_then(|e| e.fresh(now));
+            self.record(cached.is_some());
+            if let Some(results) = cached {
+                return Ok(results);
+            }
+        }
+
+        // Look up without holding the lock so slow providers don't block cache hits
+        let results = self.inner.lookup_asn(ip)?;
+        let entry = self.entry(&results);
+        let mut tables = self.tables();
+        if !tables.addresses.contains_key(&ip) {
+            self.reserve(&mut tables);
+        }
+        tables.addresses.insert(ip, entry);
+        self.record_entries(&tables);
+        Ok(results)
+    }
+
+    fn lookup_as(&self, asn: u32) -> Result<Vec<AsnInfo>, Error> {
+        let now = Instant::now();
+        {
+            let tables = self.tables();
+            let cached = tables.asns.get(&asn).and_then(|e| e.fresh(now));
+            self.record(cached.is_some());
+            if let Some(results) = cached {
+                return Ok(results);
+            }
+        }
+        let results = self.inner.lookup_as(asn)?;
+        let entry = self.entry(&results);
+        let mut tables = self.tables();
+        self.reserve(&mut tables);
+        tables.asns.insert(asn, entry);
+        self.record_entries(&tables);
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::Prefix;
+    use std::io::ErrorKind;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Answers 10.0.0.0/16 from AS64496 except for the more-specific
+    /// 10.0.1.0/24 from AS64511, fails for 198.51.100.1 and counts calls
+    struct Counting(Arc<AtomicUsize>);
+
+    impl Asn for Counting {
+        fn lookup_asn(&self, ip: IpAddr) -> Result<Vec<AsnInfo>, Error> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            if ip.to_string() == "198.51.100.1" {
+                return Err(Error::new(ErrorKind::TimedOut, "timed out"));
+            }
+            let more_specific: Prefix = "10.0.1.0/24".parse().unwrap();
+            let covering: Prefix = "10.0.0.0/16".parse().unwrap();
+            let info = if more_specific.contains(ip) {
+                AsnInfo {
+                    prefix: Some(more_specific.to_string()),
+                    ..AsnInfo::new("64511", "SPECIFIC")
+                }
+            } else if covering.contains(ip) {
+                AsnInfo {
+                    prefix: Some(covering.to_string()),
+                    ..AsnInfo::new("64496", "TEST")
+                }
+            } else {
+                AsnInfo::new("64496", "TEST")
+            };
+            Ok(vec![info])
+        }
+
+        fn lookup_as(&self, asn: u32) -> Result<Vec<AsnInfo>, Error> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(vec![AsnInfo::new(asn.to_string(), "TEST")])
+        }
+    }
+
+    fn counting_cache(ttl: Duration) -> (LookupCache, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        (
+            LookupCache::new(Box::new(Counting(calls.clone())), ttl),
+            calls,
+        )
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_results_are_cached_per_address() {
+        let (cache, calls) = counting_cache(Duration::from_secs(60));
+        cache.lookup_asn(ip("10.0.0.1")).unwrap();
+        cache.lookup_asn(ip("10.0.0.1")).unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // The cached /16 must not answer for the more-specific /24 inside it
+        let results = cache.lookup_asn(ip("10.0.1.1")).unwrap();
+        assert_eq!(results[0].asn, "64511");
+        assert_eq!(results[0].prefix.as_deref(), Some("10.0.1.0/24"));
+        let results = cache.lookup_asn(ip("10.0.2.1")).unwrap();
+        assert_eq!(results[0].asn, "64496");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(cache.len(), 3);
+    }
+
+    #[test]
+    fn test_expired_and_failed_lookups_are_repeated() {
+        let (cache, calls) = counting_cache(Duration::ZERO);
+        cache.lookup_asn(ip("10.0.0.1")).unwrap();
+        cache.lookup_asn(ip("10.0.0.1")).unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(cache.len(), 1);
+
+        let (cache, calls) = counting_cache(Duration::from_secs(60));
+        assert!(cache.lookup_asn(ip("198.51.100.1")).is_err());
+        assert!(cache.lookup_asn(ip("198.51.100.1")).is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_as_lookups_and_capacity() {
+        let (cache, calls) = counting_cache(Duration::from_secs(60));
+        let cache = cache.capacity(2);
+        cache.lookup_as(64496).unwrap();
+        cache.lookup_as(64496).unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        cache.lookup_as(64497).unwrap();
+        assert_eq!(cache.len(), 2);
+        cache.lookup_as(64498).unwrap();
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_records_metrics() {
+        let metrics = Arc::new(Metrics::new());
+        let (cache, _) = counting_cache(Duration::from_secs(60));
+        let cache = cache.metrics(Arc::clone(&metrics));
+        cache.lookup_asn(ip("10.0.0.1")).unwrap();
+        cache.lookup_asn(ip("10.0.0.1")).unwrap();
+        cache.lookup_asn(ip("10.0.0.2")).unwrap();
+        assert!(cache.lookup_asn(ip("198.51.100.1")).is_err());
+
+        let text = metrics.render();
+        assert!(text.contains("\nasn_fetcher_cache_hits_total 1\n"));
+        assert!(text.contains("\nasn_fetcher_cache_misses_total 3\n"));
+        assert!(text.contains("\nasn_fetcher_cache_entries 2\n"));
+    }
+}
@@ -0,0 +1,239 @@
+// Provider chain combining several ASN sources
+
+use super::client::Asn;
+use super::delegated::DelegatedStats;
+use super::types::AsnInfo;
+use std::io::{Error, ErrorKind};
+use std::net::IpAddr;
+
+/// Trait for sources that add metadata to results produced by an `Asn` provider
+pub trait Enricher: Send + Sync {
+    /// Fills in additional fields on a lookup result for the given IP address
+    fn enrich(&self, ip: IpAddr, info: &mut AsnInfo);
+}
+
+impl Enricher for DelegatedStats {
+    fn enrich(&self, ip: IpAddr, info: &mut AsnInfo) {
+        DelegatedStats::enrich(self, ip, info)
+    }
+}
+
+/// An ordered list of ASN providers followed by enrichment sources
+///
+/// Providers are tried in order until one returns a non-empty result; every
+/// enricher is then applied to each returned `AsnInfo`.
+#[derive(Default)]
+pub struct ProviderChain {
+    providers: Vec<(String, Box<dyn Asn>)>,
+    enrichers: Vec<Box<dyn Enricher>>,
+}
+
+impl ProviderChain {
+    /// Creates an empty chain
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a named provider to the chain
+    pub fn with_provider(mut self, name: impl Into<String>, provider: Box<dyn Asn>) -> Self {
+        self.providers.push((name.into(), provider));
+        self
+    }
+
+    /// Appends an enrichment source to the chain
+    pub fn with_enricher(mut self, enricher: Box<dyn Enricher>) -> Self {
+        self.enrichers.push(enricher);
+        self
+    }
+
+    /// Returns the names of the configured providers, in lookup order
+    pub fn provider_names(&self) -> Vec<&str> {
+        self.providers
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+}
+
+impl Asn for ProviderChain {
+    fn lookup_asn(&self, ip: IpAddr) -> Result<Vec<AsnInfo>, Error> {
+        let mut last_error = None;
+        // Once any provider answers, the address was looked up, even if it has no origin
+        let mut answered = false;
+        let mut results = Vec::new();
+        for (name, provider) in &self.providers {
+            let _span = tracing::info_span!("lookup", provider = name.as_str(), %ip).entered();
+            match provider.lookup_asn(ip) {
+                Ok(found) if !found.is_empty() => {
+                    tracing::debug!(results = found.len(), "found");
+                    results = found;
+                    answered = true;
+                    break;
+                }
+                Ok(_) => {
+                    tracing::debug!("not found");
+                    answered = true;
+                }
+                Err(e) => {
+                    tracing::debug!(error = %e, "lookup failed");
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        match last_error {
+            Some(e) if !answered => return Err(e),
+            _ => {}
+        }
+        if self.providers.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "no ASN providers configured",
+            ));
+        }
+
+        for info in &mut results {
+            for enricher in &self.enrichers {
+                enricher.enrich(ip, info);
+            }
+        }
+        Ok(results)
+    }
+
+    /// Tries each provider that supports AS lookups in order until one returns a result
+    fn lookup_as(&self, asn: u32) -> Result<Vec<AsnInfo>, Error> {
+        let mut last_error = None;
+        let mut supported = false;
+        for (name, provider) in &self.providers {
+            let _span = tracing::info_span!("lookup_as", provider = name.as_str(), asn).entered();
+            match provider.lookup_as(asn) {
+                Ok(found) if !found.is_empty() => return Ok(found),
+                Ok(_) => supported = true,
+                Err(e) if e.kind() == ErrorKind::Unsupported => {}
+                Err(e) => last_error = Some(e),
+            }
+        }
+        match last_error {
+            Some(e) => Err(e),
+            None if supported => Ok(Vec::new()),
+            None => Err(Error::new(
+                ErrorKind::Unsupported,
+                "none of the configured providers support AS lookups",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fixed(Vec<AsnInfo>);
+
+    impl Asn for Fixed {
+        fn lookup_asn(&self, _ip: IpAddr) -> Result<Vec<AsnInfo>, Error> {
+            Ok(self.0.clone())
+        }
+    }
+
+    struct Failing;
+
+    struct Holder;
+
+    impl Asn for Holder {
+        fn lookup_asn(&self, _ip: IpAddr) -> Result<Vec<AsnInfo>, Error> {
+            Ok(Vec::new())
+        }
+
+        fn lookup_as(&self, asn: u32) -> Result<Vec<AsnInfo>, Error> {
+            Ok(vec![AsnInfo::new(asn.to_string(), "HOLDER")])
+        }
+    }
+
+    impl Asn for Failing {
+        fn lookup_asn(&self, _ip: IpAddr) -> Result<Vec<AsnInfo>, Error> {
+            Err(Error::new(ErrorKind::TimedOut, "timed out"))
+        }
+    }
+
+    struct Country(&'static str);
+
+    impl Enricher for Country {
+        fn enrich(&self, _ip: IpAddr, info: &mut AsnInfo) {
+            info.country = Some(self.0.to_string());
+        }
+    }
+
+    fn ip() -> IpAddr {
+        "192.0.2.1".parse().unwrap()
+    }
+
+    #[test]
+    fn test_empty_chain_is_an_error() {
+        let chain = ProviderChain::new();
+        let err = chain.lookup_asn(ip()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_falls_through_failing_and_empty_providers() {
+        let chain = ProviderChain::new()
+            .with_provider("failing", Box::new(Failing))
+            .with_provider("empty", Box::new(Fixed(vec![])))
+            .with_provider(
+                "fixed",
+                Box::new(Fixed(vec![AsnInfo::new("64496", "TEST")])),
+            );
+        let result = chain.lookup_asn(ip()).unwrap();
+        assert_eq!(result, vec![AsnInfo::new("64496", "TEST")]);
+        assert_eq!(chain.provider_names(), vec!["failing", "empty", "fixed"]);
+    }
+
+    #[test]
+    fn test_empty_answer_outweighs_earlier_error() {
+        for chain in [
+            ProviderChain::new()
+                .with_provider("failing", Box::new(Failing))
+                .with_provider("empty", Box::new(Fixed(vec![]))),
+            ProviderChain::new()
+                .with_provider("empty", Box::new(Fixed(vec![])))
+                .with_provider("failing", Box::new(Failing)),
+        ] {
+            assert_eq!(chain.lookup_asn(ip()).unwrap(), vec![]);
+        }
+    }
+
+    #[test]
+    fn test_returns_last_error_when_all_fail() {
+        let chain = ProviderChain::new().with_provider("failing", Box::new(Failing));
+        let err = chain.lookup_asn(ip()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn test_enrichers_applied_to_results() {
+        let chain = ProviderChain::new()
+            .with_provider(
+                "fixed",
+                Box::new(Fixed(vec![AsnInfo::new("64496", "TEST")])),
+            )
+            .with_enricher(Box::new(Country("NL")));
+        let result = chain.lookup_asn(ip()).unwrap();
+        assert_eq!(result[0].country.as_deref(), Some("NL"));
+    }
+
+    #[test]
+    fn test_lookup_as_skips_unsupported_providers() {
+        let chain = ProviderChain::new()
+            .with_provider("fixed", Box::new(Fixed(vec![])))
+            .with_provider("holder", Box::new(Holder));
+        assert_eq!(
+            chain.lookup_as(64496).unwrap(),
+            vec![AsnInfo::new("64496", "HOLDER")]
+        );
+
+        let chain = ProviderChain::new().with_provider("fixed", Box::new(Fixed(vec![])));
+        let err = chain.lookup_as(64496).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+    }
+}
@@ -1,14 +1,16 @@
 // ASN lookup trait
 
 use super::types::AsnInfo;
-use std::io::Error;
+use std::fmt;
+use std::io::{Error, ErrorKind};
 use std::net::IpAddr;
 
 /// Trait for ASN lookup providers
 ///
 /// Implement this trait to provide ASN lookup functionality
-/// from different data sources (RIPE, ARIN, etc.)
-pub trait Asn {
+/// from different data sources (RIPE, ARIN, etc.). Providers must be
+/// shareable between threads so one chain can serve concurrent requests.
+pub trait Asn: Send + Sync {
     /// Looks up ASN information for a given IP address
     ///
     /// # Arguments
@@ -20,6 +22,54 @@ pub trait Asn {
     /// Returns an error if the lookup fails due to network issues,
     /// API errors, or invalid response data
     fn lookup_asn(&self, ip: IpAddr) -> Result<Vec<AsnInfo>, Error>;
+
+    /// Looks up information about an AS number itself, such as its holder
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Unsupported` error for providers that can only look up addresses
+    fn lookup_as(&self, asn: u32) -> Result<Vec<AsnInfo>, Error> {
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            format!("AS lookups are not supported for AS{}", asn),
+        ))
+    }
+}
+
+/// The cause of lookups a provider refused because its rate limit was exceeded
+#[derive(Debug)]
+pub struct RateLimited(pub String);
+
+impl fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rate limited: {}", self.0)
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+/// Creates the error for a request refused by a provider's rate limit
+pub fn rate_limited(message: impl Into<String>) -> Error {
+    Error::new(ErrorKind::Other, RateLimited(message.into()))
+}
+
+/// Returns true if the error comes from a provider's rate limit
+pub fn is_rate_limited(err: &Error) -> bool {
+    err.get_ref().is_some_and(|inner| inner.is::<RateLimited>())
+}
+
+/// Fails with a rate-limit error when a provider answered 429 Too Many Requests
+pub fn check_rate_limit(
+    provider: &str,
+    response: &reqwest::blocking::Response,
+) -> Result<(), Error> {
+    if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        return Err(rate_limited(format!(
+            "{} answered 429 Too Many Requests",
+            provider
+        )));
+    }
+    Ok(())
 }
 
 pub fn map_reqwest_error(err: reqwest::Error) -> Error {
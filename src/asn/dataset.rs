@@ -0,0 +1,98 @@
+// Helpers shared by providers that load local dataset files
+
+use super::types::AsnInfo;
+use crate::network::Prefix;
+use flate2::read::MultiGzDecoder;
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Error, ErrorKind},
+    path::Path,
+};
+
+/// Trait for local datasets whose full contents can be enumerated
+///
+/// Used to compile datasets into other formats, such as the local database.
+pub trait Dataset {
+    /// Iterates over every prefix in the dataset with the results a lookup would return
+    fn entries(&self) -> Box<dyn Iterator<Item = (Prefix, Vec<AsnInfo>)> + '_>;
+}
+
+/// Magic bytes at the start of every gzip stream
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Opens a dataset file for line-by-line reading, transparently decompressing gzip
+///
+/// Compression is detected from the file contents rather than the extension, so
+/// both `ip2asn-combined.tsv` and `ip2asn-combined.tsv.gz` are accepted.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be opened or read
+pub fn open_dataset<P: AsRef<Path>>(path: P) -> Result<Box<dyn BufRead>, Error> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|e| {
+        Error::new(
+            e.kind(),
+            format!("failed to open {}: {}", path.display(), e),
+        )
+    })?;
+    let mut reader = BufReader::new(file);
+    if reader.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(reader))))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// Wraps a parse failure with the 1-based line number it occurred on
+pub fn line_error(number: usize, msg: impl std::fmt::Display) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("line {}: {}", number + 1, msg),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::{Read, Write};
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("asn-fetcher-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_open_plain_file() {
+        let path = temp_path("plain.tsv");
+        std::fs::write(&path, "1.0.0.0\t24\t13335\n").unwrap();
+        let mut contents = String::new();
+        open_dataset(&path)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(contents, "1.0.0.0\t24\t13335\n");
+    }
+
+    #[test]
+    fn test_open_gzip_file() {
+        let path = temp_path("compressed.tsv.gz");
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"1.0.0.0\t24\t13335\n").unwrap();
+        std::fs::write(&path, encoder.finish().unwrap()).unwrap();
+        let mut contents = String::new();
+        open_dataset(&path)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(contents, "1.0.0.0\t24\t13335\n");
+    }
+
+    #[test]
+    fn test_open_missing_file() {
+        let err = open_dataset(temp_path("missing")).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+    }
+}
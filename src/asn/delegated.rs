@@ -0,0 +1,293 @@
+// RIR delegated-stats importer
+
+use super::dataset::{line_error, open_dataset};
+use super::types::AsnInfo;
+use std::{
+    io::{BufRead, Error},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    path::Path,
+};
+
+/// A single delegation record from an RIR statistics file
+#[derive(Debug, PartialEq, Clone)]
+pub struct Delegation {
+    /// The registry that made the delegation (e.g., "ripencc")
+    pub registry: String,
+    /// ISO 3166 country code of the holder
+    pub country: String,
+    /// Allocation date formatted as YYYY-MM-DD, if the registry recorded one
+    pub allocated: Option<String>,
+    /// Delegation status ("allocated" or "assigned")
+    pub status: String,
+}
+
+/// Sorted, non-overlapping ranges mapping to an index into the record list
+#[derive(Debug, Default)]
+struct RangeIndex<T> {
+    ranges: Vec<(T, T, usize)>,
+}
+
+impl<T: Ord + Copy> RangeIndex<T> {
+    fn push(&mut self, start: T, end: T, record: usize) {
+        self.ranges.push((start, end, record));
+    }
+
+    fn sort(&mut self) {
+        self.ranges.sort_unstable_by_key(|&(start, _, _)| start);
+    }
+
+    fn find(&self, key: T) -> Option<usize> {
+        let pos = self.ranges.partition_point(|&(start, _, _)| start <= key);
+        let (_, end, record) = *self.ranges.get(pos.checked_sub(1)?)?;
+        (key <= end).then_some(record)
+    }
+}
+
+/// Offline index over the RIR `delegated-*-extended-latest` files
+///
+/// Load one file per registry (ARIN, RIPE NCC, APNIC, LACNIC, AFRINIC) to
+/// answer which registry, country and allocation date an IP address or ASN
+/// belongs to. Only `allocated` and `assigned` records are indexed.
+#[derive(Debug, Default)]
+pub struct DelegatedStats {
+    records: Vec<Delegation>,
+    ipv4: RangeIndex<u32>,
+    ipv6: RangeIndex<u128>,
+    asns: RangeIndex<u32>,
+}
+
+impl DelegatedStats {
+    /// Creates an empty index
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads a delegated-stats file (optionally gzip-compressed) from disk into the index
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or contains a malformed record
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
+        self.load_reader(open_dataset(path)?)
+    }
+
+    /// Loads delegated-stats records from any buffered reader
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading fails or a record is malformed
+    pub fn load_reader<R: BufRead>(&mut self, reader: R) -> Result<(), Error> {
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            self.parse_line(&line)
+                .map_err(|msg| line_error(number, msg))?;
+        }
+        self.ipv4.sort();
+        self.ipv6.sort();
+        self.asns.sort();
+        Ok(())
+    }
+
+    /// Parses one line, skipping comments, the version header and summary lines.
+    /// Example record:
+    /// ripencc|FR|ipv4|2.0.0.0|1048576|20100712|allocated|ad1b2e29-...
+    fn parse_line(&mut self, line: &str) -> Result<(), String> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(());
+        }
+        let parts: Vec<&str> = line.split('|').collect();
+        // Version header: version|registry|serial|records|startdate|enddate|UTCoffset
+        // Summary line:   registry|*|type|*|count|summary
+        if parts.len() < 7 || parts[1] == "*" {
+            return Ok(());
+        }
+        let status = parts[6];
+        if status != "allocated" && status != "assigned" {
+            return Ok(());
+        }
+
+        let record = self.records.len();
+        let (kind, start, value) = (parts[2], parts[3], parts[4]);
+        let value: u64 = value
+            .parse()
+            .map_err(|_| format!("invalid value '{}'", value))?;
+        match kind {
+            "asn" => {
+                let start: u32 = start
+                    .parse()
+                    .map_err(|_| format!("invalid ASN '{}'", start))?;
+                let end = (start as u64 + value.saturating_sub(1)).min(u32::MAX as u64);
+                self.asns.push(start, end as u32, record);
+            }
+            "ipv4" => {
+                let start: Ipv4Addr = start
+                    .parse()
+                    .map_err(|_| format!("invalid IPv4 address '{}'", start))?;
+                let start = u32::from(start);
+                let end = (start as u64 + value.saturating_sub(1)).min(u32::MAX as u64);
+                self.ipv4.push(start, end as u32, record);
+            }
+            "ipv6" => {
+                let start: Ipv6Addr = start
+                    .parse()
+                    .map_err(|_| format!("invalid IPv6 address '{}'", start))?;
+                if value > 128 {
+                    return Err(format!("invalid IPv6 prefix length {}", value));
+                }
+                let start = u128::from(start);
+                let host_bits = u128::MAX.checked_shr(value as u32).unwrap_or(0);
+                self.ipv6.push(start, start | host_bits, record);
+            }
+            _ => return Ok(()),
+        }
+
+        self.records.push(Delegation {
+            registry: parts[0].to_string(),
+            country: parts[1].to_string(),
+            allocated: Self::parse_date(parts[5]),
+            status: status.to_string(),
+        });
+        Ok(())
+    }
+
+    /// Converts a YYYYMMDD date into YYYY-MM-DD, treating blank or zero dates as unknown
+    fn parse_date(date: &str) -> Option<String> {
+        if date.len() != 8 || !date.bytes().all(|b| b.is_ascii_digit()) || date == "00000000" {
+            return None;
+        }
+        Some(format!("{}-{}-{}", &date[..4], &date[4..6], &date[6..]))
+    }
+
+    /// Returns the number of indexed delegation records
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Returns true if no records have been loaded
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Finds the delegation covering an IP address
+    pub fn lookup_ip(&self, ip: IpAddr) -> Option<&Delegation> {
+        let record = match ip {
+            IpAddr::V4(v4) => self.ipv4.find(u32::from(v4)),
+            IpAddr::V6(v6) => self.ipv6.find(u128::from(v6)),
+        }?;
+        self.records.get(record)
+    }
+
+    /// Finds the delegation covering an AS number
+    pub fn lookup_asn(&self, asn: u32) -> Option<&Delegation> {
+        self.records.get(self.asns.find(asn)?)
+    }
+
+    /// Fills in registry metadata on a lookup result
+    ///
+    /// The ASN's own delegation is preferred; if the ASN is unknown the
+    /// delegation of the queried IP address is used instead. Fields that a
+    /// provider already populated are left untouched.
+    pub fn enrich(&self, ip: IpAddr, info: &mut AsnInfo) {
+        let delegation = info
+            .asn_number()
+            .and_then(|asn| self.lookup_asn(asn))
+            .or_else(|| self.lookup_ip(ip));
+        if let Some(delegation) = delegation {
+            info.registry
+                .get_or_insert_with(|| delegation.registry.clone());
+            info.country
+                .get_or_insert_with(|| delegation.country.clone());
+            if info.allocated.is_none() {
+                info.allocated = delegation.allocated.clone();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::ErrorKind;
+
+    const SAMPLE: &str = "\
+2|ripencc|1700000000|3|19830705|20231114|+0100
+ripencc|*|asn|*|1|summary
+ripencc|*|ipv4|*|1|summary
+ripencc|*|ipv6|*|1|summary
+# a comment
+ripencc|NL|asn|3333|1|19930901|allocated|abc
+ripencc|FR|ipv4|2.0.0.0|1048576|20100712|allocated|def
+ripencc|DE|ipv6|2001:db8::|32|20050101|assigned|ghi
+ripencc||ipv4|5.0.0.0|256||available|
+";
+
+    fn sample() -> DelegatedStats {
+        let mut stats = DelegatedStats::new();
+        stats.load_reader(SAMPLE.as_bytes()).unwrap();
+        stats
+    }
+
+    #[test]
+    fn test_load_skips_header_summary_and_available() {
+        let stats = sample();
+        assert_eq!(stats.len(), 3);
+    }
+
+    #[test]
+    fn test_lookup_ipv4_range() {
+        let stats = sample();
+        let delegation = stats.lookup_ip("2.15.255.255".parse().unwrap()).unwrap();
+        assert_eq!(delegation.registry, "ripencc");
+        assert_eq!(delegation.country, "FR");
+        assert_eq!(delegation.allocated.as_deref(), Some("2010-07-12"));
+        assert!(stats.lookup_ip("2.16.0.0".parse().unwrap()).is_none());
+        assert!(stats.lookup_ip("1.255.255.255".parse().unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_lookup_ipv6_prefix() {
+        let stats = sample();
+        let delegation = stats
+            .lookup_ip("2001:db8:ffff::1".parse().unwrap())
+            .unwrap();
+        assert_eq!(delegation.country, "DE");
+        assert_eq!(delegation.status, "assigned");
+        assert!(stats.lookup_ip("2001:db9::1".parse().unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_lookup_asn() {
+        let stats = sample();
+        assert_eq!(stats.lookup_asn(3333).unwrap().country, "NL");
+        assert!(stats.lookup_asn(3334).is_none());
+    }
+
+    #[test]
+    fn test_enrich_prefers_asn_delegation() {
+        let stats = sample();
+        let mut info = AsnInfo::new("AS3333", "RIPE-NCC-AS");
+        stats.enrich("2.0.0.1".parse().unwrap(), &mut info);
+        assert_eq!(info.registry.as_deref(), Some("ripencc"));
+        assert_eq!(info.country.as_deref(), Some("NL"));
+        assert_eq!(info.allocated.as_deref(), Some("1993-09-01"));
+    }
+
+    #[test]
+    fn test_enrich_falls_back_to_ip_delegation() {
+        let stats = sample();
+        let mut info = AsnInfo::new("N/A", "Unknown");
+        stats.enrich("2.0.0.1".parse().unwrap(), &mut info);
+        assert_eq!(info.country.as_deref(), Some("FR"));
+    }
+
+    #[test]
+    fn test_malformed_record_is_an_error() {
+        let mut stats = DelegatedStats::new();
+        let err = stats
+            .load_reader("arin|US|ipv4|not-an-ip|256|20000101|allocated".as_bytes())
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(err.to_string().contains("line 1"));
+    }
+}
@@ -29,6 +29,7 @@ impl Asn for IPApi {
         };
 
         let response = self.client.get(&url).send().map_err(map_reqwest_error)?;
+        check_rate_limit("ipapi.co", &response)?;
 
         let response_text = response.text().map_err(map_reqwest_error)?;
 
@@ -47,6 +48,9 @@ impl Asn for IPApi {
                     .get("reason")
                     .and_then(|v| v.as_str())
                     .unwrap_or("Unknown error");
+                if message == "RateLimited" {
+                    return Err(rate_limited("ipapi.co reported RateLimited"));
+                }
                 return Err(std::io::Error::new(
                     std::io::ErrorKind::Other,
                     format!("API error: {}", message),
@@ -66,7 +70,7 @@ impl Asn for IPApi {
             .unwrap_or("Unknown")
             .to_string();
 
-        Ok(vec![AsnInfo { asn, holder }])
+        Ok(vec![AsnInfo::new(asn, holder)])
     }
 }
 
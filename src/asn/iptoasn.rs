@@ -0,0 +1,223 @@
+// iptoasn.com TSV dataset provider
+
+use super::client::Asn;
+use super::dataset::{line_error, open_dataset, Dataset};
+use super::types::AsnInfo;
+use crate::network::{trie::PrefixTrie, Prefix};
+use std::{
+    io::{BufRead, Error},
+    net::IpAddr,
+    path::Path,
+};
+
+/// A single announced range from the iptoasn dataset
+#[derive(Debug, Clone)]
+struct Entry {
+    asn: u32,
+    country: String,
+    description: String,
+}
+
+/// Offline lookups against the iptoasn.com `ip2asn-combined.tsv` dataset
+///
+/// Each line holds an inclusive address range with its origin AS, country code
+/// and AS description:
+/// `1.0.0.0\t1.0.0.255\t13335\tUS\tCLOUDFLARENET`.
+/// Ranges are split into CIDR prefixes so lookups are longest-prefix matches.
+/// Ranges marked with AS 0 ("Not routed") are skipped.
+pub struct IpToAsn {
+    table: PrefixTrie<usize>,
+    entries: Vec<Entry>,
+}
+
+impl IpToAsn {
+    /// Loads an ip2asn TSV file (optionally gzip-compressed) from disk
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or contains a malformed line
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::from_reader(open_dataset(path)?)
+    }
+
+    /// Loads ip2asn records from any buffered reader
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading fails or a line is malformed
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Self, Error> {
+        let mut iptoasn = IpToAsn {
+            table: PrefixTrie::new(),
+            entries: Vec::new(),
+        };
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (prefixes, entry) =
+                Self::parse_line(&line).map_err(|msg| line_error(number, msg))?;
+            if entry.asn == 0 {
+                continue;
+            }
+            let index = iptoasn.entries.len();
+            iptoasn.entries.push(entry);
+            for prefix in prefixes {
+                iptoasn.table.insert(prefix, index);
+            }
+        }
+        Ok(iptoasn)
+    }
+
+    fn parse_line(line: &str) -> Result<(Vec<Prefix>, Entry), String> {
+        let parts: Vec<&str> = line.splitn(5, '\t').collect();
+        if parts.len() != 5 {
+            return Err(format!(
+                "expected 5 tab-separated fields, found {}",
+                parts.len()
+            ));
+        }
+        let start: IpAddr = parts[0]
+            .parse()
+            .map_err(|_| format!("invalid address '{}'", parts[0]))?;
+        let end: IpAddr = parts[1]
+            .parse()
+            .map_err(|_| format!("invalid address '{}'", parts[1]))?;
+        let asn: u32 = parts[2]
+            .parse()
+            .map_err(|_| format!("invalid AS number '{}'", parts[2]))?;
+        let prefixes = Prefix::from_range(start, end).map_err(|e| e.to_string())?;
+        Ok((
+            prefixes,
+            Entry {
+                asn,
+                country: parts[3].trim().to_string(),
+                description: parts[4].trim().to_string(),
+            },
+        ))
+    }
+
+    /// Number of announced ranges loaded
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if no ranges were loaded
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn result(&self, prefix: Prefix, index: usize) -> AsnInfo {
+        let entry = &self.entries[index];
+        let country = match entry.country.as_str() {
+            "" | "None" => None,
+            country => Some(country.to_string()),
+        };
+        AsnInfo {
+            prefix: Some(prefix.to_string()),
+            country,
+            ..AsnInfo::new(entry.asn.to_string(), entry.description.clone())
+        }
+    }
+}
+
+impl Asn for IpToAsn {
+    fn lookup_asn(&self, ip: IpAddr) -> Result<Vec<AsnInfo>, Error> {
+        Ok(self
+            .table
+            .longest_match(ip)
+            .map(|(prefix, &index)| vec![self.result(prefix, index)])
+            .unwrap_or_default())
+    }
+}
+
+impl Dataset for IpToAsn {
+    fn entries(&self) -> Box<dyn Iterator<Item = (Prefix, Vec<AsnInfo>)> + '_> {
+        Box::new(
+            self.table
+                .iter()
+                .map(|(prefix, &index)| (prefix, vec![self.result(prefix, index)])),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::ErrorKind;
+
+    const SAMPLE: &str = "\
+1.0.0.0\t1.0.0.255\t13335\tUS\tCLOUDFLARENET
+1.0.1.0\t1.0.3.255\t0\tNone\tNot routed
+8.8.8.0\t8.8.8.255\t15169\tUS\tGOOGLE
+10.0.0.1\t10.0.0.6\t64496\tNL\tEXAMPLE-AS, with comma
+2001:4860::\t2001:4860:ffff:ffff:ffff:ffff:ffff:ffff\t15169\tUS\tGOOGLE
+";
+
+    fn sample() -> IpToAsn {
+        IpToAsn::from_reader(SAMPLE.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_load_skips_not_routed() {
+        let iptoasn = sample();
+        assert_eq!(iptoasn.len(), 4);
+        assert!(iptoasn
+            .lookup_asn("1.0.2.1".parse().unwrap())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_lookup() {
+        let result = sample().lookup_asn("8.8.8.8".parse().unwrap()).unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].asn, "15169");
+        assert_eq!(result[0].holder, "GOOGLE");
+        assert_eq!(result[0].country.as_deref(), Some("US"));
+        assert_eq!(result[0].prefix.as_deref(), Some("8.8.8.0/24"));
+    }
+
+    #[test]
+    fn test_lookup_unaligned_range() {
+        let iptoasn = sample();
+        for ip in ["10.0.0.1", "10.0.0.3", "10.0.0.6"] {
+            let result = iptoasn.lookup_asn(ip.parse().unwrap()).unwrap();
+            assert_eq!(result[0].holder, "EXAMPLE-AS, with comma");
+        }
+        assert!(iptoasn
+            .lookup_asn("10.0.0.7".parse().unwrap())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_lookup_ipv6() {
+        let result = sample()
+            .lookup_asn("2001:4860:4860::8888".parse().unwrap())
+            .unwrap();
+        assert_eq!(result[0].prefix.as_deref(), Some("2001:4860::/32"));
+    }
+
+    #[test]
+    fn test_entries_split_ranges() {
+        let entries: Vec<String> = sample()
+            .entries()
+            .filter(|(_, results)| results[0].asn == "64496")
+            .map(|(prefix, _)| prefix.to_string())
+            .collect();
+        assert_eq!(
+            entries,
+            vec!["10.0.0.1/32", "10.0.0.2/31", "10.0.0.4/31", "10.0.0.6/32"]
+        );
+    }
+
+    #[test]
+    fn test_malformed_line() {
+        let err = IpToAsn::from_reader("1.0.0.0\t1.0.0.255\t13335\n".as_bytes())
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(err.to_string().contains("line 1"));
+    }
+}
@@ -0,0 +1,618 @@
+// Compiled local database provider
+
+use super::client::Asn;
+use super::dataset::{line_error, Dataset};
+use super::delegated::DelegatedStats;
+use super::types::AsnInfo;
+use crate::network::ip::{ip_to_u128, u128_to_ip};
+use crate::network::Prefix;
+use memmap2::Mmap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{BufRead, BufWriter, Error, ErrorKind, Write},
+    net::IpAddr,
+    ops::Deref,
+    path::Path,
+};
+
+/// File signature followed by the format version
+const MAGIC: &[u8; 6] = b"ASNDB\0";
+const VERSION: u16 = 1;
+const HEADER_LEN: usize = 24;
+/// Sizes of the fixed-width section entries
+const NODE_LEN: usize = 12;
+const GROUP_LEN: usize = 8;
+const RECORD_LEN: usize = 20;
+/// String offset marking an absent optional field
+const NO_STRING: u32 = u32::MAX;
+/// Node indexes of the IPv4 and IPv6 trie roots
+const V4_ROOT: u32 = 0;
+const V6_ROOT: u32 = 1;
+
+fn corrupt(msg: impl Into<String>) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.into())
+}
+
+/// Compiles datasets into the local database format
+///
+/// The file is a little-endian binary with a fixed header followed by four
+/// sections: trie nodes (left, right, group), result groups (first record,
+/// count), records (asn, holder, country, registry, allocated) and a pool of
+/// interned length-prefixed strings. Node 0 is the IPv4 root and node 1 the
+/// IPv6 root; all references are indexes or offsets, so the file can be
+/// memory-mapped and queried without decoding.
+#[derive(Debug, Default)]
+pub struct LocalDbBuilder {
+    prefixes: BTreeMap<Prefix, Vec<AsnInfo>>,
+}
+
+impl LocalDbBuilder {
+    /// Creates an empty builder
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds results for a prefix, returning false if the prefix was already present
+    ///
+    /// The first source to provide a prefix wins, matching provider chain order.
+    pub fn add(&mut self, prefix: Prefix, results: Vec<AsnInfo>) -> bool {
+        if results.is_empty() || self.prefixes.contains_key(&prefix) {
+            return false;
+        }
+        self.prefixes.insert(prefix, results);
+        true
+    }
+
+    /// Adds every prefix from a dataset, returning how many were new
+    pub fn add_dataset(&mut self, dataset: &dyn Dataset) -> usize {
+        dataset
+            .entries()
+            .filter(|(prefix, results)| self.add(*prefix, results.clone()))
+            .count()
+    }
+
+    /// Adds recorded provider results: one JSON `AsnInfo` per line with `prefix` set
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a line is not valid JSON or lacks a valid prefix
+    pub fn add_recorded<R: BufRead>(&mut self, reader: R) -> Result<usize, Error> {
+        let mut recorded: BTreeMap<Prefix, Vec<AsnInfo>> = BTreeMap::new();
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let info: AsnInfo = serde_json::from_str(&line).map_err(|e| line_error(number, e))?;
+            let prefix: Prefix = info
+                .prefix
+                .as_deref()
+                .ok_or_else(|| line_error(number, "result has no prefix"))?
+                .parse()
+                .map_err(|e| line_error(number, e))?;
+            let results = recorded.entry(prefix).or_default();
+            if !results.contains(&info) {
+                results.push(info);
+            }
+        }
+        Ok(recorded
+            .into_iter()
+            .filter(|(prefix, results)| self.add(*prefix, results.clone()))
+            .count())
+    }
+
+    /// Fills in registry metadata on every stored result
+    pub fn enrich(&mut self, stats: &DelegatedStats) {
+        for (prefix, results) in &mut self.prefixes {
+            for info in results {
+                stats.enrich(prefix.addr(), info);
+            }
+        }
+    }
+
+    /// Number of prefixes added so far
+    pub fn len(&self) -> usize {
+        self.prefixes.len()
+    }
+
+    /// Returns true if no prefixes have been added
+    pub fn is_empty(&self) -> bool {
+        self.prefixes.is_empty()
+    }
+
+    /// Writes the compiled database to a file
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written or the data exceeds format limits
+    pub fn write_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Serializes the compiled database
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing fails or the data exceeds format limits
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        let too_large = || Error::new(ErrorKind::InvalidInput, "database exceeds format limits");
+        let mut nodes: Vec<[u32; 3]> = vec![[0; 3], [0; 3]];
+        let mut groups: Vec<[u32; 2]> = Vec::new();
+        let mut records: Vec<[u32; 5]> = Vec::new();
+        let mut strings = StringPool::default();
+
+        for (prefix, results) in &self.prefixes {
+            let first = u32::try_from(records.len()).map_err(|_| too_large())?;
+            for info in results {
+                let asn = info.asn_number().unwrap_or(0);
+                records.push([
+                    asn,
+                    strings.intern(Some(&info.holder))?,
+                    strings.intern(info.country.as_deref())?,
+                    strings.intern(info.registry.as_deref())?,
+                    strings.intern(info.allocated.as_deref())?,
+                ]);
+            }
+            groups.push([first, results.len() as u32]);
+            let group = u32::try_from(groups.len()).map_err(|_| too_large())?;
+
+            let root = if prefix.is_ipv4() { V4_ROOT } else { V6_ROOT };
+            let mut node = root as usize;
+            let key = prefix.first();
+            for depth in 0..prefix.prefix_len() {
+                let bit = ((key >> (prefix.max_len() - 1 - depth)) & 1) as usize;
+                if nodes[node][bit] == 0 {
+                    nodes.push([0; 3]);
+                    nodes[node][bit] = u32::try_from(nodes.len() - 1).map_err(|_| too_large())?;
+                }
+                node = nodes[node][bit] as usize;
+            }
+            nodes[node][2] = group;
+        }
+
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&VERSION.to_le_bytes());
+        for count in [nodes.len(), groups.len(), records.len(), strings.data.len()] {
+            header.extend_from_slice(&u32::try_from(count).map_err(|_| too_large())?.to_le_bytes());
+        }
+        writer.write_all(&header)?;
+        for value in nodes
+            .iter()
+            .flatten()
+            .chain(groups.iter().flatten())
+            .chain(records.iter().flatten())
+        {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        writer.write_all(&strings.data)
+    }
+}
+
+impl Dataset for LocalDbBuilder {
+    fn entries(&self) -> Box<dyn Iterator<Item = (Prefix, Vec<AsnInfo>)> + '_> {
+        Box::new(
+            self.prefixes
+                .iter()
+                .map(|(prefix, results)| (*prefix, results.clone())),
+        )
+    }
+}
+
+/// Deduplicated pool of length-prefixed strings
+#[derive(Default)]
+struct StringPool {
+    data: Vec<u8>,
+    offsets: HashMap<String, u32>,
+}
+
+impl StringPool {
+    fn intern(&mut self, value: Option<&str>) -> Result<u32, Error> {
+        let Some(value) = value else {
+            return Ok(NO_STRING);
+        };
+        if let Some(&offset) = self.offsets.get(value) {
+            return Ok(offset);
+        }
+        let offset = u32::try_from(self.data.len())
+            .ok()
+            .filter(|&offset| offset != NO_STRING)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "string pool too large"))?;
+        // Longer strings are truncated at a character boundary to fit the u16 length
+        let mut end = value.len().min(u16::MAX as usize);
+        while !value.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.data.extend_from_slice(&(end as u16).to_le_bytes());
+        self.data.extend_from_slice(&value.as_bytes()[..end]);
+        self.offsets.insert(value.to_string(), offset);
+        Ok(offset)
+    }
+}
+
+/// Byte ranges of the sections within a database file
+#[derive(Debug, Clone, Copy)]
+struct Layout {
+    nodes: usize,
+    node_count: usize,
+    groups: usize,
+    group_count: usize,
+    records: usize,
+    record_count: usize,
+    strings: usize,
+    strings_len: usize,
+}
+
+impl Layout {
+    fn parse(data: &[u8]) -> Result<Self, Error> {
+        if data.len() < HEADER_LEN || &data[..6] != MAGIC {
+            return Err(corrupt("not an asn-fetcher local database"));
+        }
+        let version = u16::from_le_bytes([data[6], data[7]]);
+        if version != VERSION {
+            return Err(corrupt(format!(
+                "unsupported local database version {}",
+                version
+            )));
+        }
+        let count = |at: usize| read_u32(data, at).map(|v| v as usize);
+        let (node_count, group_count, record_count, strings_len) =
+            (count(8)?, count(12)?, count(16)?, count(20)?);
+
+        let nodes = HEADER_LEN;
+        let groups = nodes + node_count * NODE_LEN;
+        let records = groups + group_count * GROUP_LEN;
+        let strings = records + record_count * RECORD_LEN;
+        if node_count < 2 || strings + strings_len != data.len() {
+            return Err(corrupt("local database sections do not match file size"));
+        }
+        Ok(Layout {
+            nodes,
+            node_count,
+            groups,
+            group_count,
+            records,
+            record_count,
+            strings,
+            strings_len,
+        })
+    }
+}
+
+fn read_u32(data: &[u8], at: usize) -> Result<u32, Error> {
+    data.get(at..at + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| corrupt("offset out of bounds"))
+}
+
+/// Memory-mapped local database answering lookups without loading it into memory
+///
+/// Build files with `LocalDbBuilder` or `asn-fetcher db build`.
+pub struct LocalDb<D = Mmap> {
+    data: D,
+    layout: Layout,
+}
+
+impl LocalDb<Mmap> {
+    /// Memory-maps a compiled database file
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be opened or is not a valid database
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|e| {
+            Error::new(
+                e.kind(),
+                format!("failed to open {}: {}", path.display(), e),
+            )
+        })?;
+        // SAFETY: the map is read-only and every access is bounds-checked against the
+        // layout validated below; the file must not be truncated while it is in use.
+        let data = unsafe { Mmap::map(&file)? };
+        Self::from_bytes(data)
+    }
+}
+
+impl<D: Deref<Target = [u8]>> LocalDb<D> {
+    /// Wraps an in-memory copy of a compiled database
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bytes are not a valid database
+    pub fn from_bytes(data: D) -> Result<Self, Error> {
+        let layout = Layout::parse(&data)?;
+        Ok(LocalDb { data, layout })
+    }
+
+    /// Number of prefixes stored
+    pub fn len(&self) -> usize {
+        self.layout.group_count
+    }
+
+    /// Returns true if the database holds no prefixes
+    pub fn is_empty(&self) -> bool {
+        self.layout.group_count == 0
+    }
+
+    fn node(&self, index: u32) -> Result<[u32; 3], Error> {
+        if index as usize >= self.layout.node_count {
+            return Err(corrupt("node index out of bounds"));
+        }
+        let at = self.layout.nodes + index as usize * NODE_LEN;
+        Ok([
+            read_u32(&self.data, at)?,
+            read_u32(&self.data, at + 4)?,
+            read_u32(&self.data, at + 8)?,
+        ])
+    }
+
+    fn string(&self, offset: u32) -> Result<Option<String>, Error> {
+        if offset == NO_STRING {
+            return Ok(None);
+        }
+        let pool = &self.data[self.layout.strings..self.layout.strings + self.layout.strings_len];
+        let at = offset as usize;
+        let len = pool
+            .get(at..at + 2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+            .ok_or_else(|| corrupt("string offset out of bounds"))?;
+        let bytes = pool
+            .get(at + 2..at + 2 + len)
+            .ok_or_else(|| corrupt("string offset out of bounds"))?;
+        Ok(Some(String::from_utf8_lossy(bytes).into_owned()))
+    }
+
+    /// Decodes the results stored in a group (1-based, as stored in nodes)
+    fn results(&self, prefix: Prefix, group: u32) -> Result<Vec<AsnInfo>, Error> {
+        let index = group as usize - 1;
+        if index >= self.layout.group_count {
+            return Err(corrupt("group index out of bounds"));
+        }
+        let at = self.layout.groups + index * GROUP_LEN;
+        let (first, count) = (read_u32(&self.data, at)?, read_u32(&self.data, at + 4)?);
+        if first as usize + count as usize > self.layout.record_count {
+            return Err(corrupt("record index out of bounds"));
+        }
+
+        (first..first + count)
+            .map(|record| {
+                let at = self.layout.records + record as usize * RECORD_LEN;
+                let field = |n: usize| read_u32(&self.data, at + n * 4);
+                let asn = field(0)?;
+                Ok(AsnInfo {
+                    prefix: Some(prefix.to_string()),
+                    country: self.string(field(2)?)?,
+                    registry: self.string(field(3)?)?,
+                    allocated: self.string(field(4)?)?,
+                    ..AsnInfo::new(
+                        if asn == 0 {
+                            "N/A".to_string()
+                        } else {
+                            asn.to_string()
+                        },
+                        self.string(field(1)?)?.unwrap_or_default(),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// Finds the longest matching prefix for an address and its stored results
+    fn longest_match(&self, ip: IpAddr) -> Result<Option<(Prefix, u32)>, Error> {
+        let bits = if ip.is_ipv4() { 32 } else { 128 };
+        let key = ip_to_u128(ip);
+        let mut node = self.node(if ip.is_ipv4() { V4_ROOT } else { V6_ROOT })?;
+        let mut best = (node[2] != 0).then_some((0, node[2]));
+        for depth in 0..bits {
+            let child = node[((key >> (bits - 1 - depth)) & 1) as usize];
+            if child == 0 {
+                break;
+            }
+            node = self.node(child)?;
+            if node[2] != 0 {
+                best = Some((depth + 1, node[2]));
+            }
+        }
+        best.map(|(len, group)| Ok((Prefix::new(u128_to_ip(key, ip.is_ipv4()), len)?, group)))
+            .transpose()
+    }
+}
+
+impl<D: Deref<Target = [u8]>> Dataset for LocalDb<D> {
+    /// Walks both tries in address order; a corrupt subtree ends the iteration early
+    fn entries(&self) -> Box<dyn Iterator<Item = (Prefix, Vec<AsnInfo>)> + '_> {
+        let mut stack = vec![(V6_ROOT, 0u128, 0u8, false), (V4_ROOT, 0u128, 0u8, true)];
+        Box::new(std::iter::from_fn(move || {
+            while let Some((index, key, depth, ipv4)) = stack.pop() {
+                let node = self.node(index).ok()?;
+                let bits = if ipv4 { 32 } else { 128 };
+                for bit in [1u128, 0] {
+                    let child = node[bit as usize];
+                    if child != 0 && depth < bits {
+                        stack.push((child, key | (bit << (bits - 1 - depth)), depth + 1, ipv4));
+                    }
+                }
+                if node[2] != 0 {
+                    let prefix = Prefix::new(u128_to_ip(key, ipv4), depth).ok()?;
+                    return Some((prefix, self.results(prefix, node[2]).ok()?));
+                }
+            }
+            None
+        }))
+    }
+}
+
+impl<D: Deref<Target = [u8]> + Send + Sync> Asn for LocalDb<D> {
+    fn lookup_asn(&self, ip: IpAddr) -> Result<Vec<AsnInfo>, Error> {
+        match self.longest_match(ip)? {
+            Some((prefix, group)) => self.results(prefix, group),
+            None => Ok(vec![]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn builder() -> LocalDbBuilder {
+        let mut builder = LocalDbBuilder::new();
+        builder.add(
+            "8.8.8.0/24".parse().unwrap(),
+            vec![AsnInfo::new("15169", "GOOGLE")],
+        );
+        builder.add(
+            "8.0.0.0/9".parse().unwrap(),
+            vec![AsnInfo::new("3356", "LEVEL3")],
+        );
+        builder.add(
+            "192.0.2.0/24".parse().unwrap(),
+            vec![
+                AsnInfo::new("64496", "EXAMPLE"),
+                AsnInfo::new("64497", "EXAMPLE"),
+            ],
+        );
+        builder.add(
+            "2001:4860::/32".parse().unwrap(),
+            vec![AsnInfo::new("15169", "GOOGLE")],
+        );
+        builder
+    }
+
+    fn compile(builder: &LocalDbBuilder) -> LocalDb<Vec<u8>> {
+        let mut bytes = Vec::new();
+        builder.write(&mut bytes).unwrap();
+        LocalDb::from_bytes(bytes).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip_lookups() {
+        let db = compile(&builder());
+        assert_eq!(db.len(), 4);
+
+        let result = db.lookup_asn("8.8.8.8".parse().unwrap()).unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].asn, "15169");
+        assert_eq!(result[0].holder, "GOOGLE");
+        assert_eq!(result[0].prefix.as_deref(), Some("8.8.8.0/24"));
+
+        let result = db.lookup_asn("8.8.4.4".parse().unwrap()).unwrap();
+        assert_eq!(result[0].asn, "3356");
+
+        let result = db.lookup_asn("192.0.2.1".parse().unwrap()).unwrap();
+        assert_eq!(result.len(), 2);
+
+        let result = db.lookup_asn("2001:4860::1".parse().unwrap()).unwrap();
+        assert_eq!(result[0].prefix.as_deref(), Some("2001:4860::/32"));
+
+        assert!(db
+            .lookup_asn("203.0.113.1".parse().unwrap())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_holder_strings_interned() {
+        let mut bytes = Vec::new();
+        builder().write(&mut bytes).unwrap();
+        let occurrences = bytes.windows(6).filter(|w| w == b"GOOGLE").count();
+        assert_eq!(occurrences, 1);
+    }
+
+    #[test]
+    fn test_first_source_wins() {
+        let mut builder = builder();
+        assert!(!builder.add(
+            "8.8.8.0/24".parse().unwrap(),
+            vec![AsnInfo::new("64500", "OTHER")],
+        ));
+        let db = compile(&builder);
+        assert_eq!(
+            db.lookup_asn("8.8.8.8".parse().unwrap()).unwrap()[0].asn,
+            "15169"
+        );
+    }
+
+    #[test]
+    fn test_add_recorded_results() {
+        let recorded = r#"{"asn":"13335","holder":"CLOUDFLARENET","prefix":"1.1.1.0/24","country":"US"}
+{"asn":"64496","holder":"EXAMPLE","prefix":"198.51.100.0/24"}
+"#;
+        let mut builder = LocalDbBuilder::new();
+        assert_eq!(builder.add_recorded(recorded.as_bytes()).unwrap(), 2);
+        let db = compile(&builder);
+        let result = db.lookup_asn("1.1.1.1".parse().unwrap()).unwrap();
+        assert_eq!(result[0].holder, "CLOUDFLARENET");
+        assert_eq!(result[0].country.as_deref(), Some("US"));
+        assert_eq!(result[0].registry, None);
+    }
+
+    #[test]
+    fn test_add_recorded_requires_prefix() {
+        let err = LocalDbBuilder::new()
+            .add_recorded(r#"{"asn":"13335","holder":"CLOUDFLARENET"}"#.as_bytes())
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_enrich_with_delegated_stats() {
+        let mut stats = DelegatedStats::new();
+        stats
+            .load_reader("arin|US|asn|15169|1|20000330|assigned|x\n".as_bytes())
+            .unwrap();
+        let mut builder = builder();
+        builder.enrich(&stats);
+        let db = compile(&builder);
+        let result = db.lookup_asn("8.8.8.8".parse().unwrap()).unwrap();
+        assert_eq!(result[0].registry.as_deref(), Some("arin"));
+        assert_eq!(result[0].allocated.as_deref(), Some("2000-03-30"));
+    }
+
+    #[test]
+    fn test_entries_round_trip() {
+        let builder = builder();
+        let db = compile(&builder);
+        let from_db: Vec<(Prefix, Vec<AsnInfo>)> = db.entries().collect();
+        let from_builder: Vec<(Prefix, Vec<AsnInfo>)> = builder
+            .entries()
+            .map(|(prefix, results)| {
+                let results = results
+                    .into_iter()
+                    .map(|info| AsnInfo {
+                        prefix: Some(prefix.to_string()),
+                        ..info
+                    })
+                    .collect();
+                (prefix, results)
+            })
+            .collect();
+        assert_eq!(from_db, from_builder);
+    }
+
+    #[test]
+    fn test_rejects_invalid_files() {
+        assert!(LocalDb::from_bytes(b"not a database at all!!!".to_vec()).is_err());
+
+        let mut bytes = Vec::new();
+        builder().write(&mut bytes).unwrap();
+        bytes.pop();
+        let err = LocalDb::from_bytes(bytes).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_open_file() {
+        let path = std::env::temp_dir().join(format!("asn-fetcher-{}.asndb", std::process::id()));
+        builder().write_file(&path).unwrap();
+        let db = LocalDb::open(&path).unwrap();
+        let result = db.lookup_asn("8.8.8.8".parse().unwrap()).unwrap();
+        drop(db);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(result[0].asn, "15169");
+    }
+}
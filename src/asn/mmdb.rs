@@ -0,0 +1,464 @@
+// MaxMind DB (MMDB) reader and writer
+
+use super::client::Asn;
+use super::dataset::Dataset;
+use super::types::AsnInfo;
+use crate::network::Prefix;
+use maxminddb::{geoip2, MaxMindDBError, Mmap, Reader};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Error, ErrorKind, Write},
+    net::IpAddr,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Marker that precedes the metadata map at the end of every MMDB file
+const METADATA_MARKER: &[u8] = b"\xAB\xCD\xEFMaxMind.com";
+/// Zero bytes separating the search tree from the data section
+const DATA_SEPARATOR_LEN: usize = 16;
+/// Number of leading zero bits IPv4 addresses occupy in an IPv6 tree (::a.b.c.d)
+const IPV4_OFFSET: u8 = 96;
+
+/// Looks up ASN data in a MaxMind DB file such as GeoLite2-ASN
+pub struct Mmdb {
+    reader: Reader<Mmap>,
+}
+
+impl Mmdb {
+    /// Memory-maps an MMDB file
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be opened or is not a valid MMDB file
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let reader = Reader::open_mmap(path.as_ref()).map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("failed to open {}: {}", path.as_ref().display(), e),
+            )
+        })?;
+        Ok(Mmdb { reader })
+    }
+}
+
+impl Asn for Mmdb {
+    fn lookup_asn(&self, ip: IpAddr) -> Result<Vec<AsnInfo>, Error> {
+        let (record, len) = match self.reader.lookup_prefix::<geoip2::Asn>(ip) {
+            Ok(found) => found,
+            Err(MaxMindDBError::AddressNotFoundError(_)) => return Ok(vec![]),
+            Err(e) => return Err(Error::new(ErrorKind::InvalidData, e.to_string())),
+        };
+        let Some(asn) = record.autonomous_system_number else {
+            return Ok(vec![]);
+        };
+        let prefix = Prefix::new(ip, len as u8)?;
+        Ok(vec![AsnInfo {
+            prefix: Some(prefix.to_string()),
+            ..AsnInfo::new(
+                asn.to_string(),
+                record.autonomous_system_organization.unwrap_or("Unknown"),
+            )
+        }])
+    }
+}
+
+/// A value in the MMDB data section encoding
+enum Value<'a> {
+    String(&'a str),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    Map(Vec<(&'a str, Value<'a>)>),
+    Array(Vec<Value<'a>>),
+}
+
+impl Value<'_> {
+    /// Appends the MMDB encoding of the value
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Value::String(s) => {
+                Self::control(out, 2, s.len());
+                out.extend_from_slice(s.as_bytes());
+            }
+            Value::U16(n) => Self::unsigned(out, 5, *n as u64),
+            Value::U32(n) => Self::unsigned(out, 6, *n as u64),
+            Value::U64(n) => Self::unsigned(out, 9, *n),
+            Value::Map(entries) => {
+                Self::control(out, 7, entries.len());
+                for (key, value) in entries {
+                    Value::String(key).encode(out);
+                    value.encode(out);
+                }
+            }
+            Value::Array(items) => {
+                Self::control(out, 11, items.len());
+                for item in items {
+                    item.encode(out);
+                }
+            }
+        }
+    }
+
+    /// Encodes an unsigned integer using the minimal number of big-endian bytes
+    fn unsigned(out: &mut Vec<u8>, kind: u8, value: u64) {
+        let bytes = value.to_be_bytes();
+        let skip = bytes.iter().take_while(|&&b| b == 0).count();
+        Self::control(out, kind, bytes.len() - skip);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+
+    /// Writes the control byte(s) for a type and payload size
+    fn control(out: &mut Vec<u8>, kind: u8, size: usize) {
+        // Types above 7 are "extended": stored as 0 with the real type in the next byte
+        let (first, extended) = if kind > 7 {
+            (0, Some(kind - 7))
+        } else {
+            (kind, None)
+        };
+        let (size_bits, size_bytes): (u8, Vec<u8>) = match size {
+            0..=28 => (size as u8, vec![]),
+            29..=284 => (29, vec![(size - 29) as u8]),
+            285..=65_820 => (30, ((size - 285) as u16).to_be_bytes().to_vec()),
+            _ => (31, ((size - 65_821) as u32).to_be_bytes()[1..].to_vec()),
+        };
+        out.push((first << 5) | size_bits);
+        out.extend(extended);
+        out.extend(size_bytes);
+    }
+}
+
+/// Node of the in-memory trie used while building the search tree
+#[derive(Default)]
+struct BuildNode {
+    children: [Option<Box<BuildNode>>; 2],
+    data: Option<u32>,
+}
+
+/// Writes MaxMind-compatible GeoLite2-ASN style databases
+///
+/// Each prefix is stored with the `autonomous_system_number` and
+/// `autonomous_system_organization` keys used by GeoLite2-ASN, so existing
+/// GeoIP2 consumers (nginx, Suricata, libmaxminddb) can read the file. The
+/// tree is IPv6 with IPv4 prefixes placed under `::/96`. Multi-origin prefixes
+/// are exported with their first origin only.
+pub struct MmdbWriter {
+    database_type: String,
+    description: String,
+}
+
+impl Default for MmdbWriter {
+    fn default() -> Self {
+        MmdbWriter {
+            database_type: "GeoLite2-ASN".to_string(),
+            description: "ASN data exported by asn-fetcher".to_string(),
+        }
+    }
+}
+
+impl MmdbWriter {
+    /// Creates a writer with the GeoLite2-ASN database type
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Overrides the `database_type` metadata field
+    pub fn database_type(mut self, database_type: impl Into<String>) -> Self {
+        self.database_type = database_type.into();
+        self
+    }
+
+    /// Overrides the English `description` metadata field
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    /// Writes a dataset to an MMDB file
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written or the tree is too large
+    pub fn write_file<P: AsRef<Path>>(
+        &self,
+        dataset: &dyn Dataset,
+        path: P,
+    ) -> Result<usize, Error> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let count = self.write(dataset, &mut writer)?;
+        writer.flush()?;
+        Ok(count)
+    }
+
+    /// Serializes a dataset in MMDB format, returning the number of prefixes written
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing fails or the tree is too large
+    pub fn write<W: Write>(&self, dataset: &dyn Dataset, writer: &mut W) -> Result<usize, Error> {
+        let mut root = BuildNode::default();
+        let mut data = Vec::new();
+        let mut offsets: HashMap<(u32, String), u32> = HashMap::new();
+        let mut count = 0;
+
+        for (prefix, results) in dataset.entries() {
+            let Some((asn, info)) = results
+                .iter()
+                .find_map(|info| info.asn_number().map(|asn| (asn, info)))
+            else {
+                continue;
+            };
+            let key = (asn, info.holder.clone());
+            let offset = match offsets.get(&key) {
+                Some(&offset) => offset,
+                None => {
+                    let offset = data.len() as u32;
+                    Value::Map(vec![
+                        ("autonomous_system_number", Value::U32(asn)),
+                        (
+                            "autonomous_system_organization",
+                            Value::String(&info.holder),
+                        ),
+                    ])
+                    .encode(&mut data);
+                    offsets.insert(key, offset);
+                    offset
+                }
+            };
+            Self::insert(&mut root, prefix, offset);
+            count += 1;
+        }
+
+        // Assign node numbers in pre-order so the root is node 0
+        let mut nodes: Vec<[Record; 2]> = Vec::new();
+        Self::flatten(&root, None, &mut nodes);
+        let node_count = nodes.len() as u64;
+        let max_value = node_count + DATA_SEPARATOR_LEN as u64 + data.len() as u64;
+        let record_size: u16 = match max_value {
+            v if v < 1 << 24 => 24,
+            v if v < 1 << 28 => 28,
+            v if v < 1 << 32 => 32,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "MMDB search tree exceeds 32-bit records",
+                ))
+            }
+        };
+
+        let resolve = |record: Record| -> u32 {
+            match record {
+                Record::Node(index) => index,
+                Record::Empty => node_count as u32,
+                Record::Data(offset) => (node_count + DATA_SEPARATOR_LEN as u64) as u32 + offset,
+            }
+        };
+        let mut tree = Vec::with_capacity(nodes.len() * record_size as usize / 4);
+        for [left, right] in nodes {
+            let (left, right) = (resolve(left), resolve(right));
+            match record_size {
+                24 => {
+                    tree.extend_from_slice(&left.to_be_bytes()[1..]);
+                    tree.extend_from_slice(&right.to_be_bytes()[1..]);
+                }
+                28 => {
+                    tree.extend_from_slice(&left.to_be_bytes()[1..]);
+                    tree.push((((left >> 24) & 0x0f) << 4) as u8 | ((right >> 24) & 0x0f) as u8);
+                    tree.extend_from_slice(&right.to_be_bytes()[1..]);
+                }
+                _ => {
+                    tree.extend_from_slice(&left.to_be_bytes());
+                    tree.extend_from_slice(&right.to_be_bytes());
+                }
+            }
+        }
+
+        let build_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let mut metadata = Vec::new();
+        Value::Map(vec![
+            ("binary_format_major_version", Value::U16(2)),
+            ("binary_format_minor_version", Value::U16(0)),
+            ("build_epoch", Value::U64(build_epoch)),
+            ("database_type", Value::String(&self.database_type)),
+            (
+                "description",
+                Value::Map(vec![("en", Value::String(&self.description))]),
+            ),
+            ("ip_version", Value::U16(6)),
+            ("languages", Value::Array(vec![Value::String("en")])),
+            ("node_count", Value::U32(node_count as u32)),
+            ("record_size", Value::U16(record_size)),
+        ])
+        .encode(&mut metadata);
+
+        writer.write_all(&tree)?;
+        writer.write_all(&[0; DATA_SEPARATOR_LEN])?;
+        writer.write_all(&data)?;
+        writer.write_all(METADATA_MARKER)?;
+        writer.write_all(&metadata)?;
+        Ok(count)
+    }
+
+    /// Inserts a prefix into the build trie, mapping IPv4 into `::/96`
+    fn insert(root: &mut BuildNode, prefix: Prefix, offset: u32) {
+        let (key, len) = if prefix.is_ipv4() {
+            (prefix.first(), prefix.prefix_len() + IPV4_OFFSET)
+        } else {
+            (prefix.first(), prefix.prefix_len())
+        };
+        let mut node = root;
+        for depth in 0..len {
+            let bit = ((key >> (127 - depth)) & 1) as usize;
+            node = node.children[bit].get_or_insert_with(Box::default);
+        }
+        node.data = Some(offset);
+    }
+
+    /// Emits search tree nodes, pushing covering prefixes' data down to uncovered branches
+    fn flatten(node: &BuildNode, inherited: Option<u32>, nodes: &mut Vec<[Record; 2]>) -> Record {
+        let data = node.data.or(inherited);
+        if node.children.iter().all(Option::is_none) && !nodes.is_empty() {
+            return data.map_or(Record::Empty, Record::Data);
+        }
+        let index = nodes.len();
+        nodes.push([Record::Empty, Record::Empty]);
+        for bit in 0..2 {
+            nodes[index][bit] = match &node.children[bit] {
+                Some(child) => Self::flatten(child, data, nodes),
+                None => data.map_or(Record::Empty, Record::Data),
+            };
+        }
+        Record::Node(index as u32)
+    }
+}
+
+/// A search tree record before node numbers are final
+#[derive(Debug, Clone, Copy)]
+enum Record {
+    Node(u32),
+    Empty,
+    Data(u32),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asn::LocalDbBuilder;
+
+    fn dataset() -> LocalDbBuilder {
+        let mut builder = LocalDbBuilder::new();
+        builder.add(
+            "8.0.0.0/9".parse().unwrap(),
+            vec![AsnInfo::new("3356", "LEVEL3")],
+        );
+        builder.add(
+            "8.8.8.0/24".parse().unwrap(),
+            vec![AsnInfo::new("15169", "Google LLC")],
+        );
+        builder.add(
+            "2001:4860::/32".parse().unwrap(),
+            vec![AsnInfo::new("AS15169", "Google LLC")],
+        );
+        builder.add(
+            "192.0.2.0/24".parse().unwrap(),
+            vec![AsnInfo::new("N/A", "Unknown")],
+        );
+        builder
+    }
+
+    fn export(builder: &LocalDbBuilder) -> Reader<Vec<u8>> {
+        let mut bytes = Vec::new();
+        let count = MmdbWriter::new().write(builder, &mut bytes).unwrap();
+        assert_eq!(count, 3);
+        Reader::from_source(bytes).unwrap()
+    }
+
+    #[test]
+    fn test_export_readable_by_maxminddb() {
+        let reader = export(&dataset());
+        assert_eq!(reader.metadata.database_type, "GeoLite2-ASN");
+        assert_eq!(reader.metadata.ip_version, 6);
+
+        let (asn, len) = reader
+            .lookup_prefix::<geoip2::Asn>("8.8.8.8".parse().unwrap())
+            .unwrap();
+        assert_eq!(asn.autonomous_system_number, Some(15169));
+        assert_eq!(asn.autonomous_system_organization, Some("Google LLC"));
+        assert_eq!(len, 24);
+
+        let (asn, len) = reader
+            .lookup_prefix::<geoip2::Asn>("8.8.4.4".parse().unwrap())
+            .unwrap();
+        assert_eq!(asn.autonomous_system_number, Some(3356));
+        // The /9 is split around the /24, so the match is the sibling subtree
+        assert_eq!(len, 21);
+
+        let asn: geoip2::Asn = reader.lookup("2001:4860::8888".parse().unwrap()).unwrap();
+        assert_eq!(asn.autonomous_system_number, Some(15169));
+    }
+
+    #[test]
+    fn test_export_skips_unknown_asn_and_missing_prefixes() {
+        let reader = export(&dataset());
+        for ip in ["192.0.2.1", "203.0.113.1", "2001:db8::1"] {
+            let result = reader.lookup::<geoip2::Asn>(ip.parse().unwrap());
+            assert!(matches!(
+                result,
+                Err(MaxMindDBError::AddressNotFoundError(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_long_strings_and_large_trees() {
+        let mut builder = LocalDbBuilder::new();
+        let holder = "X".repeat(300);
+        for i in 0..2000u32 {
+            let prefix =
+                Prefix::new(IpAddr::from((0x0a00_0000 + (i << 8)).to_be_bytes()), 24).unwrap();
+            builder.add(
+                prefix,
+                vec![AsnInfo::new((64512 + i).to_string(), holder.clone())],
+            );
+        }
+        let mut bytes = Vec::new();
+        MmdbWriter::new().write(&builder, &mut bytes).unwrap();
+        let reader = Reader::from_source(bytes).unwrap();
+        let asn: geoip2::Asn = reader.lookup("10.7.207.1".parse().unwrap()).unwrap();
+        assert_eq!(asn.autonomous_system_number, Some(64512 + 0x7cf));
+        assert_eq!(asn.autonomous_system_organization, Some(holder.as_str()));
+    }
+
+    #[test]
+    fn test_mmdb_provider_round_trip() {
+        let path = std::env::temp_dir().join(format!("asn-fetcher-{}.mmdb", std::process::id()));
+        MmdbWriter::new().write_file(&dataset(), &path).unwrap();
+        let mmdb = Mmdb::open(&path).unwrap();
+        let result = mmdb.lookup_asn("8.8.8.8".parse().unwrap()).unwrap();
+        let missing = mmdb.lookup_asn("203.0.113.1".parse().unwrap()).unwrap();
+        drop(mmdb);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(result[0].asn, "15169");
+        assert_eq!(result[0].holder, "Google LLC");
+        assert_eq!(result[0].prefix.as_deref(), Some("8.8.8.0/24"));
+        assert!(missing.is_empty());
+    }
+
+    #[test]
+    fn test_empty_export() {
+        let mut bytes = Vec::new();
+        let count = MmdbWriter::new()
+            .write(&LocalDbBuilder::new(), &mut bytes)
+            .unwrap();
+        assert_eq!(count, 0);
+        let reader = Reader::from_source(bytes).unwrap();
+        assert!(reader
+            .lookup::<geoip2::Asn>("8.8.8.8".parse().unwrap())
+            .is_err());
+    }
+}
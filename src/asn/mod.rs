@@ -1,14 +1,34 @@
 // ASN lookup logic module
 
+pub mod backoff;
+pub mod cache;
+pub mod chain;
 pub mod client;
+pub mod dataset;
+pub mod delegated;
 pub mod ipapi;
+pub mod iptoasn;
+pub mod localdb;
+pub mod mmdb;
+pub mod mrt;
+pub mod pfx2as;
 pub mod ripe;
 pub mod teamcymru;
 pub mod types;
 
+pub use backoff::Backoff;
+pub use cache::LookupCache;
+pub use chain::{Enricher, ProviderChain};
 pub use client::Asn;
+pub use dataset::Dataset;
+pub use delegated::DelegatedStats;
 pub use ipapi::IPApi;
+pub use iptoasn::IpToAsn;
+pub use localdb::{LocalDb, LocalDbBuilder};
+pub use mmdb::{Mmdb, MmdbWriter};
+pub use mrt::MrtRib;
+pub use pfx2as::Pfx2as;
 pub use ripe::Ripe;
 pub use teamcymru::TeamCymruWhois;
 
-pub use types::AsnInfo;
+pub use types::{parse_asn, AsnInfo, Validity};
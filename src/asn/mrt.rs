@@ -0,0 +1,587 @@
+// MRT RIB dump provider (RFC 6396 TABLE_DUMP_V2)
+
+use super::client::Asn;
+use super::dataset::{open_dataset, Dataset};
+use super::types::AsnInfo;
+use crate::network::{bytes::ByteReader, trie::PrefixTrie, Prefix};
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{Error, ErrorKind, Read},
+    net::IpAddr,
+    path::Path,
+};
+
+/// MRT record type for TABLE_DUMP_V2
+const TABLE_DUMP_V2: u16 = 13;
+/// TABLE_DUMP_V2 subtypes
+const PEER_INDEX_TABLE: u16 = 1;
+const RIB_IPV4_UNICAST: u16 = 2;
+const RIB_IPV6_UNICAST: u16 = 4;
+/// BGP path attribute type codes
+const ATTR_AS_PATH: u8 = 2;
+const ATTR_AS4_PATH: u8 = 17;
+/// Attribute flag indicating a two-byte length field
+const FLAG_EXTENDED_LENGTH: u8 = 0x10;
+/// AS_PATH segment types
+const SEGMENT_AS_SET: u8 = 1;
+const SEGMENT_AS_SEQUENCE: u8 = 2;
+
+/// A segment of a BGP AS path
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PathSegment {
+    /// Ordered list of ASes the route traversed
+    Sequence(Vec<u32>),
+    /// Unordered set of ASes produced by aggregation
+    Set(Vec<u32>),
+}
+
+/// A BGP AS path as seen by one RIB peer
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct AsPath {
+    pub segments: Vec<PathSegment>,
+}
+
+impl AsPath {
+    /// The origin AS(es): the last AS of a trailing sequence, or every member of a trailing set
+    pub fn origins(&self) -> Vec<u32> {
+        match self.segments.last() {
+            Some(PathSegment::Sequence(asns)) => asns.last().copied().into_iter().collect(),
+            Some(PathSegment::Set(asns)) => asns.clone(),
+            None => vec![],
+        }
+    }
+
+    /// Number of AS hops, counting a set as a single hop as BGP path selection does
+    pub fn hop_count(&self) -> usize {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                PathSegment::Sequence(asns) => asns.len(),
+                PathSegment::Set(_) => 1,
+            })
+            .sum()
+    }
+
+    /// Flattens the path into a single list of ASNs, expanding sets in place
+    fn flatten(&self) -> Vec<u32> {
+        self.segments
+            .iter()
+            .flat_map(|segment| match segment {
+                PathSegment::Sequence(asns) | PathSegment::Set(asns) => asns.iter().copied(),
+            })
+            .collect()
+    }
+
+    /// Reconstructs the 4-byte path from AS_PATH and AS4_PATH as described in RFC 6793
+    fn merge_as4(self, as4_path: AsPath) -> AsPath {
+        let (count, count4) = (self.hop_count(), as4_path.hop_count());
+        if count4 == 0 || count < count4 {
+            return self;
+        }
+        // Keep the leading hops only present in AS_PATH, then append AS4_PATH
+        let mut keep = count - count4;
+        let mut segments = Vec::new();
+        for segment in self.segments {
+            if keep == 0 {
+                break;
+            }
+            match segment {
+                PathSegment::Sequence(asns) => {
+                    let take = keep.min(asns.len());
+                    keep -= take;
+                    segments.push(PathSegment::Sequence(asns[..take].to_vec()));
+                }
+                set => {
+                    keep -= 1;
+                    segments.push(set);
+                }
+            }
+        }
+        segments.extend(as4_path.segments);
+        AsPath { segments }
+    }
+}
+
+impl fmt::Display for AsPath {
+    /// Formats the path the way routers print it, e.g. "3356 15169 {64496,64497}"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = self
+            .segments
+            .iter()
+            .map(|segment| match segment {
+                PathSegment::Sequence(asns) => asns
+                    .iter()
+                    .map(u32::to_string)
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                PathSegment::Set(asns) => format!(
+                    "{{{}}}",
+                    asns.iter()
+                        .map(u32::to_string)
+                        .collect::<Vec<_>>()
+                        .join(",")
+                ),
+            })
+            .collect();
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+/// A collector peer listed in the PEER_INDEX_TABLE
+#[derive(Debug, Clone, PartialEq)]
+pub struct Peer {
+    pub address: IpAddr,
+    pub asn: u32,
+}
+
+/// Local IP-to-origin-AS table built from a RouteViews/RIS MRT RIB dump
+///
+/// Parses TABLE_DUMP_V2 `bview`/`rib` files (optionally gzip-compressed),
+/// keeping IPv4 and IPv6 unicast routes with the AS path each peer reported.
+/// Lookups return one result per origin AS of the longest matching prefix,
+/// carrying the shortest AS path observed for that origin.
+#[derive(Debug, Default)]
+pub struct MrtRib {
+    table: PrefixTrie<Vec<u32>>,
+    paths: Vec<AsPath>,
+    peers: Vec<Peer>,
+}
+
+impl MrtRib {
+    /// Loads an MRT RIB dump (optionally gzip-compressed) from disk
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or contains a malformed record
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::from_reader(open_dataset(path)?)
+    }
+
+    /// Parses MRT records from any reader until end of input
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading fails or a TABLE_DUMP_V2 record is malformed
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Self, Error> {
+        let mut rib = MrtRib::default();
+        let mut interned: HashMap<AsPath, u32> = HashMap::new();
+        let mut header = [0u8; 12];
+        let mut body = Vec::new();
+
+        while Self::read_header(&mut reader, &mut header)? {
+            let mut fields = ByteReader::new(&header);
+            let _timestamp = fields.u32()?;
+            let (kind, subtype, length) = (fields.u16()?, fields.u16()?, fields.u32()?);
+
+            // Read through take() so a corrupt length cannot force a huge allocation
+            body.clear();
+            (&mut reader).take(length as u64).read_to_end(&mut body)?;
+            if body.len() < length as usize {
+                return Err(Error::new(ErrorKind::UnexpectedEof, "truncated MRT record"));
+            }
+            if kind != TABLE_DUMP_V2 {
+                continue;
+            }
+            let mut record = ByteReader::new(&body);
+            match subtype {
+                PEER_INDEX_TABLE => rib.peers = Self::parse_peer_index(&mut record)?,
+                RIB_IPV4_UNICAST | RIB_IPV6_UNICAST => {
+                    let ipv6 = subtype == RIB_IPV6_UNICAST;
+                    let (prefix, paths) = Self::parse_rib_entry(&mut record, ipv6)?;
+                    let mut ids = Vec::new();
+                    for path in paths {
+                        let next = rib.paths.len() as u32;
+                        let id = *interned.entry(path.clone()).or_insert(next);
+                        if id == next {
+                            rib.paths.push(path);
+                        }
+                        if !ids.contains(&id) {
+                            ids.push(id);
+                        }
+                    }
+                    rib.table.insert(prefix, ids);
+                }
+                _ => {}
+            }
+        }
+        Ok(rib)
+    }
+
+    /// Reads the fixed MRT header, returning false on a clean end of input
+    fn read_header<R: Read>(reader: &mut R, header: &mut [u8; 12]) -> Result<bool, Error> {
+        let mut filled = 0;
+        while filled < header.len() {
+            match reader.read(&mut header[filled..]) {
+                Ok(0) if filled == 0 => return Ok(false),
+                Ok(0) => {
+                    return Err(Error::new(
+                        ErrorKind::UnexpectedEof,
+                        "truncated MRT record header",
+                    ))
+                }
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(true)
+    }
+
+    fn parse_peer_index(record: &mut ByteReader) -> Result<Vec<Peer>, Error> {
+        let _collector_id = record.u32()?;
+        let view_name_len = record.u16()? as usize;
+        record.bytes(view_name_len)?;
+        let count = record.u16()?;
+        (0..count)
+            .map(|_| {
+                let peer_type = record.u8()?;
+                let _bgp_id = record.u32()?;
+                let address = record.ip(peer_type & 0x01 != 0)?;
+                let asn = if peer_type & 0x02 != 0 {
+                    record.u32()?
+                } else {
+                    record.u16()? as u32
+                };
+                Ok(Peer { address, asn })
+            })
+            .collect()
+    }
+
+    fn parse_rib_entry(
+        record: &mut ByteReader,
+        ipv6: bool,
+    ) -> Result<(Prefix, Vec<AsPath>), Error> {
+        let _sequence = record.u32()?;
+        let len = record.u8()?;
+        let mut octets = [0u8; 16];
+        let width = if ipv6 { 16 } else { 4 };
+        let count = (len as usize + 7) / 8;
+        if count > width {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("invalid prefix length /{}", len),
+            ));
+        }
+        octets[..count].copy_from_slice(record.bytes(count)?);
+        let addr = if ipv6 {
+            IpAddr::from(octets)
+        } else {
+            IpAddr::from([octets[0], octets[1], octets[2], octets[3]])
+        };
+        let prefix = Prefix::new(addr, len)?;
+
+        let entries = record.u16()?;
+        let mut paths = Vec::with_capacity(entries as usize);
+        for _ in 0..entries {
+            let _peer_index = record.u16()?;
+            let _originated = record.u32()?;
+            let attr_len = record.u16()? as usize;
+            let mut attrs = record.sub(attr_len)?;
+            paths.push(Self::parse_attributes(&mut attrs)?);
+        }
+        Ok((prefix, paths))
+    }
+
+    /// Extracts the AS path from a block of BGP path attributes
+    fn parse_attributes(attrs: &mut ByteReader) -> Result<AsPath, Error> {
+        let mut as_path = AsPath::default();
+        let mut as4_path = None;
+        while !attrs.is_empty() {
+            let flags = attrs.u8()?;
+            let code = attrs.u8()?;
+            let len = if flags & FLAG_EXTENDED_LENGTH != 0 {
+                attrs.u16()? as usize
+            } else {
+                attrs.u8()? as usize
+            };
+            let mut value = attrs.sub(len)?;
+            match code {
+                ATTR_AS_PATH => as_path = Self::parse_as_path(&mut value)?,
+                ATTR_AS4_PATH => as4_path = Some(Self::parse_as_path(&mut value)?),
+                _ => {}
+            }
+        }
+        Ok(match as4_path {
+            Some(as4_path) => as_path.merge_as4(as4_path),
+            None => as_path,
+        })
+    }
+
+    /// Parses AS_PATH segments; TABLE_DUMP_V2 always encodes 4-byte ASNs (RFC 6396 4.3.4)
+    fn parse_as_path(value: &mut ByteReader) -> Result<AsPath, Error> {
+        let mut segments = Vec::new();
+        while !value.is_empty() {
+            let kind = value.u8()?;
+            let count = value.u8()?;
+            let asns = (0..count)
+                .map(|_| value.u32())
+                .collect::<Result<Vec<u32>, Error>>()?;
+            match kind {
+                SEGMENT_AS_SET => segments.push(PathSegment::Set(asns)),
+                SEGMENT_AS_SEQUENCE => segments.push(PathSegment::Sequence(asns)),
+                // Confederation segments are local to the collector's peer and not part of the path
+                _ => {}
+            }
+        }
+        Ok(AsPath { segments })
+    }
+
+    /// Peers listed in the dump's PEER_INDEX_TABLE
+    pub fn peers(&self) -> &[Peer] {
+        &self.peers
+    }
+
+    /// Number of prefixes loaded
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    /// Returns true if no prefixes were loaded
+    pub fn is_empty(&self) -> bool {
+        self.table.len() == 0
+    }
+
+    /// Builds one result per origin AS, carrying the shortest path seen for that origin
+    fn results(&self, prefix: Prefix, ids: &[u32]) -> Vec<AsnInfo> {
+        // Origins are kept in first-seen order
+        let mut best: Vec<(u32, &AsPath)> = Vec::new();
+        for path in ids.iter().map(|&id| &self.paths[id as usize]) {
+            for origin in path.origins() {
+                match best.iter_mut().find(|(asn, _)| *asn == origin) {
+                    Some(entry) if path.hop_count() < entry.1.hop_count() => entry.1 = path,
+                    Some(_) => {}
+                    None => best.push((origin, path)),
+                }
+            }
+        }
+
+        best.into_iter()
+            .map(|(origin, path)| AsnInfo {
+                prefix: Some(prefix.to_string()),
+                as_path: path.flatten(),
+                ..AsnInfo::new(origin.to_string(), "Unknown")
+            })
+            .collect()
+    }
+
+    /// Returns the longest matching prefix and every distinct AS path seen for it
+    pub fn routes(&self, ip: IpAddr) -> Option<(Prefix, Vec<&AsPath>)> {
+        let (prefix, ids) = self.table.longest_match(ip)?;
+        Some((
+            prefix,
+            ids.iter().map(|&id| &self.paths[id as usize]).collect(),
+        ))
+    }
+}
+
+impl Asn for MrtRib {
+    fn lookup_asn(&self, ip: IpAddr) -> Result<Vec<AsnInfo>, Error> {
+        Ok(self
+            .table
+            .longest_match(ip)
+            .map(|(prefix, ids)| self.results(prefix, ids))
+            .unwrap_or_default())
+    }
+}
+
+impl Dataset for MrtRib {
+    fn entries(&self) -> Box<dyn Iterator<Item = (Prefix, Vec<AsnInfo>)> + '_> {
+        Box::new(
+            self.table
+                .iter()
+                .map(|(prefix, ids)| (prefix, self.results(prefix, ids))),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encodes an MRT record with the given type, subtype and body
+    fn record(kind: u16, subtype: u16, body: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&1_700_000_000u32.to_be_bytes());
+        out.extend_from_slice(&kind.to_be_bytes());
+        out.extend_from_slice(&subtype.to_be_bytes());
+        out.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        out.extend_from_slice(body);
+        out
+    }
+
+    fn peer_index() -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&[10, 0, 0, 1]); // collector BGP ID
+        body.extend_from_slice(&0u16.to_be_bytes()); // empty view name
+        body.extend_from_slice(&2u16.to_be_bytes());
+        // IPv4 peer with 4-byte AS
+        body.push(0x02);
+        body.extend_from_slice(&[192, 0, 2, 1, 192, 0, 2, 1]);
+        body.extend_from_slice(&3356u32.to_be_bytes());
+        // IPv6 peer with 2-byte AS
+        body.push(0x01);
+        body.extend_from_slice(&[192, 0, 2, 2]);
+        body.extend_from_slice(
+            &"2001:db8::2"
+                .parse::<std::net::Ipv6Addr>()
+                .unwrap()
+                .octets(),
+        );
+        body.extend_from_slice(&174u16.to_be_bytes());
+        record(TABLE_DUMP_V2, PEER_INDEX_TABLE, &body)
+    }
+
+    /// Encodes an AS_PATH attribute value from (segment type, ASNs) pairs
+    fn as_path(segments: &[(u8, &[u32])]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (kind, asns) in segments {
+            out.push(*kind);
+            out.push(asns.len() as u8);
+            for asn in *asns {
+                out.extend_from_slice(&asn.to_be_bytes());
+            }
+        }
+        out
+    }
+
+    fn attribute(code: u8, value: &[u8]) -> Vec<u8> {
+        let mut out = vec![0x50, code];
+        out.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        out.extend_from_slice(value);
+        out
+    }
+
+    fn rib_entry(subtype: u16, prefix: &[u8], len: u8, attrs: &[Vec<u8>]) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&0u32.to_be_bytes());
+        body.push(len);
+        body.extend_from_slice(prefix);
+        body.extend_from_slice(&(attrs.len() as u16).to_be_bytes());
+        for (peer, attr) in attrs.iter().enumerate() {
+            body.extend_from_slice(&(peer as u16).to_be_bytes());
+            body.extend_from_slice(&0u32.to_be_bytes());
+            body.extend_from_slice(&(attr.len() as u16).to_be_bytes());
+            body.extend_from_slice(attr);
+        }
+        record(TABLE_DUMP_V2, subtype, &body)
+    }
+
+    fn sample() -> Vec<u8> {
+        let origin_attr = vec![0x40, 1, 1, 0]; // ORIGIN IGP, ignored
+        let mut dump = peer_index();
+        // 8.8.8.0/24 seen via two paths to the same origin
+        let mut long = origin_attr.clone();
+        long.extend(attribute(
+            ATTR_AS_PATH,
+            &as_path(&[(2, &[3356, 174, 15169])]),
+        ));
+        let short = attribute(ATTR_AS_PATH, &as_path(&[(2, &[3356, 15169])]));
+        dump.extend(rib_entry(RIB_IPV4_UNICAST, &[8, 8, 8], 24, &[long, short]));
+        // 192.0.2.0/24 aggregated with an AS_SET origin
+        let set = attribute(
+            ATTR_AS_PATH,
+            &as_path(&[(2, &[3356]), (1, &[64496, 64497])]),
+        );
+        dump.extend(rib_entry(RIB_IPV4_UNICAST, &[192, 0, 2], 24, &[set]));
+        // 2001:4860::/32
+        let v6 = attribute(ATTR_AS_PATH, &as_path(&[(2, &[174, 15169])]));
+        dump.extend(rib_entry(
+            RIB_IPV6_UNICAST,
+            &[0x20, 0x01, 0x48, 0x60],
+            32,
+            &[v6],
+        ));
+        // Unrelated MRT record types are skipped
+        dump.extend(record(16, 4, &[0; 8]));
+        dump
+    }
+
+    #[test]
+    fn test_parse_peer_index() {
+        let rib = MrtRib::from_reader(&sample()[..]).unwrap();
+        assert_eq!(rib.peers().len(), 2);
+        assert_eq!(rib.peers()[0].asn, 3356);
+        assert_eq!(rib.peers()[1].address.to_string(), "2001:db8::2");
+        assert_eq!(rib.peers()[1].asn, 174);
+        assert_eq!(rib.len(), 3);
+    }
+
+    #[test]
+    fn test_lookup_picks_shortest_path() {
+        let rib = MrtRib::from_reader(&sample()[..]).unwrap();
+        let result = rib.lookup_asn("8.8.8.8".parse().unwrap()).unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].asn, "15169");
+        assert_eq!(result[0].prefix.as_deref(), Some("8.8.8.0/24"));
+        assert_eq!(result[0].as_path, vec![3356, 15169]);
+
+        let (_, paths) = rib.routes("8.8.8.8".parse().unwrap()).unwrap();
+        assert_eq!(paths.len(), 2);
+        assert_eq!(paths[0].to_string(), "3356 174 15169");
+    }
+
+    #[test]
+    fn test_as_set_origins() {
+        let rib = MrtRib::from_reader(&sample()[..]).unwrap();
+        let result = rib.lookup_asn("192.0.2.1".parse().unwrap()).unwrap();
+        let asns: Vec<&str> = result.iter().map(|a| a.asn.as_str()).collect();
+        assert_eq!(asns, vec!["64496", "64497"]);
+        let (_, paths) = rib.routes("192.0.2.1".parse().unwrap()).unwrap();
+        assert_eq!(paths[0].to_string(), "3356 {64496,64497}");
+    }
+
+    #[test]
+    fn test_lookup_ipv6() {
+        let rib = MrtRib::from_reader(&sample()[..]).unwrap();
+        let result = rib.lookup_asn("2001:4860::8888".parse().unwrap()).unwrap();
+        assert_eq!(result[0].asn, "15169");
+        assert_eq!(result[0].prefix.as_deref(), Some("2001:4860::/32"));
+        assert!(rib
+            .lookup_asn("203.0.113.1".parse().unwrap())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_as4_path_merge() {
+        let as_path = AsPath {
+            segments: vec![PathSegment::Sequence(vec![3356, 23456, 23456])],
+        };
+        let as4_path = AsPath {
+            segments: vec![PathSegment::Sequence(vec![196608, 4200000000])],
+        };
+        assert_eq!(
+            as_path.merge_as4(as4_path).to_string(),
+            "3356 196608 4200000000"
+        );
+    }
+
+    #[test]
+    fn test_as4_path_attribute() {
+        let mut attrs = attribute(ATTR_AS_PATH, &as_path(&[(2, &[3356, 23456])]));
+        attrs.extend(attribute(ATTR_AS4_PATH, &as_path(&[(2, &[4200000000])])));
+        let path = MrtRib::parse_attributes(&mut ByteReader::new(&attrs)).unwrap();
+        assert_eq!(path.origins(), vec![4200000000]);
+    }
+
+    #[test]
+    fn test_truncated_record() {
+        let mut dump = sample();
+        dump.truncate(dump.len() - 3);
+        assert!(MrtRib::from_reader(&dump[..]).is_err());
+
+        // A corrupt length is reported without allocating for it
+        let mut dump = record(TABLE_DUMP_V2, RIB_IPV4_UNICAST, &[0; 4]);
+        dump[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+        let err = MrtRib::from_reader(&dump[..]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_invalid_prefix_length() {
+        let dump = rib_entry(RIB_IPV4_UNICAST, &[0; 5], 40, &[]);
+        let err = MrtRib::from_reader(&dump[..]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,198 @@
+// CAIDA Routeviews prefix-to-AS dataset provider
+
+use super::client::Asn;
+use super::dataset::{line_error, open_dataset, Dataset};
+use super::types::AsnInfo;
+use crate::network::{trie::PrefixTrie, Prefix};
+use std::{
+    io::{BufRead, Error},
+    net::IpAddr,
+    path::Path,
+};
+
+/// Offline lookups against a CAIDA `routeviews-rv2-*.pfx2as` file
+///
+/// Each line holds a prefix, its length and the origin AS as seen in BGP:
+/// `8.8.8.0\t24\t15169`. Multi-origin prefixes list their origins separated by
+/// `_` and AS sets use `,`; every distinct origin is returned as its own result.
+pub struct Pfx2as {
+    table: PrefixTrie<Vec<u32>>,
+}
+
+impl Pfx2as {
+    /// Loads a pfx2as file (optionally gzip-compressed) from disk
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or contains a malformed line
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::from_reader(open_dataset(path)?)
+    }
+
+    /// Loads pfx2as records from any buffered reader
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading fails or a line is malformed
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Self, Error> {
+        let mut table = PrefixTrie::new();
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (prefix, origins) =
+                Self::parse_line(&line).map_err(|msg| line_error(number, msg))?;
+            table.insert(prefix, origins);
+        }
+        Ok(Pfx2as { table })
+    }
+
+    fn parse_line(line: &str) -> Result<(Prefix, Vec<u32>), String> {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() != 3 {
+            return Err(format!("expected 3 fields, found {}", parts.len()));
+        }
+        let addr: IpAddr = parts[0]
+            .parse()
+            .map_err(|_| format!("invalid address '{}'", parts[0]))?;
+        let len: u8 = parts[1]
+            .parse()
+            .map_err(|_| format!("invalid prefix length '{}'", parts[1]))?;
+        let prefix = Prefix::new(addr, len).map_err(|e| e.to_string())?;
+
+        let mut origins = Vec::new();
+        for origin in parts[2].split(['_', ',']) {
+            let asn: u32 = origin
+                .parse()
+                .map_err(|_| format!("invalid origin AS '{}'", origin))?;
+            if !origins.contains(&asn) {
+                origins.push(asn);
+            }
+        }
+        Ok((prefix, origins))
+    }
+
+    /// Number of prefixes loaded
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    /// Returns true if no prefixes were loaded
+    pub fn is_empty(&self) -> bool {
+        self.table.len() == 0
+    }
+
+    fn results(prefix: Prefix, origins: &[u32]) -> Vec<AsnInfo> {
+        origins
+            .iter()
+            .map(|asn| AsnInfo {
+                prefix: Some(prefix.to_string()),
+                ..AsnInfo::new(asn.to_string(), "Unknown")
+            })
+            .collect()
+    }
+}
+
+impl Asn for Pfx2as {
+    fn lookup_asn(&self, ip: IpAddr) -> Result<Vec<AsnInfo>, Error> {
+        Ok(self
+            .table
+            .longest_match(ip)
+            .map(|(prefix, origins)| Self::results(prefix, origins))
+            .unwrap_or_default())
+    }
+}
+
+impl Dataset for Pfx2as {
+    fn entries(&self) -> Box<dyn Iterator<Item = (Prefix, Vec<AsnInfo>)> + '_> {
+        Box::new(
+            self.table
+                .iter()
+                .map(|(prefix, origins)| (prefix, Self::results(prefix, origins))),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::ErrorKind;
+
+    const SAMPLE: &str = "\
+1.0.0.0\t24\t13335
+8.8.8.0\t24\t15169
+8.0.0.0\t9\t3356
+192.0.2.0\t24\t64496_64497
+198.51.100.0\t24\t64500,64501
+2001:4860::\t32\t15169
+";
+
+    fn sample() -> Pfx2as {
+        Pfx2as::from_reader(SAMPLE.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_load() {
+        assert_eq!(sample().len(), 6);
+    }
+
+    #[test]
+    fn test_lookup_longest_match() {
+        let pfx2as = sample();
+        let result = pfx2as.lookup_asn("8.8.8.8".parse().unwrap()).unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].asn, "15169");
+        assert_eq!(result[0].prefix.as_deref(), Some("8.8.8.0/24"));
+
+        let result = pfx2as.lookup_asn("8.8.4.4".parse().unwrap()).unwrap();
+        assert_eq!(result[0].asn, "3356");
+        assert_eq!(result[0].prefix.as_deref(), Some("8.0.0.0/9"));
+    }
+
+    #[test]
+    fn test_lookup_ipv6() {
+        let result = sample()
+            .lookup_asn("2001:4860:4860::8888".parse().unwrap())
+            .unwrap();
+        assert_eq!(result[0].asn, "15169");
+    }
+
+    #[test]
+    fn test_multi_origin_and_as_set() {
+        let pfx2as = sample();
+        let moas = pfx2as.lookup_asn("192.0.2.1".parse().unwrap()).unwrap();
+        let asns: Vec<&str> = moas.iter().map(|a| a.asn.as_str()).collect();
+        assert_eq!(asns, vec!["64496", "64497"]);
+
+        let as_set = pfx2as.lookup_asn("198.51.100.1".parse().unwrap()).unwrap();
+        assert_eq!(as_set.len(), 2);
+    }
+
+    #[test]
+    fn test_lookup_unrouted() {
+        let result = sample().lookup_asn("203.0.113.1".parse().unwrap()).unwrap();
+        assert!(result.is_empty());
+    }
+
+    #[test]
+    fn test_entries() {
+        let pfx2as = sample();
+        let entries: Vec<(Prefix, Vec<AsnInfo>)> = pfx2as.entries().collect();
+        assert_eq!(entries.len(), 6);
+        assert_eq!(entries[0].0.to_string(), "1.0.0.0/24");
+        assert_eq!(entries[0].1[0].asn, "13335");
+    }
+
+    #[test]
+    fn test_malformed_line() {
+        let err = Pfx2as::from_reader("1.0.0.0\t24\n".as_bytes())
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        let err = Pfx2as::from_reader("1.0.0.0\t33\t13335\n".as_bytes())
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("line 1"));
+    }
+}
@@ -14,10 +14,12 @@ use std::{io::Error, net::IpAddr, time::Duration};
 pub struct Ripe {
     client: reqwest::blocking::Client,
     server_url: String,
+    as_overview_url: String,
 }
 
 impl Ripe {
     const DEFAULT_SERVER_URL: &'static str = "https://stat.ripe.net/data/prefix-overview/data.json";
+    const AS_OVERVIEW_URL: &'static str = "https://stat.ripe.net/data/as-overview/data.json";
     const TIMEOUT_SECS: u64 = 10;
 
     /// Creates a new RIPE client with default configuration
@@ -32,6 +34,7 @@ impl Ripe {
         Ok(Ripe {
             client,
             server_url: Self::DEFAULT_SERVER_URL.to_string(),
+            as_overview_url: Self::AS_OVERVIEW_URL.to_string(),
         })
     }
 }
@@ -40,6 +43,7 @@ impl Asn for Ripe {
     fn lookup_asn(&self, ip: IpAddr) -> Result<Vec<AsnInfo>, Error> {
         let url = format!("{}?resource={}", self.server_url, ip);
         let response = self.client.get(&url).send().map_err(map_reqwest_error)?;
+        check_rate_limit("stat.ripe.net", &response)?;
 
         let json_data: serde_json::Value = response.json().map_err(map_reqwest_error)?;
 
@@ -59,6 +63,12 @@ impl Asn for Ripe {
             )
         })?;
 
+        // 'resource' is the matched routed prefix when the address is announced
+        let prefix = data
+            .get("resource")
+            .and_then(|v| v.as_str())
+            .filter(|resource| resource.contains('/'));
+
         let asns = asns_array
             .iter()
             .map(|asn_obj| {
@@ -66,22 +76,50 @@ impl Asn for Ripe {
                     .as_u64()
                     .map(|n| n.to_string())
                     .unwrap_or_else(|| {
-                        eprintln!("Warning: Missing or invalid 'asn' field in ASN object");
+                        tracing::warn!("missing or invalid 'asn' field in ASN object");
                         "N/A".to_string()
                     });
                 let holder = asn_obj["holder"]
                     .as_str()
                     .unwrap_or_else(|| {
-                        eprintln!("Warning: Missing or invalid 'holder' field in ASN object");
+                        tracing::warn!("missing or invalid 'holder' field in ASN object");
                         "Unknown"
                     })
                     .to_string();
-                AsnInfo { asn, holder }
+                AsnInfo {
+                    prefix: prefix.map(str::to_string),
+                    ..AsnInfo::new(asn, holder)
+                }
             })
             .collect();
 
         Ok(asns)
     }
+
+    fn lookup_as(&self, asn: u32) -> Result<Vec<AsnInfo>, Error> {
+        let url = format!("{}?resource=AS{}", self.as_overview_url, asn);
+        let response = self.client.get(&url).send().map_err(map_reqwest_error)?;
+        check_rate_limit("stat.ripe.net", &response)?;
+        let json_data: serde_json::Value = response.json().map_err(map_reqwest_error)?;
+        parse_as_overview(asn, &json_data)
+    }
+}
+
+/// Parses an as-overview response; unannounced and unregistered ASNs have no holder
+fn parse_as_overview(asn: u32, json_data: &serde_json::Value) -> Result<Vec<AsnInfo>, Error> {
+    let data = json_data.get("data").ok_or_else(|| {
+        Error::new(
+            std::io::ErrorKind::InvalidData,
+            "Missing 'data' field in response",
+        )
+    })?;
+    Ok(data
+        .get("holder")
+        .and_then(|v| v.as_str())
+        .filter(|holder| !holder.is_empty())
+        .map(|holder| AsnInfo::new(asn.to_string(), holder))
+        .into_iter()
+        .collect())
 }
 
 #[cfg(test)]
@@ -132,7 +170,7 @@ mod tests {
                     .map(|n| n.to_string())
                     .unwrap_or_else(|| "N/A".to_string());
                 let holder = asn_obj["holder"].as_str().unwrap_or("Unknown").to_string();
-                AsnInfo { asn, holder }
+                AsnInfo::new(asn, holder)
             })
             .collect();
 
@@ -164,7 +202,7 @@ mod tests {
                     .map(|n| n.to_string())
                     .unwrap_or_else(|| "N/A".to_string());
                 let holder = asn_obj["holder"].as_str().unwrap_or("Unknown").to_string();
-                AsnInfo { asn, holder }
+                AsnInfo::new(asn, holder)
             })
             .collect();
 
@@ -197,7 +235,7 @@ mod tests {
                     .map(|n| n.to_string())
                     .unwrap_or_else(|| "N/A".to_string());
                 let holder = asn_obj["holder"].as_str().unwrap_or("Unknown").to_string();
-                AsnInfo { asn, holder }
+                AsnInfo::new(asn, holder)
             })
             .collect();
 
@@ -230,7 +268,7 @@ mod tests {
                     .map(|n| n.to_string())
                     .unwrap_or_else(|| "N/A".to_string());
                 let holder = asn_obj["holder"].as_str().unwrap_or("Unknown").to_string();
-                AsnInfo { asn, holder }
+                AsnInfo::new(asn, holder)
             })
             .collect();
 
@@ -239,4 +277,21 @@ mod tests {
         assert_eq!(asns[0].asn, "N/A");
         assert_eq!(asns[0].holder, "Google LLC");
     }
+
+    #[test]
+    fn test_parse_as_overview() {
+        use serde_json::json;
+
+        let json_data = json!({
+            "data": {"holder": "RIPE-NCC-AS - Reseaux IP Europeens Network Coordination Centre (RIPE NCC)", "announced": true}
+        });
+        let infos = parse_as_overview(3333, &json_data).unwrap();
+        assert_eq!(infos.len(), 1);
+        assert_eq!(infos[0].asn, "3333");
+        assert!(infos[0].holder.starts_with("RIPE-NCC-AS"));
+
+        let unknown = json!({"data": {"holder": "", "announced": false}});
+        assert!(parse_as_overview(64496, &unknown).unwrap().is_empty());
+        assert!(parse_as_overview(64496, &json!({})).is_err());
+    }
 }
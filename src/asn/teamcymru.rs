@@ -24,7 +24,7 @@ impl TeamCymruWhois {
         }
         let asn = parts[0].trim().to_string();
         let holder = parts[2].trim().to_string();
-        Some(AsnInfo { asn, holder })
+        Some(AsnInfo::new(asn, holder))
     }
 }
 
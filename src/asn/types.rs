@@ -1,14 +1,100 @@
 // ASN information types
 
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
 /// Information about an Autonomous System Number (ASN)
 ///
 /// Contains the ASN identifier and the organization that holds it
-#[derive(Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Default, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
 pub struct AsnInfo {
     /// The ASN identifier (e.g., "AS15169")
     pub asn: String,
     /// The organization or entity that holds this ASN
     pub holder: String,
+    /// The routed prefix the looked-up address matched (e.g., "8.8.8.0/24")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    /// AS path from the observing peer to the origin, when known from BGP data
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub as_path: Vec<u32>,
+    /// The Regional Internet Registry the resource was delegated by (e.g., "arin")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registry: Option<String>,
+    /// ISO 3166 country code recorded by the registry (e.g., "US")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    /// Date the resource was allocated, formatted as YYYY-MM-DD
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allocated: Option<String>,
+    /// RPKI route origin validation state of the (prefix, ASN) pair
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rpki: Option<Validity>,
+}
+
+impl AsnInfo {
+    /// Creates a new AsnInfo with only the ASN and holder set
+    pub fn new(asn: impl Into<String>, holder: impl Into<String>) -> Self {
+        AsnInfo {
+            asn: asn.into(),
+            holder: holder.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the numeric ASN, accepting both "15169" and "AS15169" forms
+    pub fn asn_number(&self) -> Option<u32> {
+        parse_asn(&self.asn)
+    }
+
+    /// Returns the holder, or None if it is empty or the "Unknown" placeholder
+    /// of sources that carry no organization names
+    pub fn known_holder(&self) -> Option<&str> {
+        Some(self.holder.as_str()).filter(|holder| !holder.is_empty() && *holder != "Unknown")
+    }
+}
+
+/// Route origin validation state of a (prefix, origin AS) pair
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Validity {
+    /// A covering VRP authorizes the origin at this prefix length
+    Valid,
+    /// Covering VRPs exist, but none for this origin AS
+    InvalidAsn,
+    /// A covering VRP matches the origin AS, but the prefix exceeds its max length
+    InvalidLength,
+    /// No VRP covers the prefix
+    NotFound,
+}
+
+impl Validity {
+    /// Returns true for either kind of invalid
+    pub fn is_invalid(&self) -> bool {
+        matches!(self, Validity::InvalidAsn | Validity::InvalidLength)
+    }
+}
+
+impl fmt::Display for Validity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Validity::Valid => "valid",
+            Validity::InvalidAsn => "invalid (wrong origin AS)",
+            Validity::InvalidLength => "invalid (more specific than max length)",
+            Validity::NotFound => "not found",
+        })
+    }
+}
+
+/// Parses an AS number written as "15169", "AS15169" or "as15169"
+pub fn parse_asn(asn: &str) -> Option<u32> {
+    let asn = asn.trim();
+    let digits = asn
+        .strip_prefix("AS")
+        .or_else(|| asn.strip_prefix("as"))
+        .or_else(|| asn.strip_prefix("As"))
+        .unwrap_or(asn);
+    digits.parse().ok()
 }
 
 #[cfg(test)]
@@ -17,22 +103,44 @@ mod tests {
 
     #[test]
     fn test_asn_info_creation() {
-        let info = AsnInfo {
-            asn: "AS15169".to_string(),
-            holder: "Google LLC".to_string(),
-        };
+        let info = AsnInfo::new("AS15169", "Google LLC");
         assert_eq!(info.asn, "AS15169");
         assert_eq!(info.holder, "Google LLC");
+        assert_eq!(info.registry, None);
     }
 
     #[test]
     fn test_asn_info_debug() {
-        let info = AsnInfo {
-            asn: "AS15169".to_string(),
-            holder: "Google LLC".to_string(),
-        };
+        let info = AsnInfo::new("AS15169", "Google LLC");
         let debug_str = format!("{:?}", info);
         assert!(debug_str.contains("AS15169"));
         assert!(debug_str.contains("Google LLC"));
     }
+
+    #[test]
+    fn test_asn_number() {
+        assert_eq!(AsnInfo::new("AS15169", "").asn_number(), Some(15169));
+        assert_eq!(AsnInfo::new("3561", "").asn_number(), Some(3561));
+        assert_eq!(AsnInfo::new("N/A", "").asn_number(), None);
+    }
+
+    #[test]
+    fn test_known_holder() {
+        assert_eq!(
+            AsnInfo::new("15169", "Google LLC").known_holder(),
+            Some("Google LLC")
+        );
+        assert_eq!(AsnInfo::new("64496", "Unknown").known_holder(), None);
+        assert_eq!(AsnInfo::new("64496", "").known_holder(), None);
+    }
+
+    #[test]
+    fn test_optional_fields_skipped_when_empty() {
+        let info = AsnInfo::new("15169", "Google LLC");
+        let json = serde_json::to_string(&info).unwrap();
+        assert_eq!(json, r#"{"asn":"15169","holder":"Google LLC"}"#);
+
+        let parsed: AsnInfo = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, info);
+    }
 }
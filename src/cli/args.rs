@@ -1,19 +1,423 @@
 // CLI argument parsing
 
-use clap::Parser;
+use crate::enrich::{FieldPath, Header};
+use crate::filter::Target;
+use crate::network::Prefix;
+use crate::trace::TraceFormat;
+use crate::traffic::Side;
+use crate::watch::Interval;
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
 use std::net::IpAddr;
+use std::path::PathBuf;
 
 /// Command-line arguments for ASN lookup
 #[derive(Parser, Debug)]
-#[command(author, version, about = "A tool to lookup ASN from IP addresses")]
+#[command(
+    author,
+    version,
+    about = "A tool to lookup ASN from IP addresses",
+    subcommand_negates_reqs = true
+)]
 pub struct Args {
     /// IP address to lookup ASN for (IPv4 or IPv6)
-    #[arg()]
-    pub ip: IpAddr,
+    #[arg(required_unless_present = "file")]
+    pub ip: Option<IpAddr>,
 
-    /// Specify the source DB for the ASN lookup
+    /// Look up every address listed in this file, one per line, instead of a single IP
+    #[arg(long, value_name = "FILE", conflicts_with = "ip")]
+    pub file: Option<PathBuf>,
+
+    /// Print one {"ip", "results"} or {"ip", "error"} JSON object per address,
+    /// the snapshot format read by `diff`
+    #[arg(long)]
+    pub json: bool,
+
+    /// Specify the source DB for the ASN lookup; a comma-separated list is
+    /// tried in order until one source returns a result
     #[arg(short, long, default_value = "ripe")]
     pub source: String,
+
+    /// RIR delegated-stats file used to fill in registry, country and
+    /// allocation date (may be given once per registry)
+    #[arg(long = "delegated", value_name = "FILE")]
+    pub delegated: Vec<PathBuf>,
+
+    /// Validated ROA Payloads JSON export (Routinator, rpki-client or
+    /// OctoRPKI) used to check each result's origin AS with RPKI
+    #[arg(long, value_name = "FILE")]
+    pub vrps: Option<PathBuf>,
+
+    /// RPKI cache to fetch VRPs from over RTR (e.g. localhost:3323), used
+    /// instead of a VRP file for origin validation
+    #[arg(long, value_name = "HOST:PORT", conflicts_with = "vrps")]
+    pub rtr: Option<String>,
+
+    /// Write Prometheus metrics for the run's lookups to this file when it
+    /// finishes, e.g. for node_exporter's textfile collector
+    #[arg(long, value_name = "FILE")]
+    pub metrics_file: Option<PathBuf>,
+
+    /// Log more detail: -v for each provider tried, -vv for everything
+    #[arg(short, long, action = ArgAction::Count, global = true, conflicts_with = "quiet")]
+    pub verbose: u8,
+
+    /// Log less: -q for warnings and errors only, -qq for errors only
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    pub quiet: u8,
+
+    /// Format of log messages written to stderr
+    #[arg(long, value_enum, default_value = "text", global = true)]
+    pub log_format: LogFormat,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Subcommands beyond a single IP lookup
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Manage compiled local databases
+    Db {
+        #[command(subcommand)]
+        command: DbCommand,
+    },
+
+    /// Query Internet Routing Registry (IRR) objects
+    Irr {
+        /// IRR whois server: radb, ripe, arin or a `host[:port]`
+        #[arg(long, default_value = "radb")]
+        server: String,
+
+        #[command(subcommand)]
+        command: IrrCommand,
+    },
+
+    /// Generate a router prefix filter for the prefixes an ASN or as-set originates
+    Filter {
+        /// as-set name (e.g. AS-EXAMPLE) or a single AS number
+        name: String,
+
+        /// Router configuration syntax to generate
+        #[arg(short, long, value_enum)]
+        target: Target,
+
+        /// IRR whois server: radb, ripe, arin or a `host[:port]`
+        #[arg(long, default_value = "radb")]
+        server: String,
+
+        /// Expand from local RPSL dump files instead of querying --server
+        #[arg(long = "db", value_name = "FILE")]
+        db: Vec<PathBuf>,
+
+        /// Name of the generated prefix-list (defaults to the as-set name)
+        #[arg(short = 'l', long, value_name = "NAME")]
+        list_name: Option<String>,
+
+        /// Merge prefixes into the fewest equivalent entries using ge/le ranges
+        #[arg(short = 'A', long)]
+        aggregate: bool,
+
+        /// Also accept more-specifics of each prefix down to this length
+        #[arg(short = 'R', long, value_name = "LEN")]
+        more_specifics: Option<u8>,
+
+        /// Maximum nesting depth of as-sets to follow
+        #[arg(long, default_value_t = 16)]
+        max_depth: usize,
+    },
+
+    /// Serve lookups over an HTTP JSON API using the configured sources
+    Serve {
+        /// Address to listen on
+        #[arg(short, long, default_value = "127.0.0.1:8080")]
+        listen: String,
+
+        /// Seconds to reuse a lookup result for; 0 disables the cache
+        #[arg(long, value_name = "SECS", default_value_t = 3600)]
+        cache_ttl: u64,
+
+        /// Number of connections to handle concurrently
+        #[arg(long, default_value_t = 64)]
+        workers: usize,
+    },
+
+    /// Serve lookups over the whois protocol in Team Cymru's output format
+    ServeWhois {
+        /// Address to listen on (the standard whois port 43 needs privileges)
+        #[arg(short, long, default_value = "127.0.0.1:4343")]
+        listen: String,
+
+        /// Seconds to reuse a lookup result for; 0 disables the cache
+        #[arg(long, value_name = "SECS", default_value_t = 3600)]
+        cache_ttl: u64,
+
+        /// Number of connections to handle concurrently
+        #[arg(long, default_value_t = 64)]
+        workers: usize,
+    },
+
+    /// Serve Team Cymru style origin TXT records over DNS (UDP)
+    ServeDns {
+        /// Address to listen on (the standard DNS port 53 needs privileges)
+        #[arg(short, long, default_value = "127.0.0.1:5353")]
+        listen: String,
+
+        /// Zone to answer for; queries look like `<reversed-ip>.origin.<zone>`,
+        /// `<nibbles>.origin6.<zone>` and `AS<n>.<zone>`
+        #[arg(long, default_value = "asn.cymru.com")]
+        zone: String,
+
+        /// TTL of the TXT records, in seconds
+        #[arg(long, value_name = "SECS", default_value_t = 3600)]
+        ttl: u32,
+
+        /// Seconds to reuse a lookup result for; 0 disables the cache
+        #[arg(long, value_name = "SECS", default_value_t = 3600)]
+        cache_ttl: u64,
+
+        /// Number of queries to answer concurrently
+        #[arg(long, default_value_t = 64)]
+        workers: usize,
+    },
+
+    /// Annotate IP addresses in text read from stdin (logs, firewall output) with their ASNs
+    Enrich {
+        /// Annotate each address where it appears instead of at the end of the line
+        #[arg(long)]
+        inline: bool,
+
+        /// Number of lookups to run concurrently
+        #[arg(long, default_value_t = 8)]
+        workers: usize,
+
+        /// Seconds to reuse a lookup result for; 0 disables the cache
+        #[arg(long, value_name = "SECS", default_value_t = 3600)]
+        cache_ttl: u64,
+    },
+
+    /// Add ECS style AS fields next to the address fields of JSON lines read from stdin
+    EnrichJson {
+        /// Address field as a JSON pointer (/source/ip) or dotted path (source.ip); repeatable
+        #[arg(short, long = "field", value_name = "PATH", required = true)]
+        fields: Vec<FieldPath>,
+
+        /// Number of lookups to run concurrently
+        #[arg(long, default_value_t = 8)]
+        workers: usize,
+
+        /// Seconds to reuse a lookup result for; 0 disables the cache
+        #[arg(long, value_name = "SECS", default_value_t = 3600)]
+        cache_ttl: u64,
+    },
+
+    /// Append ASN columns for the addresses in columns of a CSV file read from stdin
+    EnrichCsv {
+        /// Column holding addresses, by header name or 1-based position; repeatable
+        #[arg(short, long = "column", value_name = "COLUMN", required = true)]
+        columns: Vec<String>,
+
+        /// Field delimiter (e.g. ';', or $'\t' for tab-separated input)
+        #[arg(short, long, default_value_t = ',')]
+        delimiter: char,
+
+        /// Whether the first row is a header; auto treats it as one unless it holds an address
+        #[arg(long, value_enum, default_value_t = Header::Auto)]
+        header: Header,
+
+        /// Number of lookups to run concurrently
+        #[arg(long, default_value_t = 8)]
+        workers: usize,
+
+        /// Seconds to reuse a lookup result for; 0 disables the cache
+        #[arg(long, value_name = "SECS", default_value_t = 3600)]
+        cache_ttl: u64,
+    },
+
+    /// Summarize the traffic in a pcap or pcapng capture by the ASNs of its endpoints
+    Pcap {
+        /// Capture file (Ethernet, Linux cooked, raw IP or loopback link types)
+        file: PathBuf,
+
+        /// Number of lookups to run concurrently
+        #[arg(long, default_value_t = 8)]
+        workers: usize,
+    },
+
+    /// Report the top ASNs in NetFlow v5/v9 or IPFIX exports from a file or a UDP listener
+    Flows {
+        /// pcap capture of export packets, or a file of NetFlow v5 or IPFIX messages
+        #[arg(required_unless_present = "listen", conflicts_with = "listen")]
+        file: Option<PathBuf>,
+
+        /// Collect exports sent to this UDP address instead of reading a file
+        #[arg(short, long)]
+        listen: Option<String>,
+
+        /// Seconds to collect exports for before reporting, with --listen
+        #[arg(long, value_name = "SECS", default_value_t = 60)]
+        duration: u64,
+
+        /// Attribute traffic to the source AS, the destination AS or both
+        #[arg(long, value_enum, default_value_t = Side::Either)]
+        by: Side,
+
+        /// Only show the ASNs with the most bytes
+        #[arg(long, value_name = "N")]
+        top: Option<usize>,
+
+        /// Number of lookups to run concurrently
+        #[arg(long, default_value_t = 8)]
+        workers: usize,
+    },
+
+    /// Annotate captured traceroute, mtr or tracepath output with the ASN of each hop
+    Hops {
+        /// File holding the output, read from standard input when omitted
+        file: Option<PathBuf>,
+
+        /// Tool that produced the output, detected from its contents by default
+        #[arg(long, value_enum, default_value_t = TraceFormat::Auto)]
+        format: TraceFormat,
+    },
+
+    /// Periodically look up addresses and prefixes and report changes in their origin ASNs
+    Watch {
+        /// File listing the addresses and prefixes to watch, one per line;
+        /// prefixes are looked up at 16 evenly spaced addresses
+        #[arg(short, long)]
+        file: PathBuf,
+
+        /// Time between checks, e.g. 30s, 15m, 1h or 1d
+        #[arg(long, default_value = "1h")]
+        interval: Interval,
+
+        /// JSON file keeping the last known origins between checks and runs
+        #[arg(long)]
+        state: PathBuf,
+
+        /// Also post each event as JSON to this URL
+        #[arg(long, value_name = "URL")]
+        webhook: Option<String>,
+
+        /// Check once and exit instead of repeating every interval
+        #[arg(long)]
+        once: bool,
+
+        /// Number of lookups to run concurrently
+        #[arg(long, default_value_t = 8)]
+        workers: usize,
+    },
+
+    /// Compare two snapshots of JSON lookup results and report what moved
+    Diff {
+        /// Earlier snapshot: the output of a --json lookup or a /bulk response
+        before: PathBuf,
+
+        /// Later snapshot in the same format
+        after: PathBuf,
+
+        /// Print one JSON object per differing address instead of a text summary
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// IRR query subcommands
+#[derive(Subcommand, Debug)]
+pub enum IrrCommand {
+    /// Show route/route6 objects for an address or prefix and its less-specifics
+    Routes {
+        /// IP address or prefix (e.g. 192.0.2.0/24)
+        resource: Prefix,
+
+        /// Also look up the BGP origin with --source and check it is registered
+        #[arg(long)]
+        compare: bool,
+    },
+
+    /// Show the aut-num object for an AS
+    AutNum {
+        /// AS number, with or without the "AS" prefix
+        asn: String,
+    },
+
+    /// Show an as-set object
+    AsSet {
+        /// as-set name (e.g. AS-EXAMPLE)
+        name: String,
+    },
+
+    /// Recursively expand an as-set into its ASNs and their registered prefixes
+    Expand {
+        /// as-set name (e.g. AS-EXAMPLE) or a single AS number
+        name: String,
+
+        /// Expand from local RPSL dump files (e.g. radb.db.gz) instead of querying --server
+        #[arg(long = "db", value_name = "FILE")]
+        db: Vec<PathBuf>,
+
+        /// Maximum nesting depth of as-sets to follow
+        #[arg(long, default_value_t = 16)]
+        max_depth: usize,
+
+        /// Only list ASNs, skipping the route object lookups
+        #[arg(long)]
+        asns_only: bool,
+    },
+}
+
+/// Local database subcommands
+#[derive(Subcommand, Debug)]
+pub enum DbCommand {
+    /// Compile datasets into a memory-mappable local database
+    ///
+    /// Inputs are given as `<format>:<path>` with format one of pfx2as, iptoasn,
+    /// mrt, delegated or results (NDJSON of recorded results with a prefix).
+    /// When several inputs cover the same prefix the first one wins.
+    Build {
+        /// Path of the database file to write
+        #[arg(short, long, value_name = "FILE")]
+        output: PathBuf,
+
+        /// Datasets to compile, as `<format>:<path>`
+        #[arg(required = true, value_name = "FORMAT:PATH")]
+        inputs: Vec<String>,
+    },
+
+    /// Export datasets in a format other tools can read
+    ///
+    /// Accepts the same inputs as `db build`, plus `localdb:<path>` to export a
+    /// previously compiled database.
+    Export {
+        /// Output file format
+        #[arg(short, long, value_enum, default_value = "mmdb")]
+        format: ExportFormat,
+
+        /// Path of the file to write
+        #[arg(short, long, value_name = "FILE")]
+        output: PathBuf,
+
+        /// Datasets to export, as `<format>:<path>`
+        #[arg(required = true, value_name = "FORMAT:PATH")]
+        inputs: Vec<String>,
+    },
+}
+
+/// File formats supported by `db export`
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    /// MaxMind DB with GeoLite2-ASN style records
+    Mmdb,
+}
+
+/// Formats for log messages
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Default)]
+pub enum LogFormat {
+    /// One human-readable line per message
+    #[default]
+    Text,
+    /// One JSON object per message, including the fields of enclosing spans
+    Json,
 }
 
 #[cfg(test)]
@@ -25,10 +429,20 @@ mod tests {
     fn test_args_ipv4_parsing() {
         let ip = IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8));
         let args = Args {
-            ip,
+            ip: Some(ip),
+            file: None,
+            json: false,
             source: "ripe".to_string(),
+            delegated: vec![],
+            vrps: None,
+            rtr: None,
+            metrics_file: None,
+            verbose: 0,
+            quiet: 0,
+            log_format: LogFormat::Text,
+            command: None,
         };
-        assert_eq!(args.ip, IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8)));
+        assert_eq!(args.ip, Some(IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8))));
         assert_eq!(args.source, "ripe")
     }
 
@@ -36,12 +450,24 @@ mod tests {
     fn test_args_ipv6_parsing() {
         let ip = IpAddr::V6(Ipv6Addr::new(0x2001, 0x4860, 0x4860, 0, 0, 0, 0, 0x8888));
         let args = Args {
-            ip,
+            ip: Some(ip),
+            file: None,
+            json: false,
             source: "".to_string(),
+            delegated: vec![],
+            vrps: None,
+            rtr: None,
+            metrics_file: None,
+            verbose: 0,
+            quiet: 0,
+            log_format: LogFormat::Text,
+            command: None,
         };
         assert_eq!(
             args.ip,
-            IpAddr::V6(Ipv6Addr::new(0x2001, 0x4860, 0x4860, 0, 0, 0, 0, 0x8888))
+            Some(IpAddr::V6(Ipv6Addr::new(
+                0x2001, 0x4860, 0x4860, 0, 0, 0, 0, 0x8888
+            )))
         );
     }
 
@@ -49,8 +475,18 @@ mod tests {
     fn test_args_debug() {
         let ip = IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1));
         let args = Args {
-            ip,
+            ip: Some(ip),
+            file: None,
+            json: false,
             source: "ripe".to_string(),
+            delegated: vec![],
+            vrps: None,
+            rtr: None,
+            metrics_file: None,
+            verbose: 0,
+            quiet: 0,
+            log_format: LogFormat::Text,
+            command: None,
         };
         let debug_str = format!("{:?}", args);
         assert!(debug_str.contains("1.1.1.1"));
@@ -60,8 +496,18 @@ mod tests {
     fn test_args_source_default() {
         let ip = IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1));
         let args = Args {
-            ip,
+            ip: Some(ip),
+            file: None,
+            json: false,
             source: "ripe".to_string(),
+            delegated: vec![],
+            vrps: None,
+            rtr: None,
+            metrics_file: None,
+            verbose: 0,
+            quiet: 0,
+            log_format: LogFormat::Text,
+            command: None,
         };
         assert_eq!(args.source, "ripe");
     }
@@ -70,19 +516,39 @@ mod tests {
     fn test_args_source_ipapi() {
         let ip = IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8));
         let args = Args {
-            ip,
+            ip: Some(ip),
+            file: None,
+            json: false,
             source: "ipapi".to_string(),
+            delegated: vec![],
+            vrps: None,
+            rtr: None,
+            metrics_file: None,
+            verbose: 0,
+            quiet: 0,
+            log_format: LogFormat::Text,
+            command: None,
         };
         assert_eq!(args.source, "ipapi");
-        assert_eq!(args.ip, IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8)));
+        assert_eq!(args.ip, Some(IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8))));
     }
 
     #[test]
     fn test_args_source_custom() {
         let ip = IpAddr::V4(Ipv4Addr::new(1, 0, 0, 1));
         let args = Args {
-            ip,
+            ip: Some(ip),
+            file: None,
+            json: false,
             source: "custom".to_string(),
+            delegated: vec![],
+            vrps: None,
+            rtr: None,
+            metrics_file: None,
+            verbose: 0,
+            quiet: 0,
+            log_format: LogFormat::Text,
+            command: None,
         };
         assert_eq!(args.source, "custom");
     }
@@ -0,0 +1,51 @@
+// Log output for the command-line tool
+
+use super::args::LogFormat;
+use std::io::IsTerminal;
+use tracing_subscriber::EnvFilter;
+
+/// The level logged by default, raised by each `-v` and lowered by each `-q`
+pub fn default_level(verbose: u8, quiet: u8) -> &'static str {
+    match (verbose, quiet) {
+        (0, 0) => "info",
+        (1, _) => "debug",
+        (_, 0) => "trace",
+        (_, 1) => "warn",
+        _ => "error",
+    }
+}
+
+/// Installs a subscriber writing log messages to stderr
+///
+/// `RUST_LOG` takes precedence over the level chosen with `-v` and `-q`, so
+/// individual modules can be made more or less verbose.
+pub fn init_logging(verbose: u8, quiet: u8, format: LogFormat) {
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(default_level(verbose, quiet)));
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+    let _ = match format {
+        LogFormat::Text => builder
+            .with_ansi(std::io::stderr().is_terminal())
+            .without_time()
+            .with_target(false)
+            .compact()
+            .try_init(),
+        LogFormat::Json => builder.json().with_current_span(false).try_init(),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_level() {
+        assert_eq!(default_level(0, 0), "info");
+        assert_eq!(default_level(1, 0), "debug");
+        assert_eq!(default_level(3, 0), "trace");
+        assert_eq!(default_level(0, 1), "warn");
+        assert_eq!(default_level(0, 2), "error");
+    }
+}
@@ -1,5 +1,7 @@
 // CLI module exports
 
 pub mod args;
+pub mod logging;
 
-pub use args::Args;
+pub use args::{Args, Command, DbCommand, ExportFormat, IrrCommand, LogFormat};
+pub use logging::init_logging;
@@ -0,0 +1,400 @@
+// Enriching CSV exports
+
+use super::{lookup_origins, BATCH_SIZE, DEFAULT_WORKERS};
+use crate::asn::{Asn, AsnInfo};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::{self, BufRead, Error, ErrorKind, Write};
+use std::net::IpAddr;
+
+/// Names of the columns appended for each looked-up column
+///
+/// With several looked-up columns the names are prefixed with the column's
+/// header, as in `src_ip_asn`.
+pub const RESULT_COLUMNS: [&str; 6] = [
+    "asn",
+    "holder",
+    "prefix",
+    "country",
+    "registry",
+    "allocated",
+];
+
+/// Whether the first record of the input is a header
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Header {
+    /// A header unless one of its fields is an IP address
+    #[default]
+    Auto,
+    /// The first record is a header
+    Yes,
+    /// The first record is data
+    No,
+}
+
+/// A CSV record as read, with its parsed fields
+struct Record {
+    /// The record without its line ending, quoting intact
+    raw: String,
+    ending: &'static str,
+    fields: Vec<String>,
+}
+
+/// Reads the next record, which spans several lines when a quoted field contains line breaks
+fn read_record<R: BufRead>(input: &mut R, delimiter: char) -> io::Result<Option<Record>> {
+    let mut buf = Vec::new();
+    loop {
+        if input.read_until(b'\n', &mut buf)? == 0 || !buf.ends_with(b"\n") {
+            break;
+        }
+        // An odd number of quotes means a quoted field continues on the next line
+        if buf.iter().filter(|&&b| b == b'"').count() % 2 == 0 {
+            break;
+        }
+    }
+    if buf.is_empty() {
+        return Ok(None);
+    }
+    let mut raw = String::from_utf8_lossy(&buf).into_owned();
+    let ending = if raw.ends_with("\r\n") {
+        "\r\n"
+    } else if raw.ends_with('\n') {
+        "\n"
+    } else {
+        ""
+    };
+    raw.truncate(raw.len() - ending.len());
+    let fields = split_fields(&raw, delimiter);
+    Ok(Some(Record {
+        raw,
+        ending,
+        fields,
+    }))
+}
+
+/// Splits a record into fields, removing quotes and unescaping doubled quotes
+fn split_fields(raw: &str, delimiter: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut field_start = true;
+    let mut chars = raw.chars().peekable();
+    while let Some(c) = chars.next() {
+        if quoted {
+            if c != '"' {
+                field.push(c);
+            } else if chars.peek() == Some(&'"') {
+                field.push('"');
+                chars.next();
+            } else {
+                quoted = false;
+            }
+        } else if c == delimiter {
+            fields.push(std::mem::take(&mut field));
+            field_start = true;
+            continue;
+        } else if c == '"' && field_start {
+            quoted = true;
+        } else {
+            field.push(c);
+        }
+        field_start = false;
+    }
+    fields.push(field);
+    fields
+}
+
+/// Quotes a field if it contains the delimiter, quotes or line breaks
+fn quote(field: &str, delimiter: char) -> Cow<'_, str> {
+    if field.contains([delimiter, '"', '\r', '\n']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+/// Returns the values of the result columns for an address's first result
+fn result_fields(info: Option<&AsnInfo>) -> [String; 6] {
+    let Some(info) = info else {
+        return Default::default();
+    };
+    let asn = match info.asn_number() {
+        Some(asn) => asn.to_string(),
+        None => info.asn.clone(),
+    };
+    let optional = |value: &Option<String>| value.clone().unwrap_or_default();
+    [
+        asn,
+        info.known_holder().unwrap_or_default().to_string(),
+        optional(&info.prefix),
+        optional(&info.country),
+        optional(&info.registry),
+        optional(&info.allocated),
+    ]
+}
+
+/// Appends ASN result columns to each row of a CSV file for the addresses in
+/// the named columns
+///
+/// Rows are copied byte for byte, including their quoting and line endings,
+/// with the new columns added at the end. Blank lines are passed through.
+pub struct CsvEnricher<'a> {
+    lookup: &'a dyn Asn,
+    columns: Vec<String>,
+    delimiter: char,
+    header: Header,
+    workers: usize,
+}
+
+impl<'a> CsvEnricher<'a> {
+    /// Creates an enricher for comma-separated input
+    ///
+    /// Columns are given by header name, or by 1-based position.
+    pub fn new(lookup: &'a dyn Asn, columns: Vec<String>) -> Self {
+        CsvEnricher {
+            lookup,
+            columns,
+            delimiter: ',',
+            header: Header::Auto,
+            workers: DEFAULT_WORKERS,
+        }
+    }
+
+    /// Sets the field delimiter
+    pub fn delimiter(mut self, delimiter: char) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Sets whether the first record is a header
+    pub fn header(mut self, header: Header) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Sets how many lookups run concurrently
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers;
+        self
+    }
+
+    /// Returns the index of each column, looking names up in the header
+    fn column_indexes(&self, header: Option<&[String]>) -> io::Result<Vec<usize>> {
+        self.columns
+            .iter()
+            .map(|column| {
+                let by_name = header.and_then(|names| {
+                    names
+                        .iter()
+                        .position(|name| name.trim().eq_ignore_ascii_case(column.trim()))
+                });
+                by_name
+                    .or_else(|| {
+                        column
+                            .parse::<usize>()
+                            .ok()
+                            .filter(|&n| n > 0)
+                            .map(|n| n - 1)
+                    })
+                    .ok_or_else(|| {
+                        Error::new(
+                            ErrorKind::InvalidInput,
+                            format!("column '{}' not found in the CSV header", column),
+                        )
+                    })
+            })
+            .collect()
+    }
+
+    /// Enriches every record of `input`, writing the results to `output`
+    ///
+    /// Addresses are looked up a batch of records at a time.
+    pub fn run<R: BufRead, W: Write>(&self, mut input: R, mut output: W) -> io::Result<()> {
+        if self.delimiter == '"' || self.delimiter == '\r' || self.delimiter == '\n' {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid CSV delimiter {:?}", self.delimiter),
+            ));
+        }
+        let Some(first) = read_record(&mut input, self.delimiter)? else {
+            return Ok(());
+        };
+        let has_header = match self.header {
+            Header::Yes => true,
+            Header::No => false,
+            Header::Auto => !first
+                .fields
+                .iter()
+                .any(|field| field.trim().parse::<IpAddr>().is_ok()),
+        };
+
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        let indexes;
+        if has_header {
+            indexes = self.column_indexes(Some(&first.fields))?;
+            let mut line = first.raw.clone();
+            for &index in &indexes {
+                let column = first.fields.get(index).map_or("", |name| name.trim());
+                for name in RESULT_COLUMNS {
+                    line.push(self.delimiter);
+                    if indexes.len() == 1 {
+                        line.push_str(name);
+                    } else {
+                        line.push_str(&quote(&format!("{}_{}", column, name), self.delimiter));
+                    }
+                }
+            }
+            write!(output, "{}{}", line, first.ending)?;
+        } else {
+            indexes = self.column_indexes(None)?;
+            batch.push(first);
+        }
+
+        loop {
+            let record = read_record(&mut input, self.delimiter)?;
+            let done = record.is_none();
+            batch.extend(record);
+            if batch.len() == BATCH_SIZE || (done && !batch.is_empty()) {
+                self.write_batch(&batch, &indexes, &mut output)?;
+                batch.clear();
+            }
+            if done {
+                return output.flush();
+            }
+        }
+    }
+
+    /// Looks up the addresses of a batch of records and writes the enriched records
+    fn write_batch<W: Write>(
+        &self,
+        batch: &[Record],
+        indexes: &[usize],
+        output: &mut W,
+    ) -> io::Result<()> {
+        let address = |record: &Record, index: usize| -> Option<IpAddr> {
+            record.fields.get(index)?.trim().parse().ok()
+        };
+        let ips: Vec<IpAddr> = batch
+            .iter()
+            .flat_map(|record| indexes.iter().filter_map(|&i| address(record, i)))
+            .collect();
+        let results: HashMap<IpAddr, AsnInfo> = lookup_origins(self.lookup, &ips, self.workers);
+
+        for record in batch {
+            let mut line = record.raw.clone();
+            if !line.is_empty() {
+                for &index in indexes {
+                    let info = address(record, index).and_then(|ip| results.get(&ip));
+                    for value in result_fields(info) {
+                        line.push(self.delimiter);
+                        line.push_str(&quote(&value, self.delimiter));
+                    }
+                }
+            }
+            write!(output, "{}{}", line, record.ending)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    struct MockAsn;
+
+    impl Asn for MockAsn {
+        fn lookup_asn(&self, ip: IpAddr) -> Result<Vec<AsnInfo>, Error> {
+            match ip.to_string().as_str() {
+                "8.8.8.8" => Ok(vec![AsnInfo {
+                    prefix: Some("8.8.8.0/24".to_string()),
+                    country: Some("US".to_string()),
+                    ..AsnInfo::new("AS15169", "Google, LLC")
+                }]),
+                "192.0.2.2" => Err(Error::new(ErrorKind::Other, "unreachable")),
+                "192.0.2.3" => Ok(vec![AsnInfo::new("64496", "Unknown")]),
+                _ => Ok(Vec::new()),
+            }
+        }
+    }
+
+    fn enrich(enricher: CsvEnricher, input: &str) -> io::Result<String> {
+        let mut output = Vec::new();
+        enricher.run(Cursor::new(input.to_string()), &mut output)?;
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_split_fields() {
+        assert_eq!(split_fields("a,b,,c", ','), vec!["a", "b", "", "c"]);
+        assert_eq!(
+            split_fields(r#""a,1","say ""hi""",x"y"#, ','),
+            vec!["a,1", r#"say "hi""#, r#"x"y"#]
+        );
+        assert_eq!(
+            split_fields("\"line\nbreak\";b", ';'),
+            vec!["line\nbreak", "b"]
+        );
+        assert_eq!(split_fields("", ','), vec![""]);
+    }
+
+    #[test]
+    fn test_header_by_name() {
+        let input = "time,\"Src IP\",action\r\n\
+                     12:00,8.8.8.8,\"deny, logged\"\r\n\
+                     \r\n\
+                     12:01,10.0.0.1,allow\r\n";
+        let enricher = CsvEnricher::new(&MockAsn, vec!["src ip".to_string()]);
+        assert_eq!(
+            enrich(enricher, input).unwrap(),
+            "time,\"Src IP\",action,asn,holder,prefix,country,registry,allocated\r\n\
+             12:00,8.8.8.8,\"deny, logged\",15169,\"Google, LLC\",8.8.8.0/24,US,,\r\n\
+             \r\n\
+             12:01,10.0.0.1,allow,,,,,,\r\n"
+        );
+    }
+
+    #[test]
+    fn test_headerless_multiple_columns() {
+        let input = "8.8.8.8\t\"a\nb\"\t192.0.2.2\n10.0.0.1\t\t192.0.2.3";
+        let enricher = CsvEnricher::new(&MockAsn, vec!["1".to_string(), "3".to_string()])
+            .delimiter('\t')
+            .workers(1);
+        assert_eq!(
+            enrich(enricher, input).unwrap(),
+            "8.8.8.8\t\"a\nb\"\t192.0.2.2\t15169\tGoogle, LLC\t8.8.8.0/24\tUS\t\t\t\t\t\t\t\t\n\
+             10.0.0.1\t\t192.0.2.3\t\t\t\t\t\t\t64496\t\t\t\t\t"
+        );
+    }
+
+    #[test]
+    fn test_header_modes() {
+        let input = "src,dst\n8.8.8.8,10.0.0.1\n";
+        let enricher = CsvEnricher::new(&MockAsn, vec!["src".to_string(), "dst".to_string()]);
+        let output = enrich(enricher, input).unwrap();
+        assert!(output.starts_with("src,dst,src_asn,src_holder,"));
+        assert!(output.contains(",dst_allocated\n"));
+
+        let enricher = CsvEnricher::new(&MockAsn, vec!["1".to_string()]).header(Header::No);
+        assert!(enrich(enricher, input)
+            .unwrap()
+            .starts_with("src,dst,,,,,,\n8.8.8.8,10.0.0.1,15169,"));
+
+        let enricher = CsvEnricher::new(&MockAsn, vec!["1".to_string(), "5".to_string()]);
+        let output = enrich(enricher, input).unwrap();
+        assert!(output.starts_with("src,dst,src_asn,"));
+        assert!(output.contains(",_allocated\n"));
+    }
+
+    #[test]
+    fn test_missing_column() {
+        let enricher = CsvEnricher::new(&MockAsn, vec!["src_ip".to_string()]);
+        let err = enrich(enricher, "src,dst\n8.8.8.8,10.0.0.1\n").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+        let enricher = CsvEnricher::new(&MockAsn, vec!["src".to_string()]);
+        assert!(enrich(enricher, "8.8.8.8,10.0.0.1\n").is_err());
+        assert_eq!(enrich(CsvEnricher::new(&MockAsn, vec![]), "").unwrap(), "");
+    }
+}
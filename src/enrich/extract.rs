@@ -0,0 +1,181 @@
+// Finding IP addresses in free-form text
+
+use std::net::{IpAddr, Ipv4Addr};
+use std::ops::Range;
+
+/// An address found in text and the byte range it occupies
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IpMatch {
+    pub ip: IpAddr,
+    pub span: Range<usize>,
+}
+
+fn is_candidate(c: u8) -> bool {
+    c.is_ascii_hexdigit() || c == b':' || c == b'.'
+}
+
+/// Returns true if the byte before `at` could not continue a word or address
+fn boundary_before(text: &[u8], at: usize) -> bool {
+    at == 0 || !(text[at - 1].is_ascii_alphanumeric() || text[at - 1] == b'_')
+}
+
+/// Returns true if the byte at `at` could not continue a word or address
+fn boundary_after(text: &[u8], at: usize) -> bool {
+    at >= text.len() || !(text[at].is_ascii_alphanumeric() || text[at] == b'_')
+}
+
+/// Finds every IPv4 and IPv6 address in a line of text, in order
+///
+/// Addresses are recognised inside brackets (`[2001:db8::1]:443`), with ports
+/// (`192.0.2.1:8080`, `192.0.2.1.8080` is not an address), and followed by
+/// punctuation (`192.0.2.1.` or `2001:db8::1,`), including after a `key:`
+/// prefix (`src:192.0.2.1`). Version numbers, MAC
+/// addresses, timestamps and addresses embedded in longer words are ignored.
+pub fn find_ips(line: &str) -> Vec<IpMatch> {
+    let text = line.as_bytes();
+    let mut found = Vec::new();
+    let mut i = 0;
+    while i < text.len() {
+        if !is_candidate(text[i]) {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < text.len() && is_candidate(text[i]) {
+            i += 1;
+        }
+        if !boundary_after(text, i) {
+            continue;
+        }
+        if boundary_before(text, start) {
+            scan_run(line, start, i, &mut found);
+            continue;
+        }
+        // The run continues a word, as in "addr:192.0.2.1" or "id:2001:db8::1";
+        // an address can still start right after one of its colons
+        let before = found.len();
+        for (offset, _) in line[start..i].match_indices(':') {
+            scan_run(line, start + offset + 1, i, &mut found);
+            if found.len() > before {
+                break;
+            }
+        }
+    }
+    found
+}
+
+/// Looks for addresses in a run of hex digits, colons and dots
+fn scan_run(line: &str, start: usize, end: usize, found: &mut Vec<IpMatch>) {
+    let run = &line[start..end];
+
+    // Trailing sentence punctuation, but not the "::" that ends "2001:db8::"
+    let mut trimmed = run.trim_end_matches('.');
+    if trimmed.ends_with(':') && !trimmed.ends_with("::") {
+        trimmed = &trimmed[..trimmed.len() - 1];
+    }
+    if trimmed.contains(':') {
+        if let Ok(ip) = trimmed.parse::<IpAddr>() {
+            found.push(IpMatch {
+                ip,
+                span: start..start + trimmed.len(),
+            });
+            return;
+        }
+    }
+
+    // IPv4 addresses, possibly followed by ":port"
+    let bytes = run.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if !bytes[i].is_ascii_digit() || (i > 0 && bytes[i - 1] != b':') {
+            i += 1;
+            continue;
+        }
+        let from = i;
+        while i < bytes.len() && (bytes[i].is_ascii_digit() || bytes[i] == b'.') {
+            i += 1;
+        }
+        let candidate = run[from..i].trim_end_matches('.');
+        let followed_by_hex = bytes.get(i).is_some_and(|b| b.is_ascii_hexdigit());
+        if let (Ok(ip), false) = (candidate.parse::<Ipv4Addr>(), followed_by_hex) {
+            found.push(IpMatch {
+                ip: IpAddr::V4(ip),
+                span: start + from..start + from + candidate.len(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ips(line: &str) -> Vec<String> {
+        find_ips(line)
+            .into_iter()
+            .map(|m| {
+                assert_eq!(line[m.span.clone()].parse::<IpAddr>().unwrap(), m.ip);
+                m.ip.to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_plain_addresses() {
+        assert_eq!(
+            ips("Accepted publickey for root from 192.0.2.7 port 51234 ssh2"),
+            vec!["192.0.2.7"]
+        );
+        assert_eq!(
+            ips("connect from 2001:db8::1 and ::1 and fe80::1%eth0"),
+            vec!["2001:db8::1", "::1", "fe80::1"]
+        );
+        assert_eq!(ips("::ffff:192.0.2.1"), vec!["::ffff:192.0.2.1"]);
+    }
+
+    #[test]
+    fn test_ports_brackets_and_punctuation() {
+        assert_eq!(
+            ips(r#"198.51.100.4 - - [10/Oct/2024:13:55:36 +0000] "GET / HTTP/1.1" 200"#),
+            vec!["198.51.100.4"]
+        );
+        assert_eq!(
+            ips("SRC=203.0.113.9:443 DST=[2001:db8::2]:8443"),
+            vec!["203.0.113.9", "2001:db8::2"]
+        );
+        assert_eq!(
+            ips("Blocked 192.0.2.1. Then 2001:db8::, (192.0.2.2); \"192.0.2.3\""),
+            vec!["192.0.2.1", "2001:db8::", "192.0.2.2", "192.0.2.3"]
+        );
+    }
+
+    #[test]
+    fn test_key_prefixes() {
+        assert_eq!(
+            ips("inet addr:192.168.1.1 Bcast:192.168.1.255"),
+            vec!["192.168.1.1", "192.168.1.255"]
+        );
+        assert_eq!(
+            ips("src:192.0.2.1 dst:198.51.100.2"),
+            vec!["192.0.2.1", "198.51.100.2"]
+        );
+        assert_eq!(ips("id:2001:db8::1"), vec!["2001:db8::1"]);
+        assert_eq!(ips("peer:[2001:db8::2]:179"), vec!["2001:db8::2"]);
+        assert!(ips("ts:12:34:56 build:1.2.3.4.5").is_empty());
+    }
+
+    #[test]
+    fn test_ignores_lookalikes() {
+        assert!(ips("version 1.2.3.4.5 released").is_empty());
+        assert!(ips("mac 00:1a:2b:3c:4d:5e at 12:34:56").is_empty());
+        assert!(ips("host-a1.2.3.4 and 1.2.3.4b and 999.1.1.1 and 01.2.3.4").is_empty());
+        assert!(ips("deadbeef cafe:babe").is_empty());
+    }
+
+    #[test]
+    fn test_spans_are_byte_offsets() {
+        let line = "é 192.0.2.1";
+        let found = find_ips(line);
+        assert_eq!(found[0].span, 3..12);
+    }
+}
@@ -0,0 +1,388 @@
+// Enriching NDJSON logs
+
+use super::{lookup_origins, run_batched, DEFAULT_WORKERS};
+use crate::asn::{Asn, AsnInfo};
+use serde_json::value::RawValue;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::{self, BufRead, Error, ErrorKind, Write};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// The location of an address field in a JSON document
+///
+/// Parsed from a JSON pointer (`/source/ip`, `/id.orig_h`) or a dotted path
+/// (`source.ip`). Numeric segments index into arrays.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldPath {
+    segments: Vec<String>,
+}
+
+impl FieldPath {
+    /// Returns the key the AS object is added under, next to the address field
+    ///
+    /// ECS style `ip` fields get `as`, fields such as `src_ip` get `src_as`
+    /// and any other field gets `<field>_as`.
+    pub fn as_key(&self) -> String {
+        let key = self.segments.last().map(String::as_str).unwrap_or_default();
+        match key.strip_suffix("ip") {
+            Some("") => "as".to_string(),
+            Some(stem) if stem.ends_with(['_', '-', '.']) => format!("{}as", stem),
+            _ => format!("{}_as", key),
+        }
+    }
+
+    /// Returns the address stored in the field, if it holds one
+    fn address(&self, line: &str) -> Option<IpAddr> {
+        let raw = value_at(line, &self.segments)?;
+        serde_json::from_str::<String>(raw)
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    }
+
+    /// Returns the line with the AS fields for `info` set next to the field
+    ///
+    /// Returns None, leaving the line alone, if the field's parent is missing
+    /// or the AS key already holds something that is not an object.
+    fn enrich(&self, line: &str, info: &AsnInfo) -> Option<String> {
+        let (_, path) = self.segments.split_last()?;
+        let parent = value_at(line, path)?;
+        let as_fields = match object_members(parent)?.get(self.as_key().as_str()) {
+            Some(existing) => with_as_fields(existing.get(), info)?,
+            None => with_as_fields("{}", info)?,
+        };
+        let parent = with_member(parent, &self.as_key(), &as_fields)?;
+        Some(splice(line, value_at(line, path)?, &parent))
+    }
+}
+
+impl FromStr for FieldPath {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let segments: Vec<String> = match s.strip_prefix('/') {
+            Some(pointer) => pointer
+                .split('/')
+                .map(|segment| segment.replace("~1", "/").replace("~0", "~"))
+                .collect(),
+            None => s.split('.').map(str::to_string).collect(),
+        };
+        if s.is_empty() || (!s.starts_with('/') && segments.iter().any(String::is_empty)) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid field path '{}'", s),
+            ));
+        }
+        Ok(FieldPath { segments })
+    }
+}
+
+impl fmt::Display for FieldPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for segment in &self.segments {
+            write!(f, "/{}", segment.replace('~', "~0").replace('/', "~1"))?;
+        }
+        Ok(())
+    }
+}
+
+/// Adds ECS style `as.number` and `as.organization.name` fields next to the
+/// address fields of JSON lines
+///
+/// Lines that are not JSON objects, or whose fields hold no address with a
+/// result, are passed through unchanged.
+pub struct JsonEnricher<'a> {
+    lookup: &'a dyn Asn,
+    fields: Vec<FieldPath>,
+    workers: usize,
+}
+
+impl<'a> JsonEnricher<'a> {
+    /// Creates an enricher for the given address fields
+    pub fn new(lookup: &'a dyn Asn, fields: Vec<FieldPath>) -> Self {
+        JsonEnricher {
+            lookup,
+            fields,
+            workers: DEFAULT_WORKERS,
+        }
+    }
+
+    /// Sets how many lookups run concurrently
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers;
+        self
+    }
+
+    /// Enriches a batch of lines, looking up every address in them together
+    ///
+    /// Failed lookups are logged as warnings and leave the field unenriched.
+    pub fn enrich(&self, lines: &[String]) -> Vec<String> {
+        let ips: Vec<IpAddr> = lines
+            .iter()
+            .filter(|line| object_members(line.trim()).is_some())
+            .flat_map(|line| {
+                self.fields
+                    .iter()
+                    .filter_map(|field| field.address(line))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        let results: HashMap<IpAddr, AsnInfo> = lookup_origins(self.lookup, &ips, self.workers);
+
+        lines
+            .iter()
+            .map(|line| {
+                if object_members(line.trim()).is_none() {
+                    return line.clone();
+                }
+                let mut line = line.clone();
+                for field in &self.fields {
+                    let Some(info) = field.address(&line).and_then(|ip| results.get(&ip)) else {
+                        continue;
+                    };
+                    match field.enrich(&line, info) {
+                        Some(enriched) => line = enriched,
+                        None => tracing::warn!(
+                            field = %field,
+                            "not enriching: '{}' already holds a value that is not an object",
+                            field.as_key()
+                        ),
+                    }
+                }
+                line
+            })
+            .collect()
+    }
+
+    /// Enriches every line of `input`, writing the results to `output`
+    ///
+    /// Lines are looked up in batches as described for `run_batched`.
+    pub fn run<R, W>(&self, input: R, output: W) -> io::Result<()>
+    where
+        R: BufRead + Send + 'static,
+        W: Write,
+    {
+        run_batched(input, output, |lines| self.enrich(lines))
+    }
+}
+
+// Lines are edited as text rather than re-serialized, so that everything
+// but the added fields keeps its original formatting and number precision.
+// Values are located with borrowed `RawValue`s, which point into the line.
+
+/// Returns the members of a JSON object, or None if `text` is not one
+fn object_members(text: &str) -> Option<BTreeMap<String, &RawValue>> {
+    if !text.starts_with('{') {
+        return None;
+    }
+    serde_json::from_str(text).ok()
+}
+
+/// Returns the text of the value at `path` within the JSON document `text`
+///
+/// The result borrows from `text`, so its position can be recovered.
+fn value_at<'t>(text: &'t str, path: &[String]) -> Option<&'t str> {
+    let mut value = text.trim();
+    for segment in path {
+        value = if value.starts_with('[') {
+            let items: Vec<&RawValue> = serde_json::from_str(value).ok()?;
+            items.get(segment.parse::<usize>().ok()?)?.get()
+        } else {
+            object_members(value)?.get(segment.as_str())?.get()
+        };
+    }
+    Some(value)
+}
+
+/// Replaces `part`, a slice borrowed from `text`, with `replacement`
+fn splice(text: &str, part: &str, replacement: &str) -> String {
+    let start = part.as_ptr() as usize - text.as_ptr() as usize;
+    let end = start + part.len();
+    format!("{}{}{}", &text[..start], replacement, &text[end..])
+}
+
+/// Returns the object `object` with `key` set to the JSON text `value`
+///
+/// An existing member is replaced where it stands; a new one is appended.
+fn with_member(object: &str, key: &str, value: &str) -> Option<String> {
+    let members = object_members(object)?;
+    if let Some(existing) = members.get(key) {
+        return Some(splice(object, existing.get(), value));
+    }
+    let head = object[..object.rfind('}')?].trim_end();
+    let separator = if members.is_empty() { "" } else { "," };
+    let key = Value::from(key).to_string();
+    Some(format!(
+        "{}{}{}:{}{}",
+        head,
+        separator,
+        key,
+        value,
+        &object[head.len()..]
+    ))
+}
+
+/// Returns the AS object `object` with `number` set, and `organization.name`
+/// when the holder is known, keeping its other fields
+///
+/// Returns None if `object` or its `organization` is something other than an object.
+fn with_as_fields(object: &str, info: &AsnInfo) -> Option<String> {
+    let members = object_members(object)?;
+    let organization = members
+        .get("organization")
+        .map(|organization| organization.get());
+    if organization.is_some_and(|organization| object_members(organization).is_none()) {
+        return None;
+    }
+    let number = match info.asn_number() {
+        Some(asn) => Value::from(asn),
+        None => Value::from(info.asn.clone()),
+    };
+    let mut object = with_member(object, "number", &number.to_string())?;
+    if let Some(holder) = info.known_holder() {
+        let name = Value::from(holder).to_string();
+        let organization = with_member(organization.unwrap_or("{}"), "name", &name)?;
+        object = with_member(&object, "organization", &organization)?;
+    }
+    Some(object)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct MockAsn;
+
+    impl Asn for MockAsn {
+        fn lookup_asn(&self, ip: IpAddr) -> Result<Vec<AsnInfo>, Error> {
+            match ip.to_string().as_str() {
+                "8.8.8.8" => Ok(vec![AsnInfo::new("15169", "Google LLC")]),
+                "2001:db8::1" => Ok(vec![AsnInfo::new("AS64496", "Unknown")]),
+                "192.0.2.2" => Err(Error::new(ErrorKind::Other, "unreachable")),
+                _ => Ok(Vec::new()),
+            }
+        }
+    }
+
+    fn fields(paths: &[&str]) -> Vec<FieldPath> {
+        paths.iter().map(|path| path.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_field_path_parsing() {
+        let dotted: FieldPath = "source.ip".parse().unwrap();
+        let pointer: FieldPath = "/source/ip".parse().unwrap();
+        assert_eq!(dotted, pointer);
+        assert_eq!(dotted.to_string(), "/source/ip");
+
+        let escaped: FieldPath = "/a~1b/c~0d".parse().unwrap();
+        assert_eq!(escaped.segments, vec!["a/b", "c~d"]);
+        assert_eq!(escaped.to_string(), "/a~1b/c~0d");
+
+        assert!("".parse::<FieldPath>().is_err());
+        assert!("source..ip".parse::<FieldPath>().is_err());
+    }
+
+    #[test]
+    fn test_as_key() {
+        assert_eq!(fields(&["source.ip"])[0].as_key(), "as");
+        assert_eq!(fields(&["src_ip"])[0].as_key(), "src_as");
+        assert_eq!(fields(&["/source.ip"])[0].as_key(), "source.as");
+        assert_eq!(fields(&["/id.orig_h"])[0].as_key(), "id.orig_h_as");
+        assert_eq!(
+            fields(&["sourceIPAddress"])[0].as_key(),
+            "sourceIPAddress_as"
+        );
+    }
+
+    #[test]
+    fn test_ecs_enrichment() {
+        let enricher = JsonEnricher::new(&MockAsn, fields(&["source.ip", "/destination/ip"]));
+        let lines = vec![
+            r#"{"@timestamp":"2024-01-01T00:00:00Z","source":{"ip":"8.8.8.8","port":53},"destination":{"ip":"2001:db8::1","as":{"number":1,"extra":true}}}"#.to_string(),
+        ];
+        assert_eq!(
+            enricher.enrich(&lines),
+            vec![
+                r#"{"@timestamp":"2024-01-01T00:00:00Z","source":{"ip":"8.8.8.8","port":53,"as":{"number":15169,"organization":{"name":"Google LLC"}}},"destination":{"ip":"2001:db8::1","as":{"number":64496,"extra":true}}}"#
+            ]
+        );
+    }
+
+    #[test]
+    fn test_flat_keys_and_arrays() {
+        let enricher = JsonEnricher::new(&MockAsn, fields(&["/id.orig_h", "hops.1.addr"]));
+        let lines = vec![
+            r#"{"id.orig_h":"8.8.8.8","hops":[{"addr":"10.0.0.1"},{"addr":"8.8.8.8"}]}"#
+                .to_string(),
+        ];
+        assert_eq!(
+            enricher.enrich(&lines),
+            vec![
+                r#"{"id.orig_h":"8.8.8.8","hops":[{"addr":"10.0.0.1"},{"addr":"8.8.8.8","addr_as":{"number":15169,"organization":{"name":"Google LLC"}}}],"id.orig_h_as":{"number":15169,"organization":{"name":"Google LLC"}}}"#
+            ]
+        );
+    }
+
+    #[test]
+    fn test_other_fields_keep_their_numbers() {
+        let enricher = JsonEnricher::new(&MockAsn, fields(&["source.ip"]));
+        let lines = vec![
+            r#"{"source":{"ip":"8.8.8.8"},"price":1.50,"id":123456789012345678901234567890,"small":-0.0}"#.to_string(),
+        ];
+        assert_eq!(
+            enricher.enrich(&lines),
+            vec![
+                r#"{"source":{"ip":"8.8.8.8","as":{"number":15169,"organization":{"name":"Google LLC"}}},"price":1.50,"id":123456789012345678901234567890,"small":-0.0}"#
+            ]
+        );
+    }
+
+    #[test]
+    fn test_formatting_is_kept() {
+        let enricher = JsonEnricher::new(&MockAsn, fields(&["source.ip", "dst_ip"]));
+        let lines = vec![
+            r#"{ "source": { "ip": "8.8.8.8" }, "dst_ip": "8.8.8.8", "dst_as": { "org": 1e3 } }"#
+                .to_string(),
+        ];
+        assert_eq!(
+            enricher.enrich(&lines),
+            vec![
+                r#"{ "source": { "ip": "8.8.8.8","as":{"number":15169,"organization":{"name":"Google LLC"}} }, "dst_ip": "8.8.8.8", "dst_as": { "org": 1e3,"number":15169,"organization":{"name":"Google LLC"} } }"#
+            ]
+        );
+    }
+
+    #[test]
+    fn test_existing_values_are_not_overwritten() {
+        let enricher = JsonEnricher::new(&MockAsn, fields(&["source.ip", "src_ip", "dst_ip"]));
+        let lines: Vec<String> = [
+            r#"{"source":{"ip":"8.8.8.8","as":"unknown"},"src_ip":"8.8.8.8","src_as":15169}"#,
+            r#"{"dst_ip":"8.8.8.8","dst_as":{"organization":"Google"}}"#,
+        ]
+        .iter()
+        .map(|line| line.to_string())
+        .collect();
+        assert_eq!(enricher.enrich(&lines), lines);
+    }
+
+    #[test]
+    fn test_untouched_lines() {
+        let enricher = JsonEnricher::new(&MockAsn, fields(&["source.ip"]));
+        let lines: Vec<String> = [
+            "not json",
+            "[1, 2]",
+            r#"{ "source": { "ip": "10.0.0.1" }, "n": 1.50 }"#,
+            r#"{ "source": { "ip": "192.0.2.2" } }"#,
+            r#"{ "source": { "ip": 3 } }"#,
+            "",
+        ]
+        .iter()
+        .map(|line| line.to_string())
+        .collect();
+        assert_eq!(enricher.enrich(&lines), lines);
+    }
+}
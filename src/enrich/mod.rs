@@ -0,0 +1,225 @@
+// Annotating logs and other data with the ASNs of the addresses they mention
+
+pub mod csv;
+pub mod extract;
+pub mod json;
+pub mod text;
+
+pub use csv::{CsvEnricher, Header};
+pub use extract::{find_ips, IpMatch};
+pub use json::{FieldPath, JsonEnricher};
+pub use text::{Placement, TextEnricher};
+
+use crate::asn::{Asn, AsnInfo};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Error, Write};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+
+/// Number of lookups run concurrently by default
+pub const DEFAULT_WORKERS: usize = 8;
+
+/// Maximum number of lines whose addresses are looked up together
+pub const BATCH_SIZE: usize = 1000;
+
+/// Looks up each distinct address once, spreading the lookups over `workers` threads
+pub fn lookup_all(
+    lookup: &dyn Asn,
+    ips: &[IpAddr],
+    workers: usize,
+) -> HashMap<IpAddr, Result<Vec<AsnInfo>, Error>> {
+    let mut pending = ips.to_vec();
+    pending.sort();
+    pending.dedup();
+
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(HashMap::with_capacity(pending.len()));
+    thread::scope(|scope| {
+        for _ in 0..workers.clamp(1, pending.len().max(1)) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(&ip) = pending.get(i) else {
+                    break;
+                };
+                let result = lookup.lookup_asn(ip);
+                results.lock().unwrap().insert(ip, result);
+            });
+        }
+    });
+    results.into_inner().unwrap()
+}
+
+/// Looks up each distinct address once and keeps its first result
+///
+/// Failed lookups are logged as warnings and, like addresses without a
+/// result, are missing from the returned map.
+pub fn lookup_origins(
+    lookup: &dyn Asn,
+    ips: &[IpAddr],
+    workers: usize,
+) -> HashMap<IpAddr, AsnInfo> {
+    lookup_all(lookup, ips, workers)
+        .into_iter()
+        .filter_map(|(ip, result)| match result {
+            Ok(results) => results.into_iter().next().map(|info| (ip, info)),
+            Err(e) => {
+                tracing::warn!(%ip, error = %e, "lookup failed");
+                None
+            }
+        })
+        .collect()
+}
+
+/// Formats results as "AS15169 Google LLC", joining several origins with ", "
+///
+/// Returns None when there are no results.
+pub fn label(results: &[AsnInfo]) -> Option<String> {
+    let mut labels: Vec<String> = Vec::new();
+    for info in results {
+        let asn = match info.asn_number() {
+            Some(asn) => format!("AS{}", asn),
+            None => info.asn.clone(),
+        };
+        let label = match info.known_holder() {
+            Some(holder) => format!("{} {}", asn, holder),
+            None => asn,
+        };
+        if !labels.contains(&label) {
+            labels.push(label);
+        }
+    }
+    (!labels.is_empty()).then(|| labels.join(", "))
+}
+
+/// Feeds the lines of `input` through `enrich` in batches, writing the results to `output`
+///
+/// Lines are read on a separate thread and handed over in batches of
+/// whatever has arrived so far, so piped files are looked up in bulk while
+/// followed logs (`tail -f`) are annotated as each line arrives. Line endings
+/// are stripped and invalid UTF-8 is replaced rather than rejected.
+pub fn run_batched<R, W, F>(input: R, mut output: W, mut enrich: F) -> io::Result<()>
+where
+    R: BufRead + Send + 'static,
+    W: Write,
+    F: FnMut(&[String]) -> Vec<String>,
+{
+    let (sender, receiver) = mpsc::sync_channel(BATCH_SIZE);
+    thread::spawn(move || read_lines(input, sender));
+
+    while let Ok(first) = receiver.recv() {
+        let mut batch = vec![first?];
+        while batch.len() < BATCH_SIZE {
+            match receiver.try_recv() {
+                Ok(line) => batch.push(line?),
+                Err(_) => break,
+            }
+        }
+        for line in enrich(&batch) {
+            writeln!(output, "{}", line)?;
+        }
+        output.flush()?;
+    }
+    Ok(())
+}
+
+/// Sends each line of `input` until EOF, a read error or the receiver hangs up
+fn read_lines<R: BufRead>(mut input: R, sender: mpsc::SyncSender<io::Result<String>>) {
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        let line = match input.read_until(b'\n', &mut buf) {
+            Ok(0) => return,
+            Ok(_) => {
+                let line = buf.strip_suffix(b"\n").unwrap_or(&buf);
+                let line = line.strip_suffix(b"\r").unwrap_or(line);
+                Ok(String::from_utf8_lossy(line).into_owned())
+            }
+            Err(e) => Err(e),
+        };
+        let failed = line.is_err();
+        if sender.send(line).is_err() || failed {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::ErrorKind;
+
+    struct MockAsn;
+
+    impl Asn for MockAsn {
+        fn lookup_asn(&self, ip: IpAddr) -> Result<Vec<AsnInfo>, Error> {
+            match ip.to_string().as_str() {
+                "192.0.2.1" => Ok(vec![AsnInfo::new("64496", "Example Net")]),
+                "192.0.2.2" => Err(Error::new(ErrorKind::Other, "unreachable")),
+                _ => Ok(Vec::new()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_lookup_all() {
+        let ips: Vec<IpAddr> = ["192.0.2.1", "192.0.2.2", "192.0.2.1", "198.51.100.1"]
+            .iter()
+            .map(|ip| ip.parse().unwrap())
+            .collect();
+        let results = lookup_all(&MockAsn, &ips, 4);
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[&ips[0]].as_ref().unwrap()[0].holder, "Example Net");
+        assert!(results[&ips[1]].is_err());
+        assert!(results[&ips[3]].as_ref().unwrap().is_empty());
+        assert!(lookup_all(&MockAsn, &[], 4).is_empty());
+    }
+
+    #[test]
+    fn test_lookup_origins() {
+        let ips: Vec<IpAddr> = ["192.0.2.1", "192.0.2.2", "198.51.100.1"]
+            .iter()
+            .map(|ip| ip.parse().unwrap())
+            .collect();
+        let origins = lookup_origins(&MockAsn, &ips, 2);
+        assert_eq!(origins.len(), 1);
+        assert_eq!(origins[&ips[0]].asn, "64496");
+    }
+
+    #[test]
+    fn test_run_batched() {
+        let input = std::io::Cursor::new(b"one\r\ntwo \xff\nthree".to_vec());
+        let mut output = Vec::new();
+        let mut batches = 0;
+        run_batched(input, &mut output, |lines| {
+            batches += 1;
+            lines.iter().map(|line| line.to_uppercase()).collect()
+        })
+        .unwrap();
+        assert!(batches >= 1);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "ONE\nTWO \u{fffd}\nTHREE\n"
+        );
+    }
+
+    #[test]
+    fn test_label() {
+        assert_eq!(label(&[]), None);
+        assert_eq!(
+            label(&[AsnInfo::new("AS15169", "Google LLC")]).as_deref(),
+            Some("AS15169 Google LLC")
+        );
+        let moas = [
+            AsnInfo::new("64496", "Example Net"),
+            AsnInfo::new("64496", "Example Net"),
+            AsnInfo::new("64497", ""),
+            AsnInfo::new("64498", "Unknown"),
+        ];
+        assert_eq!(
+            label(&moas).as_deref(),
+            Some("AS64496 Example Net, AS64497, AS64498")
+        );
+    }
+}
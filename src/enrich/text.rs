@@ -0,0 +1,208 @@
+// Enriching free-form log lines
+
+use super::{find_ips, label, lookup_all, run_batched, DEFAULT_WORKERS};
+use crate::asn::Asn;
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::net::IpAddr;
+
+/// Where annotations are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Placement {
+    /// After the end of the line, as " [8.8.8.8 AS15169 Google LLC]"
+    #[default]
+    Append,
+    /// Right after each address (and its port), as "8.8.8.8 [AS15169 Google LLC]"
+    Inline,
+}
+
+/// Annotates the addresses in lines of text with their origin ASNs
+///
+/// Addresses are looked up a batch of lines at a time; wrap the lookup in a
+/// `LookupCache` to avoid repeating lookups across batches.
+pub struct TextEnricher<'a> {
+    lookup: &'a dyn Asn,
+    placement: Placement,
+    workers: usize,
+}
+
+impl<'a> TextEnricher<'a> {
+    /// Creates an enricher that appends annotations to the end of each line
+    pub fn new(lookup: &'a dyn Asn) -> Self {
+        TextEnricher {
+            lookup,
+            placement: Placement::Append,
+            workers: DEFAULT_WORKERS,
+        }
+    }
+
+    /// Sets where annotations are written
+    pub fn placement(mut self, placement: Placement) -> Self {
+        self.placement = placement;
+        self
+    }
+
+    /// Sets how many lookups run concurrently
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers;
+        self
+    }
+
+    /// Annotates a batch of lines, looking up every address in them together
+    ///
+    /// Failed lookups are logged as warnings and leave the address unannotated.
+    pub fn enrich(&self, lines: &[String]) -> Vec<String> {
+        let matches: Vec<_> = lines.iter().map(|line| find_ips(line)).collect();
+        let ips: Vec<IpAddr> = matches.iter().flatten().map(|m| m.ip).collect();
+        let labels: HashMap<IpAddr, String> = lookup_all(self.lookup, &ips, self.workers)
+            .into_iter()
+            .filter_map(|(ip, result)| match result {
+                Ok(results) => label(&results).map(|label| (ip, label)),
+                Err(e) => {
+                    tracing::warn!(%ip, error = %e, "lookup failed");
+                    None
+                }
+            })
+            .collect();
+
+        lines
+            .iter()
+            .zip(&matches)
+            .map(|(line, matches)| {
+                let mut enriched = String::with_capacity(line.len() + 32);
+                match self.placement {
+                    Placement::Append => {
+                        enriched.push_str(line);
+                        let mut seen = Vec::new();
+                        for m in matches {
+                            if seen.contains(&m.ip) {
+                                continue;
+                            }
+                            seen.push(m.ip);
+                            if let Some(label) = labels.get(&m.ip) {
+                                enriched.push_str(&format!(" [{} {}]", m.ip, label));
+                            }
+                        }
+                    }
+                    Placement::Inline => {
+                        let mut copied = 0;
+                        for m in matches {
+                            if let Some(label) = labels.get(&m.ip) {
+                                let end = end_of_endpoint(line, m.span.end);
+                                enriched.push_str(&line[copied..end]);
+                                enriched.push_str(&format!(" [{}]", label));
+                                copied = end;
+                            }
+                        }
+                        enriched.push_str(&line[copied..]);
+                    }
+                }
+                enriched
+            })
+            .collect()
+    }
+
+    /// Annotates every line of `input`, writing the results to `output`
+    ///
+    /// Lines are looked up in batches as described for `run_batched`.
+    pub fn run<R, W>(&self, input: R, output: W) -> io::Result<()>
+    where
+        R: BufRead + Send + 'static,
+        W: Write,
+    {
+        run_batched(input, output, |lines| self.enrich(lines))
+    }
+}
+
+/// Extends the end of an address past a closing bracket and a ":port" suffix
+fn end_of_endpoint(line: &str, mut end: usize) -> usize {
+    let bytes = line.as_bytes();
+    if bytes.get(end) == Some(&b']') {
+        end += 1;
+    }
+    if bytes.get(end) == Some(&b':') {
+        let digits = bytes[end + 1..]
+            .iter()
+            .take_while(|b| b.is_ascii_digit())
+            .count();
+        if digits > 0 {
+            end += 1 + digits;
+        }
+    }
+    end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asn::AsnInfo;
+    use std::io::{Cursor, Error, ErrorKind};
+
+    struct MockAsn;
+
+    impl Asn for MockAsn {
+        fn lookup_asn(&self, ip: IpAddr) -> Result<Vec<AsnInfo>, Error> {
+            match ip.to_string().as_str() {
+                "8.8.8.8" => Ok(vec![AsnInfo::new("15169", "Google LLC")]),
+                "2001:db8::1" => Ok(vec![AsnInfo::new("AS64496", "Example Net")]),
+                "192.0.2.2" => Err(Error::new(ErrorKind::Other, "unreachable")),
+                _ => Ok(Vec::new()),
+            }
+        }
+    }
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn test_append() {
+        let enricher = TextEnricher::new(&MockAsn);
+        let enriched = enricher.enrich(&lines(&[
+            "DROP SRC=8.8.8.8 DST=10.0.0.1 (retry from 8.8.8.8)",
+            "conn from [2001:db8::1]:443 and 192.0.2.2",
+            "no addresses here",
+        ]));
+        assert_eq!(
+            enriched,
+            vec![
+                "DROP SRC=8.8.8.8 DST=10.0.0.1 (retry from 8.8.8.8) [8.8.8.8 AS15169 Google LLC]",
+                "conn from [2001:db8::1]:443 and 192.0.2.2 [2001:db8::1 AS64496 Example Net]",
+                "no addresses here",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_inline() {
+        let enricher = TextEnricher::new(&MockAsn).placement(Placement::Inline);
+        let enriched = enricher.enrich(&lines(&[
+            "8.8.8.8:53 -> [2001:db8::1]:443, 8.8.8.8.",
+            "10.0.0.1 unknown",
+        ]));
+        assert_eq!(
+            enriched,
+            vec![
+                "8.8.8.8:53 [AS15169 Google LLC] -> [2001:db8::1]:443 [AS64496 Example Net], \
+                 8.8.8.8 [AS15169 Google LLC].",
+                "10.0.0.1 unknown",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_run() {
+        let input = Cursor::new(b"a 8.8.8.8\r\nb \xff 10.0.0.1\nc 8.8.8.8".to_vec());
+        let mut output = Vec::new();
+        TextEnricher::new(&MockAsn)
+            .workers(1)
+            .run(input, &mut output)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "a 8.8.8.8 [8.8.8.8 AS15169 Google LLC]\n\
+             b \u{fffd} 10.0.0.1\n\
+             c 8.8.8.8 [8.8.8.8 AS15169 Google LLC]\n"
+        );
+    }
+}
@@ -0,0 +1,317 @@
+// Prefix filter generation for router configurations
+
+pub mod render;
+
+pub use render::Target;
+
+use crate::network::Prefix;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+/// A prefix-list entry matching every prefix within `prefix` whose length is in `ge..=le`
+///
+/// An exact entry has `ge == le == prefix.prefix_len()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PrefixEntry {
+    pub prefix: Prefix,
+    pub ge: u8,
+    pub le: u8,
+}
+
+impl PrefixEntry {
+    /// An entry matching only the prefix itself
+    pub fn exact(prefix: Prefix) -> Self {
+        PrefixEntry {
+            prefix,
+            ge: prefix.prefix_len(),
+            le: prefix.prefix_len(),
+        }
+    }
+
+    /// Returns true if the entry matches only its own prefix
+    pub fn is_exact(&self) -> bool {
+        self.ge == self.prefix.prefix_len() && self.le == self.prefix.prefix_len()
+    }
+
+    /// Returns true if every prefix matched by this entry is also matched by `other`
+    fn is_covered_by(&self, other: &PrefixEntry) -> bool {
+        other.ge <= self.ge
+            && self.le <= other.le
+            && other.prefix.prefix_len() <= self.prefix.prefix_len()
+            && other.prefix.contains(self.prefix.addr())
+    }
+}
+
+/// A named prefix filter that can be rendered for different router platforms
+///
+/// Entries always match exactly the prefixes they were built from (plus any
+/// more-specifics explicitly allowed); aggregation only changes how that set
+/// is written down.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrefixFilter {
+    name: String,
+    entries: Vec<PrefixEntry>,
+}
+
+impl PrefixFilter {
+    /// Creates a filter with one exact entry per distinct prefix
+    pub fn new(name: impl Into<String>, prefixes: impl IntoIterator<Item = Prefix>) -> Self {
+        let entries: BTreeSet<PrefixEntry> = prefixes.into_iter().map(PrefixEntry::exact).collect();
+        PrefixFilter {
+            name: name.into(),
+            entries: entries.into_iter().collect(),
+        }
+    }
+
+    /// Also accepts more-specifics of each prefix down to `max_len` bits (like `le`)
+    ///
+    /// The limit is capped at the address family's width and never shortens an entry.
+    pub fn allow_more_specifics(mut self, max_len: u8) -> Self {
+        for entry in &mut self.entries {
+            entry.le = entry.le.max(max_len.min(entry.prefix.max_len()));
+        }
+        self.entries = prune(self.entries);
+        self
+    }
+
+    /// Rewrites the entries into the fewest equivalent entries
+    ///
+    /// Sibling entries with the same length range are merged into their supernet
+    /// (two /24s become a /23 with `ge 24 le 24`), contiguous length ranges on the
+    /// same prefix are joined, and entries covered by another entry are dropped.
+    pub fn aggregate(mut self) -> Self {
+        let mut entries: HashSet<PrefixEntry> = self.entries.drain(..).collect();
+        loop {
+            let before = entries.len();
+            entries = merge_siblings(entries);
+            entries = merge_ranges(entries);
+            entries = prune(entries.into_iter().collect()).into_iter().collect();
+            if entries.len() == before {
+                break;
+            }
+        }
+        let mut entries: Vec<PrefixEntry> = entries.into_iter().collect();
+        entries.sort();
+        self.entries = entries;
+        self
+    }
+
+    /// The name used for the generated prefix-list
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The entries in address order, IPv4 before IPv6
+    pub fn entries(&self) -> &[PrefixEntry] {
+        &self.entries
+    }
+
+    /// Renders the filter as configuration for a router platform
+    pub fn render(&self, target: Target) -> String {
+        render::render(target, &self.name, &self.entries)
+    }
+}
+
+/// Replaces pairs of sibling entries that share a length range with their supernet
+fn merge_siblings(mut entries: HashSet<PrefixEntry>) -> HashSet<PrefixEntry> {
+    let mut by_length: Vec<PrefixEntry> = entries.iter().copied().collect();
+    // Most specific first, so merged supernets can merge again within this pass
+    by_length.sort_by_key(|entry| std::cmp::Reverse(entry.prefix.prefix_len()));
+    let mut queue = std::collections::VecDeque::from(by_length);
+    while let Some(entry) = queue.pop_front() {
+        if !entries.contains(&entry) {
+            continue;
+        }
+        let (Some(sibling), Some(supernet)) = (entry.prefix.sibling(), entry.prefix.supernet())
+        else {
+            continue;
+        };
+        let sibling = PrefixEntry {
+            prefix: sibling,
+            ..entry
+        };
+        if entries.remove(&sibling) {
+            entries.remove(&entry);
+            let merged = PrefixEntry {
+                prefix: supernet,
+                ..entry
+            };
+            entries.insert(merged);
+            queue.push_front(merged);
+        }
+    }
+    entries
+}
+
+/// Joins overlapping or adjacent length ranges on the same prefix
+fn merge_ranges(entries: HashSet<PrefixEntry>) -> HashSet<PrefixEntry> {
+    let mut ranges: BTreeMap<Prefix, Vec<(u8, u8)>> = BTreeMap::new();
+    for entry in entries {
+        ranges
+            .entry(entry.prefix)
+            .or_default()
+            .push((entry.ge, entry.le));
+    }
+    let mut merged = HashSet::new();
+    for (prefix, mut ranges) in ranges {
+        ranges.sort();
+        let mut current = ranges[0];
+        for &(ge, le) in &ranges[1..] {
+            if ge <= current.1 + 1 {
+                current.1 = current.1.max(le);
+            } else {
+                merged.insert(PrefixEntry {
+                    prefix,
+                    ge: current.0,
+                    le: current.1,
+                });
+                current = (ge, le);
+            }
+        }
+        merged.insert(PrefixEntry {
+            prefix,
+            ge: current.0,
+            le: current.1,
+        });
+    }
+    merged
+}
+
+/// Drops entries whose matches are all matched by another entry, keeping address order
+fn prune(mut entries: Vec<PrefixEntry>) -> Vec<PrefixEntry> {
+    entries.sort();
+    entries.dedup();
+    let by_prefix: BTreeMap<Prefix, Vec<PrefixEntry>> =
+        entries.iter().fold(BTreeMap::new(), |mut map, entry| {
+            map.entry(entry.prefix).or_default().push(*entry);
+            map
+        });
+    entries
+        .into_iter()
+        .filter(|entry| {
+            let mut prefix = Some(entry.prefix);
+            while let Some(candidate) = prefix {
+                let covered = by_prefix.get(&candidate).is_some_and(|others| {
+                    others
+                        .iter()
+                        .any(|other| other != entry && entry.is_covered_by(other))
+                });
+                if covered {
+                    return false;
+                }
+                prefix = candidate.supernet();
+            }
+            true
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prefixes(list: &[&str]) -> Vec<Prefix> {
+        list.iter().map(|p| p.parse().unwrap()).collect()
+    }
+
+    fn entries(filter: &PrefixFilter) -> Vec<(String, u8, u8)> {
+        filter
+            .entries()
+            .iter()
+            .map(|e| (e.prefix.to_string(), e.ge, e.le))
+            .collect()
+    }
+
+    /// Every prefix an entry list matches, for checking aggregation is lossless
+    fn expand(entries: &[PrefixEntry]) -> BTreeSet<Prefix> {
+        let mut matched = BTreeSet::new();
+        for entry in entries {
+            for len in entry.ge..=entry.le {
+                let count = 1u128 << (len - entry.prefix.prefix_len());
+                for i in 0..count {
+                    let offset = i << (entry.prefix.max_len() - len);
+                    let addr = crate::network::ip::u128_to_ip(
+                        entry.prefix.first() + offset,
+                        entry.prefix.is_ipv4(),
+                    );
+                    matched.insert(Prefix::new(addr, len).unwrap());
+                }
+            }
+        }
+        matched
+    }
+
+    #[test]
+    fn test_exact_entries_are_deduplicated_and_sorted() {
+        let filter = PrefixFilter::new(
+            "AS-TEST",
+            prefixes(&["2001:db8::/32", "192.0.2.0/24", "192.0.2.0/24"]),
+        );
+        assert_eq!(
+            entries(&filter),
+            vec![
+                ("192.0.2.0/24".to_string(), 24, 24),
+                ("2001:db8::/32".to_string(), 32, 32)
+            ]
+        );
+        assert!(filter.entries().iter().all(PrefixEntry::is_exact));
+    }
+
+    #[test]
+    fn test_aggregate_siblings_and_ranges() {
+        let input = prefixes(&[
+            "10.0.0.0/24",
+            "10.0.1.0/24",
+            "10.0.2.0/24",
+            "10.0.3.0/24",
+            "10.0.0.0/23",
+            "10.0.2.0/23",
+            "10.0.0.0/22",
+            "192.0.2.0/25",
+            "192.0.2.128/25",
+        ]);
+        let filter = PrefixFilter::new("X", input.clone()).aggregate();
+        assert_eq!(
+            entries(&filter),
+            vec![
+                ("10.0.0.0/22".to_string(), 22, 24),
+                ("192.0.2.0/24".to_string(), 25, 25)
+            ]
+        );
+        assert_eq!(
+            expand(filter.entries()),
+            input.into_iter().collect::<BTreeSet<_>>()
+        );
+    }
+
+    #[test]
+    fn test_aggregate_keeps_gaps() {
+        let input = prefixes(&["10.0.0.0/22", "10.0.0.0/24", "10.0.1.0/24", "10.0.2.0/24"]);
+        let filter = PrefixFilter::new("X", input.clone()).aggregate();
+        assert_eq!(
+            expand(filter.entries()),
+            input.into_iter().collect::<BTreeSet<_>>()
+        );
+        assert_eq!(filter.entries().len(), 3);
+    }
+
+    #[test]
+    fn test_more_specifics() {
+        let filter = PrefixFilter::new(
+            "X",
+            prefixes(&["10.0.0.0/22", "10.0.1.0/24", "2001:db8::/32"]),
+        )
+        .allow_more_specifics(24);
+        assert_eq!(
+            entries(&filter),
+            vec![
+                ("10.0.0.0/22".to_string(), 22, 24),
+                ("2001:db8::/32".to_string(), 32, 32)
+            ]
+        );
+
+        let filter = PrefixFilter::new("X", prefixes(&["10.0.0.0/24", "10.0.1.0/24"]))
+            .allow_more_specifics(25);
+        let filter = filter.aggregate();
+        assert_eq!(entries(&filter), vec![("10.0.0.0/23".to_string(), 24, 25)]);
+    }
+}
@@ -0,0 +1,253 @@
+// Router configuration syntax for prefix filters
+
+use super::PrefixEntry;
+use std::fmt::Write;
+
+/// Router platforms prefix filters can be rendered for
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// Juniper Junos route-filter-list
+    Junos,
+    /// Cisco IOS XR prefix-set
+    Iosxr,
+    /// BIRD 2 prefix set constants
+    Bird,
+    /// FRRouting ip/ipv6 prefix-lists
+    Frr,
+    /// OpenBGPD prefix-set
+    Openbgpd,
+}
+
+/// Splits entries into IPv4 and IPv6 lists
+fn families(entries: &[PrefixEntry]) -> [(&'static str, Vec<&PrefixEntry>); 2] {
+    let (v4, v6) = entries.iter().partition(|entry| entry.prefix.is_ipv4());
+    [("4", v4), ("6", v6)]
+}
+
+/// Replaces characters that are not valid in identifiers with underscores
+fn identifier(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+pub(crate) fn render(target: Target, name: &str, entries: &[PrefixEntry]) -> String {
+    let mut out = String::new();
+    // Writing to a String cannot fail
+    let _ = match target {
+        Target::Junos => junos(&mut out, name, entries),
+        Target::Iosxr => iosxr(&mut out, name, entries),
+        Target::Bird => bird(&mut out, name, entries),
+        Target::Frr => frr(&mut out, name, entries),
+        Target::Openbgpd => openbgpd(&mut out, name, entries),
+    };
+    out
+}
+
+fn junos(out: &mut String, name: &str, entries: &[PrefixEntry]) -> std::fmt::Result {
+    writeln!(out, "policy-options {{")?;
+    writeln!(out, "replace:")?;
+    writeln!(out, " route-filter-list {} {{", name)?;
+    for entry in entries {
+        let len = entry.prefix.prefix_len();
+        let range = if entry.is_exact() {
+            "exact".to_string()
+        } else if entry.ge == len {
+            format!("upto /{}", entry.le)
+        } else {
+            format!("prefix-length-range /{}-/{}", entry.ge, entry.le)
+        };
+        writeln!(out, "    {} {};", entry.prefix, range)?;
+    }
+    writeln!(out, " }}")?;
+    writeln!(out, "}}")
+}
+
+/// The ge/le suffix shared by the Cisco-style syntaxes, or "" for exact entries
+fn cisco_range(entry: &PrefixEntry) -> String {
+    let len = entry.prefix.prefix_len();
+    if entry.is_exact() {
+        String::new()
+    } else if entry.ge == len {
+        format!(" le {}", entry.le)
+    } else {
+        format!(" ge {} le {}", entry.ge, entry.le)
+    }
+}
+
+fn iosxr(out: &mut String, name: &str, entries: &[PrefixEntry]) -> std::fmt::Result {
+    writeln!(out, "prefix-set {}", name)?;
+    for (i, entry) in entries.iter().enumerate() {
+        let separator = if i + 1 < entries.len() { "," } else { "" };
+        writeln!(out, " {}{}{}", entry.prefix, cisco_range(entry), separator)?;
+    }
+    writeln!(out, "end-set")
+}
+
+fn frr(out: &mut String, name: &str, entries: &[PrefixEntry]) -> std::fmt::Result {
+    if entries.is_empty() {
+        writeln!(out, "! prefix-list {} is empty", name)?;
+        writeln!(out, "no ip prefix-list {}", name)?;
+        return writeln!(out, "ip prefix-list {} deny any", name);
+    }
+    for (family, entries) in families(entries) {
+        if entries.is_empty() {
+            continue;
+        }
+        let command = if family == "4" { "ip" } else { "ipv6" };
+        writeln!(out, "no {} prefix-list {}", command, name)?;
+        for entry in entries {
+            writeln!(
+                out,
+                "{} prefix-list {} permit {}{}",
+                command,
+                name,
+                entry.prefix,
+                cisco_range(entry)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn bird(out: &mut String, name: &str, entries: &[PrefixEntry]) -> std::fmt::Result {
+    let name = identifier(name);
+    if entries.is_empty() {
+        return writeln!(out, "# prefix set {} is empty", name);
+    }
+    for (family, entries) in families(entries) {
+        if entries.is_empty() {
+            continue;
+        }
+        writeln!(out, "define {}_V{} = [", name, family)?;
+        for (i, entry) in entries.iter().enumerate() {
+            let range = if entry.is_exact() {
+                String::new()
+            } else {
+                format!("{{{},{}}}", entry.ge, entry.le)
+            };
+            let separator = if i + 1 < entries.len() { "," } else { "" };
+            writeln!(out, "    {}{}{}", entry.prefix, range, separator)?;
+        }
+        writeln!(out, "];")?;
+    }
+    Ok(())
+}
+
+fn openbgpd(out: &mut String, name: &str, entries: &[PrefixEntry]) -> std::fmt::Result {
+    writeln!(out, "prefix-set \"{}\" {{", name)?;
+    for entry in entries {
+        let range = if entry.is_exact() {
+            String::new()
+        } else if entry.ge == entry.le {
+            format!(" prefixlen = {}", entry.ge)
+        } else {
+            format!(" prefixlen {} - {}", entry.ge, entry.le)
+        };
+        writeln!(out, "\t{}{}", entry.prefix, range)?;
+    }
+    writeln!(out, "}}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::PrefixFilter;
+    use super::*;
+
+    fn filter() -> PrefixFilter {
+        let prefixes = [
+            "192.0.2.0/24",
+            "198.51.100.0/24",
+            "198.51.101.0/24",
+            "2001:db8::/32",
+        ]
+        .iter()
+        .map(|p| p.parse().unwrap());
+        let mut filter = PrefixFilter::new("AS-EXAMPLE", prefixes).aggregate();
+        // Add an "upto" style entry alongside the aggregated one
+        filter.entries.push(PrefixEntry {
+            prefix: "203.0.113.0/24".parse().unwrap(),
+            ge: 24,
+            le: 26,
+        });
+        filter.entries.sort();
+        filter
+    }
+
+    #[test]
+    fn test_junos() {
+        assert_eq!(
+            filter().render(Target::Junos),
+            "policy-options {
+replace:
+ route-filter-list AS-EXAMPLE {
+    192.0.2.0/24 exact;
+    198.51.100.0/23 prefix-length-range /24-/24;
+    203.0.113.0/24 upto /26;
+    2001:db8::/32 exact;
+ }
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_iosxr() {
+        assert_eq!(
+            filter().render(Target::Iosxr),
+            "prefix-set AS-EXAMPLE
+ 192.0.2.0/24,
+ 198.51.100.0/23 ge 24 le 24,
+ 203.0.113.0/24 le 26,
+ 2001:db8::/32
+end-set
+"
+        );
+    }
+
+    #[test]
+    fn test_frr() {
+        assert_eq!(
+            filter().render(Target::Frr),
+            "no ip prefix-list AS-EXAMPLE
+ip prefix-list AS-EXAMPLE permit 192.0.2.0/24
+ip prefix-list AS-EXAMPLE permit 198.51.100.0/23 ge 24 le 24
+ip prefix-list AS-EXAMPLE permit 203.0.113.0/24 le 26
+no ipv6 prefix-list AS-EXAMPLE
+ipv6 prefix-list AS-EXAMPLE permit 2001:db8::/32
+"
+        );
+        let empty = PrefixFilter::new("EMPTY", Vec::new()).render(Target::Frr);
+        assert!(empty.ends_with("ip prefix-list EMPTY deny any\n"));
+    }
+
+    #[test]
+    fn test_bird() {
+        assert_eq!(
+            filter().render(Target::Bird),
+            "define AS_EXAMPLE_V4 = [
+    192.0.2.0/24,
+    198.51.100.0/23{24,24},
+    203.0.113.0/24{24,26}
+];
+define AS_EXAMPLE_V6 = [
+    2001:db8::/32
+];
+"
+        );
+    }
+
+    #[test]
+    fn test_openbgpd() {
+        assert_eq!(
+            filter().render(Target::Openbgpd),
+            "prefix-set \"AS-EXAMPLE\" {
+\t192.0.2.0/24
+\t198.51.100.0/23 prefixlen = 24
+\t203.0.113.0/24 prefixlen 24 - 26
+\t2001:db8::/32
+}
+"
+        );
+    }
+}
@@ -0,0 +1,236 @@
+// IRR whois client (RADb, RIPE, ARIN and other IRRd-compatible servers)
+
+use super::expand::IrrSource;
+use super::objects::{AsSet, AutNum, Route};
+use super::rpsl::{parse_objects, RpslObject};
+use crate::network::Prefix;
+use std::{
+    io::{Error, ErrorKind, Read, Write},
+    net::{Ipv6Addr, TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+/// Queries an IRR whois server using RIPE-style flags, which IRRd also accepts
+pub struct IrrClient {
+    server: String,
+}
+
+impl IrrClient {
+    pub const RADB: &'static str = "whois.radb.net:43";
+    pub const RIPE: &'static str = "whois.ripe.net:43";
+    pub const ARIN: &'static str = "rr.arin.net:43";
+    const TIMEOUT_SECS: u64 = 10;
+
+    /// Creates a client for a `host:port` or `[v6]:port` server; port 43 is
+    /// assumed when omitted, including for a bare IPv6 address
+    pub fn new(server: impl Into<String>) -> Self {
+        let mut server = server.into();
+        if server.parse::<Ipv6Addr>().is_ok() {
+            server = format!("[{}]", server);
+        }
+        let port = match server.strip_prefix('[') {
+            Some(rest) => rest.split_once("]:").map(|(_, port)| port),
+            None => server
+                .split_once(':')
+                .map(|(_, port)| port)
+                .filter(|port| !port.contains(':')),
+        };
+        if !port.is_some_and(|port| port.parse::<u16>().is_ok()) {
+            server.push_str(":43");
+        }
+        IrrClient { server }
+    }
+
+    /// Creates a client from a well-known registry name (radb, ripe, arin) or a server address
+    pub fn named(name: &str) -> Self {
+        match name.to_ascii_lowercase().as_str() {
+            "radb" => Self::new(Self::RADB),
+            "ripe" => Self::new(Self::RIPE),
+            "arin" => Self::new(Self::ARIN),
+            _ => Self::new(name),
+        }
+    }
+
+    /// The "host:port" this client queries
+    pub fn server(&self) -> &str {
+        &self.server
+    }
+
+    /// Sends a raw whois query and parses the RPSL objects in the reply
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the server cannot be reached or reports an error
+    pub fn query(&self, query: &str) -> Result<Vec<RpslObject>, Error> {
+        let timeout = Duration::from_secs(Self::TIMEOUT_SECS);
+        let addr = self
+            .server
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "IRR server did not resolve"))?;
+        let mut stream = TcpStream::connect_timeout(&addr, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.write_all(format!("{}\r\n", query).as_bytes())?;
+
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply)?;
+        let reply = String::from_utf8_lossy(&reply);
+        Self::check_errors(&reply)?;
+        parse_objects(&reply)
+    }
+
+    /// Turns server error comments into errors; "no entries found" is not an error
+    fn check_errors(reply: &str) -> Result<(), Error> {
+        for line in reply.lines() {
+            let message = line
+                .strip_prefix("%ERROR:")
+                .or_else(|| line.strip_prefix("%% ERROR:"));
+            match message {
+                Some(message) if message.starts_with("101") => {}
+                Some(message) => {
+                    return Err(Error::new(
+                        ErrorKind::Other,
+                        format!("IRR query failed: {}", message.trim()),
+                    ))
+                }
+                None => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the route and route6 objects for the prefix and all less-specific prefixes
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query fails
+    pub fn routes(&self, resource: Prefix) -> Result<Vec<Route>, Error> {
+        let resource = if resource.prefix_len() == resource.max_len() {
+            resource.addr().to_string()
+        } else {
+            resource.to_string()
+        };
+        self.query_as(&format!("-r -T route,route6 -L {}", resource))
+    }
+
+    /// Returns the aut-num object for an AS, if registered
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query fails
+    pub fn aut_num(&self, asn: u32) -> Result<Option<AutNum>, Error> {
+        let objects = self.query(&format!("-r -T aut-num AS{}", asn))?;
+        Ok(objects
+            .iter()
+            .find_map(|object| AutNum::try_from(object).ok()))
+    }
+
+    /// Returns the as-set object with the given name, if registered
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query fails
+    pub fn as_set(&self, name: &str) -> Result<Option<AsSet>, Error> {
+        let objects = self.query(&format!("-r -T as-set {}", name))?;
+        Ok(objects
+            .iter()
+            .find_map(|object| AsSet::try_from(object).ok()))
+    }
+
+    /// Runs a query and keeps the objects that convert to `T`
+    fn query_as<T>(&self, query: &str) -> Result<Vec<T>, Error>
+    where
+        T: for<'a> TryFrom<&'a RpslObject>,
+    {
+        let objects = self.query(query)?;
+        Ok(objects
+            .iter()
+            .filter_map(|object| T::try_from(object).ok())
+            .collect())
+    }
+}
+
+impl IrrSource for IrrClient {
+    fn as_set(&self, name: &str) -> Result<Option<AsSet>, Error> {
+        IrrClient::as_set(self, name)
+    }
+
+    fn members_of(&self, set: &str) -> Result<Vec<AutNum>, Error> {
+        self.query_as(&format!("-r -T aut-num -i member-of {}", set))
+    }
+
+    fn routes_by_origin(&self, asn: u32) -> Result<Vec<Route>, Error> {
+        self.query_as(&format!("-r -T route,route6 -i origin AS{}", asn))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::thread::{self, JoinHandle};
+
+    /// Serves one whois reply and returns the query it received
+    fn serve(reply: &'static str) -> (String, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut query = String::new();
+            BufReader::new(&stream).read_line(&mut query).unwrap();
+            stream.write_all(reply.as_bytes()).unwrap();
+            query.trim_end().to_string()
+        });
+        (addr, handle)
+    }
+
+    #[test]
+    fn test_server_names() {
+        assert_eq!(IrrClient::named("RADB").server(), IrrClient::RADB);
+        assert_eq!(IrrClient::named("arin").server(), "rr.arin.net:43");
+        assert_eq!(
+            IrrClient::new("whois.example.net").server(),
+            "whois.example.net:43"
+        );
+        assert_eq!(IrrClient::new("127.0.0.1:4343").server(), "127.0.0.1:4343");
+        // The last group of a bare IPv6 address is not a port
+        assert_eq!(IrrClient::new("2001:db8::43").server(), "[2001:db8::43]:43");
+        assert_eq!(IrrClient::new("[2001:db8::1]").server(), "[2001:db8::1]:43");
+        assert_eq!(
+            IrrClient::new("[2001:db8::1]:4343").server(),
+            "[2001:db8::1]:4343"
+        );
+    }
+
+    #[test]
+    fn test_routes_query() {
+        let (addr, server) = serve(
+            "route:      192.0.2.0/24\norigin:     AS64496\nsource:     RADB\n\n\
+             route:      192.0.0.0/16\norigin:     AS64497\nsource:     RADB\n\n",
+        );
+        let routes = IrrClient::new(addr)
+            .routes("192.0.2.1".parse().unwrap())
+            .unwrap();
+        assert_eq!(server.join().unwrap(), "-r -T route,route6 -L 192.0.2.1");
+        assert_eq!(
+            routes.iter().map(|r| r.origin).collect::<Vec<_>>(),
+            vec![64496, 64497]
+        );
+    }
+
+    #[test]
+    fn test_aut_num_not_found() {
+        let (addr, server) = serve("%ERROR:101: no entries found\n\n");
+        assert_eq!(IrrClient::new(addr).aut_num(64496).unwrap(), None);
+        assert_eq!(server.join().unwrap(), "-r -T aut-num AS64496");
+    }
+
+    #[test]
+    fn test_server_error() {
+        let (addr, server) = serve("%ERROR:201: access denied for 127.0.0.1\n");
+        let err = IrrClient::new(addr).as_set("AS-EXAMPLE").unwrap_err();
+        assert!(err.to_string().contains("access denied"));
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,154 @@
+// In-memory IRR database loaded from RPSL dump files
+
+use super::expand::IrrSource;
+use super::objects::{AsSet, AutNum, Route};
+use super::rpsl::RpslReader;
+use crate::asn::dataset::open_dataset;
+use std::{
+    collections::HashMap,
+    io::{BufRead, Error, ErrorKind},
+    path::Path,
+};
+
+/// Route, aut-num and as-set objects from registry dumps such as `radb.db.gz`
+///
+/// Answers as-set expansion queries locally, without a whois round trip per
+/// set and ASN. Other object classes and malformed objects are skipped.
+#[derive(Debug, Default)]
+pub struct RpslDatabase {
+    as_sets: HashMap<String, AsSet>,
+    /// aut-nums indexed by each as-set they claim membership of
+    members_of: HashMap<String, Vec<AutNum>>,
+    routes: HashMap<u32, Vec<Route>>,
+    len: usize,
+}
+
+impl RpslDatabase {
+    /// Creates an empty database
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads a dump file, transparently decompressing gzip, into a new database
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let mut db = RpslDatabase::new();
+        db.add_reader(open_dataset(path)?)?;
+        Ok(db)
+    }
+
+    /// Parses RPSL text into a new database
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading fails
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Self, Error> {
+        let mut db = RpslDatabase::new();
+        db.add_reader(reader)?;
+        Ok(db)
+    }
+
+    /// Adds every supported object from RPSL text, returning how many were added
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading fails; malformed objects are skipped
+    pub fn add_reader<R: BufRead>(&mut self, reader: R) -> Result<usize, Error> {
+        let mut added = 0;
+        for object in RpslReader::new(reader) {
+            let object = match object {
+                Ok(object) => object,
+                Err(e) if e.kind() == ErrorKind::InvalidData => continue,
+                Err(e) => return Err(e),
+            };
+            let stored = match object.class() {
+                "route" | "route6" => Route::try_from(&object).map(|route| {
+                    self.routes.entry(route.origin).or_default().push(route);
+                }),
+                "aut-num" => AutNum::try_from(&object).map(|aut_num| {
+                    for set in &aut_num.member_of {
+                        self.members_of
+                            .entry(set.to_ascii_uppercase())
+                            .or_default()
+                            .push(aut_num.clone());
+                    }
+                }),
+                "as-set" => AsSet::try_from(&object).map(|as_set| {
+                    self.as_sets
+                        .insert(as_set.name.to_ascii_uppercase(), as_set);
+                }),
+                _ => continue,
+            };
+            if stored.is_ok() {
+                added += 1;
+            }
+        }
+        self.len += added;
+        Ok(added)
+    }
+
+    /// Number of objects stored
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl IrrSource for RpslDatabase {
+    fn as_set(&self, name: &str) -> Result<Option<AsSet>, Error> {
+        Ok(self.as_sets.get(&name.to_ascii_uppercase()).cloned())
+    }
+
+    fn members_of(&self, set: &str) -> Result<Vec<AutNum>, Error> {
+        Ok(self
+            .members_of
+            .get(&set.to_ascii_uppercase())
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    fn routes_by_origin(&self, asn: u32) -> Result<Vec<Route>, Error> {
+        Ok(self.routes.get(&asn).cloned().unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_indexes_supported_objects() {
+        let dump = "\
+route: 192.0.2.0/24
+origin: AS64496
+
+route: not-a-prefix
+origin: AS64496
+
+mntner: MAINT-EX
+
+broken line without colon
+
+as-set: as-example
+members: AS64496
+
+aut-num: AS64497
+member-of: AS-EXAMPLE
+";
+        let db = RpslDatabase::from_reader(dump.as_bytes()).unwrap();
+        assert_eq!(db.len(), 3);
+        assert_eq!(db.routes_by_origin(64496).unwrap().len(), 1);
+        assert!(db.routes_by_origin(1).unwrap().is_empty());
+        assert_eq!(
+            db.as_set("AS-EXAMPLE").unwrap().unwrap().members,
+            vec!["AS64496"]
+        );
+        assert_eq!(db.members_of("as-example").unwrap()[0].asn, 64497);
+    }
+}
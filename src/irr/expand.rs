@@ -0,0 +1,270 @@
+// Recursive as-set expansion
+
+use super::objects::{AsSet, AutNum, Route};
+use crate::asn::parse_asn;
+use crate::network::Prefix;
+use std::collections::BTreeSet;
+use std::io::Error;
+
+/// Trait for IRR data sources that can answer the queries needed to expand an as-set
+pub trait IrrSource {
+    /// Looks up an as-set by name
+    fn as_set(&self, name: &str) -> Result<Option<AsSet>, Error>;
+
+    /// Returns the aut-num objects that declare `member-of: <set>`
+    fn members_of(&self, set: &str) -> Result<Vec<AutNum>, Error>;
+
+    /// Returns the route and route6 objects registered with the given origin
+    fn routes_by_origin(&self, asn: u32) -> Result<Vec<Route>, Error>;
+}
+
+/// The result of expanding an as-set
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Expansion {
+    /// Every ASN reachable from the set
+    pub asns: BTreeSet<u32>,
+    /// Prefixes registered in route/route6 objects originated by those ASNs
+    pub prefixes: BTreeSet<Prefix>,
+    /// Names of the as-sets that were expanded, uppercased
+    pub sets: BTreeSet<String>,
+    /// Sets that were not found, or members that are neither an ASN nor an as-set
+    pub missing: BTreeSet<String>,
+    /// Sets referenced by a set that was already being expanded further up
+    pub cycles: BTreeSet<String>,
+    /// Sets left unexpanded because they were nested deeper than the depth limit
+    pub truncated: BTreeSet<String>,
+}
+
+/// Flattens as-sets into ASNs and prefixes, like `bgpq4` does for filter generation
+///
+/// Nested sets are followed recursively. Sets already on the current path are
+/// recorded as cycles rather than followed again, and sets nested more than
+/// `max_depth` levels below the root are recorded as truncated.
+pub struct AsSetExpander<'a> {
+    source: &'a dyn IrrSource,
+    max_depth: usize,
+    with_prefixes: bool,
+}
+
+impl<'a> AsSetExpander<'a> {
+    pub const DEFAULT_MAX_DEPTH: usize = 16;
+
+    /// Creates an expander that also collects prefixes
+    pub fn new(source: &'a dyn IrrSource) -> Self {
+        AsSetExpander {
+            source,
+            max_depth: Self::DEFAULT_MAX_DEPTH,
+            with_prefixes: true,
+        }
+    }
+
+    /// Sets how many levels of nested as-sets are followed
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Sets whether route objects are looked up for the expanded ASNs
+    pub fn with_prefixes(mut self, with_prefixes: bool) -> Self {
+        self.with_prefixes = with_prefixes;
+        self
+    }
+
+    /// Expands an as-set name (or a single "AS64496") into ASNs and prefixes
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if querying the source fails
+    pub fn expand(&self, name: &str) -> Result<Expansion, Error> {
+        let mut expansion = Expansion::default();
+        match parse_asn(name) {
+            Some(asn) => {
+                expansion.asns.insert(asn);
+            }
+            None => {
+                let mut path = Vec::new();
+                self.expand_set(&name.to_ascii_uppercase(), 0, &mut path, &mut expansion)?;
+            }
+        }
+
+        if self.with_prefixes {
+            for asn in &expansion.asns {
+                for route in self.source.routes_by_origin(*asn)? {
+                    expansion.prefixes.insert(route.prefix);
+                }
+            }
+        }
+        Ok(expansion)
+    }
+
+    fn expand_set(
+        &self,
+        name: &str,
+        depth: usize,
+        path: &mut Vec<String>,
+        expansion: &mut Expansion,
+    ) -> Result<(), Error> {
+        if path.iter().any(|set| set == name) {
+            expansion.cycles.insert(name.to_string());
+            return Ok(());
+        }
+        if !expansion.sets.insert(name.to_string()) {
+            // Already expanded through another branch
+            return Ok(());
+        }
+        let Some(set) = self.source.as_set(name)? else {
+            expansion.sets.remove(name);
+            expansion.missing.insert(name.to_string());
+            return Ok(());
+        };
+
+        path.push(name.to_string());
+        for member in &set.members {
+            if let Some(asn) = parse_asn(member) {
+                expansion.asns.insert(asn);
+            } else if is_as_set_name(member) {
+                let member = member.to_ascii_uppercase();
+                if depth >= self.max_depth {
+                    expansion.truncated.insert(member);
+                } else {
+                    self.expand_set(&member, depth + 1, path, expansion)?;
+                }
+            } else {
+                expansion.missing.insert(member.clone());
+            }
+        }
+
+        // mbrs-by-ref lets aut-nums join the set via member-of, if their maintainer is listed
+        if !set.mbrs_by_ref.is_empty() {
+            for aut_num in self.source.members_of(&set.name)? {
+                if accepts_reference(&set, &aut_num) {
+                    expansion.asns.insert(aut_num.asn);
+                }
+            }
+        }
+        path.pop();
+        Ok(())
+    }
+}
+
+/// Returns true for "AS-FOO" and hierarchical names such as "AS64496:AS-CUSTOMERS"
+fn is_as_set_name(name: &str) -> bool {
+    name.split(':')
+        .any(|part| part.len() > 3 && part.get(..3).is_some_and(|p| p.eq_ignore_ascii_case("AS-")))
+}
+
+fn accepts_reference(set: &AsSet, aut_num: &AutNum) -> bool {
+    set.mbrs_by_ref.iter().any(|maintainer| {
+        maintainer.eq_ignore_ascii_case("ANY")
+            || aut_num
+                .mnt_by
+                .iter()
+                .any(|mnt| mnt.eq_ignore_ascii_case(maintainer))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::irr::RpslDatabase;
+
+    const DUMP: &str = "\
+as-set:     AS-ROOT
+members:    AS64496, AS-CUSTOMERS,
+            as-peers
+members:    AS64496:AS-PRIVATE, RS-NOT-AN-AS-SET, AS-GONE, ÉÉ-SET
+mbrs-by-ref: MAINT-ROOT
+
+as-set:     AS-CUSTOMERS
+members:    AS64497, AS-ROOT
+
+as-set:     AS-PEERS
+members:    AS64498, AS-CUSTOMERS, AS-DEEP
+
+as-set:     AS64496:AS-PRIVATE
+members:    AS64499
+
+as-set:     AS-DEEP
+members:    AS-DEEPER
+
+as-set:     AS-DEEPER
+members:    AS64510
+
+aut-num:    AS64500
+member-of:  AS-ROOT
+mnt-by:     MAINT-ROOT
+
+aut-num:    AS64501
+member-of:  AS-ROOT
+mnt-by:     MAINT-SOMEONE-ELSE
+
+route:      192.0.2.0/24
+origin:     AS64496
+
+route6:     2001:db8::/32
+origin:     AS64497
+
+route:      198.51.100.0/24
+origin:     AS64500
+
+route:      203.0.113.0/24
+origin:     AS64501
+";
+
+    fn database() -> RpslDatabase {
+        RpslDatabase::from_reader(DUMP.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_expand_recursively() {
+        let db = database();
+        let expansion = AsSetExpander::new(&db).expand("as-root").unwrap();
+        assert_eq!(
+            expansion.asns.into_iter().collect::<Vec<_>>(),
+            vec![64496, 64497, 64498, 64499, 64500, 64510]
+        );
+        assert_eq!(
+            expansion
+                .prefixes
+                .iter()
+                .map(Prefix::to_string)
+                .collect::<Vec<_>>(),
+            vec!["192.0.2.0/24", "198.51.100.0/24", "2001:db8::/32"]
+        );
+        assert!(expansion.sets.contains("AS64496:AS-PRIVATE"));
+        assert_eq!(
+            expansion.missing.into_iter().collect::<Vec<_>>(),
+            vec!["AS-GONE", "RS-NOT-AN-AS-SET", "ÉÉ-SET"]
+        );
+    }
+
+    #[test]
+    fn test_cycles_are_detected() {
+        let db = database();
+        let expansion = AsSetExpander::new(&db).expand("AS-CUSTOMERS").unwrap();
+        assert!(expansion.cycles.contains("AS-CUSTOMERS"));
+        assert!(expansion.asns.contains(&64497));
+        assert!(expansion.asns.contains(&64498));
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        let db = database();
+        let expansion = AsSetExpander::new(&db)
+            .max_depth(1)
+            .with_prefixes(false)
+            .expand("AS-ROOT")
+            .unwrap();
+        assert!(expansion.truncated.contains("AS-DEEP"));
+        assert!(!expansion.asns.contains(&64510));
+        assert!(expansion.prefixes.is_empty());
+    }
+
+    #[test]
+    fn test_single_asn() {
+        let db = database();
+        let expansion = AsSetExpander::new(&db).expand("AS64497").unwrap();
+        assert_eq!(expansion.asns.len(), 1);
+        assert_eq!(expansion.prefixes.len(), 1);
+    }
+}
@@ -0,0 +1,13 @@
+// Internet Routing Registry (IRR) queries and RPSL objects
+
+pub mod client;
+pub mod database;
+pub mod expand;
+pub mod objects;
+pub mod rpsl;
+
+pub use client::IrrClient;
+pub use database::RpslDatabase;
+pub use expand::{AsSetExpander, Expansion, IrrSource};
+pub use objects::{AsSet, AutNum, Route};
+pub use rpsl::{RpslObject, RpslReader};
@@ -0,0 +1,170 @@
+// Typed views of the RPSL objects used for routing policy checks
+
+use super::rpsl::RpslObject;
+use crate::asn::parse_asn;
+use crate::network::Prefix;
+use std::io::{Error, ErrorKind};
+
+fn missing(object: &RpslObject, attribute: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!(
+            "{} object '{}' has no valid '{}' attribute",
+            object.class(),
+            object.key(),
+            attribute
+        ),
+    )
+}
+
+fn list(object: &RpslObject, attribute: &str) -> Vec<String> {
+    object
+        .get_list(attribute)
+        .into_iter()
+        .map(str::to_string)
+        .collect()
+}
+
+/// A `route` or `route6` object registering the origin AS of a prefix
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    pub prefix: Prefix,
+    pub origin: u32,
+    pub descr: Option<String>,
+    pub mnt_by: Vec<String>,
+    /// The registry the object came from, e.g. "RADB" or "RIPE"
+    pub source: Option<String>,
+}
+
+impl TryFrom<&RpslObject> for Route {
+    type Error = Error;
+
+    fn try_from(object: &RpslObject) -> Result<Self, Error> {
+        if object.class() != "route" && object.class() != "route6" {
+            return Err(missing(object, "route"));
+        }
+        let prefix = object.key().parse()?;
+        let origin = object
+            .get("origin")
+            .and_then(parse_asn)
+            .ok_or_else(|| missing(object, "origin"))?;
+        Ok(Route {
+            prefix,
+            origin,
+            descr: object.get("descr").map(str::to_string),
+            mnt_by: list(object, "mnt-by"),
+            source: object.get("source").map(str::to_string),
+        })
+    }
+}
+
+/// An `aut-num` object describing an autonomous system
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AutNum {
+    pub asn: u32,
+    pub as_name: Option<String>,
+    pub descr: Option<String>,
+    /// as-sets this AS claims membership of
+    pub member_of: Vec<String>,
+    pub mnt_by: Vec<String>,
+    pub source: Option<String>,
+}
+
+impl TryFrom<&RpslObject> for AutNum {
+    type Error = Error;
+
+    fn try_from(object: &RpslObject) -> Result<Self, Error> {
+        if object.class() != "aut-num" {
+            return Err(missing(object, "aut-num"));
+        }
+        let asn = parse_asn(object.key()).ok_or_else(|| missing(object, "aut-num"))?;
+        Ok(AutNum {
+            asn,
+            as_name: object.get("as-name").map(str::to_string),
+            descr: object.get("descr").map(str::to_string),
+            member_of: list(object, "member-of"),
+            mnt_by: list(object, "mnt-by"),
+            source: object.get("source").map(str::to_string),
+        })
+    }
+}
+
+/// An `as-set` object grouping ASNs and other as-sets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsSet {
+    pub name: String,
+    /// Direct members, either "AS64496" or another as-set name
+    pub members: Vec<String>,
+    pub mbrs_by_ref: Vec<String>,
+    pub mnt_by: Vec<String>,
+    pub source: Option<String>,
+}
+
+impl TryFrom<&RpslObject> for AsSet {
+    type Error = Error;
+
+    fn try_from(object: &RpslObject) -> Result<Self, Error> {
+        if object.class() != "as-set" {
+            return Err(missing(object, "as-set"));
+        }
+        Ok(AsSet {
+            name: object.key().to_string(),
+            members: list(object, "members"),
+            mbrs_by_ref: list(object, "mbrs-by-ref"),
+            mnt_by: list(object, "mnt-by"),
+            source: object.get("source").map(str::to_string),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(text: &str) -> RpslObject {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn test_route_and_route6() {
+        let route = Route::try_from(&object(
+            "route: 192.0.2.0/24\ndescr: Example\norigin: AS64496\nmnt-by: MAINT-EX\nsource: RADB\n",
+        ))
+        .unwrap();
+        assert_eq!(route.prefix, "192.0.2.0/24".parse().unwrap());
+        assert_eq!(route.origin, 64496);
+        assert_eq!(route.source.as_deref(), Some("RADB"));
+
+        let route6 = Route::try_from(&object("route6: 2001:db8::/32\norigin: as64497\n")).unwrap();
+        assert_eq!(route6.origin, 64497);
+        assert!(route6.mnt_by.is_empty());
+    }
+
+    #[test]
+    fn test_route_requires_origin() {
+        let err = Route::try_from(&object("route: 192.0.2.0/24\ndescr: x\n")).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(Route::try_from(&object("aut-num: AS1\n")).is_err());
+    }
+
+    #[test]
+    fn test_aut_num() {
+        let aut_num = AutNum::try_from(&object(
+            "aut-num: AS64496\nas-name: EXAMPLE\nmember-of: AS-EXAMPLE\nsource: RIPE\n",
+        ))
+        .unwrap();
+        assert_eq!(aut_num.asn, 64496);
+        assert_eq!(aut_num.as_name.as_deref(), Some("EXAMPLE"));
+        assert_eq!(aut_num.member_of, vec!["AS-EXAMPLE"]);
+    }
+
+    #[test]
+    fn test_as_set_members() {
+        let as_set = AsSet::try_from(&object(
+            "as-set: AS-EXAMPLE\nmembers: AS64496, AS64497\nmembers: AS-CUSTOMERS\n",
+        ))
+        .unwrap();
+        assert_eq!(as_set.name, "AS-EXAMPLE");
+        assert_eq!(as_set.members, vec!["AS64496", "AS64497", "AS-CUSTOMERS"]);
+    }
+}
@@ -0,0 +1,324 @@
+// RPSL (RFC 2622) object text parsing
+
+use crate::asn::dataset::line_error;
+use std::{
+    fmt,
+    io::{BufRead, Error},
+    str::FromStr,
+};
+
+/// A generic RPSL object: an ordered list of attributes whose first names the class
+///
+/// Attribute names are lowercased; values have comments removed and continuation
+/// lines joined with '\n'.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RpslObject {
+    attributes: Vec<(String, String)>,
+}
+
+impl RpslObject {
+    /// Creates an object from (name, value) attribute pairs
+    pub fn new(attributes: Vec<(String, String)>) -> Self {
+        RpslObject { attributes }
+    }
+
+    /// The object class, e.g. "route" or "aut-num"
+    pub fn class(&self) -> &str {
+        self.attributes.first().map_or("", |(name, _)| name)
+    }
+
+    /// The value of the class attribute, e.g. the prefix of a route object
+    pub fn key(&self) -> &str {
+        self.attributes.first().map_or("", |(_, value)| value)
+    }
+
+    /// Returns the first value of an attribute
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(attr, _)| attr == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns every value of an attribute, in order
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.attributes
+            .iter()
+            .filter(move |(attr, _)| attr == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the items of a list-valued attribute such as `members` or `mnt-by`
+    ///
+    /// Items may be spread over several attribute lines and separated by commas
+    /// or whitespace.
+    pub fn get_list<'a>(&'a self, name: &'a str) -> Vec<&'a str> {
+        self.get_all(name)
+            .flat_map(|value| value.split(|c: char| c == ',' || c.is_whitespace()))
+            .filter(|item| !item.is_empty())
+            .collect()
+    }
+
+    /// All attributes in their original order
+    pub fn attributes(&self) -> &[(String, String)] {
+        &self.attributes
+    }
+}
+
+impl fmt::Display for RpslObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, value) in &self.attributes {
+            let mut lines = value.split('\n');
+            writeln!(f, "{}: {}", name, lines.next().unwrap_or(""))?;
+            for line in lines {
+                if line.is_empty() {
+                    writeln!(f, "+")?;
+                } else {
+                    writeln!(f, "\t{}", line)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for RpslObject {
+    type Err = Error;
+
+    /// Parses text containing exactly one object
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let mut objects = parse_objects(s)?;
+        match objects.len() {
+            1 => Ok(objects.remove(0)),
+            n => Err(Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("expected one RPSL object, found {}", n),
+            )),
+        }
+    }
+}
+
+/// Removes an end-of-line '#' comment and surrounding whitespace
+fn strip_comment(value: &str) -> &str {
+    value.split('#').next().unwrap_or("").trim()
+}
+
+fn is_attribute_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Streams RPSL objects from whois output or a registry database dump
+///
+/// Objects are separated by blank lines. Lines starting with '%' (server
+/// messages) or '#' are skipped, and lines starting with whitespace or '+'
+/// continue the previous attribute. A malformed object is reported as an
+/// error, after which reading resumes with the next object.
+pub struct RpslReader<R> {
+    reader: R,
+    line: Vec<u8>,
+    number: usize,
+}
+
+impl<R: BufRead> RpslReader<R> {
+    pub fn new(reader: R) -> Self {
+        RpslReader {
+            reader,
+            line: Vec::new(),
+            number: 0,
+        }
+    }
+
+    /// Reads the next line (lossily decoded, since dumps mix encodings); None at EOF
+    fn next_line(&mut self) -> Result<Option<String>, Error> {
+        self.line.clear();
+        if self.reader.read_until(b'\n', &mut self.line)? == 0 {
+            return Ok(None);
+        }
+        self.number += 1;
+        let line = String::from_utf8_lossy(&self.line);
+        Ok(Some(line.trim_end_matches(['\n', '\r']).to_string()))
+    }
+
+    /// Skips the rest of a malformed object
+    fn skip_object(&mut self) -> Result<(), Error> {
+        while let Some(line) = self.next_line()? {
+            if line.trim().is_empty() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn read_object(&mut self) -> Result<Option<RpslObject>, Error> {
+        let mut attributes: Vec<(String, String)> = Vec::new();
+        while let Some(line) = self.next_line()? {
+            if line.trim().is_empty() {
+                if attributes.is_empty() {
+                    continue;
+                }
+                break;
+            }
+            if line.starts_with('%') || line.starts_with('#') {
+                continue;
+            }
+
+            let number = self.number - 1;
+            if let Some(rest) = line
+                .strip_prefix('+')
+                .or_else(|| line.strip_prefix([' ', '\t']))
+            {
+                let Some((_, value)) = attributes.last_mut() else {
+                    self.skip_object()?;
+                    return Err(line_error(number, "continuation line without an attribute"));
+                };
+                value.push('\n');
+                value.push_str(strip_comment(rest));
+                continue;
+            }
+
+            match line.split_once(':') {
+                Some((name, value)) if is_attribute_name(name) => {
+                    attributes.push((name.to_ascii_lowercase(), strip_comment(value).to_string()));
+                }
+                _ => {
+                    self.skip_object()?;
+                    return Err(line_error(
+                        number,
+                        format!("malformed RPSL line '{}'", line),
+                    ));
+                }
+            }
+        }
+
+        if attributes.is_empty() {
+            return Ok(None);
+        }
+        // Trailing empty continuation lines carry no information
+        for (_, value) in &mut attributes {
+            let trimmed = value.trim_end_matches('\n').len();
+            value.truncate(trimmed);
+        }
+        Ok(Some(RpslObject::new(attributes)))
+    }
+}
+
+impl<R: BufRead> Iterator for RpslReader<R> {
+    type Item = Result<RpslObject, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_object().transpose()
+    }
+}
+
+/// Parses every RPSL object in a string
+///
+/// # Errors
+///
+/// Returns an error, with its line number, for the first malformed object
+pub fn parse_objects(text: &str) -> Result<Vec<RpslObject>, Error> {
+    RpslReader::new(text.as_bytes()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUTPUT: &str = "\
+% This is the RIPE Database query service.
+
+route:          193.0.0.0/21
+descr:          RIPE-NCC
+origin:         AS3333
+mnt-by:         RIPE-NCC-MNT
+source:         RIPE
+
+aut-num:        AS3333
+as-name:        RIPE-NCC-AS
+mnt-by:         RIPE-NCC-MNT
+mnt-by:         RIPE-NCC-HM-MNT
+source:         RIPE
+";
+
+    #[test]
+    fn test_parse_objects() {
+        let objects = parse_objects(OUTPUT).unwrap();
+        assert_eq!(objects.len(), 2);
+        assert_eq!(objects[0].class(), "route");
+        assert_eq!(objects[0].key(), "193.0.0.0/21");
+        assert_eq!(objects[0].get("origin"), Some("AS3333"));
+        assert_eq!(objects[1].class(), "aut-num");
+        assert_eq!(
+            objects[1].get_all("mnt-by").collect::<Vec<_>>(),
+            vec!["RIPE-NCC-MNT", "RIPE-NCC-HM-MNT"]
+        );
+        assert_eq!(objects[1].get("import"), None);
+    }
+
+    #[test]
+    fn test_empty_and_comment_only_output() {
+        assert!(parse_objects("").unwrap().is_empty());
+        assert!(parse_objects("%ERROR:101: no entries found\n\n")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_continuation_lines_and_comments() {
+        let object: RpslObject = "\
+as-set:     AS-EXAMPLE  # our customers
+descr:      Example customers
++
+            second paragraph
+# a full-line comment inside the object
+members:    AS64496, AS64497,
+            AS-DOWNSTREAM
+\tAS64498
+members:    AS64499 AS64500
+MNT-BY:     MAINT-EX
+"
+        .parse()
+        .unwrap();
+        assert_eq!(object.key(), "AS-EXAMPLE");
+        assert_eq!(
+            object.get("descr"),
+            Some("Example customers\n\nsecond paragraph")
+        );
+        assert_eq!(
+            object.get_list("members"),
+            vec![
+                "AS64496",
+                "AS64497",
+                "AS-DOWNSTREAM",
+                "AS64498",
+                "AS64499",
+                "AS64500"
+            ]
+        );
+        assert_eq!(object.get("mnt-by"), Some("MAINT-EX"));
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let objects = parse_objects(OUTPUT).unwrap();
+        let multi_line: RpslObject = "descr: one\n+\n two\n".parse().unwrap();
+        for object in [objects[0].clone(), multi_line] {
+            assert_eq!(object.to_string().parse::<RpslObject>().unwrap(), object);
+        }
+    }
+
+    #[test]
+    fn test_malformed_object_is_reported_and_skipped() {
+        let text = "route: 192.0.2.0/24\nnot an attribute\norigin: AS1\n\naut-num: AS2\n";
+        let results: Vec<_> = RpslReader::new(text.as_bytes()).collect();
+        assert_eq!(results.len(), 2);
+        let err = results[0].as_ref().unwrap_err();
+        assert!(err.to_string().starts_with("line 2:"));
+        assert_eq!(results[1].as_ref().unwrap().key(), "AS2");
+
+        assert!(parse_objects("  leading continuation\n").is_err());
+        assert!("a: 1\n\nb: 2\n".parse::<RpslObject>().is_err());
+    }
+}
@@ -2,4 +2,14 @@
 
 pub mod asn;
 pub mod cli;
+pub mod enrich;
+pub mod filter;
+pub mod irr;
+pub mod metrics;
 pub mod network;
+pub mod rpki;
+pub mod server;
+pub mod snapshot;
+pub mod trace;
+pub mod traffic;
+pub mod watch;
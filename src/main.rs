@@ -1,30 +1,766 @@
-use asn_fetcher::asn::{Asn, IPApi, Ripe, TeamCymruWhois};
-use asn_fetcher::cli::Args;
+use asn_fetcher::asn::dataset::{line_error, open_dataset};
+use asn_fetcher::asn::{
+    parse_asn, Asn, Backoff, DelegatedStats, IPApi, IpToAsn, LocalDb, LocalDbBuilder, LookupCache,
+    Mmdb, MmdbWriter, MrtRib, Pfx2as, ProviderChain, Ripe, TeamCymruWhois,
+};
+use asn_fetcher::cli::{init_logging, Args, Command, DbCommand, ExportFormat, IrrCommand};
+use asn_fetcher::enrich::{
+    lookup_all, lookup_origins, CsvEnricher, JsonEnricher, Placement, TextEnricher, DEFAULT_WORKERS,
+};
+use asn_fetcher::filter::PrefixFilter;
+use asn_fetcher::irr::{AsSetExpander, Expansion, IrrClient, IrrSource, RpslDatabase};
+use asn_fetcher::metrics::{Measured, Metrics};
+use asn_fetcher::rpki::{RtrClient, VrpSet};
+use asn_fetcher::server::{DnsServer, HttpServer, WhoisServer};
+use asn_fetcher::snapshot::{record_line, Snapshot, SnapshotDiff};
+use asn_fetcher::trace::{parse_trace, public_addresses, render, TraceFormat};
+use asn_fetcher::traffic::{
+    parse_packet, render_table, split_messages, udp_payload, FlowDecoder, PcapReader, Side,
+    TrafficSummary,
+};
+use asn_fetcher::watch::{read_targets, Interval, WatchState, Watcher, Webhook};
 use clap::Parser;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Metrics recorded by every provider and cache of this process
+fn metrics() -> Arc<Metrics> {
+    static METRICS: OnceLock<Arc<Metrics>> = OnceLock::new();
+    Arc::clone(METRICS.get_or_init(|| Arc::new(Metrics::new())))
+}
+
+/// Appends a provider to the chain, backing off when it is rate limited and
+/// recording its lookups under `name`
+fn add_measured(chain: ProviderChain, name: &str, provider: Box<dyn Asn>) -> ProviderChain {
+    let provider = Backoff::new(name, provider).metrics(metrics());
+    chain.with_provider(
+        name,
+        Box::new(Measured::new(name, Box::new(provider), metrics())),
+    )
+}
+
+/// Creates the appropriate ASN fetcher based on the source string and appends it to the chain
+fn add_asn_fetcher(
+    chain: ProviderChain,
+    source: &str,
+) -> Result<ProviderChain, Box<dyn std::error::Error>> {
+    // Local datasets are given as "<format>:<path>"
+    if let Some((format, path)) = source.split_once(':') {
+        let provider: Box<dyn Asn> = match format {
+            "pfx2as" => Box::new(Pfx2as::load(path)?),
+            "iptoasn" => Box::new(IpToAsn::load(path)?),
+            "mrt" => Box::new(MrtRib::load(path)?),
+            "localdb" => Box::new(LocalDb::open(path)?),
+            "mmdb" => Box::new(Mmdb::open(path)?),
+            _ => return Err(format!("Unknown dataset format '{}'", format).into()),
+        };
+        return Ok(add_measured(chain, format, provider));
+    }
 
-/// Creates the appropriate ASN fetcher based on the source string
-fn create_asn_fetcher(source: &str) -> Result<Box<dyn Asn>, Box<dyn std::error::Error>> {
     let (provider, provider_name) = match source {
         "ipapi" => (Box::new(IPApi::new()?) as Box<dyn Asn>, "ipapi"),
         "cymru-whois" => (Box::new(TeamCymruWhois) as Box<dyn Asn>, "cymru-whois"),
         "ripe" => (Box::new(Ripe::new()?) as Box<dyn Asn>, "ripe"),
         _ => {
-            eprintln!(
-                "Unknown provider '{}', falling back to default provider: ripe",
+            tracing::warn!(
+                "unknown provider '{}', falling back to default provider: ripe",
                 source
             );
             (Box::new(Ripe::new()?) as Box<dyn Asn>, "ripe")
         }
     };
+    Ok(add_measured(chain, provider_name, provider))
+}
+
+/// Builds the provider chain from the comma-separated source list and enrichment files
+fn create_provider_chain(args: &Args) -> Result<ProviderChain, Box<dyn std::error::Error>> {
+    let mut chain = ProviderChain::new();
+    for source in args.source.split(',').map(str::trim) {
+        chain = add_asn_fetcher(chain, source)?;
+    }
+
+    if !args.delegated.is_empty() {
+        let mut stats = DelegatedStats::new();
+        for path in &args.delegated {
+            stats.load(path)?;
+        }
+        chain = chain.with_enricher(Box::new(stats));
+    }
+
+    if let Some(path) = &args.vrps {
+        let vrps = VrpSet::load(path)?;
+        tracing::info!("Loaded {} VRPs from {}", vrps.len(), path.display());
+        chain = chain.with_enricher(Box::new(vrps));
+    }
+
+    if let Some(addr) = &args.rtr {
+        let mut client = RtrClient::connect(addr.as_str())?;
+        client.sync()?;
+        tracing::info!(
+            "Synchronized {} VRPs from RTR cache {} (version {})",
+            client.len(),
+            addr,
+            client.version()
+        );
+        chain = chain.with_enricher(Box::new(client.spawn_refresh()));
+    }
+
+    tracing::info!("Using provider: {}", chain.provider_names().join(", "));
+    Ok(chain)
+}
+
+/// Builds the provider chain for a long-running server, behind a cache unless `cache_ttl` is 0
+fn create_shared_lookup(
+    args: &Args,
+    cache_ttl: u64,
+) -> Result<Arc<dyn Asn>, Box<dyn std::error::Error>> {
+    let chain = create_provider_chain(args)?;
+    if cache_ttl == 0 {
+        return Ok(Arc::new(chain));
+    }
+    Ok(Arc::new(
+        LookupCache::new(Box::new(chain), Duration::from_secs(cache_ttl)).metrics(metrics()),
+    ))
+}
+
+/// Loads the given "<format>:<path>" inputs, merging them in order with delegated stats applied
+fn load_inputs(inputs: &[String]) -> Result<LocalDbBuilder, Box<dyn std::error::Error>> {
+    let mut builder = LocalDbBuilder::new();
+    let mut stats = DelegatedStats::new();
+    for input in inputs {
+        let (format, path) = input
+            .split_once(':')
+            .ok_or_else(|| format!("Input '{}' must be given as <format>:<path>", input))?;
+        let added = match format {
+            "pfx2as" => builder.add_dataset(&Pfx2as::load(path)?),
+            "iptoasn" => builder.add_dataset(&IpToAsn::load(path)?),
+            "mrt" => builder.add_dataset(&MrtRib::load(path)?),
+            "localdb" => builder.add_dataset(&LocalDb::open(path)?),
+            "results" => builder.add_recorded(open_dataset(path)?)?,
+            "delegated" => {
+                stats.load(path)?;
+                continue;
+            }
+            _ => return Err(format!("Unknown dataset format '{}'", format).into()),
+        };
+        tracing::info!("Loaded {} prefixes from {}", added, input);
+    }
+
+    if builder.is_empty() {
+        return Err("No prefixes loaded; delegated stats only enrich routing data".into());
+    }
+    builder.enrich(&stats);
+    Ok(builder)
+}
+
+/// Runs a `db` subcommand
+fn run_db_command(command: &DbCommand) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        DbCommand::Build { output, inputs } => {
+            let builder = load_inputs(inputs)?;
+            builder.write_file(output)?;
+            tracing::info!("Wrote {} prefixes to {}", builder.len(), output.display());
+        }
+        DbCommand::Export {
+            format: ExportFormat::Mmdb,
+            output,
+            inputs,
+        } => {
+            let builder = load_inputs(inputs)?;
+            let written = MmdbWriter::new().write_file(&builder, output)?;
+            tracing::info!("Wrote {} prefixes to {}", written, output.display());
+        }
+    }
+    Ok(())
+}
+
+/// Expands an as-set from dump files, or from the IRR server when none are given
+fn expand_as_set(
+    server: &str,
+    db: &[PathBuf],
+    name: &str,
+    max_depth: usize,
+    with_prefixes: bool,
+) -> Result<Expansion, Box<dyn std::error::Error>> {
+    let source: Box<dyn IrrSource> = if db.is_empty() {
+        Box::new(IrrClient::named(server))
+    } else {
+        let mut database = RpslDatabase::new();
+        for path in db {
+            database.add_reader(open_dataset(path)?)?;
+        }
+        tracing::info!("Loaded {} IRR objects", database.len());
+        Box::new(database)
+    };
+    let expansion = AsSetExpander::new(source.as_ref())
+        .max_depth(max_depth)
+        .with_prefixes(with_prefixes)
+        .expand(name)?;
+
+    for (label, names) in [
+        ("not found", &expansion.missing),
+        ("cyclic reference to", &expansion.cycles),
+        ("depth limit reached at", &expansion.truncated),
+    ] {
+        for set in names {
+            tracing::warn!("{} {}", label, set);
+        }
+    }
+    Ok(expansion)
+}
+
+/// A query that found nothing; the message is the command's result, so it is
+/// printed as is rather than logged
+#[derive(Debug)]
+struct NotFound(String);
+
+impl std::fmt::Display for NotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for NotFound {}
+
+/// Runs an `irr` subcommand against the given server
+fn run_irr_command(
+    args: &Args,
+    server: &str,
+    command: &IrrCommand,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let client = IrrClient::named(server);
+    match command {
+        IrrCommand::Routes { resource, compare } => {
+            let routes = client.routes(*resource)?;
+            routes.iter().for_each(|route| println!("{:?}", route));
+            if *compare {
+                let chain = create_provider_chain(args)?;
+                for info in chain.lookup_asn(resource.addr())? {
+                    let Some(origin) = info.asn_number() else {
+                        continue;
+                    };
+                    let registered = routes.iter().any(|route| route.origin == origin);
+                    println!(
+                        "BGP origin AS{}: {}",
+                        origin,
+                        if registered {
+                            "registered in IRR"
+                        } else {
+                            "not registered in IRR"
+                        }
+                    );
+                }
+            }
+        }
+        IrrCommand::AutNum { asn } => {
+            let asn = parse_asn(asn).ok_or_else(|| format!("Invalid AS number '{}'", asn))?;
+            match client.aut_num(asn)? {
+                Some(aut_num) => println!("{:?}", aut_num),
+                None => {
+                    let msg = format!("No aut-num found for AS{} on {}", asn, client.server());
+                    return Err(NotFound(msg).into());
+                }
+            }
+        }
+        IrrCommand::AsSet { name } => match client.as_set(name)? {
+            Some(as_set) => println!("{:?}", as_set),
+            None => {
+                let msg = format!("No as-set {} found on {}", name, client.server());
+                return Err(NotFound(msg).into());
+            }
+        },
+        IrrCommand::Expand {
+            name,
+            db,
+            max_depth,
+            asns_only,
+        } => {
+            let expansion = expand_as_set(server, db, name, *max_depth, !asns_only)?;
+            expansion.asns.iter().for_each(|asn| println!("AS{}", asn));
+            expansion
+                .prefixes
+                .iter()
+                .for_each(|prefix| println!("{}", prefix));
+        }
+    }
+    Ok(())
+}
+
+/// How long commands that look up many addresses in one run reuse results for the same address
+const BATCH_CACHE_TTL: u64 = 3600;
+
+/// Prints per-ASN packet, byte and flow totals for a capture file
+fn summarize_pcap(
+    args: &Args,
+    file: &Path,
+    workers: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut reader = PcapReader::open(file)?;
+    let mut summary = TrafficSummary::new();
+    let (mut packets, mut skipped) = (0u64, 0u64);
+    while let Some(packet) = reader.next_packet()? {
+        packets += 1;
+        match parse_packet(packet.linktype, &packet.data) {
+            Some(traffic) => summary.add(traffic),
+            None => skipped += 1,
+        }
+    }
+    if reader.truncated() {
+        tracing::warn!("{} ends part way through a packet", file.display());
+    }
+
+    let addresses = summary.addresses();
+    tracing::info!(
+        "Read {} packets ({} not IP) between {} addresses",
+        packets,
+        skipped,
+        addresses.len()
+    );
+    let lookup = create_shared_lookup(args, BATCH_CACHE_TTL)?;
+    let origins = lookup_origins(lookup.as_ref(), &addresses, workers);
+    print!("{}", render_table(&summary.by_asn(&origins, Side::Either)));
+    Ok(())
+}
+
+/// Prints the ASNs with the most traffic in flow exports read from a file or a UDP socket
+fn summarize_flows(
+    args: &Args,
+    file: Option<&Path>,
+    listen: Option<&str>,
+    duration: Duration,
+    side: Side,
+    top: Option<usize>,
+    workers: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut decoder = FlowDecoder::new();
+    let mut summary = TrafficSummary::new();
+    let (mut messages, mut records) = (0u64, 0u64);
+    let mut decode =
+        |exporter: Option<SocketAddr>, message: &[u8]| match decoder.decode(exporter, message) {
+            Ok(flows) => {
+                messages += 1;
+                records += flows.len() as u64;
+                flows.into_iter().for_each(|traffic| summary.add(traffic));
+            }
+            Err(e) => match exporter {
+                Some(exporter) => tracing::warn!(%exporter, error = %e, "skipping export"),
+                None => tracing::warn!(error = %e, "skipping export"),
+            },
+        };
+
+    if let Some(listen) = listen {
+        let socket = UdpSocket::bind(listen)?;
+        tracing::info!("Listening on udp://{}", socket.local_addr()?);
+        let deadline = Instant::now() + duration;
+        let mut buf = vec![0u8; 65535];
+        while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+            if remaining.is_zero() {
+                break;
+            }
+            socket.set_read_timeout(Some(remaining))?;
+            match socket.recv_from(&mut buf) {
+                Ok((len, exporter)) => decode(Some(exporter), &buf[..len]),
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    } else if let Some(file) = file {
+        match PcapReader::open(file) {
+            Ok(mut reader) => {
+                while let Some(packet) = reader.next_packet()? {
+                    if let Some((exporter, payload)) = udp_payload(packet.linktype, &packet.data) {
+                        decode(Some(exporter), payload);
+                    }
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::InvalidData => {
+                let data = std::fs::read(file)?;
+                for message in split_messages(&data)? {
+                    decode(None, message);
+                }
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    if decoder.dropped() > 0 {
+        tracing::warn!(
+            "skipped {} data sets sent before their template",
+            decoder.dropped()
+        );
+    }
+
+    let addresses = summary.addresses();
+    tracing::info!(
+        "Decoded {} flow records from {} export packets between {} addresses",
+        records,
+        messages,
+        addresses.len()
+    );
+    let lookup = create_shared_lookup(args, BATCH_CACHE_TTL)?;
+    let origins = lookup_origins(lookup.as_ref(), &addresses, workers);
+    let mut totals = summary.by_asn(&origins, side);
+    if let Some(top) = top {
+        totals.truncate(top);
+    }
+    print!("{}", render_table(&totals));
+    Ok(())
+}
+
+/// Prints the hops of a captured trace with their origin ASNs and the collapsed AS path
+fn annotate_hops(
+    args: &Args,
+    file: Option<&Path>,
+    format: TraceFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let output = match file {
+        Some(file) => std::fs::read_to_string(file)?,
+        None => {
+            let mut output = String::new();
+            io::stdin().read_to_string(&mut output)?;
+            output
+        }
+    };
+    let hops = parse_trace(&output, format)?;
+    let unaddressed: usize = hops.iter().map(|hop| hop.names.len()).sum();
+    if unaddressed > 0 {
+        tracing::warn!(
+            "{} routers are shown without an address; trace with -n or -b to look them up",
+            unaddressed
+        );
+    }
+
+    let lookup = create_shared_lookup(args, BATCH_CACHE_TTL)?;
+    let origins = lookup_origins(lookup.as_ref(), &public_addresses(&hops), DEFAULT_WORKERS);
+    print!("{}", render(&hops, &origins));
+    Ok(())
+}
+
+/// Checks the targets listed in `file` every interval, printing changes in their origins as JSON lines
+fn watch_targets(
+    args: &Args,
+    file: &Path,
+    interval: Interval,
+    state_path: &Path,
+    webhook: Option<&str>,
+    once: bool,
+    workers: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let targets = read_targets(BufReader::new(File::open(file)?))?;
+    if targets.is_empty() {
+        return Err(format!("No targets listed in {}", file.display()).into());
+    }
+    let count = targets.len();
+    let webhook = webhook.map(Webhook::new).transpose()?;
+    let mut state = WatchState::load(state_path)?;
+    // Origins must be fresh on every check, so lookups are never cached
+    let lookup = create_shared_lookup(args, 0)?;
+    let watcher = Watcher::new(lookup.as_ref(), targets).workers(workers);
+
+    loop {
+        let events = watcher.check(&mut state);
+        state.save(state_path)?;
+        let mut stdout = io::stdout().lock();
+        for event in &events {
+            writeln!(stdout, "{}", serde_json::to_string(event)?)?;
+            if let Some(webhook) = &webhook {
+                if let Err(e) = webhook.send(event) {
+                    tracing::warn!(error = %e, "could not deliver event");
+                }
+            }
+        }
+        stdout.flush()?;
+        drop(stdout);
+        tracing::info!("Checked {} targets, {} changes", count, events.len());
+        if let Some(path) = &args.metrics_file {
+            metrics().write_file(path)?;
+        }
+        if once {
+            return Ok(());
+        }
+        std::thread::sleep(interval.0);
+    }
+}
+
+/// Prints the addresses whose ASN, holder or prefix differ between two snapshots
+fn diff_snapshots(
+    before: &Path,
+    after: &Path,
+    json: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let read = |path: &Path| -> Result<Snapshot, Box<dyn std::error::Error>> {
+        Snapshot::read(open_dataset(path)?).map_err(|e| format!("{}: {}", path.display(), e).into())
+    };
+    let diff = SnapshotDiff::new(&read(before)?, &read(after)?);
+    if json {
+        let mut stdout = io::stdout().lock();
+        for entry in &diff.entries {
+            writeln!(stdout, "{}", serde_json::to_string(entry)?)?;
+        }
+    } else {
+        print!("{}", diff.render());
+    }
 
-    eprintln!("Using provider: {}", provider_name);
-    Ok(provider)
+    let (added, removed, changed) = diff.counts();
+    tracing::info!(
+        "{} changed, {} added, {} removed, {} unchanged",
+        changed,
+        added,
+        removed,
+        diff.unchanged
+    );
+    if diff.failed > 0 {
+        tracing::warn!(
+            "skipped {} addresses whose lookup failed in either snapshot",
+            diff.failed
+        );
+    }
+    Ok(())
+}
+
+/// Reads the addresses listed in a file, one per line, skipping blank
+/// lines, `#` comments and repeated addresses
+fn read_addresses(path: &Path) -> Result<Vec<IpAddr>, Box<dyn std::error::Error>> {
+    let mut addresses = Vec::new();
+    for (number, line) in open_dataset(path)?.lines().enumerate() {
+        let line = line?;
+        let address = line.split('#').next().unwrap_or_default().trim();
+        if address.is_empty() {
+            continue;
+        }
+        let ip: IpAddr = address.parse().map_err(|_| {
+            format!(
+                "{}: {}",
+                path.display(),
+                line_error(number, format!("invalid IP address '{}'", address))
+            )
+        })?;
+        if !addresses.contains(&ip) {
+            addresses.push(ip);
+        }
+    }
+    Ok(addresses)
+}
+
+/// Looks up the address given on the command line, or every address in `--file`
+///
+/// With `--json`, each address is printed as a snapshot line for `diff`.
+/// A failed single lookup is an error; failures in `--file` are reported
+/// per address.
+fn lookup_addresses(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let asn_fetcher = create_provider_chain(args)?;
+    let Some(path) = &args.file else {
+        let ip = args.ip.ok_or("An IP address is required")?;
+        let lookup = asn_fetcher.lookup_asn(ip);
+        if args.json {
+            println!("{}", record_line(ip, &lookup));
+        }
+        let asns = lookup?;
+        if !args.json {
+            asns.iter().for_each(|asn| println!("{:?}", asn));
+        }
+        return Ok(());
+    };
+
+    let addresses = read_addresses(path)?;
+    let results = lookup_all(&asn_fetcher, &addresses, DEFAULT_WORKERS);
+    let mut stdout = io::stdout().lock();
+    for ip in &addresses {
+        let lookup = &results[ip];
+        if args.json {
+            writeln!(stdout, "{}", record_line(*ip, lookup))?;
+            continue;
+        }
+        match lookup {
+            Ok(asns) => {
+                for asn in asns {
+                    writeln!(stdout, "{} {:?}", ip, asn)?;
+                }
+            }
+            Err(e) => tracing::warn!(%ip, error = %e, "lookup failed"),
+        }
+    }
+    Ok(())
+}
+
+/// Runs the command given on the command line
+fn run(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    match &args.command {
+        Some(Command::Db { command }) => run_db_command(command),
+        Some(Command::Irr { server, command }) => run_irr_command(args, server, command),
+        Some(Command::Filter {
+            name,
+            target,
+            server,
+            db,
+            list_name,
+            aggregate,
+            more_specifics,
+            max_depth,
+        }) => {
+            let expansion = expand_as_set(server, db, name, *max_depth, true)?;
+            tracing::info!(
+                "Expanded {} to {} ASNs and {} prefixes",
+                name,
+                expansion.asns.len(),
+                expansion.prefixes.len()
+            );
+            let list_name = list_name.clone().unwrap_or_else(|| name.replace(':', "_"));
+            let mut filter = PrefixFilter::new(list_name, expansion.prefixes);
+            if let Some(max_len) = more_specifics {
+                filter = filter.allow_more_specifics(*max_len);
+            }
+            if *aggregate {
+                filter = filter.aggregate();
+            }
+            print!("{}", filter.render(*target));
+            Ok(())
+        }
+        Some(Command::Serve {
+            listen,
+            cache_ttl,
+            workers,
+        }) => {
+            let server =
+                HttpServer::bind(listen.as_str(), create_shared_lookup(args, *cache_ttl)?)?
+                    .metrics(metrics())
+                    .workers(*workers);
+            tracing::info!("Listening on http://{}", server.local_addr()?);
+            server.run()?;
+            Ok(())
+        }
+        Some(Command::ServeWhois {
+            listen,
+            cache_ttl,
+            workers,
+        }) => {
+            let server =
+                WhoisServer::bind(listen.as_str(), create_shared_lookup(args, *cache_ttl)?)?
+                    .workers(*workers);
+            tracing::info!("Listening on whois://{}", server.local_addr()?);
+            server.run()?;
+            Ok(())
+        }
+        Some(Command::ServeDns {
+            listen,
+            zone,
+            ttl,
+            cache_ttl,
+            workers,
+        }) => {
+            let lookup = create_shared_lookup(args, *cache_ttl)?;
+            let server = DnsServer::bind(listen.as_str(), zone, lookup)?
+                .ttl(*ttl)
+                .workers(*workers);
+            tracing::info!(
+                "Listening on dns://{} for zone {}",
+                server.local_addr()?,
+                zone
+            );
+            server.run()?;
+            Ok(())
+        }
+        Some(Command::Enrich {
+            inline,
+            workers,
+            cache_ttl,
+        }) => {
+            let lookup = create_shared_lookup(args, *cache_ttl)?;
+            let placement = if *inline {
+                Placement::Inline
+            } else {
+                Placement::Append
+            };
+            TextEnricher::new(lookup.as_ref())
+                .placement(placement)
+                .workers(*workers)
+                .run(BufReader::new(io::stdin()), io::stdout().lock())?;
+            Ok(())
+        }
+        Some(Command::EnrichJson {
+            fields,
+            workers,
+            cache_ttl,
+        }) => {
+            let lookup = create_shared_lookup(args, *cache_ttl)?;
+            JsonEnricher::new(lookup.as_ref(), fields.clone())
+                .workers(*workers)
+                .run(BufReader::new(io::stdin()), io::stdout().lock())?;
+            Ok(())
+        }
+        Some(Command::EnrichCsv {
+            columns,
+            delimiter,
+            header,
+            workers,
+            cache_ttl,
+        }) => {
+            let lookup = create_shared_lookup(args, *cache_ttl)?;
+            CsvEnricher::new(lookup.as_ref(), columns.clone())
+                .delimiter(*delimiter)
+                .header(*header)
+                .workers(*workers)
+                .run(io::stdin().lock(), io::stdout().lock())?;
+            Ok(())
+        }
+        Some(Command::Pcap { file, workers }) => summarize_pcap(args, file, *workers),
+        Some(Command::Flows {
+            file,
+            listen,
+            duration,
+            by,
+            top,
+            workers,
+        }) => summarize_flows(
+            args,
+            file.as_deref(),
+            listen.as_deref(),
+            Duration::from_secs(*duration),
+            *by,
+            *top,
+            *workers,
+        ),
+        Some(Command::Hops { file, format }) => annotate_hops(args, file.as_deref(), *format),
+        Some(Command::Watch {
+            file,
+            interval,
+            state,
+            webhook,
+            once,
+            workers,
+        }) => watch_targets(
+            args,
+            file,
+            *interval,
+            state,
+            webhook.as_deref(),
+            *once,
+            *workers,
+        ),
+        Some(Command::Diff {
+            before,
+            after,
+            json,
+        }) => diff_snapshots(before, after, *json),
+        None => lookup_addresses(args),
+    }
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = Args::parse();
-    let asn_fetcher = create_asn_fetcher(&args.source)?;
-    let asns = asn_fetcher.lookup_asn(args.ip)?;
-    asns.iter().for_each(|asn| println!("{:?}", asn));
-    Ok(())
+    init_logging(args.verbose, args.quiet, args.log_format);
+    let result = run(&args);
+    if let Some(path) = &args.metrics_file {
+        if let Err(e) = metrics().write_file(path) {
+            tracing::warn!("could not write metrics to {}: {}", path.display(), e);
+        }
+    }
+    if let Some(NotFound(msg)) = result.as_ref().err().and_then(|e| e.downcast_ref()) {
+        eprintln!("{}", msg);
+        std::process::exit(1);
+    }
+    result
 }
@@ -0,0 +1,83 @@
+// Recording metrics for the lookups made by a provider
+
+use super::Metrics;
+use crate::asn::{Asn, AsnInfo};
+use std::io::Error;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Wraps a provider and records the latency and outcome of each address lookup
+///
+/// AS lookups are passed through unrecorded, since a chain asks every
+/// provider in turn and most of them do not support them.
+pub struct Measured {
+    name: String,
+    inner: Box<dyn Asn>,
+    metrics: Arc<Metrics>,
+}
+
+impl Measured {
+    /// Records the lookups of `inner` under the provider label `name`
+    pub fn new(name: impl Into<String>, inner: Box<dyn Asn>, metrics: Arc<Metrics>) -> Self {
+        Measured {
+            name: name.into(),
+            inner,
+            metrics,
+        }
+    }
+}
+
+impl Asn for Measured {
+    fn lookup_asn(&self, ip: IpAddr) -> Result<Vec<AsnInfo>, Error> {
+        let start = Instant::now();
+        let result = self.inner.lookup_asn(ip);
+        self.metrics
+            .record_lookup(&self.name, start.elapsed(), result.as_ref().err());
+        result
+    }
+
+    fn lookup_as(&self, asn: u32) -> Result<Vec<AsnInfo>, Error> {
+        self.inner.lookup_as(asn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::ErrorKind;
+
+    struct Flaky;
+
+    impl Asn for Flaky {
+        fn lookup_asn(&self, ip: IpAddr) -> Result<Vec<AsnInfo>, Error> {
+            if ip.is_ipv4() {
+                Ok(vec![AsnInfo::new("64496", "EXAMPLE")])
+            } else {
+                Err(Error::new(ErrorKind::ConnectionRefused, "refused"))
+            }
+        }
+
+        fn lookup_as(&self, asn: u32) -> Result<Vec<AsnInfo>, Error> {
+            Ok(vec![AsnInfo::new(asn.to_string(), "EXAMPLE")])
+        }
+    }
+
+    #[test]
+    fn test_records_lookups() {
+        let metrics = Arc::new(Metrics::new());
+        let measured = Measured::new("flaky", Box::new(Flaky), Arc::clone(&metrics));
+        assert_eq!(
+            measured.lookup_asn("192.0.2.1".parse().unwrap()).unwrap()[0].asn,
+            "64496"
+        );
+        assert!(measured.lookup_asn("2001:db8::1".parse().unwrap()).is_err());
+        assert!(measured.lookup_as(64496).is_ok());
+
+        let text = metrics.render();
+        assert!(text.contains("asn_fetcher_lookups_total{provider=\"flaky\"} 2\n"));
+        assert!(text.contains(
+            "asn_fetcher_lookup_errors_total{provider=\"flaky\",kind=\"connection_refused\"} 1\n"
+        ));
+    }
+}
@@ -0,0 +1,370 @@
+// Prometheus metrics for lookups, the cache and provider health
+
+pub mod measured;
+
+pub use measured::Measured;
+
+use crate::asn::client::is_rate_limited;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::io::Error;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+/// Upper bounds of the lookup latency histogram buckets, in seconds
+pub const LATENCY_BUCKETS: [f64; 12] = [
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 10.0,
+];
+
+#[derive(Debug, Default, Clone)]
+struct Histogram {
+    /// Observations per bucket, not cumulative; the last counts those above every bound
+    buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|&bound| value <= bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.buckets[bucket] += 1;
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct ProviderMetrics {
+    lookups: u64,
+    errors: BTreeMap<String, u64>,
+    latency: Histogram,
+    /// Seconds spent waiting before retrying rate-limited lookups
+    rate_limit_wait: f64,
+}
+
+#[derive(Debug, Default)]
+struct Registry {
+    providers: BTreeMap<String, ProviderMetrics>,
+    cache_hits: u64,
+    cache_misses: u64,
+    cache_entries: usize,
+}
+
+/// Counters and histograms describing the lookups made by a process
+///
+/// Shared between threads behind an `Arc`; providers record into it through
+/// `Measured` and the cache through `LookupCache::metrics`.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    registry: Mutex<Registry>,
+}
+
+/// Labels an error for the errors metric: "rate_limited" for rate-limit
+/// refusals, otherwise its `ErrorKind` in snake case (e.g. "timed_out")
+pub fn error_kind(err: &Error) -> String {
+    if is_rate_limited(err) {
+        return "rate_limited".to_string();
+    }
+    let mut kind = String::new();
+    for (i, c) in format!("{:?}", err.kind()).chars().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            kind.push('_');
+        }
+        kind.push(c.to_ascii_lowercase());
+    }
+    kind
+}
+
+/// Writes the HELP and TYPE lines introducing a metric
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Escapes a label value for the text exposition format
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl Metrics {
+    /// Creates an empty set of metrics
+    pub fn new() -> Self {
+        Metrics::default()
+    }
+
+    fn registry(&self) -> MutexGuard<'_, Registry> {
+        // Counters stay meaningful even if a recording thread panicked
+        self.registry.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Records one address lookup made by `provider`, with its error if it failed
+    pub fn record_lookup(&self, provider: &str, elapsed: Duration, error: Option<&Error>) {
+        let mut registry = self.registry();
+        let metrics = registry.providers.entry(provider.to_string()).or_default();
+        metrics.lookups += 1;
+        metrics.latency.observe(elapsed.as_secs_f64());
+        if let Some(error) = error {
+            *metrics.errors.entry(error_kind(error)).or_default() += 1;
+        }
+    }
+
+    /// Records time spent waiting for `provider`'s rate limit before retrying a lookup
+    pub fn record_rate_limit_wait(&self, provider: &str, waited: Duration) {
+        let mut registry = self.registry();
+        let metrics = registry.providers.entry(provider.to_string()).or_default();
+        metrics.rate_limit_wait += waited.as_secs_f64();
+    }
+
+    /// Records whether a cache lookup was answered from the cache
+    pub fn record_cache(&self, hit: bool) {
+        let mut registry = self.registry();
+        if hit {
+            registry.cache_hits += 1;
+        } else {
+            registry.cache_misses += 1;
+        }
+    }
+
+    /// Sets the number of entries the cache holds
+    pub fn set_cache_entries(&self, entries: usize) {
+        self.registry().cache_entries = entries;
+    }
+
+    /// Renders every metric in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let registry = self.registry();
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "asn_fetcher_lookups_total",
+            "counter",
+            "Address lookups made by each provider.",
+        );
+        for (provider, metrics) in &registry.providers {
+            let _ = writeln!(
+                out,
+                "asn_fetcher_lookups_total{{provider=\"{}\"}} {}",
+                escape(provider),
+                metrics.lookups
+            );
+        }
+
+        header(
+            &mut out,
+            "asn_fetcher_lookup_errors_total",
+            "counter",
+            "Failed address lookups by provider and kind of error.",
+        );
+        for (provider, metrics) in &registry.providers {
+            for (kind, count) in &metrics.errors {
+                let _ = writeln!(
+                    out,
+                    "asn_fetcher_lookup_errors_total{{provider=\"{}\",kind=\"{}\"}} {}",
+                    escape(provider),
+                    kind,
+                    count
+                );
+            }
+        }
+
+        header(
+            &mut out,
+            "asn_fetcher_rate_limit_wait_seconds",
+            "counter",
+            "Time spent backing off before retrying rate-limited lookups, by provider.",
+        );
+        for (provider, metrics) in &registry.providers {
+            let _ = writeln!(
+                out,
+                "asn_fetcher_rate_limit_wait_seconds{{provider=\"{}\"}} {}",
+                escape(provider),
+                metrics.rate_limit_wait
+            );
+        }
+
+        header(
+            &mut out,
+            "asn_fetcher_lookup_duration_seconds",
+            "histogram",
+            "Time taken by address lookups, by provider.",
+        );
+        for (provider, metrics) in &registry.providers {
+            let provider = escape(provider);
+            let histogram = &metrics.latency;
+            let mut cumulative = 0;
+            for (i, count) in histogram.buckets.iter().enumerate() {
+                cumulative += count;
+                let bound = LATENCY_BUCKETS
+                    .get(i)
+                    .map_or("+Inf".to_string(), f64::to_string);
+                let _ = writeln!(
+                    out,
+                    "asn_fetcher_lookup_duration_seconds_bucket{{provider=\"{}\",le=\"{}\"}} {}",
+                    provider, bound, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "asn_fetcher_lookup_duration_seconds_sum{{provider=\"{}\"}} {}",
+                provider, histogram.sum
+            );
+            let _ = writeln!(
+                out,
+                "asn_fetcher_lookup_duration_seconds_count{{provider=\"{}\"}} {}",
+                provider, histogram.count
+            );
+        }
+
+        for (name, help, value) in [
+            (
+                "asn_fetcher_cache_hits_total",
+                "Lookups answered from the cache.",
+                registry.cache_hits,
+            ),
+            (
+                "asn_fetcher_cache_misses_total",
+                "Lookups passed on to the providers by the cache.",
+                registry.cache_misses,
+            ),
+        ] {
+            header(&mut out, name, "counter", help);
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+        header(
+            &mut out,
+            "asn_fetcher_cache_entries",
+            "gauge",
+            "Entries held by the cache.",
+        );
+        let _ = writeln!(out, "asn_fetcher_cache_entries {}", registry.cache_entries);
+        let total = registry.cache_hits + registry.cache_misses;
+        if total > 0 {
+            header(
+                &mut out,
+                "asn_fetcher_cache_hit_ratio",
+                "gauge",
+                "Share of cache lookups answered from the cache.",
+            );
+            let _ = writeln!(
+                out,
+                "asn_fetcher_cache_hit_ratio {}",
+                registry.cache_hits as f64 / total as f64
+            );
+        }
+        out
+    }
+
+    /// Writes the metrics for a textfile collector, replacing `path` only once
+    /// the new file is complete
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written
+    pub fn write_file(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        fs::write(&temp, self.render())?;
+        fs::rename(&temp, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asn::client::rate_limited;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn test_error_kind() {
+        assert_eq!(
+            error_kind(&Error::new(ErrorKind::TimedOut, "slow")),
+            "timed_out"
+        );
+        assert_eq!(
+            error_kind(&Error::new(ErrorKind::InvalidData, "bad")),
+            "invalid_data"
+        );
+        assert_eq!(error_kind(&Error::new(ErrorKind::Other, "x")), "other");
+        assert_eq!(error_kind(&rate_limited("slow down")), "rate_limited");
+        assert_eq!(
+            rate_limited("slow down").to_string(),
+            "rate limited: slow down"
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new();
+        metrics.record_lookup("ripe", Duration::from_millis(3), None);
+        metrics.record_lookup("ripe", Duration::from_millis(200), None);
+        metrics.record_lookup(
+            "ripe",
+            Duration::from_secs(30),
+            Some(&Error::new(ErrorKind::TimedOut, "timed out")),
+        );
+        metrics.record_lookup(
+            "ipapi",
+            Duration::from_millis(1),
+            Some(&rate_limited("429")),
+        );
+        metrics.record_rate_limit_wait("ipapi", Duration::from_millis(1500));
+        let text = metrics.render();
+        let lines: Vec<&str> = text.lines().collect();
+
+        for expected in [
+            "asn_fetcher_lookups_total{provider=\"ipapi\"} 1",
+            "asn_fetcher_lookups_total{provider=\"ripe\"} 3",
+            "asn_fetcher_lookup_errors_total{provider=\"ipapi\",kind=\"rate_limited\"} 1",
+            "asn_fetcher_lookup_errors_total{provider=\"ripe\",kind=\"timed_out\"} 1",
+            "asn_fetcher_rate_limit_wait_seconds{provider=\"ipapi\"} 1.5",
+            "asn_fetcher_rate_limit_wait_seconds{provider=\"ripe\"} 0",
+            "asn_fetcher_lookup_duration_seconds_bucket{provider=\"ripe\",le=\"0.001\"} 0",
+            "asn_fetcher_lookup_duration_seconds_bucket{provider=\"ripe\",le=\"0.005\"} 1",
+            "asn_fetcher_lookup_duration_seconds_bucket{provider=\"ripe\",le=\"0.25\"} 2",
+            "asn_fetcher_lookup_duration_seconds_bucket{provider=\"ripe\",le=\"10\"} 2",
+            "asn_fetcher_lookup_duration_seconds_bucket{provider=\"ripe\",le=\"+Inf\"} 3",
+            "asn_fetcher_lookup_duration_seconds_count{provider=\"ripe\"} 3",
+            "asn_fetcher_cache_hits_total 0",
+            "# TYPE asn_fetcher_lookup_duration_seconds histogram",
+        ] {
+            assert!(lines.contains(&expected), "missing {}\n{}", expected, text);
+        }
+        assert!(!text.contains("asn_fetcher_cache_hit_ratio"));
+
+        metrics.record_cache(false);
+        metrics.record_cache(true);
+        metrics.record_cache(true);
+        metrics.record_cache(true);
+        metrics.set_cache_entries(2);
+        let text = metrics.render();
+        assert!(text.contains("\nasn_fetcher_cache_hits_total 3\n"));
+        assert!(text.contains("\nasn_fetcher_cache_misses_total 1\n"));
+        assert!(text.contains("\nasn_fetcher_cache_entries 2\n"));
+        assert!(text.contains("\nasn_fetcher_cache_hit_ratio 0.75\n"));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape(r#"a"b\c"#), r#"a\"b\\c"#);
+    }
+
+    #[test]
+    fn test_write_file() {
+        let path = std::env::temp_dir().join(format!("asn-fetcher-{}.prom", std::process::id()));
+        let metrics = Metrics::new();
+        metrics.record_lookup("pfx2as", Duration::from_micros(20), None);
+        metrics.write_file(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), metrics.render());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,118 @@
+// Bounds-checked reader for binary wire formats
+
+use std::io::{Error, ErrorKind};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Reads big-endian (network order) fields from a byte slice
+///
+/// Every read is bounds-checked and returns `ErrorKind::UnexpectedEof` instead
+/// of panicking on truncated input.
+#[derive(Debug, Clone)]
+pub(crate) struct ByteReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        ByteReader { data, pos: 0 }
+    }
+
+    /// Number of unread bytes
+    pub(crate) fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    pub(crate) fn bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if len > self.remaining() {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                format!(
+                    "truncated data: needed {} bytes at offset {}, {} available",
+                    len,
+                    self.pos,
+                    self.remaining()
+                ),
+            ));
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, Error> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, Error> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub(crate) fn ipv4(&mut self) -> Result<Ipv4Addr, Error> {
+        Ok(Ipv4Addr::from(self.u32()?))
+    }
+
+    pub(crate) fn ipv6(&mut self) -> Result<Ipv6Addr, Error> {
+        let mut buf = [0u8; 16];
+        buf.copy_from_slice(self.bytes(16)?);
+        Ok(Ipv6Addr::from(buf))
+    }
+
+    /// Reads a 4- or 16-byte address depending on the family flag
+    pub(crate) fn ip(&mut self, ipv6: bool) -> Result<IpAddr, Error> {
+        Ok(if ipv6 {
+            IpAddr::V6(self.ipv6()?)
+        } else {
+            IpAddr::V4(self.ipv4()?)
+        })
+    }
+
+    /// Splits off the next `len` bytes as an independent reader
+    pub(crate) fn sub(&mut self, len: usize) -> Result<ByteReader<'a>, Error> {
+        Ok(ByteReader::new(self.bytes(len)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reads_big_endian_fields() {
+        let data = [0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 192, 0, 2, 1];
+        let mut reader = ByteReader::new(&data);
+        assert_eq!(reader.u8().unwrap(), 0x01);
+        assert_eq!(reader.u16().unwrap(), 0x0203);
+        assert_eq!(reader.u32().unwrap(), 0x04050607);
+        assert_eq!(reader.ip(false).unwrap().to_string(), "192.0.2.1");
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn test_truncated_read_is_an_error() {
+        let mut reader = ByteReader::new(&[0x01]);
+        let err = reader.u16().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(reader.remaining(), 1);
+    }
+
+    #[test]
+    fn test_sub_reader() {
+        let data = [1, 2, 3, 4];
+        let mut reader = ByteReader::new(&data);
+        let mut sub = reader.sub(2).unwrap();
+        assert_eq!(sub.u16().unwrap(), 0x0102);
+        assert_eq!(reader.remaining(), 2);
+        assert!(reader.sub(3).is_err());
+    }
+}
@@ -1,2 +1,714 @@
 // IP address utilities
-// This file will contain IP address validation and manipulation functions
+// This file contains IP address validation and manipulation functions
+
+use std::{
+    fmt,
+    io::{Error, ErrorKind},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
+
+/// Converts an IP address into its integer value
+pub fn ip_to_u128(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(v4) => u32::from(v4) as u128,
+        IpAddr::V6(v6) => u128::from(v6),
+    }
+}
+
+/// Converts an integer value back into an IP address of the requested family
+pub fn u128_to_ip(value: u128, ipv4: bool) -> IpAddr {
+    if ipv4 {
+        IpAddr::V4(Ipv4Addr::from(value as u32))
+    } else {
+        IpAddr::V6(Ipv6Addr::from(value))
+    }
+}
+
+/// Returns the number of bits in an address of the given family (32 or 128)
+pub fn address_bits(ip: IpAddr) -> u8 {
+    if ip.is_ipv4() {
+        32
+    } else {
+        128
+    }
+}
+
+/// An IPv4 or IPv6 network prefix in CIDR notation (e.g., "192.0.2.0/24")
+///
+/// Host bits are always cleared, so "192.0.2.1/24" and "192.0.2.0/24" are equal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Prefix {
+    addr: IpAddr,
+    len: u8,
+}
+
+impl Prefix {
+    /// Creates a prefix from an address and a prefix length, clearing host bits
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the length exceeds the address family's bit width
+    pub fn new(addr: IpAddr, len: u8) -> Result<Self, Error> {
+        let bits = address_bits(addr);
+        if len > bits {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid prefix length /{} for {}", len, addr),
+            ));
+        }
+        let value = ip_to_u128(addr) & Self::mask(len, bits);
+        Ok(Prefix {
+            addr: u128_to_ip(value, addr.is_ipv4()),
+            len,
+        })
+    }
+
+    /// Returns a host prefix (/32 or /128) covering exactly one address
+    pub fn host(addr: IpAddr) -> Self {
+        Prefix {
+            addr,
+            len: address_bits(addr),
+        }
+    }
+
+    /// Network mask for a prefix length, expressed in the low `bits` bits
+    fn mask(len: u8, bits: u8) -> u128 {
+        let all = u128::MAX >> (128 - bits as u32);
+        let host = all.checked_shr(len as u32).unwrap_or(0);
+        all & !host
+    }
+
+    /// The network address of the prefix
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    /// The prefix length in bits
+    pub fn prefix_len(&self) -> u8 {
+        self.len
+    }
+
+    /// The bit width of the prefix's address family (32 or 128)
+    pub fn max_len(&self) -> u8 {
+        address_bits(self.addr)
+    }
+
+    /// Returns true if this is an IPv4 prefix
+    pub fn is_ipv4(&self) -> bool {
+        self.addr.is_ipv4()
+    }
+
+    /// The first address of the prefix as an integer
+    pub fn first(&self) -> u128 {
+        ip_to_u128(self.addr)
+    }
+
+    /// The last address of the prefix as an integer
+    pub fn last(&self) -> u128 {
+        let bits = self.max_len();
+        self.first() | (Self::mask(bits, bits) & !Self::mask(self.len, bits))
+    }
+
+    /// Returns true if the address falls within this prefix
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        if ip.is_ipv4() != self.is_ipv4() {
+            return false;
+        }
+        let value = ip_to_u128(ip);
+        value >= self.first() && value <= self.last()
+    }
+
+    /// The prefix one bit shorter that contains this one, or None for a /0
+    pub fn supernet(&self) -> Option<Prefix> {
+        let len = self.len.checked_sub(1)?;
+        Prefix::new(self.addr, len).ok()
+    }
+
+    /// The other half of this prefix's supernet, or None for a /0
+    pub fn sibling(&self) -> Option<Prefix> {
+        if self.len == 0 {
+            return None;
+        }
+        let bit = 1u128 << (self.max_len() - self.len);
+        Some(Prefix {
+            addr: u128_to_ip(self.first() ^ bit, self.is_ipv4()),
+            len: self.len,
+        })
+    }
+
+    /// Returns true if `other` lies entirely within this prefix
+    pub fn contains_prefix(&self, other: &Prefix) -> bool {
+        self.is_ipv4() == other.is_ipv4() && self.len <= other.len && self.contains(other.addr)
+    }
+
+    /// Returns true if the two prefixes share any address, i.e. one contains the other
+    pub fn overlaps(&self, other: &Prefix) -> bool {
+        self.contains_prefix(other) || other.contains_prefix(self)
+    }
+
+    /// Splits the prefix into its more-specifics of length `len`, in address order
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `len` is shorter than the prefix or longer than the address family allows
+    pub fn subnets(&self, len: u8) -> Result<Subnets, Error> {
+        if len < self.len || len > self.max_len() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("cannot split {} into /{} prefixes", self, len),
+            ));
+        }
+        Ok(Subnets {
+            next: Some(self.first()),
+            last: self.last(),
+            len,
+            ipv4: self.is_ipv4(),
+        })
+    }
+
+    /// Iterates over the usable host addresses of the prefix
+    ///
+    /// Like Python's `ipaddress`, the network and broadcast addresses of IPv4
+    /// prefixes and the subnet-router anycast address of IPv6 prefixes are
+    /// skipped, except for point-to-point /31 and /127 and single-address prefixes.
+    pub fn hosts(&self) -> Hosts {
+        let (mut first, mut last) = (self.first(), self.last());
+        if self.max_len() - self.len >= 2 {
+            first += 1;
+            if self.is_ipv4() {
+                last -= 1;
+            }
+        }
+        Hosts {
+            next: Some(first),
+            last,
+            ipv4: self.is_ipv4(),
+        }
+    }
+
+    /// Splits an inclusive address range into the minimal list of covering prefixes
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the addresses are of different families or `start > end`
+    pub fn from_range(start: IpAddr, end: IpAddr) -> Result<Vec<Prefix>, Error> {
+        if start.is_ipv4() != end.is_ipv4() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("mixed address families in range {} - {}", start, end),
+            ));
+        }
+        let (current, end) = (ip_to_u128(start), ip_to_u128(end));
+        if current > end {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("range start {} is after end {}", start, end),
+            ));
+        }
+
+        Ok(cover_range(current, end, start.is_ipv4()))
+    }
+}
+
+/// Covers the inclusive integer range `start..=end` with the fewest prefixes
+fn cover_range(start: u128, end: u128, ipv4: bool) -> Vec<Prefix> {
+    let bits = if ipv4 { 32 } else { 128 };
+    let mut current = start;
+    let mut prefixes = Vec::new();
+    loop {
+        // Largest block aligned at `current` that does not run past `end`
+        let mut size = current.trailing_zeros().min(bits as u32);
+        while size > 0 && (current | (u128::MAX >> (128 - size))) > end {
+            size -= 1;
+        }
+        prefixes.push(Prefix {
+            addr: u128_to_ip(current, ipv4),
+            len: bits - size as u8,
+        });
+        let last = current | u128::MAX.checked_shr(128 - size).unwrap_or(0);
+        if last >= end {
+            break;
+        }
+        current = last + 1;
+    }
+    prefixes
+}
+
+/// Sorted, merged inclusive address ranges per family, IPv4 first
+fn merged_ranges(prefixes: impl IntoIterator<Item = Prefix>) -> Vec<(bool, u128, u128)> {
+    let mut ranges: Vec<(bool, u128, u128)> = prefixes
+        .into_iter()
+        .map(|p| (!p.is_ipv4(), p.first(), p.last()))
+        .collect();
+    ranges.sort_unstable();
+    let mut merged: Vec<(bool, u128, u128)> = Vec::with_capacity(ranges.len());
+    for (v6, first, last) in ranges {
+        match merged.last_mut() {
+            Some(current)
+                if current.0 == v6 && current.2.checked_add(1).map_or(true, |n| first <= n) =>
+            {
+                current.2 = current.2.max(last);
+            }
+            _ => merged.push((v6, first, last)),
+        }
+    }
+    merged
+        .into_iter()
+        .map(|(v6, first, last)| (!v6, first, last))
+        .collect()
+}
+
+/// Returns true for private address space: RFC 1918, carrier-grade NAT
+/// (100.64.0.0/10) and unique local IPv6 (fc00::/7)
+pub fn is_private(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, _, _] = v4.octets();
+            v4.is_private() || (a == 100 && b & 0xc0 == 64)
+        }
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => is_private(IpAddr::V4(v4)),
+            None => v6.segments()[0] & 0xfe00 == 0xfc00,
+        },
+    }
+}
+
+/// Returns true for addresses that are never routed on the public internet
+///
+/// Covers private space along with unspecified, loopback, link-local,
+/// documentation, benchmarking, multicast and reserved ranges, and any IPv6
+/// address outside 2000::/3.
+pub fn is_bogon(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, c, _] = v4.octets();
+            is_private(ip)
+                || a == 0
+                || a >= 224
+                || v4.is_loopback()
+                || v4.is_link_local()
+                || v4.is_documentation()
+                || (a == 192 && b == 0 && c == 0)
+                || (a == 198 && b & 0xfe == 18)
+        }
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => is_bogon(IpAddr::V4(v4)),
+            None => {
+                let segments = v6.segments();
+                segments[0] & 0xe000 != 0x2000 || (segments[0] == 0x2001 && segments[1] == 0xdb8)
+            }
+        },
+    }
+}
+
+/// Collapses prefixes into the smallest list covering exactly the same addresses
+///
+/// Duplicates and contained prefixes are dropped and adjacent prefixes are
+/// merged, so 192.0.2.0/25 and 192.0.2.128/25 become 192.0.2.0/24. The result
+/// is sorted with IPv4 before IPv6.
+pub fn aggregate(prefixes: impl IntoIterator<Item = Prefix>) -> Vec<Prefix> {
+    merged_ranges(prefixes)
+        .into_iter()
+        .flat_map(|(ipv4, first, last)| cover_range(first, last, ipv4))
+        .collect()
+}
+
+/// Removes the addresses of `remove` from `prefixes`, returning the aggregated remainder
+pub fn subtract(
+    prefixes: impl IntoIterator<Item = Prefix>,
+    remove: impl IntoIterator<Item = Prefix>,
+) -> Vec<Prefix> {
+    let remove = merged_ranges(remove);
+    let mut result = Vec::new();
+    for (ipv4, mut first, last) in merged_ranges(prefixes) {
+        let mut remaining = true;
+        for &(_, cut_first, cut_last) in remove.iter().filter(|r| r.0 == ipv4) {
+            if cut_last < first || cut_first > last {
+                continue;
+            }
+            if cut_first > first {
+                result.extend(cover_range(first, cut_first - 1, ipv4));
+            }
+            if cut_last >= last {
+                remaining = false;
+                break;
+            }
+            first = cut_last + 1;
+        }
+        if remaining {
+            result.extend(cover_range(first, last, ipv4));
+        }
+    }
+    result
+}
+
+/// Iterator over the more-specifics of a prefix, created by [`Prefix::subnets`]
+#[derive(Debug, Clone)]
+pub struct Subnets {
+    next: Option<u128>,
+    last: u128,
+    len: u8,
+    ipv4: bool,
+}
+
+impl Iterator for Subnets {
+    type Item = Prefix;
+
+    fn next(&mut self) -> Option<Prefix> {
+        let current = self.next?;
+        let prefix = Prefix {
+            addr: u128_to_ip(current, self.ipv4),
+            len: self.len,
+        };
+        let bits = if self.ipv4 { 32 } else { 128 };
+        let end = current
+            | u128::MAX
+                .checked_shr(128 - (bits - self.len) as u32)
+                .unwrap_or(0);
+        self.next = if end >= self.last {
+            None
+        } else {
+            Some(end + 1)
+        };
+        Some(prefix)
+    }
+}
+
+/// Iterator over the host addresses of a prefix, created by [`Prefix::hosts`]
+#[derive(Debug, Clone)]
+pub struct Hosts {
+    next: Option<u128>,
+    last: u128,
+    ipv4: bool,
+}
+
+impl Iterator for Hosts {
+    type Item = IpAddr;
+
+    fn next(&mut self) -> Option<IpAddr> {
+        let current = self.next?;
+        self.next = if current >= self.last {
+            None
+        } else {
+            Some(current + 1)
+        };
+        Some(u128_to_ip(current, self.ipv4))
+    }
+}
+
+impl fmt::Display for Prefix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.len)
+    }
+}
+
+impl FromStr for Prefix {
+    type Err = Error;
+
+    /// Parses "address/length"; a bare address is treated as a host prefix
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::new(ErrorKind::InvalidInput, format!("invalid prefix '{}'", s));
+        match s.trim().split_once('/') {
+            Some((addr, len)) => {
+                let addr: IpAddr = addr.parse().map_err(|_| invalid())?;
+                let len: u8 = len.parse().map_err(|_| invalid())?;
+                Prefix::new(addr, len)
+            }
+            None => Ok(Prefix::host(s.trim().parse().map_err(|_| invalid())?)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prefix(s: &str) -> Prefix {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        assert_eq!(prefix("192.0.2.0/24").to_string(), "192.0.2.0/24");
+        assert_eq!(prefix("2001:db8::/32").to_string(), "2001:db8::/32");
+        assert_eq!(prefix("192.0.2.7").to_string(), "192.0.2.7/32");
+    }
+
+    #[test]
+    fn test_host_bits_cleared() {
+        assert_eq!(prefix("192.0.2.77/24"), prefix("192.0.2.0/24"));
+        assert_eq!(prefix("10.1.2.3/0").to_string(), "0.0.0.0/0");
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!("192.0.2.0/33".parse::<Prefix>().is_err());
+        assert!("2001:db8::/129".parse::<Prefix>().is_err());
+        assert!("not-a-prefix/8".parse::<Prefix>().is_err());
+        assert!("192.0.2.0/x".parse::<Prefix>().is_err());
+    }
+
+    #[test]
+    fn test_contains() {
+        let p = prefix("192.0.2.0/24");
+        assert!(p.contains("192.0.2.255".parse().unwrap()));
+        assert!(!p.contains("192.0.3.0".parse().unwrap()));
+        assert!(!p.contains("::ffff:192.0.2.1".parse().unwrap()));
+        assert!(prefix("::/0").contains("2001:db8::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_first_last() {
+        let p = prefix("10.0.0.0/8");
+        assert_eq!(p.first(), 0x0a00_0000);
+        assert_eq!(p.last(), 0x0aff_ffff);
+        assert_eq!(prefix("0.0.0.0/0").last(), u32::MAX as u128);
+        assert_eq!(prefix("::/0").last(), u128::MAX);
+    }
+
+    #[test]
+    fn test_supernet_and_sibling() {
+        assert_eq!(
+            prefix("192.0.3.0/24").supernet(),
+            Some(prefix("192.0.2.0/23"))
+        );
+        assert_eq!(
+            prefix("192.0.3.0/24").sibling(),
+            Some(prefix("192.0.2.0/24"))
+        );
+        assert_eq!(prefix("0.0.0.0/1").sibling(), Some(prefix("128.0.0.0/1")));
+        assert_eq!(
+            prefix("2001:db8::/32").sibling(),
+            Some(prefix("2001:db9::/32"))
+        );
+        assert_eq!(prefix("::/0").supernet(), None);
+        assert_eq!(prefix("0.0.0.0/0").sibling(), None);
+    }
+
+    #[test]
+    fn test_containment_and_overlap() {
+        let net = prefix("10.0.0.0/8");
+        assert!(net.contains_prefix(&prefix("10.1.0.0/16")));
+        assert!(net.contains_prefix(&net));
+        assert!(!prefix("10.1.0.0/16").contains_prefix(&net));
+        assert!(!net.contains_prefix(&prefix("::a00:0/104")));
+        assert!(net.overlaps(&prefix("10.1.0.0/16")));
+        assert!(prefix("10.1.0.0/16").overlaps(&net));
+        assert!(!net.overlaps(&prefix("11.0.0.0/8")));
+        assert!(prefix("2001:db8::/32").overlaps(&prefix("::/0")));
+    }
+
+    #[test]
+    fn test_subnets() {
+        let subnets: Vec<Prefix> = prefix("192.0.2.0/24").subnets(26).unwrap().collect();
+        let expected: Vec<Prefix> = [
+            "192.0.2.0/26",
+            "192.0.2.64/26",
+            "192.0.2.128/26",
+            "192.0.2.192/26",
+        ]
+        .iter()
+        .map(|s| prefix(s))
+        .collect();
+        assert_eq!(subnets, expected);
+        assert_eq!(
+            prefix("192.0.2.0/24")
+                .subnets(24)
+                .unwrap()
+                .collect::<Vec<_>>(),
+            vec![prefix("192.0.2.0/24")]
+        );
+        let mut halves = prefix("::/0").subnets(1).unwrap();
+        assert_eq!(halves.next(), Some(prefix("::/1")));
+        assert_eq!(halves.next(), Some(prefix("8000::/1")));
+        assert_eq!(halves.next(), None);
+        assert_eq!(
+            prefix("::/0").subnets(128).unwrap().nth(5),
+            Some(prefix("::5/128"))
+        );
+        assert!(prefix("192.0.2.0/24").subnets(23).is_err());
+        assert!(prefix("192.0.2.0/24").subnets(33).is_err());
+    }
+
+    #[test]
+    fn test_hosts() {
+        let hosts: Vec<IpAddr> = prefix("192.0.2.0/30").hosts().collect();
+        assert_eq!(
+            hosts,
+            vec![
+                "192.0.2.1".parse::<IpAddr>().unwrap(),
+                "192.0.2.2".parse().unwrap()
+            ]
+        );
+        assert_eq!(prefix("192.0.2.0/24").hosts().count(), 254);
+        assert_eq!(prefix("192.0.2.0/31").hosts().count(), 2);
+        assert_eq!(
+            prefix("192.0.2.7/32").hosts().collect::<Vec<_>>(),
+            vec!["192.0.2.7".parse::<IpAddr>().unwrap()]
+        );
+        let v6: Vec<IpAddr> = prefix("2001:db8::/126").hosts().collect();
+        assert_eq!(v6.len(), 3);
+        assert_eq!(v6[2], "2001:db8::3".parse::<IpAddr>().unwrap());
+        assert_eq!(prefix("::/0").hosts().next(), Some("::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let input = [
+            "192.0.2.128/25",
+            "10.0.0.0/24",
+            "192.0.2.0/25",
+            "10.0.0.0/16",
+            "10.1.0.0/16",
+            "2001:db8:1::/48",
+            "2001:db8::/48",
+            "198.51.100.0/24",
+            "198.51.101.0/24",
+            "198.51.102.0/24",
+        ];
+        let aggregated = aggregate(input.iter().map(|s| prefix(s)));
+        let expected: Vec<Prefix> = [
+            "10.0.0.0/15",
+            "192.0.2.0/24",
+            "198.51.100.0/23",
+            "198.51.102.0/24",
+            "2001:db8::/47",
+        ]
+        .iter()
+        .map(|s| prefix(s))
+        .collect();
+        assert_eq!(aggregated, expected);
+        assert_eq!(
+            aggregate(vec![
+                prefix("0.0.0.0/1"),
+                prefix("128.0.0.0/1"),
+                prefix("::/0")
+            ]),
+            vec![prefix("0.0.0.0/0"), prefix("::/0")]
+        );
+        assert!(aggregate(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn test_subtract() {
+        let remainder = subtract(vec![prefix("192.0.2.0/24")], vec![prefix("192.0.2.64/26")]);
+        let expected: Vec<Prefix> = ["192.0.2.0/26", "192.0.2.128/25"]
+            .iter()
+            .map(|s| prefix(s))
+            .collect();
+        assert_eq!(remainder, expected);
+
+        // Removing everything, something unrelated, and the other family
+        assert!(subtract(vec![prefix("10.0.0.0/24")], vec![prefix("10.0.0.0/8")]).is_empty());
+        assert_eq!(
+            subtract(
+                vec![prefix("10.0.0.0/24"), prefix("2001:db8::/32")],
+                vec![prefix("11.0.0.0/8"), prefix("::/0")]
+            ),
+            vec![prefix("10.0.0.0/24")]
+        );
+        assert_eq!(
+            subtract(
+                vec![prefix("::/0")],
+                vec![prefix("::/1"), prefix("ffff::/16")]
+            ),
+            vec![
+                prefix("8000::/2"),
+                prefix("c000::/3"),
+                prefix("e000::/4"),
+                prefix("f000::/5"),
+                prefix("f800::/6"),
+                prefix("fc00::/7"),
+                prefix("fe00::/8"),
+                prefix("ff00::/9"),
+                prefix("ff80::/10"),
+                prefix("ffc0::/11"),
+                prefix("ffe0::/12"),
+                prefix("fff0::/13"),
+                prefix("fff8::/14"),
+                prefix("fffc::/15"),
+                prefix("fffe::/16")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_from_range_aligned() {
+        let prefixes =
+            Prefix::from_range("192.0.2.0".parse().unwrap(), "192.0.2.255".parse().unwrap())
+                .unwrap();
+        assert_eq!(prefixes, vec![prefix("192.0.2.0/24")]);
+    }
+
+    #[test]
+    fn test_from_range_unaligned() {
+        let prefixes =
+            Prefix::from_range("10.0.0.1".parse().unwrap(), "10.0.0.6".parse().unwrap()).unwrap();
+        let expected: Vec<Prefix> = ["10.0.0.1/32", "10.0.0.2/31", "10.0.0.4/31", "10.0.0.6/32"]
+            .iter()
+            .map(|s| prefix(s))
+            .collect();
+        assert_eq!(prefixes, expected);
+    }
+
+    #[test]
+    fn test_from_range_full_space() {
+        let v4 = Prefix::from_range(
+            "0.0.0.0".parse().unwrap(),
+            "255.255.255.255".parse().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(v4, vec![prefix("0.0.0.0/0")]);
+        let v6 = Prefix::from_range(
+            "::".parse().unwrap(),
+            "ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff".parse().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(v6, vec![prefix("::/0")]);
+    }
+
+    #[test]
+    fn test_from_range_invalid() {
+        assert!(
+            Prefix::from_range("10.0.0.2".parse().unwrap(), "10.0.0.1".parse().unwrap()).is_err()
+        );
+        assert!(Prefix::from_range("10.0.0.1".parse().unwrap(), "::1".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_private_and_bogon() {
+        let ip = |s: &str| -> IpAddr { s.parse().unwrap() };
+        for private in [
+            "10.1.2.3",
+            "172.31.0.1",
+            "192.168.1.1",
+            "100.64.0.1",
+            "fd00::1",
+            "::ffff:10.0.0.1",
+        ] {
+            assert!(is_private(ip(private)), "{}", private);
+            assert!(is_bogon(ip(private)), "{}", private);
+        }
+        for bogon in [
+            "0.1.2.3",
+            "127.0.0.1",
+            "169.254.1.1",
+            "192.0.0.8",
+            "192.0.2.1",
+            "198.19.0.1",
+            "224.0.0.1",
+            "255.255.255.255",
+            "::1",
+            "fe80::1",
+            "2001:db8::1",
+        ] {
+            assert!(!is_private(ip(bogon)), "{}", bogon);
+            assert!(is_bogon(ip(bogon)), "{}", bogon);
+        }
+        for public in ["8.8.8.8", "100.128.0.1", "172.32.0.1", "2606:4700::1111"] {
+            assert!(!is_private(ip(public)), "{}", public);
+            assert!(!is_bogon(ip(public)), "{}", public);
+        }
+    }
+}
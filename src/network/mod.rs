@@ -1,4 +1,9 @@
 // Network utilities module
 // This module contains network-related utility functions
 
+pub(crate) mod bytes;
 pub mod ip;
+pub mod trie;
+
+pub use ip::{aggregate, is_bogon, is_private, subtract, Prefix};
+pub use trie::PrefixTrie;
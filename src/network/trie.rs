@@ -0,0 +1,444 @@
+// Longest-prefix-match trie
+
+use super::ip::{ip_to_u128, u128_to_ip, Prefix};
+use std::net::IpAddr;
+
+/// A node in the binary trie; child index 0 means "no child" since the root is never a child
+#[derive(Debug, Clone)]
+struct Node<V> {
+    children: [u32; 2],
+    value: Option<V>,
+}
+
+impl<V> Node<V> {
+    fn new() -> Self {
+        Node {
+            children: [0, 0],
+            value: None,
+        }
+    }
+}
+
+/// Binary trie for a single address family
+#[derive(Debug, Clone)]
+struct Tree<V> {
+    nodes: Vec<Node<V>>,
+    bits: u8,
+}
+
+impl<V> Tree<V> {
+    fn new(bits: u8) -> Self {
+        Tree {
+            nodes: vec![Node::new()],
+            bits,
+        }
+    }
+
+    /// Returns bit `depth` of `key`, counting from the most significant address bit
+    fn bit(&self, key: u128, depth: u8) -> usize {
+        ((key >> (self.bits - 1 - depth)) & 1) as usize
+    }
+
+    fn insert(&mut self, key: u128, len: u8, value: V) -> Option<V> {
+        let mut node = 0;
+        for depth in 0..len {
+            let bit = self.bit(key, depth);
+            let child = self.nodes[node].children[bit];
+            node = if child == 0 {
+                self.nodes.push(Node::new());
+                let child = (self.nodes.len() - 1) as u32;
+                self.nodes[node].children[bit] = child;
+                child as usize
+            } else {
+                child as usize
+            };
+        }
+        self.nodes[node].value.replace(value)
+    }
+
+    /// Returns the index of the node at exactly `len` bits of `key`, if one exists
+    fn find(&self, key: u128, len: u8) -> Option<usize> {
+        let mut node = 0;
+        for depth in 0..len {
+            let child = self.nodes[node].children[self.bit(key, depth)];
+            if child == 0 {
+                return None;
+            }
+            node = child as usize;
+        }
+        Some(node)
+    }
+
+    fn longest_match(&self, key: u128) -> Option<(u8, &V)> {
+        let mut node = 0;
+        let mut best = self.nodes[0].value.as_ref().map(|v| (0, v));
+        for depth in 0..self.bits {
+            let child = self.nodes[node].children[self.bit(key, depth)];
+            if child == 0 {
+                break;
+            }
+            node = child as usize;
+            if let Some(value) = &self.nodes[node].value {
+                best = Some((depth + 1, value));
+            }
+        }
+        best
+    }
+
+    /// Returns the (length, value) pairs of every stored prefix covering the first `len` bits of `key`
+    fn covering(&self, key: u128, len: u8) -> Vec<(u8, &V)> {
+        let mut node = 0;
+        let mut found: Vec<(u8, &V)> = self.nodes[0].value.iter().map(|v| (0, v)).collect();
+        for depth in 0..len {
+            let child = self.nodes[node].children[self.bit(key, depth)];
+            if child == 0 {
+                break;
+            }
+            node = child as usize;
+            if let Some(value) = &self.nodes[node].value {
+                found.push((depth + 1, value));
+            }
+        }
+        found
+    }
+
+    /// Iterates over the subtree below the node for `len` bits of `key`, including that node
+    fn iter_from(&self, key: u128, len: u8, ipv4: bool) -> Iter<'_, V> {
+        let stack = match self.find(key, len) {
+            Some(node) => vec![(node as u32, key, len)],
+            None => Vec::new(),
+        };
+        Iter {
+            tree: self,
+            stack,
+            ipv4,
+        }
+    }
+}
+
+/// Pre-order traversal of a trie, yielding prefixes that carry a value
+///
+/// Created by [`PrefixTrie::iter`] and [`PrefixTrie::more_specifics`].
+pub struct Iter<'a, V> {
+    tree: &'a Tree<V>,
+    /// Pending (node, key, depth) triples, with the next node on top
+    stack: Vec<(u32, u128, u8)>,
+    ipv4: bool,
+}
+
+impl<'a, V> Iterator for Iter<'a, V> {
+    type Item = (Prefix, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((node, key, depth)) = self.stack.pop() {
+            let node = &self.tree.nodes[node as usize];
+            // Push the one-branch first so the zero-branch is visited first
+            for bit in [1u128, 0] {
+                let child = node.children[bit as usize];
+                if child != 0 {
+                    let shift = self.tree.bits - 1 - depth;
+                    self.stack.push((child, key | (bit << shift), depth + 1));
+                }
+            }
+            if let Some(value) = &node.value {
+                let prefix = Prefix::new(u128_to_ip(key, self.ipv4), depth).ok()?;
+                return Some((prefix, value));
+            }
+        }
+        None
+    }
+}
+
+/// Maps IPv4 and IPv6 prefixes to values and answers longest-prefix-match queries
+#[derive(Debug, Clone)]
+pub struct PrefixTrie<V> {
+    v4: Tree<V>,
+    v6: Tree<V>,
+    len: usize,
+}
+
+impl<V> Default for PrefixTrie<V> {
+    fn default() -> Self {
+        PrefixTrie {
+            v4: Tree::new(32),
+            v6: Tree::new(128),
+            len: 0,
+        }
+    }
+}
+
+impl<V> PrefixTrie<V> {
+    /// Creates an empty trie
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn tree(&self, ipv4: bool) -> &Tree<V> {
+        if ipv4 {
+            &self.v4
+        } else {
+            &self.v6
+        }
+    }
+
+    /// Inserts a value for a prefix, returning the value it replaced
+    pub fn insert(&mut self, prefix: Prefix, value: V) -> Option<V> {
+        let tree = if prefix.is_ipv4() {
+            &mut self.v4
+        } else {
+            &mut self.v6
+        };
+        let old = tree.insert(prefix.first(), prefix.prefix_len(), value);
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+    /// Returns the value stored for exactly this prefix
+    pub fn get(&self, prefix: Prefix) -> Option<&V> {
+        let tree = self.tree(prefix.is_ipv4());
+        let node = tree.find(prefix.first(), prefix.prefix_len())?;
+        tree.nodes[node].value.as_ref()
+    }
+
+    /// Returns a mutable reference to the value stored for exactly this prefix
+    pub fn get_mut(&mut self, prefix: Prefix) -> Option<&mut V> {
+        let tree = if prefix.is_ipv4() {
+            &mut self.v4
+        } else {
+            &mut self.v6
+        };
+        let node = tree.find(prefix.first(), prefix.prefix_len())?;
+        tree.nodes[node].value.as_mut()
+    }
+
+    /// Returns true if a value is stored for exactly this prefix
+    pub fn contains(&self, prefix: Prefix) -> bool {
+        self.get(prefix).is_some()
+    }
+
+    /// Finds the most specific prefix containing the address
+    pub fn longest_match(&self, ip: IpAddr) -> Option<(Prefix, &V)> {
+        let key = ip_to_u128(ip);
+        let (len, value) = self.tree(ip.is_ipv4()).longest_match(key)?;
+        let prefix = Prefix::new(u128_to_ip(key, ip.is_ipv4()), len).ok()?;
+        Some((prefix, value))
+    }
+
+    /// Returns every stored prefix that covers (or equals) the given prefix, least specific first
+    pub fn covering(&self, prefix: Prefix) -> Vec<(Prefix, &V)> {
+        let key = prefix.first();
+        self.tree(prefix.is_ipv4())
+            .covering(key, prefix.prefix_len())
+            .into_iter()
+            .filter_map(|(len, value)| {
+                let covering = Prefix::new(prefix.addr(), len).ok()?;
+                Some((covering, value))
+            })
+            .collect()
+    }
+
+    /// Iterates over every stored prefix covered by (or equal to) the given prefix, in address order
+    pub fn more_specifics(&self, prefix: Prefix) -> Iter<'_, V> {
+        self.tree(prefix.is_ipv4())
+            .iter_from(prefix.first(), prefix.prefix_len(), prefix.is_ipv4())
+    }
+
+    /// Number of prefixes stored
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if no prefixes are stored
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Iterates over all stored prefixes, IPv4 before IPv6, in address order with
+    /// covering prefixes before their more-specifics
+    pub fn iter(&self) -> impl Iterator<Item = (Prefix, &V)> {
+        self.v4
+            .iter_from(0, 0, true)
+            .chain(self.v6.iter_from(0, 0, false))
+    }
+}
+
+impl<V> FromIterator<(Prefix, V)> for PrefixTrie<V> {
+    /// Builds a trie from prefix/value pairs; later values replace earlier ones for the same prefix
+    fn from_iter<I: IntoIterator<Item = (Prefix, V)>>(iter: I) -> Self {
+        let mut trie = PrefixTrie::new();
+        trie.extend(iter);
+        trie
+    }
+}
+
+impl<V> Extend<(Prefix, V)> for PrefixTrie<V> {
+    fn extend<I: IntoIterator<Item = (Prefix, V)>>(&mut self, iter: I) {
+        for (prefix, value) in iter {
+            self.insert(prefix, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prefix(s: &str) -> Prefix {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_longest_match_prefers_more_specific() {
+        let mut trie = PrefixTrie::new();
+        trie.insert(prefix("10.0.0.0/8"), "eight");
+        trie.insert(prefix("10.1.0.0/16"), "sixteen");
+
+        let (matched, value) = trie.longest_match("10.1.2.3".parse().unwrap()).unwrap();
+        assert_eq!(matched, prefix("10.1.0.0/16"));
+        assert_eq!(*value, "sixteen");
+
+        let (matched, value) = trie.longest_match("10.2.0.1".parse().unwrap()).unwrap();
+        assert_eq!(matched, prefix("10.0.0.0/8"));
+        assert_eq!(*value, "eight");
+
+        assert!(trie.longest_match("11.0.0.1".parse().unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_families_are_separate() {
+        let mut trie = PrefixTrie::new();
+        trie.insert(prefix("0.0.0.0/0"), 4);
+        trie.insert(prefix("2001:db8::/32"), 6);
+        assert_eq!(
+            trie.longest_match("2001:db8::1".parse().unwrap())
+                .unwrap()
+                .1,
+            &6
+        );
+        assert!(trie.longest_match("2001:db9::1".parse().unwrap()).is_none());
+        assert_eq!(
+            trie.longest_match("192.0.2.1".parse().unwrap()).unwrap().1,
+            &4
+        );
+    }
+
+    #[test]
+    fn test_insert_replaces_value() {
+        let mut trie = PrefixTrie::new();
+        assert_eq!(trie.insert(prefix("192.0.2.0/24"), 1), None);
+        assert_eq!(trie.insert(prefix("192.0.2.0/24"), 2), Some(1));
+        assert_eq!(trie.len(), 1);
+
+        *trie.get_mut(prefix("192.0.2.0/24")).unwrap() += 1;
+        assert_eq!(trie.get_mut(prefix("192.0.2.0/24")), Some(&mut 3));
+        assert_eq!(trie.get_mut(prefix("192.0.2.0/25")), None);
+    }
+
+    #[test]
+    fn test_iter_in_order() {
+        let mut trie = PrefixTrie::new();
+        for (p, v) in [
+            ("2001:db8::/32", 4),
+            ("10.1.0.0/16", 2),
+            ("10.0.0.0/8", 1),
+            ("192.0.2.0/24", 3),
+        ] {
+            trie.insert(prefix(p), v);
+        }
+        let items: Vec<(String, i32)> = trie.iter().map(|(p, v)| (p.to_string(), *v)).collect();
+        assert_eq!(
+            items,
+            vec![
+                ("10.0.0.0/8".to_string(), 1),
+                ("10.1.0.0/16".to_string(), 2),
+                ("192.0.2.0/24".to_string(), 3),
+                ("2001:db8::/32".to_string(), 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_covering() {
+        let mut trie = PrefixTrie::new();
+        trie.insert(prefix("10.0.0.0/8"), 1);
+        trie.insert(prefix("10.1.0.0/16"), 2);
+        trie.insert(prefix("10.1.2.0/24"), 3);
+        trie.insert(prefix("10.2.0.0/16"), 4);
+
+        let found: Vec<(String, i32)> = trie
+            .covering(prefix("10.1.0.0/16"))
+            .into_iter()
+            .map(|(p, v)| (p.to_string(), *v))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("10.0.0.0/8".to_string(), 1),
+                ("10.1.0.0/16".to_string(), 2)
+            ]
+        );
+        assert!(trie.covering(prefix("11.0.0.0/8")).is_empty());
+    }
+
+    #[test]
+    fn test_more_specifics() {
+        let trie: PrefixTrie<i32> = [
+            ("10.0.0.0/8", 1),
+            ("10.1.0.0/16", 2),
+            ("10.1.2.0/24", 3),
+            ("10.2.0.0/16", 4),
+            ("11.0.0.0/8", 5),
+            ("::/0", 6),
+        ]
+        .iter()
+        .map(|(p, v)| (prefix(p), *v))
+        .collect();
+
+        let found: Vec<i32> = trie
+            .more_specifics(prefix("10.0.0.0/8"))
+            .map(|(_, v)| *v)
+            .collect();
+        assert_eq!(found, vec![1, 2, 3, 4]);
+        let found: Vec<String> = trie
+            .more_specifics(prefix("10.1.0.0/15"))
+            .map(|(p, _)| p.to_string())
+            .collect();
+        assert_eq!(found, vec!["10.1.0.0/16", "10.1.2.0/24"]);
+        assert_eq!(trie.more_specifics(prefix("0.0.0.0/0")).count(), 5);
+        assert_eq!(trie.more_specifics(prefix("::/0")).count(), 1);
+        assert_eq!(trie.more_specifics(prefix("12.0.0.0/8")).count(), 0);
+    }
+
+    #[test]
+    fn test_get_and_collect() {
+        let mut trie: PrefixTrie<&str> = vec![(prefix("192.0.2.0/24"), "a")].into_iter().collect();
+        trie.extend([
+            (prefix("2001:db8::/32"), "b"),
+            (prefix("192.0.2.0/24"), "c"),
+        ]);
+        assert_eq!(trie.len(), 2);
+        assert_eq!(trie.get(prefix("192.0.2.0/24")), Some(&"c"));
+        assert!(trie.contains(prefix("2001:db8::/32")));
+        assert!(!trie.contains(prefix("192.0.2.0/23")));
+        assert!(!trie.is_empty());
+        assert!(PrefixTrie::<()>::new().is_empty());
+    }
+
+    #[test]
+    fn test_host_routes() {
+        let mut trie = PrefixTrie::new();
+        trie.insert(prefix("192.0.2.1/32"), "host");
+        trie.insert(prefix("::1/128"), "loopback");
+        assert_eq!(
+            *trie.longest_match("192.0.2.1".parse().unwrap()).unwrap().1,
+            "host"
+        );
+        assert!(trie.longest_match("192.0.2.2".parse().unwrap()).is_none());
+        assert_eq!(
+            *trie.longest_match("::1".parse().unwrap()).unwrap().1,
+            "loopback"
+        );
+    }
+}
@@ -0,0 +1,8 @@
+// RPKI route origin validation
+
+pub mod rtr;
+pub mod vrp;
+
+pub use crate::asn::Validity;
+pub use rtr::{LiveVrps, RtrClient};
+pub use vrp::{Vrp, VrpSet};
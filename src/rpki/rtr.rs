@@ -0,0 +1,868 @@
+// RPKI-to-Router protocol client (RFC 6810 version 0, RFC 8210 version 1)
+
+use super::vrp::{Vrp, VrpSet};
+use crate::asn::{AsnInfo, Enricher};
+use crate::network::{bytes::ByteReader, Prefix};
+use std::{
+    collections::HashSet,
+    io::{BufReader, Error, ErrorKind, Read, Write},
+    net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs},
+    sync::{Arc, RwLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Error Report code sent by a cache that does not speak the requested version
+const UNSUPPORTED_VERSION: u16 = 4;
+/// Error Report code for a cache that has not finished its first validation run
+const NO_DATA_AVAILABLE: u16 = 2;
+/// Upper bound on a single PDU; Error Reports are the only variable-length PDUs we parse
+const MAX_PDU_LEN: u32 = 64 * 1024;
+
+/// Refresh, retry and expire intervals advertised by the cache, in seconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timers {
+    pub refresh: u32,
+    pub retry: u32,
+    pub expire: u32,
+}
+
+impl Default for Timers {
+    /// The RFC 8210 defaults, also used for version 0 caches that do not send timers
+    fn default() -> Self {
+        Timers {
+            refresh: 3600,
+            retry: 600,
+            expire: 7200,
+        }
+    }
+}
+
+/// A single RTR protocol data unit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pdu {
+    SerialNotify {
+        session: u16,
+        serial: u32,
+    },
+    SerialQuery {
+        session: u16,
+        serial: u32,
+    },
+    ResetQuery,
+    CacheResponse {
+        session: u16,
+    },
+    /// IPv4 or IPv6 Prefix PDU; `announce` is false for withdrawals
+    Prefix {
+        announce: bool,
+        vrp: Vrp,
+    },
+    EndOfData {
+        session: u16,
+        serial: u32,
+        /// Only present in version 1
+        timers: Option<Timers>,
+    },
+    CacheReset,
+    /// Router Key PDU (BGPsec); its contents are not used for origin validation
+    RouterKey,
+    ErrorReport {
+        code: u16,
+        text: String,
+    },
+}
+
+impl Pdu {
+    /// Serializes the PDU for the given protocol version
+    pub fn encode(&self, version: u8) -> Vec<u8> {
+        let (kind, field, body): (u8, u16, Vec<u8>) = match self {
+            Pdu::SerialNotify { session, serial } => (0, *session, serial.to_be_bytes().to_vec()),
+            Pdu::SerialQuery { session, serial } => (1, *session, serial.to_be_bytes().to_vec()),
+            Pdu::ResetQuery => (2, 0, vec![]),
+            Pdu::CacheResponse { session } => (3, *session, vec![]),
+            Pdu::Prefix { announce, vrp } => {
+                let mut body = vec![*announce as u8, vrp.prefix.prefix_len(), vrp.max_length, 0];
+                match vrp.prefix.addr() {
+                    IpAddr::V4(addr) => body.extend_from_slice(&addr.octets()),
+                    IpAddr::V6(addr) => body.extend_from_slice(&addr.octets()),
+                }
+                body.extend_from_slice(&vrp.asn.to_be_bytes());
+                let kind = if vrp.prefix.is_ipv4() { 4 } else { 6 };
+                (kind, 0, body)
+            }
+            Pdu::EndOfData {
+                session,
+                serial,
+                timers,
+            } => {
+                let mut body = serial.to_be_bytes().to_vec();
+                if version > 0 {
+                    let timers = timers.unwrap_or_default();
+                    for value in [timers.refresh, timers.retry, timers.expire] {
+                        body.extend_from_slice(&value.to_be_bytes());
+                    }
+                }
+                (7, *session, body)
+            }
+            Pdu::CacheReset => (8, 0, vec![]),
+            Pdu::RouterKey => (9, 0, vec![]),
+            Pdu::ErrorReport { code, text } => {
+                let mut body = 0u32.to_be_bytes().to_vec();
+                body.extend_from_slice(&(text.len() as u32).to_be_bytes());
+                body.extend_from_slice(text.as_bytes());
+                (10, *code, body)
+            }
+        };
+        let mut out = vec![version, kind];
+        out.extend_from_slice(&field.to_be_bytes());
+        out.extend_from_slice(&(8 + body.len() as u32).to_be_bytes());
+        out.extend(body);
+        out
+    }
+
+    /// Reads one PDU, returning it with the protocol version from its header
+    ///
+    /// # Errors
+    ///
+    /// Returns an error on I/O failure, unknown versions or types, or malformed PDUs
+    pub fn read<R: Read>(reader: &mut R) -> Result<(u8, Pdu), Error> {
+        let mut header = [0u8; 8];
+        reader.read_exact(&mut header)?;
+        let mut head = ByteReader::new(&header);
+        let version = head.u8()?;
+        let kind = head.u8()?;
+        let field = head.u16()?;
+        let len = head.u32()?;
+        if version > 1 {
+            return Err(invalid(format!("unsupported RTR version {}", version)));
+        }
+        if !(8..=MAX_PDU_LEN).contains(&len) {
+            return Err(invalid(format!("invalid RTR PDU length {}", len)));
+        }
+        let mut body = vec![0u8; len as usize - 8];
+        reader.read_exact(&mut body)?;
+        let mut body = ByteReader::new(&body);
+
+        let pdu = match kind {
+            0 => Pdu::SerialNotify {
+                session: field,
+                serial: body.u32()?,
+            },
+            1 => Pdu::SerialQuery {
+                session: field,
+                serial: body.u32()?,
+            },
+            2 => Pdu::ResetQuery,
+            3 => Pdu::CacheResponse { session: field },
+            4 | 6 => {
+                let flags = body.u8()?;
+                let prefix_len = body.u8()?;
+                let max_length = body.u8()?;
+                body.u8()?;
+                let addr = body.ip(kind == 6)?;
+                let width = if kind == 6 { 128 } else { 32 };
+                if max_length < prefix_len || max_length > width {
+                    return Err(invalid(format!(
+                        "invalid max length {} for a /{} prefix",
+                        max_length, prefix_len
+                    )));
+                }
+                let vrp = Vrp {
+                    prefix: Prefix::new(addr, prefix_len)?,
+                    max_length,
+                    asn: body.u32()?,
+                };
+                Pdu::Prefix {
+                    announce: flags & 1 == 1,
+                    vrp,
+                }
+            }
+            7 => {
+                let serial = body.u32()?;
+                let timers = if version > 0 {
+                    Some(Timers {
+                        refresh: body.u32()?,
+                        retry: body.u32()?,
+                        expire: body.u32()?,
+                    })
+                } else {
+                    None
+                };
+                Pdu::EndOfData {
+                    session: field,
+                    serial,
+                    timers,
+                }
+            }
+            8 => Pdu::CacheReset,
+            9 => Pdu::RouterKey,
+            10 => {
+                let encapsulated = body.u32()? as usize;
+                body.bytes(encapsulated)?;
+                let text_len = body.u32()? as usize;
+                let text = String::from_utf8_lossy(body.bytes(text_len)?).into_owned();
+                Pdu::ErrorReport { code: field, text }
+            }
+            _ => return Err(invalid(format!("unknown RTR PDU type {}", kind))),
+        };
+        Ok((version, pdu))
+    }
+}
+
+fn invalid(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// Outcome of reading a cache's reply to a query
+enum Reply {
+    Synced,
+    /// The cache cannot serve incremental updates and asked for a Reset Query
+    Reset,
+    /// The cache only speaks an older protocol version
+    Downgrade,
+}
+
+/// VRPs kept up to date by a background thread; every clone sees the latest set
+#[derive(Clone, Default)]
+pub struct LiveVrps {
+    current: Arc<RwLock<Option<Arc<VrpSet>>>>,
+}
+
+impl LiveVrps {
+    /// Starts from the given set
+    pub fn new(vrps: VrpSet) -> Self {
+        LiveVrps {
+            current: Arc::new(RwLock::new(Some(Arc::new(vrps)))),
+        }
+    }
+
+    /// Returns the current set, unaffected by later updates, or None once it expired
+    pub fn snapshot(&self) -> Option<Arc<VrpSet>> {
+        // A set is replaced whole, so a panicked writer cannot leave it half-updated
+        self.current
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    fn replace(&self, vrps: Option<VrpSet>) {
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = vrps.map(Arc::new);
+    }
+
+    /// True when no other clone is left to read updates
+    fn abandoned(&self) -> bool {
+        Arc::strong_count(&self.current) == 1
+    }
+}
+
+impl Enricher for LiveVrps {
+    /// Validates like `VrpSet`, leaving results unvalidated while the VRPs are expired
+    fn enrich(&self, ip: IpAddr, info: &mut AsnInfo) {
+        if let Some(vrps) = self.snapshot() {
+            vrps.enrich(ip, info)
+        }
+    }
+}
+
+/// Keeps a VRP set synchronized with an RPKI cache (e.g. Routinator on port 3323)
+///
+/// The first `sync` sends a Reset Query for the full set; later calls send a
+/// Serial Query and apply only the announced and withdrawn VRPs. Version 1 is
+/// tried first, falling back to version 0 for caches that only speak RFC 6810.
+pub struct RtrClient {
+    addr: SocketAddr,
+    reader: BufReader<TcpStream>,
+    version: u8,
+    session: Option<u16>,
+    serial: u32,
+    timers: Timers,
+    vrps: HashSet<Vrp>,
+}
+
+impl RtrClient {
+    const TIMEOUT_SECS: u64 = 30;
+
+    /// Connects to a cache without synchronizing yet
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the address does not resolve or the connection fails
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<Self, Error> {
+        let addr = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "RTR address did not resolve"))?;
+        Ok(RtrClient {
+            addr,
+            reader: Self::open(addr)?,
+            version: 1,
+            session: None,
+            serial: 0,
+            timers: Timers::default(),
+            vrps: HashSet::new(),
+        })
+    }
+
+    fn open(addr: SocketAddr) -> Result<BufReader<TcpStream>, Error> {
+        let timeout = Duration::from_secs(Self::TIMEOUT_SECS);
+        let stream = TcpStream::connect_timeout(&addr, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        Ok(BufReader::new(stream))
+    }
+
+    /// Brings the VRP set up to date with the cache, returning the number of VRPs held
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection fails, the cache reports an error or
+    /// sends PDUs that do not fit the current session
+    pub fn sync(&mut self) -> Result<usize, Error> {
+        loop {
+            let query = match self.session {
+                Some(session) => Pdu::SerialQuery {
+                    session,
+                    serial: self.serial,
+                },
+                None => Pdu::ResetQuery,
+            };
+            self.reader
+                .get_mut()
+                .write_all(&query.encode(self.version))?;
+
+            match self.read_reply()? {
+                Reply::Synced => return Ok(self.vrps.len()),
+                Reply::Reset => self.session = None,
+                Reply::Downgrade => {
+                    self.version = 0;
+                    self.reader = Self::open(self.addr)?;
+                }
+            }
+        }
+    }
+
+    fn read_reply(&mut self) -> Result<Reply, Error> {
+        let requested = self.version;
+        let mut announced = Vec::new();
+        let mut withdrawn = Vec::new();
+        let mut response_session = None;
+        loop {
+            let (version, pdu) = Pdu::read(&mut self.reader)?;
+            if version != self.version {
+                if self.session.is_none() && version < self.version {
+                    // A version 0 cache may answer a version 1 query in its own version
+                    self.version = version;
+                } else {
+                    return Err(invalid(format!(
+                        "cache switched from RTR version {} to {}",
+                        self.version, version
+                    )));
+                }
+            }
+
+            match pdu {
+                Pdu::SerialNotify { .. } => {}
+                Pdu::CacheResponse { session } => {
+                    if self.session.is_some_and(|current| current != session) {
+                        return Err(invalid(format!(
+                            "cache session changed from {} to {}",
+                            self.session.unwrap_or_default(),
+                            session
+                        )));
+                    }
+                    response_session = Some(session);
+                }
+                Pdu::Prefix { announce, vrp } => {
+                    if response_session.is_none() {
+                        return Err(invalid("Prefix PDU before Cache Response".to_string()));
+                    }
+                    if announce {
+                        announced.push(vrp);
+                    } else {
+                        withdrawn.push(vrp);
+                    }
+                }
+                Pdu::RouterKey => {}
+                Pdu::EndOfData {
+                    session,
+                    serial,
+                    timers,
+                } => {
+                    if response_session != Some(session) {
+                        return Err(invalid(format!(
+                            "End of Data for unexpected session {}",
+                            session
+                        )));
+                    }
+                    // A Reset Query answer replaces the whole set
+                    if self.session.is_none() {
+                        self.vrps.clear();
+                    }
+                    for vrp in withdrawn {
+                        self.vrps.remove(&vrp);
+                    }
+                    self.vrps.extend(announced);
+                    self.session = Some(session);
+                    self.serial = serial;
+                    self.timers = timers.unwrap_or_default();
+                    return Ok(Reply::Synced);
+                }
+                Pdu::CacheReset => return Ok(Reply::Reset),
+                Pdu::ErrorReport { code, .. }
+                    if code == UNSUPPORTED_VERSION && requested > 0 && self.session.is_none() =>
+                {
+                    return Ok(Reply::Downgrade);
+                }
+                Pdu::ErrorReport { code, text } => {
+                    let kind = if code == NO_DATA_AVAILABLE {
+                        ErrorKind::WouldBlock
+                    } else {
+                        ErrorKind::Other
+                    };
+                    return Err(Error::new(
+                        kind,
+                        format!("RTR cache error {}: {}", code, text),
+                    ));
+                }
+                Pdu::SerialQuery { .. } | Pdu::ResetQuery => {
+                    return Err(invalid("unexpected query PDU from cache".to_string()));
+                }
+            }
+        }
+    }
+
+    /// Returns a snapshot of the synchronized VRPs for origin validation
+    pub fn vrps(&self) -> VrpSet {
+        self.vrps.iter().copied().collect()
+    }
+
+    /// Number of VRPs currently held
+    pub fn len(&self) -> usize {
+        self.vrps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vrps.is_empty()
+    }
+
+    /// The negotiated protocol version (0 or 1)
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// The cache's session ID and serial number, once synchronized
+    pub fn session(&self) -> Option<(u16, u32)> {
+        self.session.map(|session| (session, self.serial))
+    }
+
+    /// How long to wait before the next `sync`, as advertised by the cache
+    pub fn refresh_interval(&self) -> Duration {
+        Duration::from_secs(self.timers.refresh as u64)
+    }
+
+    /// How long to wait before trying again after a failed `sync`
+    pub fn retry_interval(&self) -> Duration {
+        Duration::from_secs(self.timers.retry as u64)
+    }
+
+    /// How long the VRPs may be used without a successful `sync`
+    pub fn expire_interval(&self) -> Duration {
+        Duration::from_secs(self.timers.expire as u64)
+    }
+
+    /// Synchronizes, reconnecting first if the connection was lost
+    fn refresh(&mut self) -> Result<usize, Error> {
+        match self.sync() {
+            Ok(len) => Ok(len),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Err(e),
+            Err(_) => {
+                self.reader = Self::open(self.addr)?;
+                self.sync()
+            }
+        }
+    }
+
+    /// Moves the client to a background thread that syncs on the cache's timers
+    ///
+    /// Serial Queries are sent every refresh interval, or every retry interval
+    /// after a failure. If no sync succeeds within the expire interval the VRPs
+    /// are dropped, as RFC 8210 requires, until the cache answers again. The
+    /// thread stops once every clone of the returned `LiveVrps` is dropped.
+    pub fn spawn_refresh(mut self) -> LiveVrps {
+        let live = LiveVrps::new(self.vrps());
+        let shared = live.clone();
+        thread::spawn(move || {
+            let mut synced = Instant::now();
+            let mut wait = self.refresh_interval();
+            loop {
+                // RFC 8210 timers are at least a second; don't spin on a cache sending 0
+                thread::sleep(wait.max(Duration::from_secs(1)));
+                if shared.abandoned() {
+                    return;
+                }
+                match self.refresh() {
+                    Ok(len) => {
+                        tracing::debug!(vrps = len, serial = self.serial, "RTR cache synchronized");
+                        shared.replace(Some(self.vrps()));
+                        synced = Instant::now();
+                        wait = self.refresh_interval();
+                    }
+                    Err(e) => {
+                        tracing::warn!(cache = %self.addr, error = %e, "RTR refresh failed");
+                        let expired = synced.elapsed() >= self.expire_interval();
+                        if expired && shared.snapshot().is_some() {
+                            tracing::warn!(cache = %self.addr, "VRPs expired, validation paused");
+                            shared.replace(None);
+                        }
+                        wait = self.retry_interval();
+                    }
+                }
+            }
+        });
+        live
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread::{self, JoinHandle};
+
+    fn vrp(prefix: &str, max_length: u8, asn: u32) -> Vrp {
+        Vrp {
+            prefix: prefix.parse().unwrap(),
+            max_length,
+            asn,
+        }
+    }
+
+    /// Expected queries, each with a (version, PDUs) reply or `None` to refuse version 1
+    type Script = Vec<(Pdu, Option<(u8, Vec<Pdu>)>)>;
+
+    /// A scripted stand-in cache: for each expected query, replies with the given PDUs.
+    /// A `None` reply closes the connection, and the next query arrives on a new one.
+    fn serve(script: Script) -> (SocketAddr, JoinHandle<Vec<(u8, Pdu)>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let mut received = Vec::new();
+            let (mut stream, _) = listener.accept().unwrap();
+            for (expected, reply) in script {
+                let (version, query) = Pdu::read(&mut stream).unwrap();
+                assert_eq!(query, expected);
+                received.push((version, query));
+                match reply {
+                    Some((version, pdus)) => {
+                        for pdu in pdus {
+                            stream.write_all(&pdu.encode(version)).unwrap();
+                        }
+                    }
+                    None => {
+                        let error = Pdu::ErrorReport {
+                            code: UNSUPPORTED_VERSION,
+                            text: "version 0 only".to_string(),
+                        };
+                        stream.write_all(&error.encode(0)).unwrap();
+                        stream = listener.accept().unwrap().0;
+                    }
+                }
+            }
+            received
+        });
+        (addr, handle)
+    }
+
+    fn end_of_data(session: u16, serial: u32) -> Pdu {
+        Pdu::EndOfData {
+            session,
+            serial,
+            timers: Some(Timers {
+                refresh: 900,
+                retry: 300,
+                expire: 3600,
+            }),
+        }
+    }
+
+    fn announce(vrp: Vrp) -> Pdu {
+        Pdu::Prefix {
+            announce: true,
+            vrp,
+        }
+    }
+
+    #[test]
+    fn test_pdu_round_trip() {
+        let pdus = [
+            Pdu::SerialQuery {
+                session: 7,
+                serial: 42,
+            },
+            Pdu::ResetQuery,
+            announce(vrp("192.0.2.0/24", 24, 64496)),
+            Pdu::Prefix {
+                announce: false,
+                vrp: vrp("2001:db8::/32", 48, 64497),
+            },
+            end_of_data(7, 43),
+            Pdu::ErrorReport {
+                code: 2,
+                text: "no data".to_string(),
+            },
+        ];
+        for pdu in pdus {
+            let bytes = pdu.encode(1);
+            let (version, decoded) = Pdu::read(&mut bytes.as_slice()).unwrap();
+            assert_eq!(version, 1);
+            assert_eq!(decoded, pdu);
+        }
+
+        // Version 0 End of Data carries no timers
+        let bytes = end_of_data(7, 43).encode(0);
+        assert_eq!(bytes.len(), 12);
+        let (_, decoded) = Pdu::read(&mut bytes.as_slice()).unwrap();
+        assert_eq!(
+            decoded,
+            Pdu::EndOfData {
+                session: 7,
+                serial: 43,
+                timers: None
+            }
+        );
+    }
+
+    #[test]
+    fn test_rejects_malformed_pdus() {
+        let mut bad_version = Pdu::ResetQuery.encode(1);
+        bad_version[0] = 2;
+        assert!(Pdu::read(&mut bad_version.as_slice()).is_err());
+
+        let mut bad_len = Pdu::ResetQuery.encode(1);
+        bad_len[7] = 4;
+        assert!(Pdu::read(&mut bad_len.as_slice()).is_err());
+
+        let truncated = &announce(vrp("192.0.2.0/24", 24, 1)).encode(1)[..12];
+        assert!(Pdu::read(&mut &truncated[..]).is_err());
+
+        // RFC 8210 section 5.6: max length between the prefix length and the address width
+        for bad in [
+            vrp("192.0.2.0/24", 16, 1),
+            vrp("192.0.2.0/24", 33, 1),
+            vrp("2001:db8::/32", 129, 1),
+        ] {
+            let bytes = announce(bad).encode(1);
+            let err = Pdu::read(&mut bytes.as_slice()).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn test_reset_then_serial_sync() {
+        let (addr, server) = serve(vec![
+            (
+                Pdu::ResetQuery,
+                Some((
+                    1,
+                    vec![
+                        Pdu::CacheResponse { session: 9 },
+                        announce(vrp("192.0.2.0/24", 24, 64496)),
+                        announce(vrp("198.51.100.0/22", 24, 64497)),
+                        end_of_data(9, 1),
+                    ],
+                )),
+            ),
+            (
+                Pdu::SerialQuery {
+                    session: 9,
+                    serial: 1,
+                },
+                Some((
+                    1,
+                    vec![
+                        Pdu::CacheResponse { session: 9 },
+                        Pdu::Prefix {
+                            announce: false,
+                            vrp: vrp("198.51.100.0/22", 24, 64497),
+                        },
+                        announce(vrp("2001:db8::/32", 48, 64498)),
+                        end_of_data(9, 2),
+                    ],
+                )),
+            ),
+        ]);
+
+        let mut client = RtrClient::connect(addr).unwrap();
+        assert_eq!(client.sync().unwrap(), 2);
+        assert_eq!(client.session(), Some((9, 1)));
+        assert_eq!(client.refresh_interval(), Duration::from_secs(900));
+
+        assert_eq!(client.sync().unwrap(), 2);
+        assert_eq!(client.session(), Some((9, 2)));
+        let vrps = client.vrps();
+        assert!(vrps.covering("198.51.100.0/24".parse().unwrap()).is_empty());
+        assert_eq!(vrps.covering("2001:db8::/48".parse().unwrap()).len(), 1);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_refresh_in_background_until_expired() {
+        let timers = Some(Timers {
+            refresh: 1,
+            retry: 1,
+            expire: 1,
+        });
+        let (addr, server) = serve(vec![
+            (
+                Pdu::ResetQuery,
+                Some((
+                    1,
+                    vec![
+                        Pdu::CacheResponse { session: 9 },
+                        announce(vrp("192.0.2.0/24", 24, 64496)),
+                        Pdu::EndOfData {
+                            session: 9,
+                            serial: 1,
+                            timers,
+                        },
+                    ],
+                )),
+            ),
+            (
+                Pdu::SerialQuery {
+                    session: 9,
+                    serial: 1,
+                },
+                Some((
+                    1,
+                    vec![
+                        Pdu::CacheResponse { session: 9 },
+                        announce(vrp("2001:db8::/32", 48, 64497)),
+                        Pdu::EndOfData {
+                            session: 9,
+                            serial: 2,
+                            timers,
+                        },
+                    ],
+                )),
+            ),
+        ]);
+
+        let mut client = RtrClient::connect(addr).unwrap();
+        client.sync().unwrap();
+        let live = client.spawn_refresh();
+        let len = || live.snapshot().map(|vrps| vrps.len());
+        assert_eq!(len(), Some(1));
+
+        let wait_for = |expected: Option<usize>| {
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while len() != expected {
+                assert!(Instant::now() < deadline, "still {:?} VRPs", len());
+                thread::sleep(Duration::from_millis(20));
+            }
+        };
+        wait_for(Some(2));
+        // The cache is gone once its script ends, so the VRPs expire
+        server.join().unwrap();
+        wait_for(None);
+
+        let mut info = AsnInfo {
+            prefix: Some("192.0.2.0/24".to_string()),
+            ..AsnInfo::new("64496", "TEST")
+        };
+        live.enrich("192.0.2.1".parse().unwrap(), &mut info);
+        assert_eq!(info.rpki, None);
+    }
+
+    #[test]
+    fn test_cache_reset_triggers_reset_query() {
+        let (addr, server) = serve(vec![
+            (
+                Pdu::ResetQuery,
+                Some((
+                    1,
+                    vec![
+                        Pdu::CacheResponse { session: 1 },
+                        announce(vrp("192.0.2.0/24", 24, 64496)),
+                        end_of_data(1, 5),
+                    ],
+                )),
+            ),
+            (
+                Pdu::SerialQuery {
+                    session: 1,
+                    serial: 5,
+                },
+                Some((1, vec![Pdu::CacheReset])),
+            ),
+            (
+                Pdu::ResetQuery,
+                Some((
+                    1,
+                    vec![
+                        Pdu::CacheResponse { session: 2 },
+                        announce(vrp("203.0.113.0/24", 24, 64499)),
+                        end_of_data(2, 1),
+                    ],
+                )),
+            ),
+        ]);
+
+        let mut client = RtrClient::connect(addr).unwrap();
+        client.sync().unwrap();
+        assert_eq!(client.sync().unwrap(), 1);
+        assert_eq!(client.session(), Some((2, 1)));
+        assert!(client
+            .vrps()
+            .covering("192.0.2.0/24".parse().unwrap())
+            .is_empty());
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_downgrades_to_version_0() {
+        let (addr, server) = serve(vec![
+            (Pdu::ResetQuery, None),
+            (
+                Pdu::ResetQuery,
+                Some((
+                    0,
+                    vec![
+                        Pdu::CacheResponse { session: 3 },
+                        announce(vrp("192.0.2.0/24", 24, 64496)),
+                        end_of_data(3, 1),
+                    ],
+                )),
+            ),
+        ]);
+
+        let mut client = RtrClient::connect(addr).unwrap();
+        assert_eq!(client.sync().unwrap(), 1);
+        assert_eq!(client.version(), 0);
+        assert_eq!(client.refresh_interval(), Duration::from_secs(3600));
+
+        let received = server.join().unwrap();
+        assert_eq!(received[0].0, 1);
+        assert_eq!(received[1].0, 0);
+    }
+
+    #[test]
+    fn test_no_data_available_is_an_error() {
+        let (addr, server) = serve(vec![(
+            Pdu::ResetQuery,
+            Some((
+                1,
+                vec![Pdu::ErrorReport {
+                    code: NO_DATA_AVAILABLE,
+                    text: "still validating".to_string(),
+                }],
+            )),
+        )]);
+        let mut client = RtrClient::connect(addr).unwrap();
+        let err = client.sync().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+        assert!(client.is_empty());
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,283 @@
+// Validated ROA Payloads and route origin validation (RFC 6811)
+
+use crate::asn::{parse_asn, AsnInfo, Enricher, Validity};
+use crate::network::{trie::PrefixTrie, Prefix};
+use serde::Deserialize;
+use std::{
+    fs::File,
+    io::{BufReader, Error, ErrorKind, Read},
+    net::IpAddr,
+    path::Path,
+};
+
+/// A single Validated ROA Payload: `asn` may originate `prefix` up to `max_length`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Vrp {
+    pub prefix: Prefix,
+    pub max_length: u8,
+    pub asn: u32,
+}
+
+/// The `roas` array entry shared by the Routinator, rpki-client and OctoRPKI JSON exports
+#[derive(Deserialize)]
+struct RawVrp {
+    prefix: String,
+    #[serde(rename = "maxLength")]
+    max_length: Option<u8>,
+    /// "AS13335" in Routinator/OctoRPKI, 13335 in rpki-client
+    asn: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct RawExport {
+    roas: Vec<RawVrp>,
+}
+
+/// An indexed set of VRPs used to validate route origins
+#[derive(Debug, Default)]
+pub struct VrpSet {
+    trie: PrefixTrie<Vec<Vrp>>,
+    len: usize,
+}
+
+impl VrpSet {
+    /// Creates an empty set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads VRPs from a JSON export file
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not a valid VRP export
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = File::open(path.as_ref()).map_err(|e| {
+            Error::new(
+                e.kind(),
+                format!("failed to open {}: {}", path.as_ref().display(), e),
+            )
+        })?;
+        Self::from_reader(BufReader::new(file))
+    }
+
+    /// Parses a JSON export with a top-level `roas` array
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the JSON is malformed or an entry is invalid
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, Error> {
+        let export: RawExport = serde_json::from_reader(reader)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, format!("invalid VRP JSON: {}", e)))?;
+        let mut set = VrpSet::new();
+        for (index, raw) in export.roas.iter().enumerate() {
+            let vrp = Self::parse(raw).map_err(|e| {
+                Error::new(ErrorKind::InvalidData, format!("roas[{}]: {}", index, e))
+            })?;
+            set.insert(vrp);
+        }
+        Ok(set)
+    }
+
+    fn parse(raw: &RawVrp) -> Result<Vrp, Error> {
+        let prefix: Prefix = raw.prefix.parse()?;
+        let asn = match &raw.asn {
+            serde_json::Value::Number(n) => n.as_u64().and_then(|n| u32::try_from(n).ok()),
+            serde_json::Value::String(s) => parse_asn(s),
+            _ => None,
+        }
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("invalid asn {}", raw.asn)))?;
+        let max_length = raw.max_length.unwrap_or(prefix.prefix_len());
+        if max_length < prefix.prefix_len() || max_length > prefix.max_len() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("invalid maxLength {} for {}", max_length, prefix),
+            ));
+        }
+        Ok(Vrp {
+            prefix,
+            max_length,
+            asn,
+        })
+    }
+
+    /// Adds a VRP to the set, ignoring exact duplicates
+    pub fn insert(&mut self, vrp: Vrp) {
+        match self.trie.get_mut(vrp.prefix) {
+            Some(vrps) if vrps.contains(&vrp) => return,
+            Some(vrps) => vrps.push(vrp),
+            None => {
+                self.trie.insert(vrp.prefix, vec![vrp]);
+            }
+        }
+        self.len += 1;
+    }
+
+    /// Number of distinct VRPs
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns every VRP whose prefix covers the route prefix
+    pub fn covering(&self, prefix: Prefix) -> Vec<Vrp> {
+        self.trie
+            .covering(prefix)
+            .into_iter()
+            .flat_map(|(_, vrps)| vrps.iter().copied())
+            .collect()
+    }
+
+    /// Validates a route's origin AS following RFC 6811
+    pub fn validate(&self, prefix: Prefix, origin: u32) -> Validity {
+        let covering = self.covering(prefix);
+        if covering.is_empty() {
+            return Validity::NotFound;
+        }
+        // AS0 VRPs (RFC 6483) never match, since AS0 cannot originate routes
+        let same_origin = covering
+            .iter()
+            .filter(|vrp| vrp.asn == origin && origin != 0);
+        let mut seen_origin = false;
+        for vrp in same_origin {
+            if prefix.prefix_len() <= vrp.max_length {
+                return Validity::Valid;
+            }
+            seen_origin = true;
+        }
+        if seen_origin {
+            Validity::InvalidLength
+        } else {
+            Validity::InvalidAsn
+        }
+    }
+}
+
+impl FromIterator<Vrp> for VrpSet {
+    fn from_iter<I: IntoIterator<Item = Vrp>>(iter: I) -> Self {
+        let mut set = VrpSet::new();
+        for vrp in iter {
+            set.insert(vrp);
+        }
+        set
+    }
+}
+
+impl Enricher for VrpSet {
+    /// Annotates results that carry both a routed prefix and a numeric origin AS
+    fn enrich(&self, _ip: IpAddr, info: &mut AsnInfo) {
+        let prefix = info
+            .prefix
+            .as_deref()
+            .and_then(|p| p.parse::<Prefix>().ok());
+        if let (Some(prefix), Some(origin)) = (prefix, info.asn_number()) {
+            info.rpki = Some(self.validate(prefix, origin));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROUTINATOR: &str = r#"{
+        "metadata": {"generated": 1700000000},
+        "roas": [
+            {"asn": "AS13335", "prefix": "1.1.1.0/24", "maxLength": 24, "ta": "apnic"},
+            {"asn": "AS15169", "prefix": "8.8.0.0/16", "maxLength": 24, "ta": "arin"},
+            {"asn": "AS0", "prefix": "192.0.2.0/24", "maxLength": 32, "ta": "test"},
+            {"asn": "AS64496", "prefix": "2001:db8::/32", "maxLength": 48, "ta": "test"}
+        ]
+    }"#;
+
+    fn set() -> VrpSet {
+        VrpSet::from_reader(ROUTINATOR.as_bytes()).unwrap()
+    }
+
+    fn prefix(s: &str) -> Prefix {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_load_routinator_json() {
+        let set = set();
+        assert_eq!(set.len(), 4);
+        assert_eq!(
+            set.covering(prefix("1.1.1.0/24")),
+            vec![Vrp {
+                prefix: prefix("1.1.1.0/24"),
+                max_length: 24,
+                asn: 13335
+            }]
+        );
+    }
+
+    #[test]
+    fn test_load_rpki_client_json() {
+        let json = r#"{"roas": [{"asn": 13335, "prefix": "1.0.0.0/24", "maxLength": 24, "ta": "apnic", "expires": 1}]}"#;
+        let set = VrpSet::from_reader(json.as_bytes()).unwrap();
+        assert_eq!(set.validate(prefix("1.0.0.0/24"), 13335), Validity::Valid);
+    }
+
+    #[test]
+    fn test_validate_states() {
+        let set = set();
+        assert_eq!(set.validate(prefix("8.8.8.0/24"), 15169), Validity::Valid);
+        assert_eq!(set.validate(prefix("8.8.0.0/16"), 15169), Validity::Valid);
+        assert_eq!(
+            set.validate(prefix("8.8.8.0/25"), 15169),
+            Validity::InvalidLength
+        );
+        assert_eq!(
+            set.validate(prefix("8.8.8.0/24"), 64496),
+            Validity::InvalidAsn
+        );
+        assert_eq!(
+            set.validate(prefix("9.9.9.0/24"), 19281),
+            Validity::NotFound
+        );
+        assert_eq!(
+            set.validate(prefix("2001:db8:1::/48"), 64496),
+            Validity::Valid
+        );
+    }
+
+    #[test]
+    fn test_as0_vrp_makes_routes_invalid() {
+        let set = set();
+        assert_eq!(
+            set.validate(prefix("192.0.2.0/24"), 0),
+            Validity::InvalidAsn
+        );
+        assert_eq!(
+            set.validate(prefix("192.0.2.0/24"), 64496),
+            Validity::InvalidAsn
+        );
+    }
+
+    #[test]
+    fn test_invalid_entries_rejected() {
+        let bad_len = r#"{"roas": [{"asn": 1, "prefix": "10.0.0.0/16", "maxLength": 8}]}"#;
+        assert!(VrpSet::from_reader(bad_len.as_bytes()).is_err());
+        let bad_asn = r#"{"roas": [{"asn": "ASX", "prefix": "10.0.0.0/16"}]}"#;
+        assert!(VrpSet::from_reader(bad_asn.as_bytes()).is_err());
+        assert!(VrpSet::from_reader("{}".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_enrich_requires_prefix() {
+        let set = set();
+        let ip = "8.8.8.8".parse().unwrap();
+
+        let mut info = AsnInfo::new("15169", "Google LLC");
+        set.enrich(ip, &mut info);
+        assert_eq!(info.rpki, None);
+
+        info.prefix = Some("8.8.8.0/24".to_string());
+        set.enrich(ip, &mut info);
+        assert_eq!(info.rpki, Some(Validity::Valid));
+    }
+}
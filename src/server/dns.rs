@@ -0,0 +1,598 @@
+// DNS server answering Team Cymru style origin TXT queries
+
+use super::run_pool;
+use crate::asn::{parse_asn, Asn, AsnInfo};
+use crate::network::bytes::ByteReader;
+use std::{
+    io::{Error, ErrorKind},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket},
+    sync::Arc,
+};
+
+const TYPE_TXT: u16 = 16;
+const TYPE_OPT: u16 = 41;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+
+const RCODE_FORMERR: u8 = 1;
+const RCODE_SERVFAIL: u8 = 2;
+const RCODE_NXDOMAIN: u8 = 3;
+const RCODE_NOTIMP: u8 = 4;
+const RCODE_REFUSED: u8 = 5;
+
+/// Largest response sent over UDP without EDNS
+const MAX_UDP_RESPONSE: usize = 512;
+/// Largest response sent over UDP to clients advertising a bigger EDNS
+/// buffer, the size recommended to avoid IP fragmentation
+const MAX_EDNS_RESPONSE: u16 = 1232;
+/// Length of the OPT record added to responses to EDNS requests
+const OPT_LEN: usize = 11;
+
+/// What a name inside the zone asks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Query {
+    /// `<reversed octets>.origin.<zone>` or `<reversed nibbles>.origin6.<zone>`
+    Origin(IpAddr),
+    /// `AS<n>.<zone>`
+    As(u32),
+    /// The zone apex, which exists but has no TXT data
+    Apex,
+}
+
+/// The question section of a request
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Question {
+    labels: Vec<String>,
+    qtype: u16,
+    qclass: u16,
+    /// The question exactly as received, echoed back in the response
+    raw: Vec<u8>,
+}
+
+fn formerr(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Reads an uncompressed question name and its type and class
+fn read_question(reader: &mut ByteReader<'_>) -> Result<Question, Error> {
+    let start = reader.remaining();
+    let mut raw = Vec::new();
+    let mut labels = Vec::new();
+    loop {
+        let len = reader.u8()?;
+        raw.push(len);
+        if len == 0 {
+            break;
+        }
+        if len & 0xc0 != 0 {
+            return Err(formerr("compressed question names are not supported"));
+        }
+        let label = reader.bytes(len as usize)?;
+        raw.extend_from_slice(label);
+        labels.push(String::from_utf8_lossy(label).to_ascii_lowercase());
+        if start - reader.remaining() > 255 {
+            return Err(formerr("question name too long"));
+        }
+    }
+    let qtype = reader.u16()?;
+    let qclass = reader.u16()?;
+    raw.extend_from_slice(&qtype.to_be_bytes());
+    raw.extend_from_slice(&qclass.to_be_bytes());
+    Ok(Question {
+        labels,
+        qtype,
+        qclass,
+        raw,
+    })
+}
+
+/// Reads an OPT record from the additional section, returning the UDP payload
+/// size the client advertises, or None if the record is not an OPT record
+fn read_opt(reader: &mut ByteReader<'_>) -> Option<u16> {
+    if reader.u8().ok()? != 0 || reader.u16().ok()? != TYPE_OPT {
+        return None;
+    }
+    reader.u16().ok()
+}
+
+/// Parses the labels in front of the zone, or None if the name does not exist
+fn parse_query(labels: &[String]) -> Option<Query> {
+    match labels {
+        [] => Some(Query::Apex),
+        [label] => {
+            let asn = label.strip_prefix("as").and_then(parse_asn)?;
+            Some(Query::As(asn))
+        }
+        [octets @ .., origin] if origin == "origin" && octets.len() == 4 => {
+            let mut bytes = [0u8; 4];
+            for (byte, label) in bytes.iter_mut().rev().zip(octets) {
+                *byte = label.parse().ok()?;
+            }
+            Some(Query::Origin(IpAddr::V4(Ipv4Addr::from(bytes))))
+        }
+        [nibbles @ .., origin] if origin == "origin6" && nibbles.len() == 32 => {
+            let mut value = 0u128;
+            for label in nibbles.iter().rev() {
+                let nibble = u8::from_str_radix(label, 16)
+                    .ok()
+                    .filter(|_| label.len() == 1)?;
+                value = (value << 4) | u128::from(nibble);
+            }
+            Some(Query::Origin(IpAddr::V6(Ipv6Addr::from(value))))
+        }
+        _ => None,
+    }
+}
+
+/// Formats origin results as Cymru does: "ASN [ASN...] | prefix | CC | registry | allocated"
+///
+/// Origins announcing the same prefix share one record.
+fn origin_records(results: &[AsnInfo]) -> Vec<String> {
+    let mut groups: Vec<(Vec<String>, &AsnInfo)> = Vec::new();
+    for info in results {
+        let asn = info
+            .asn_number()
+            .map(|n| n.to_string())
+            .unwrap_or_else(|| info.asn.clone());
+        match groups
+            .iter_mut()
+            .find(|(_, first)| first.prefix == info.prefix)
+        {
+            Some((asns, _)) => asns.push(asn),
+            None => groups.push((vec![asn], info)),
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(asns, info)| {
+            format!(
+                "{} | {} | {} | {} | {}",
+                asns.join(" "),
+                info.prefix.as_deref().unwrap_or_default(),
+                info.country.as_deref().unwrap_or_default(),
+                info.registry.as_deref().unwrap_or_default(),
+                info.allocated.as_deref().unwrap_or_default()
+            )
+        })
+        .collect()
+}
+
+/// Formats AS results as "ASN | CC | registry | allocated | AS name"
+fn as_records(asn: u32, results: &[AsnInfo]) -> Vec<String> {
+    results
+        .iter()
+        .map(|info| {
+            format!(
+                "{} | {} | {} | {} | {}",
+                asn,
+                info.country.as_deref().unwrap_or_default(),
+                info.registry.as_deref().unwrap_or_default(),
+                info.allocated.as_deref().unwrap_or_default(),
+                info.holder
+            )
+        })
+        .collect()
+}
+
+/// Serves origin and AS lookups as DNS TXT records over UDP, like Team Cymru's
+/// `origin.asn.cymru.com`, `origin6.asn.cymru.com` and `asn.cymru.com` zones
+///
+/// Names outside the configured zone are refused; names inside it that do not
+/// resolve to a lookup, or whose lookup returns nothing, get NXDOMAIN.
+///
+/// There is no TCP listener, so answers are cut to the records that fit in
+/// 512 bytes, or in the client's EDNS buffer up to 1232 bytes.
+pub struct DnsServer {
+    socket: UdpSocket,
+    zone: Vec<String>,
+    ttl: u32,
+    lookup: Arc<dyn Asn>,
+    workers: usize,
+}
+
+impl DnsServer {
+    pub const DEFAULT_TTL: u32 = 3600;
+    /// Queries answered concurrently by default
+    pub const DEFAULT_WORKERS: usize = 64;
+
+    /// Binds the UDP socket for `zone`, e.g. "asn.cymru.com"
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the address cannot be bound
+    pub fn bind<A: ToSocketAddrs>(
+        addr: A,
+        zone: &str,
+        lookup: Arc<dyn Asn>,
+    ) -> Result<Self, Error> {
+        Ok(DnsServer {
+            socket: UdpSocket::bind(addr)?,
+            zone: zone
+                .trim_end_matches('.')
+                .split('.')
+                .filter(|label| !label.is_empty())
+                .map(str::to_ascii_lowercase)
+                .collect(),
+            ttl: Self::DEFAULT_TTL,
+            lookup,
+            workers: Self::DEFAULT_WORKERS,
+        })
+    }
+
+    /// Sets the TTL of answers, in seconds
+    pub fn ttl(mut self, ttl: u32) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Sets how many queries are answered concurrently
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers;
+        self
+    }
+
+    /// The address the server is listening on
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        self.socket.local_addr()
+    }
+
+    /// Answers queries on a fixed number of worker threads until receiving fails
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the socket fails
+    pub fn run(self) -> Result<(), Error> {
+        let mut buf = [0u8; 4096];
+        let packets = std::iter::from_fn(|| {
+            Some(
+                self.socket
+                    .recv_from(&mut buf)
+                    .map(|(len, peer)| (buf[..len].to_vec(), peer)),
+            )
+        });
+        run_pool(self.workers, packets, |(packet, peer)| {
+            if let Some(response) = self.respond(&packet) {
+                if let Err(e) = self.socket.send_to(&response, peer) {
+                    tracing::warn!(%peer, error = %e, "DNS reply failed");
+                }
+            }
+        })
+    }
+
+    /// Builds the response to a request packet, or None if it cannot be answered at all
+    fn respond(&self, packet: &[u8]) -> Option<Vec<u8>> {
+        let mut reader = ByteReader::new(packet);
+        let id = reader.u16().ok()?;
+        let flags = reader.u16().ok()?;
+        if flags & 0x8000 != 0 {
+            // Never answer responses
+            return None;
+        }
+        let qdcount = reader.u16().ok()?;
+        let ancount = reader.u16().ok()?;
+        let nscount = reader.u16().ok()?;
+        let arcount = reader.u16().ok()?;
+
+        let opcode = ((flags >> 11) & 0xf) as u8;
+        let header = |rcode: u8, question: Option<&Question>, answers: u16| {
+            let mut out = Vec::with_capacity(MAX_UDP_RESPONSE);
+            out.extend_from_slice(&id.to_be_bytes());
+            // QR, the request's opcode and RD, and AA
+            let flags = 0x8000 | (flags & 0x7900) | 0x0400 | u16::from(rcode);
+            out.extend_from_slice(&flags.to_be_bytes());
+            out.extend_from_slice(&u16::from(question.is_some()).to_be_bytes());
+            out.extend_from_slice(&answers.to_be_bytes());
+            out.extend_from_slice(&[0, 0, 0, 0]);
+            if let Some(question) = question {
+                out.extend_from_slice(&question.raw);
+            }
+            out
+        };
+
+        if opcode != 0 {
+            return Some(header(RCODE_NOTIMP, None, 0));
+        }
+        if qdcount != 1 {
+            return Some(header(RCODE_FORMERR, None, 0));
+        }
+        let question = match read_question(&mut reader) {
+            Ok(question) => question,
+            Err(_) => return Some(header(RCODE_FORMERR, None, 0)),
+        };
+
+        let edns = if ancount == 0 && nscount == 0 && arcount > 0 {
+            read_opt(&mut reader)
+        } else {
+            None
+        };
+        let limit = match edns {
+            Some(size) => {
+                usize::from(size.clamp(MAX_UDP_RESPONSE as u16, MAX_EDNS_RESPONSE)) - OPT_LEN
+            }
+            None => MAX_UDP_RESPONSE,
+        };
+        // Answers an EDNS request with an OPT record advertising our own buffer size
+        let reply = |mut out: Vec<u8>| {
+            if edns.is_some() {
+                out[10..12].copy_from_slice(&1u16.to_be_bytes());
+                out.push(0);
+                out.extend_from_slice(&TYPE_OPT.to_be_bytes());
+                out.extend_from_slice(&MAX_EDNS_RESPONSE.to_be_bytes());
+                out.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+            }
+            Some(out)
+        };
+
+        let labels = &question.labels;
+        let in_zone = labels.len() >= self.zone.len()
+            && labels[labels.len() - self.zone.len()..] == self.zone[..];
+        if !in_zone || question.qclass != CLASS_IN {
+            return reply(header(RCODE_REFUSED, Some(&question), 0));
+        }
+        let Some(query) = parse_query(&labels[..labels.len() - self.zone.len()]) else {
+            return reply(header(RCODE_NXDOMAIN, Some(&question), 0));
+        };
+
+        let records = match query {
+            Query::Apex => Ok(Vec::new()),
+            Query::Origin(ip) => self.lookup.lookup_asn(ip).map(|r| origin_records(&r)),
+            Query::As(asn) => self.lookup.lookup_as(asn).map(|r| as_records(asn, &r)),
+        };
+        let records = match records {
+            Ok(records) if records.is_empty() && query != Query::Apex => {
+                return reply(header(RCODE_NXDOMAIN, Some(&question), 0))
+            }
+            Ok(records) => records,
+            Err(e) => {
+                tracing::warn!(?query, error = %e, "lookup for DNS query failed");
+                return reply(header(RCODE_SERVFAIL, Some(&question), 0));
+            }
+        };
+        if question.qtype != TYPE_TXT && question.qtype != TYPE_ANY {
+            // The name exists but has no records of the requested type
+            return reply(header(0, Some(&question), 0));
+        }
+
+        let mut out = header(0, Some(&question), 0);
+        let mut answers = 0u16;
+        for record in &records {
+            let rdata: Vec<u8> = record
+                .as_bytes()
+                .chunks(255)
+                .flat_map(|chunk| std::iter::once(chunk.len() as u8).chain(chunk.iter().copied()))
+                .collect();
+            if out.len() + 12 + rdata.len() > limit {
+                // Without TCP to retry over, send the records that fit
+                tracing::debug!(
+                    ?query,
+                    "answer cut to {} of {} records",
+                    answers,
+                    records.len()
+                );
+                break;
+            }
+            // A pointer to the question name at offset 12
+            out.extend_from_slice(&[0xc0, 0x0c]);
+            out.extend_from_slice(&TYPE_TXT.to_be_bytes());
+            out.extend_from_slice(&CLASS_IN.to_be_bytes());
+            out.extend_from_slice(&self.ttl.to_be_bytes());
+            out.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+            out.extend_from_slice(&rdata);
+            answers += 1;
+        }
+        out[6..8].copy_from_slice(&answers.to_be_bytes());
+        reply(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    struct Fixed;
+
+    impl Asn for Fixed {
+        fn lookup_asn(&self, ip: IpAddr) -> Result<Vec<AsnInfo>, Error> {
+            let info = |asn: &str| AsnInfo {
+                prefix: Some("192.0.2.0/24".to_string()),
+                country: Some("US".to_string()),
+                registry: Some("arin".to_string()),
+                allocated: Some("2014-03-28".to_string()),
+                ..AsnInfo::new(asn, "EXAMPLE")
+            };
+            match ip.to_string().as_str() {
+                "192.0.2.1" => Ok(vec![info("AS64496"), info("64497")]),
+                "2001:db8::1" => Ok(vec![AsnInfo {
+                    prefix: Some("2001:db8::/32".to_string()),
+                    ..AsnInfo::new("64498", "V6")
+                }]),
+                "198.51.100.1" => Err(Error::new(ErrorKind::TimedOut, "timed out")),
+                "203.0.113.1" => Ok((0..30)
+                    .map(|i| AsnInfo {
+                        prefix: Some(format!("203.0.113.{}/32", i)),
+                        ..info("64500")
+                    })
+                    .collect()),
+                _ => Ok(Vec::new()),
+            }
+        }
+
+        fn lookup_as(&self, asn: u32) -> Result<Vec<AsnInfo>, Error> {
+            Ok(vec![AsnInfo::new(asn.to_string(), "EXAMPLE-AS, US")])
+        }
+    }
+
+    fn server() -> DnsServer {
+        DnsServer::bind("127.0.0.1:0", "asn.example.net.", Arc::new(Fixed)).unwrap()
+    }
+
+    fn query(name: &str, qtype: u16) -> Vec<u8> {
+        let mut packet = vec![0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+        for label in name.split('.') {
+            packet.push(label.len() as u8);
+            packet.extend_from_slice(label.as_bytes());
+        }
+        packet.push(0);
+        packet.extend_from_slice(&qtype.to_be_bytes());
+        packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+        packet
+    }
+
+    /// Returns the rcode and the TXT strings of a response
+    fn answers(response: &[u8]) -> (u8, Vec<String>) {
+        let mut reader = ByteReader::new(response);
+        assert_eq!(reader.u16().unwrap(), 0x1234);
+        let flags = reader.u16().unwrap();
+        assert_eq!(flags & 0x8500, 0x8500, "QR, AA and RD are set");
+        let qdcount = reader.u16().unwrap();
+        let ancount = reader.u16().unwrap();
+        reader.bytes(4).unwrap();
+        if qdcount == 1 {
+            read_question(&mut reader).unwrap();
+        }
+        let mut texts = Vec::new();
+        for _ in 0..ancount {
+            assert_eq!(reader.bytes(2).unwrap(), [0xc0, 0x0c]);
+            assert_eq!(reader.u16().unwrap(), TYPE_TXT);
+            reader.bytes(6).unwrap();
+            let len = reader.u16().unwrap() as usize;
+            let mut rdata = reader.sub(len).unwrap();
+            let mut text = String::new();
+            while !rdata.is_empty() {
+                let len = rdata.u8().unwrap() as usize;
+                text.push_str(&String::from_utf8_lossy(rdata.bytes(len).unwrap()));
+            }
+            texts.push(text);
+        }
+        ((flags & 0xf) as u8, texts)
+    }
+
+    #[test]
+    fn test_parse_query() {
+        let labels = |name: &str| -> Vec<String> { name.split('.').map(str::to_string).collect() };
+        assert_eq!(
+            parse_query(&labels("1.2.0.192.origin")),
+            Some(Query::Origin("192.0.2.1".parse().unwrap()))
+        );
+        let nibbles = "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.origin6";
+        assert_eq!(
+            parse_query(&labels(nibbles)),
+            Some(Query::Origin("2001:db8::1".parse().unwrap()))
+        );
+        assert_eq!(parse_query(&labels("as64496")), Some(Query::As(64496)));
+        assert_eq!(parse_query(&[]), Some(Query::Apex));
+        assert_eq!(parse_query(&labels("2.0.192.origin")), None);
+        assert_eq!(parse_query(&labels("1.2.0.300.origin")), None);
+        assert_eq!(parse_query(&labels("www")), None);
+    }
+
+    #[test]
+    fn test_origin_txt() {
+        let response = server()
+            .respond(&query("1.2.0.192.origin.ASN.example.net", TYPE_TXT))
+            .unwrap();
+        assert_eq!(
+            answers(&response),
+            (
+                0,
+                vec!["64496 64497 | 192.0.2.0/24 | US | arin | 2014-03-28".to_string()]
+            )
+        );
+
+        let name = "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.origin6.asn.example.net";
+        let response = server().respond(&query(name, TYPE_ANY)).unwrap();
+        assert_eq!(
+            answers(&response).1,
+            vec!["64498 | 2001:db8::/32 |  |  | ".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_as_txt() {
+        let response = server()
+            .respond(&query("AS64496.asn.example.net", TYPE_TXT))
+            .unwrap();
+        assert_eq!(
+            answers(&response).1,
+            vec!["64496 |  |  |  | EXAMPLE-AS, US".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_error_codes() {
+        let rcode =
+            |name: &str, qtype: u16| answers(&server().respond(&query(name, qtype)).unwrap());
+        assert_eq!(
+            rcode("1.2.0.192.origin.asn.example.org", TYPE_TXT).0,
+            RCODE_REFUSED
+        );
+        assert_eq!(
+            rcode("9.9.0.192.origin.asn.example.net", TYPE_TXT).0,
+            RCODE_NXDOMAIN
+        );
+        assert_eq!(rcode("bogus.asn.example.net", TYPE_TXT).0, RCODE_NXDOMAIN);
+        assert_eq!(
+            rcode("1.100.51.198.origin.asn.example.net", TYPE_TXT).0,
+            RCODE_SERVFAIL
+        );
+        // A records for an existing name and the apex are empty NOERROR answers
+        assert_eq!(rcode("1.2.0.192.origin.asn.example.net", 1), (0, vec![]));
+        assert_eq!(rcode("asn.example.net", TYPE_TXT), (0, vec![]));
+
+        let mut notify = query("asn.example.net", TYPE_TXT);
+        // NOTIFY (opcode 4), keeping RD set
+        notify[2] = 0x21;
+        assert_eq!(answers(&server().respond(&notify).unwrap()).0, RCODE_NOTIMP);
+        let mut response = query("asn.example.net", TYPE_TXT);
+        response[2] |= 0x80;
+        assert!(server().respond(&response).is_none());
+        assert_eq!(
+            answers(
+                &server()
+                    .respond(&query("asn.example.net", TYPE_TXT)[..14])
+                    .unwrap()
+            )
+            .0,
+            RCODE_FORMERR
+        );
+    }
+
+    #[test]
+    fn test_large_answers_fit_without_truncation() {
+        let request = query("1.113.0.203.origin.asn.example.net", TYPE_TXT);
+        let response = server().respond(&request).unwrap();
+        assert!(response.len() <= MAX_UDP_RESPONSE);
+        assert_eq!(response[2] & 0x02, 0, "TC is not set");
+        let plain = answers(&response).1.len();
+        assert!(plain > 0 && plain < 30);
+
+        let mut edns = request.clone();
+        edns[11] = 1;
+        edns.extend_from_slice(&[0, 0, 41, 0x10, 0x00, 0, 0, 0, 0, 0, 0]);
+        let response = server().respond(&edns).unwrap();
+        assert!(response.len() <= usize::from(MAX_EDNS_RESPONSE));
+        assert_eq!(response[10..12], [0, 1]);
+        assert!(response.ends_with(&[0, 0, 41, 0x04, 0xd0, 0, 0, 0, 0, 0, 0]));
+        let extended = answers(&response).1.len();
+        assert!(extended > plain && extended < 30);
+    }
+
+    #[test]
+    fn test_serves_over_udp() {
+        let server = server();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client
+            .set_read_timeout(Some(std::time::Duration::from_secs(5)))
+            .unwrap();
+        client
+            .send_to(&query("as64496.asn.example.net", TYPE_TXT), addr)
+            .unwrap();
+        let mut buf = [0u8; 512];
+        let len = client.recv(&mut buf).unwrap();
+        assert_eq!(answers(&buf[..len]).1.len(), 1);
+    }
+}
@@ -0,0 +1,453 @@
+// HTTP API exposing lookups to other services
+
+use super::run_pool;
+use crate::asn::{parse_asn, Asn, AsnInfo};
+use crate::metrics::Metrics;
+use serde_json::{json, Value};
+use std::{
+    io::{BufRead, BufReader, Error, ErrorKind, Read, Write},
+    net::{IpAddr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::Arc,
+    time::Duration,
+};
+
+/// Longest request line or header line accepted
+const MAX_LINE: u64 = 8 * 1024;
+/// Most headers accepted in one request
+const MAX_HEADERS: usize = 100;
+/// Largest request body accepted, enough for a full bulk request
+const MAX_BODY: usize = 1024 * 1024;
+
+/// A parsed HTTP request; only the parts the API uses are kept
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Request {
+    method: String,
+    /// The path without any query string
+    path: String,
+    body: Vec<u8>,
+}
+
+/// Content type of the Prometheus text exposition format
+const PROMETHEUS: &str = "text/plain; version=0.0.4";
+
+/// The body of a response
+#[derive(Debug, Clone, PartialEq)]
+enum Body {
+    Json(Value),
+    Text {
+        content_type: &'static str,
+        text: String,
+    },
+}
+
+impl Body {
+    fn content_type(&self) -> &'static str {
+        match self {
+            Body::Json(_) => "application/json",
+            Body::Text { content_type, .. } => content_type,
+        }
+    }
+
+    fn render(&self) -> String {
+        match self {
+            Body::Json(value) => value.to_string(),
+            Body::Text { text, .. } => text.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Response {
+    status: u16,
+    body: Body,
+}
+
+impl Response {
+    fn ok(body: Value) -> Self {
+        Response {
+            status: 200,
+            body: Body::Json(body),
+        }
+    }
+
+    fn text(content_type: &'static str, text: String) -> Self {
+        Response {
+            status: 200,
+            body: Body::Text { content_type, text },
+        }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        Response {
+            status,
+            body: Body::Json(json!({ "error": message.into() })),
+        }
+    }
+
+    /// Maps a lookup failure to the closest status code
+    fn lookup_error(err: &Error) -> Self {
+        let status = match err.kind() {
+            ErrorKind::InvalidInput => 400,
+            ErrorKind::Unsupported => 501,
+            _ => 502,
+        };
+        Response::error(status, err.to_string())
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        501 => "Not Implemented",
+        502 => "Bad Gateway",
+        _ => "Error",
+    }
+}
+
+/// Serves lookups from a provider over HTTP
+///
+/// Routes:
+///
+/// * `GET /ip/{ip}` returns the `AsnInfo` results for an address
+/// * `GET /as/{asn}` returns the `AsnInfo` results for an AS number
+/// * `POST /bulk` takes a JSON array of addresses and returns one
+///   `{"ip", "results"}` or `{"ip", "error"}` object per address
+/// * `GET /metrics` returns Prometheus metrics, when enabled with `metrics`
+///
+/// Connections are handled by a fixed number of worker threads and closed
+/// after one response.
+pub struct HttpServer {
+    listener: TcpListener,
+    lookup: Arc<dyn Asn>,
+    metrics: Option<Arc<Metrics>>,
+    workers: usize,
+}
+
+impl HttpServer {
+    /// Most addresses accepted in one bulk request
+    pub const MAX_BULK: usize = 10_000;
+    /// Connections handled concurrently by default
+    pub const DEFAULT_WORKERS: usize = 64;
+    const TIMEOUT_SECS: u64 = 30;
+
+    /// Binds the listening socket
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the address cannot be bound
+    pub fn bind<A: ToSocketAddrs>(addr: A, lookup: Arc<dyn Asn>) -> Result<Self, Error> {
+        Ok(HttpServer {
+            listener: TcpListener::bind(addr)?,
+            lookup,
+            metrics: None,
+            workers: Self::DEFAULT_WORKERS,
+        })
+    }
+
+    /// Sets how many connections are handled concurrently
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers;
+        self
+    }
+
+    /// Serves `metrics` on `GET /metrics`
+    pub fn metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// The address the server is listening on
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        self.listener.local_addr()
+    }
+
+    /// Accepts connections until the listener fails
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if accepting connections fails
+    pub fn run(&self) -> Result<(), Error> {
+        run_pool(self.workers, self.listener.incoming(), |stream| {
+            if let Err(e) = handle_connection(stream, self.lookup.as_ref(), self.metrics.as_deref())
+            {
+                tracing::warn!(error = %e, "HTTP connection failed");
+            }
+        })
+    }
+}
+
+fn handle_connection(
+    stream: TcpStream,
+    lookup: &dyn Asn,
+    metrics: Option<&Metrics>,
+) -> Result<(), Error> {
+    let timeout = Duration::from_secs(HttpServer::TIMEOUT_SECS);
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let response = match read_request(&mut reader) {
+        Ok(request) => route(&request, lookup, metrics),
+        Err(e) if e.kind() == ErrorKind::InvalidData => Response::error(400, e.to_string()),
+        Err(e) if e.kind() == ErrorKind::OutOfMemory => Response::error(413, e.to_string()),
+        Err(e) => return Err(e),
+    };
+    write_response(&mut &stream, &response)
+}
+
+/// Reads one line, failing on lines longer than `MAX_LINE`
+fn read_line<R: BufRead>(reader: &mut R) -> Result<String, Error> {
+    let mut line = String::new();
+    reader.by_ref().take(MAX_LINE).read_line(&mut line)?;
+    if !line.ends_with('\n') {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "request line too long or connection closed",
+        ));
+    }
+    Ok(line.trim_end().to_string())
+}
+
+fn read_request<R: BufRead>(reader: &mut R) -> Result<Request, Error> {
+    let request_line = read_line(reader)?;
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target), Some(_version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("malformed request line '{}'", request_line),
+        ));
+    };
+    let path = target.split('?').next().unwrap_or_default().to_string();
+
+    let mut content_length = 0;
+    for _ in 0..=MAX_HEADERS {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body)?;
+            return Ok(Request {
+                method: method.to_string(),
+                path,
+                body,
+            });
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("malformed header '{}'", line),
+            ));
+        };
+        if name.trim().eq_ignore_ascii_case("content-length") {
+            content_length = value
+                .trim()
+                .parse()
+                .map_err(|_| Error::new(ErrorKind::InvalidData, "invalid Content-Length header"))?;
+            if content_length > MAX_BODY {
+                return Err(Error::new(
+                    ErrorKind::OutOfMemory,
+                    format!("request body larger than {} bytes", MAX_BODY),
+                ));
+            }
+        }
+    }
+    Err(Error::new(ErrorKind::InvalidData, "too many headers"))
+}
+
+fn write_response<W: Write>(writer: &mut W, response: &Response) -> Result<(), Error> {
+    let body = response.body.render();
+    write!(
+        writer,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        reason(response.status),
+        response.body.content_type(),
+        body.len(),
+        body
+    )?;
+    writer.flush()
+}
+
+fn results(lookup: Result<Vec<AsnInfo>, Error>) -> Response {
+    match lookup {
+        Ok(results) => Response::ok(json!(results)),
+        Err(e) => Response::lookup_error(&e),
+    }
+}
+
+fn route(request: &Request, lookup: &dyn Asn, metrics: Option<&Metrics>) -> Response {
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["ip", ip]) => match ip.parse::<IpAddr>() {
+            Ok(ip) => results(lookup.lookup_asn(ip)),
+            Err(_) => Response::error(400, format!("invalid IP address '{}'", ip)),
+        },
+        ("GET", ["as", asn]) => match parse_asn(asn) {
+            Some(asn) => results(lookup.lookup_as(asn)),
+            None => Response::error(400, format!("invalid AS number '{}'", asn)),
+        },
+        ("POST", ["bulk"]) => bulk(&request.body, lookup),
+        ("GET", ["metrics"]) => match metrics {
+            Some(metrics) => Response::text(PROMETHEUS, metrics.render()),
+            None => Response::error(404, "metrics are not enabled"),
+        },
+        (_, ["ip", _]) | (_, ["as", _]) | (_, ["bulk"]) => Response::error(
+            405,
+            format!("{} not allowed on {}", request.method, request.path),
+        ),
+        _ => Response::error(404, format!("no route for {}", request.path)),
+    }
+}
+
+fn bulk(body: &[u8], lookup: &dyn Asn) -> Response {
+    let addresses: Vec<String> = match serde_json::from_slice(body) {
+        Ok(addresses) => addresses,
+        Err(e) => {
+            return Response::error(400, format!("expected a JSON array of addresses: {}", e))
+        }
+    };
+    if addresses.len() > HttpServer::MAX_BULK {
+        return Response::error(
+            413,
+            format!("at most {} addresses per request", HttpServer::MAX_BULK),
+        );
+    }
+    let answers: Vec<Value> = addresses
+        .iter()
+        .map(|address| match address.trim().parse::<IpAddr>() {
+            Ok(ip) => match lookup.lookup_asn(ip) {
+                Ok(results) => json!({ "ip": address, "results": results }),
+                Err(e) => json!({ "ip": address, "error": e.to_string() }),
+            },
+            Err(_) => json!({ "ip": address, "error": "invalid IP address" }),
+        })
+        .collect();
+    Response::ok(Value::Array(answers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    struct Fixed;
+
+    impl Asn for Fixed {
+        fn lookup_asn(&self, ip: IpAddr) -> Result<Vec<AsnInfo>, Error> {
+            match ip.to_string().as_str() {
+                "192.0.2.1" => Ok(vec![AsnInfo::new("64496", "EXAMPLE")]),
+                "192.0.2.99" => Err(Error::new(ErrorKind::TimedOut, "upstream timed out")),
+                _ => Ok(Vec::new()),
+            }
+        }
+    }
+
+    fn request(method: &str, path: &str, body: &str) -> Response {
+        let request = Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            body: body.as_bytes().to_vec(),
+        };
+        route(&request, &Fixed, None)
+    }
+
+    fn json(response: &Response) -> &Value {
+        match &response.body {
+            Body::Json(value) => value,
+            Body::Text { .. } => panic!("expected a JSON body"),
+        }
+    }
+
+    #[test]
+    fn test_read_request() {
+        let raw =
+            "POST /bulk?pretty HTTP/1.1\r\nHost: localhost\r\ncontent-length: 5\r\n\r\n[\"a\"]";
+        let request = read_request(&mut raw.as_bytes()).unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/bulk");
+        assert_eq!(request.body, b"[\"a\"]");
+
+        let err = read_request(&mut "GARBAGE\r\n\r\n".as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        let huge = format!(
+            "POST /bulk HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY + 1
+        );
+        let err = read_request(&mut huge.as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::OutOfMemory);
+    }
+
+    #[test]
+    fn test_ip_route() {
+        let response = request("GET", "/ip/192.0.2.1", "");
+        assert_eq!(response.status, 200);
+        assert_eq!(json(&response)[0]["asn"], "64496");
+        assert_eq!(json(&response)[0]["holder"], "EXAMPLE");
+
+        assert_eq!(json(&request("GET", "/ip/192.0.2.2", "")), &json!([]));
+        assert_eq!(request("GET", "/ip/not-an-ip", "").status, 400);
+        assert_eq!(request("GET", "/ip/192.0.2.99", "").status, 502);
+        assert_eq!(request("DELETE", "/ip/192.0.2.1", "").status, 405);
+        assert_eq!(request("GET", "/elsewhere", "").status, 404);
+    }
+
+    #[test]
+    fn test_as_route_unsupported() {
+        let response = request("GET", "/as/AS64496", "");
+        assert_eq!(response.status, 501);
+        assert_eq!(request("GET", "/as/bogus", "").status, 400);
+    }
+
+    #[test]
+    fn test_bulk_route() {
+        let response = request("POST", "/bulk", r#"["192.0.2.1", "192.0.2.99", "nope"]"#);
+        assert_eq!(response.status, 200);
+        let body = json(&response);
+        assert_eq!(body[0]["results"][0]["asn"], "64496");
+        assert_eq!(body[1]["error"], "upstream timed out");
+        assert_eq!(body[2]["error"], "invalid IP address");
+        assert_eq!(request("POST", "/bulk", "{}").status, 400);
+    }
+
+    #[test]
+    fn test_serves_over_tcp() {
+        let server = HttpServer::bind("127.0.0.1:0", Arc::new(Fixed)).unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"GET /ip/192.0.2.1 HTTP/1.1\r\nHost: test\r\n\r\n")
+            .unwrap();
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).unwrap();
+        assert!(reply.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(reply.ends_with(r#"[{"asn":"64496","holder":"EXAMPLE"}]"#));
+    }
+
+    #[test]
+    fn test_metrics_route() {
+        let request = Request {
+            method: "GET".to_string(),
+            path: "/metrics".to_string(),
+            body: Vec::new(),
+        };
+        assert_eq!(route(&request, &Fixed, None).status, 404);
+
+        let metrics = Metrics::new();
+        metrics.record_cache(true);
+        let response = route(&request, &Fixed, Some(&metrics));
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body.content_type(), PROMETHEUS);
+
+        let mut written = Vec::new();
+        write_response(&mut written, &response).unwrap();
+        let written = String::from_utf8(written).unwrap();
+        assert!(written.contains("Content-Type: text/plain; version=0.0.4\r\n"));
+        assert!(written.contains("\r\n\r\n# HELP "));
+        assert!(written.contains("\nasn_fetcher_cache_hits_total 1\n"));
+    }
+}
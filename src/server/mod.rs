@@ -0,0 +1,78 @@
+// Servers exposing lookups to other programs
+
+pub mod dns;
+pub mod http;
+pub mod whois;
+
+pub use dns::DnsServer;
+pub use http::HttpServer;
+pub use whois::WhoisServer;
+
+use std::io::{Error, ErrorKind};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+
+/// Handles each job from `jobs` on one of `workers` threads, until `jobs` fails
+///
+/// While every thread is busy, up to `workers` jobs wait in a queue and `jobs`
+/// is not read from again until there is room, so a flood of clients cannot
+/// make a server start unlimited threads. Jobs already accepted are finished
+/// before the error is returned.
+pub(crate) fn run_pool<T, I, F>(workers: usize, jobs: I, handle: F) -> Result<(), Error>
+where
+    T: Send,
+    I: IntoIterator<Item = Result<T, Error>>,
+    F: Fn(T) + Sync,
+{
+    let workers = workers.max(1);
+    let (sender, receiver) = mpsc::sync_channel::<T>(workers);
+    let receiver = Mutex::new(receiver);
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let job = receiver.lock().unwrap().recv();
+                match job {
+                    Ok(job) => handle(job),
+                    Err(_) => break,
+                }
+            });
+        }
+        let result = jobs.into_iter().try_for_each(|job| {
+            sender
+                .send(job?)
+                .map_err(|_| Error::new(ErrorKind::Other, "every worker thread stopped"))
+        });
+        drop(sender);
+        result
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn test_run_pool_limits_threads() {
+        let (running, most, done) = (
+            AtomicUsize::new(0),
+            AtomicUsize::new(0),
+            AtomicUsize::new(0),
+        );
+        let jobs = (0..20)
+            .map(Ok)
+            .chain([Err(Error::new(ErrorKind::Other, "closed"))]);
+        let err = run_pool(3, jobs, |_: i32| {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            most.fetch_max(now, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(5));
+            running.fetch_sub(1, Ordering::SeqCst);
+            done.fetch_add(1, Ordering::SeqCst);
+        })
+        .unwrap_err();
+        assert_eq!(err.to_string(), "closed");
+        assert_eq!(done.load(Ordering::SeqCst), 20);
+        assert!(most.load(Ordering::SeqCst) <= 3);
+    }
+}
@@ -0,0 +1,464 @@
+// Whois server speaking Team Cymru's IP to ASN protocol
+
+use super::run_pool;
+use crate::asn::{parse_asn, Asn, AsnInfo};
+use std::{
+    io::{BufRead, BufReader, Error, Read, Write},
+    net::{IpAddr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Longest query line accepted
+const MAX_LINE: u64 = 1024;
+
+/// Which optional columns to print, set by flags (`-v`) or bulk keywords (`verbose`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Options {
+    prefix: bool,
+    country: bool,
+    registry: bool,
+    allocated: bool,
+    as_name: bool,
+    header: bool,
+}
+
+impl Options {
+    /// Single queries print a header; bulk answers do not unless asked to
+    fn new(header: bool) -> Self {
+        Options {
+            prefix: false,
+            country: false,
+            registry: false,
+            allocated: false,
+            as_name: true,
+            header,
+        }
+    }
+
+    fn verbose(&mut self) {
+        self.prefix = true;
+        self.country = true;
+        self.registry = true;
+        self.allocated = true;
+    }
+
+    /// Applies a single-query flag such as "-v", returning false if it is unknown
+    fn apply_flag(&mut self, flag: &str) -> bool {
+        for c in flag.chars() {
+            match c {
+                'v' => self.verbose(),
+                'p' => self.prefix = true,
+                'c' => self.country = true,
+                'r' => self.registry = true,
+                'a' => self.allocated = true,
+                _ => return false,
+            }
+        }
+        true
+    }
+
+    /// Applies a bulk-mode keyword line, returning false if the line is not one
+    fn apply_keyword(&mut self, keyword: &str) -> bool {
+        match keyword.to_ascii_lowercase().as_str() {
+            "verbose" => self.verbose(),
+            "prefix" => self.prefix = true,
+            "noprefix" => self.prefix = false,
+            "countrycode" => self.country = true,
+            "nocountrycode" => self.country = false,
+            "registry" => self.registry = true,
+            "noregistry" => self.registry = false,
+            "allocdate" => self.allocated = true,
+            "noallocdate" => self.allocated = false,
+            "asname" => self.as_name = true,
+            "noasname" => self.as_name = false,
+            "header" => self.header = true,
+            "noheader" => self.header = false,
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// Builds one `|`-separated row in Cymru's column widths; without `info` every field is "NA"
+fn row(options: &Options, asn: &str, ip: Option<&str>, info: Option<&AsnInfo>) -> String {
+    let field = |value: Option<&str>| match info {
+        Some(_) => value.unwrap_or("").to_string(),
+        None => "NA".to_string(),
+    };
+    let mut columns = vec![format!("{:<7}", asn)];
+    if let Some(ip) = ip {
+        columns.push(format!("{:<16}", ip));
+        if options.prefix {
+            columns.push(format!(
+                "{:<19}",
+                field(info.and_then(|i| i.prefix.as_deref()))
+            ));
+        }
+    }
+    if options.country {
+        columns.push(format!(
+            "{:<2}",
+            field(info.and_then(|i| i.country.as_deref()))
+        ));
+    }
+    if options.registry {
+        columns.push(format!(
+            "{:<8}",
+            field(info.and_then(|i| i.registry.as_deref()))
+        ));
+    }
+    if options.allocated {
+        columns.push(format!(
+            "{:<10}",
+            field(info.and_then(|i| i.allocated.as_deref()))
+        ));
+    }
+    if options.as_name {
+        columns.push(field(info.map(|i| i.holder.as_str())));
+    }
+    columns.join(" | ")
+}
+
+/// The column header for IP (`ip` is true) or AS queries
+fn header(options: &Options, ip: bool) -> String {
+    let mut columns = vec![format!("{:<7}", "AS")];
+    if ip {
+        columns.push(format!("{:<16}", "IP"));
+        if options.prefix {
+            columns.push(format!("{:<19}", "BGP Prefix"));
+        }
+    }
+    if options.country {
+        columns.push("CC".to_string());
+    }
+    if options.registry {
+        columns.push(format!("{:<8}", "Registry"));
+    }
+    if options.allocated {
+        columns.push(format!("{:<10}", "Allocated"));
+    }
+    if options.as_name {
+        columns.push("AS Name".to_string());
+    }
+    columns.join(" | ")
+}
+
+/// Answers one query line ("8.8.8.8" or "AS15169"), numbered for error messages
+fn answer(query: &str, options: &Options, line: usize, lookup: &dyn Asn) -> Vec<String> {
+    let mut out = Vec::new();
+    if let Ok(ip) = query.parse::<IpAddr>() {
+        if options.header {
+            out.push(header(options, true));
+        }
+        let ip_text = ip.to_string();
+        match lookup.lookup_asn(ip) {
+            Ok(results) if results.is_empty() => out.push(row(options, "NA", Some(&ip_text), None)),
+            Ok(results) => out.extend(results.iter().map(|info| {
+                let asn = info.asn_number().map(|n| n.to_string());
+                let asn = asn.as_deref().unwrap_or(info.asn.as_str());
+                row(options, asn, Some(&ip_text), Some(info))
+            })),
+            Err(e) => out.push(format!("Error: lookup failed for {}: {}", ip_text, e)),
+        }
+    } else if let Some(asn) = query
+        .get(..2)
+        .filter(|p| p.eq_ignore_ascii_case("AS"))
+        .and_then(|_| parse_asn(query))
+    {
+        if options.header {
+            out.push(header(options, false));
+        }
+        match lookup.lookup_as(asn) {
+            Ok(results) if results.is_empty() => {
+                out.push(row(options, &asn.to_string(), None, None))
+            }
+            Ok(results) => out.extend(
+                results
+                    .iter()
+                    .map(|info| row(options, &asn.to_string(), None, Some(info))),
+            ),
+            Err(e) => out.push(format!("Error: lookup failed for AS{}: {}", asn, e)),
+        }
+    } else {
+        out.push(format!("Error: no ASN or IP match on line {}.", line));
+    }
+    out
+}
+
+/// Formats seconds since the Unix epoch as "YYYY-MM-DD HH:MM:SS +0000"
+fn utc_timestamp(secs: u64) -> String {
+    let (days, rem) = ((secs / 86_400) as i64, secs % 86_400);
+    // Civil-from-days conversion for the proleptic Gregorian calendar
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} +0000",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Serves IP and AS lookups in the format of whois.cymru.com
+///
+/// A connection carries either one query, optionally preceded by flags
+/// (`-v 8.8.8.8`), or a bulk request: `begin`, then keyword lines such as
+/// `verbose` and one query per line, then `end`. Connections are handled by
+/// a fixed number of worker threads.
+pub struct WhoisServer {
+    listener: TcpListener,
+    lookup: Arc<dyn Asn>,
+    workers: usize,
+}
+
+impl WhoisServer {
+    /// Connections handled concurrently by default
+    pub const DEFAULT_WORKERS: usize = 64;
+    const TIMEOUT_SECS: u64 = 30;
+
+    /// Binds the listening socket
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the address cannot be bound
+    pub fn bind<A: ToSocketAddrs>(addr: A, lookup: Arc<dyn Asn>) -> Result<Self, Error> {
+        Ok(WhoisServer {
+            listener: TcpListener::bind(addr)?,
+            lookup,
+            workers: Self::DEFAULT_WORKERS,
+        })
+    }
+
+    /// Sets how many connections are handled concurrently
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers;
+        self
+    }
+
+    /// The address the server is listening on
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        self.listener.local_addr()
+    }
+
+    /// Accepts connections until the listener fails
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if accepting connections fails
+    pub fn run(&self) -> Result<(), Error> {
+        run_pool(self.workers, self.listener.incoming(), |stream| {
+            if let Err(e) = handle_connection(stream, self.lookup.as_ref()) {
+                tracing::warn!(error = %e, "whois connection failed");
+            }
+        })
+    }
+}
+
+fn handle_connection(stream: TcpStream, lookup: &dyn Asn) -> Result<(), Error> {
+    let timeout = Duration::from_secs(WhoisServer::TIMEOUT_SECS);
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let reader = BufReader::new(stream.try_clone()?);
+    let mut writer = &stream;
+    serve(reader, &mut writer, lookup)?;
+    writer.flush()
+}
+
+/// Reads one query line, or None at end of input
+fn read_line<R: BufRead>(reader: &mut R) -> Result<Option<String>, Error> {
+    let mut line = String::new();
+    if reader.by_ref().take(MAX_LINE).read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim().to_string()))
+}
+
+/// Answers a single query or a bulk request read from `reader`
+fn serve<R: BufRead, W: Write>(
+    mut reader: R,
+    writer: &mut W,
+    lookup: &dyn Asn,
+) -> Result<(), Error> {
+    let Some(first) = read_line(&mut reader)? else {
+        return Ok(());
+    };
+
+    if !first.eq_ignore_ascii_case("begin") {
+        let mut options = Options::new(true);
+        let mut query = None;
+        for token in first.split_whitespace() {
+            match token.strip_prefix('-') {
+                Some(flag) if options.apply_flag(flag) => {}
+                Some(_) => {
+                    writeln!(writer, "Error: unknown flag '{}'.", token)?;
+                    return Ok(());
+                }
+                None => query = query.or(Some(token)),
+            }
+        }
+        for line in answer(query.unwrap_or_default(), &options, 1, lookup) {
+            writeln!(writer, "{}", line)?;
+        }
+        return Ok(());
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    writeln!(writer, "Bulk mode; asn-fetcher [{}]", utc_timestamp(now))?;
+    let mut options = Options::new(false);
+    let mut header_written = false;
+    let mut number = 1;
+    while let Some(line) = read_line(&mut reader)? {
+        number += 1;
+        // Anything after the query, such as a "# comment", is ignored
+        let query = line.split_whitespace().next().unwrap_or_default();
+        if query.is_empty() || query.starts_with('#') || options.apply_keyword(query) {
+            continue;
+        }
+        if query.eq_ignore_ascii_case("end") {
+            break;
+        }
+        let mut line_options = options;
+        line_options.header = options.header && !header_written;
+        for out in answer(query, &line_options, number, lookup) {
+            writeln!(writer, "{}", out)?;
+        }
+        header_written |= line_options.header;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::ErrorKind;
+    use std::thread;
+
+    struct Fixed;
+
+    impl Asn for Fixed {
+        fn lookup_asn(&self, ip: IpAddr) -> Result<Vec<AsnInfo>, Error> {
+            if ip.to_string() != "8.8.8.8" {
+                return Ok(Vec::new());
+            }
+            Ok(vec![AsnInfo {
+                prefix: Some("8.8.8.0/24".to_string()),
+                country: Some("US".to_string()),
+                registry: Some("arin".to_string()),
+                allocated: Some("2023-12-28".to_string()),
+                ..AsnInfo::new("AS15169", "GOOGLE, US")
+            }])
+        }
+
+        fn lookup_as(&self, asn: u32) -> Result<Vec<AsnInfo>, Error> {
+            if asn == 0 {
+                return Err(Error::new(ErrorKind::Unsupported, "unsupported"));
+            }
+            Ok(vec![AsnInfo::new(asn.to_string(), "GOOGLE, US")])
+        }
+    }
+
+    fn session(input: &str) -> String {
+        let mut output = Vec::new();
+        serve(input.as_bytes(), &mut output, &Fixed).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_single_query() {
+        assert_eq!(
+            session("8.8.8.8\r\n"),
+            "AS      | IP               | AS Name\n\
+             15169   | 8.8.8.8          | GOOGLE, US\n"
+        );
+        assert_eq!(
+            session(" -v 8.8.8.8\n"),
+            "AS      | IP               | BGP Prefix          | CC | Registry | Allocated  | AS Name\n\
+             15169   | 8.8.8.8          | 8.8.8.0/24          | US | arin     | 2023-12-28 | GOOGLE, US\n"
+        );
+        assert_eq!(
+            session("192.0.2.1\n"),
+            "AS      | IP               | AS Name\n\
+             NA      | 192.0.2.1        | NA\n"
+        );
+    }
+
+    #[test]
+    fn test_as_query() {
+        assert_eq!(
+            session("AS15169\n"),
+            "AS      | AS Name\n\
+             15169   | GOOGLE, US\n"
+        );
+        assert!(session("AS0\n").starts_with("AS      | AS Name\nError: lookup failed"));
+    }
+
+    #[test]
+    fn test_invalid_queries() {
+        assert_eq!(
+            session("nonsense\n"),
+            "Error: no ASN or IP match on line 1.\n"
+        );
+        assert_eq!(session("-x 8.8.8.8\n"), "Error: unknown flag '-x'.\n");
+        assert_eq!(session(""), "");
+    }
+
+    #[test]
+    fn test_bulk_mode() {
+        let output =
+            session("begin\nverbose\n8.8.8.8 # resolver\n\nbogus\n192.0.2.1\nend\n8.8.4.4\n");
+        let lines: Vec<&str> = output.lines().collect();
+        assert!(lines[0].starts_with("Bulk mode; asn-fetcher ["));
+        assert_eq!(
+            &lines[1..],
+            [
+                "15169   | 8.8.8.8          | 8.8.8.0/24          | US | arin     | 2023-12-28 | GOOGLE, US",
+                "Error: no ASN or IP match on line 5.",
+                "NA      | 192.0.2.1        | NA                  | NA | NA       | NA         | NA",
+            ]
+        );
+
+        let output = session("begin\nheader\nnoasname\n8.8.8.8\n8.8.8.8\nend\n");
+        assert_eq!(
+            output.lines().skip(1).collect::<Vec<_>>(),
+            [
+                "AS      | IP              ",
+                "15169   | 8.8.8.8         ",
+                "15169   | 8.8.8.8         "
+            ]
+        );
+    }
+
+    #[test]
+    fn test_utc_timestamp() {
+        assert_eq!(utc_timestamp(0), "1970-01-01 00:00:00 +0000");
+        assert_eq!(utc_timestamp(951_782_400), "2000-02-29 00:00:00 +0000");
+        assert_eq!(utc_timestamp(1_700_000_000), "2023-11-14 22:13:20 +0000");
+    }
+
+    #[test]
+    fn test_serves_over_tcp() {
+        let server = WhoisServer::bind("127.0.0.1:0", Arc::new(Fixed)).unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"8.8.8.8\r\n").unwrap();
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).unwrap();
+        assert!(reply.ends_with("15169   | 8.8.8.8          | GOOGLE, US\n"));
+    }
+}
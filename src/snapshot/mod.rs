@@ -0,0 +1,371 @@
+// Comparing snapshots of lookup results taken at different times
+
+use crate::asn::dataset::line_error;
+use crate::asn::AsnInfo;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::io::{BufRead, Error, ErrorKind};
+use std::net::IpAddr;
+
+/// One address of a snapshot, as returned by the `/bulk` API
+#[derive(Debug, Serialize, Deserialize)]
+struct Record {
+    ip: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    results: Option<Vec<AsnInfo>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Formats an address and its lookup result as one snapshot line, in the
+/// format `Snapshot::read` accepts
+pub fn record_line(ip: IpAddr, lookup: &Result<Vec<AsnInfo>, Error>) -> String {
+    let record = match lookup {
+        Ok(results) => Record {
+            ip: ip.to_string(),
+            results: Some(results.clone()),
+            error: None,
+        },
+        Err(e) => Record {
+            ip: ip.to_string(),
+            results: None,
+            error: Some(e.to_string()),
+        },
+    };
+    serde_json::to_string(&record).unwrap_or_default()
+}
+
+/// Lookup results per address, with None for addresses whose lookup failed
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Snapshot {
+    pub entries: BTreeMap<IpAddr, Option<Vec<AsnInfo>>>,
+}
+
+impl Snapshot {
+    /// Reads `{"ip", "results"}` and `{"ip", "error"}` objects, either one per
+    /// line or as the JSON array returned by `/bulk`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the line or array entry of the first object that cannot be parsed
+    pub fn read(mut reader: impl BufRead) -> Result<Self, Error> {
+        let mut data = String::new();
+        reader.read_to_string(&mut data)?;
+        let records: Vec<(String, Record)> = if data.trim_start().starts_with('[') {
+            let records: Vec<Record> = serde_json::from_str(&data).map_err(|e| {
+                Error::new(ErrorKind::InvalidData, format!("invalid snapshot: {}", e))
+            })?;
+            records
+                .into_iter()
+                .enumerate()
+                .map(|(i, record)| (format!("entry {}", i + 1), record))
+                .collect()
+        } else {
+            data.lines()
+                .enumerate()
+                .filter(|(_, line)| !line.trim().is_empty())
+                .map(|(number, line)| {
+                    serde_json::from_str(line)
+                        .map(|record| (format!("line {}", number + 1), record))
+                        .map_err(|e| line_error(number, e))
+                })
+                .collect::<Result<_, _>>()?
+        };
+
+        let mut snapshot = Snapshot::default();
+        for (location, record) in records {
+            let invalid =
+                |msg: String| Error::new(ErrorKind::InvalidData, format!("{}: {}", location, msg));
+            let ip: IpAddr = record
+                .ip
+                .parse()
+                .map_err(|_| invalid(format!("invalid IP address '{}'", record.ip)))?;
+            let results = match (record.results, record.error) {
+                (Some(results), _) => Some(results),
+                (None, Some(_)) => None,
+                (None, None) => return Err(invalid("missing 'results' or 'error'".to_string())),
+            };
+            snapshot.entries.insert(ip, results);
+        }
+        Ok(snapshot)
+    }
+}
+
+/// A field compared between snapshots
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Field {
+    Asn,
+    Holder,
+    Prefix,
+}
+
+impl Field {
+    const ALL: [Field; 3] = [Field::Asn, Field::Holder, Field::Prefix];
+
+    fn name(self) -> &'static str {
+        match self {
+            Field::Asn => "asn",
+            Field::Holder => "holder",
+            Field::Prefix => "prefix",
+        }
+    }
+
+    /// The distinct values of the field across an address's results, sorted
+    fn values(self, results: &[AsnInfo]) -> Vec<String> {
+        let mut values: Vec<String> = results
+            .iter()
+            .filter_map(|info| match self {
+                Field::Asn => Some(match info.asn_number() {
+                    Some(asn) => format!("AS{}", asn),
+                    None => info.asn.clone(),
+                }),
+                Field::Holder => Some(info.holder.clone()),
+                Field::Prefix => info.prefix.clone(),
+            })
+            .collect();
+        values.sort();
+        values.dedup();
+        values
+    }
+}
+
+/// How an address differs between snapshots
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// An address that differs between snapshots
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EntryDiff {
+    pub ip: IpAddr,
+    pub status: Status,
+    /// Fields whose values differ, for changed addresses
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub changed: Vec<Field>,
+    pub before: Option<Vec<AsnInfo>>,
+    pub after: Option<Vec<AsnInfo>>,
+}
+
+/// The differences between two snapshots, in address order
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SnapshotDiff {
+    pub entries: Vec<EntryDiff>,
+    /// Addresses present in both snapshots with the same ASNs, holders and prefixes
+    pub unchanged: usize,
+    /// Addresses left out because their lookup failed in either snapshot
+    pub failed: usize,
+}
+
+impl SnapshotDiff {
+    /// Compares the ASNs, holders and prefixes of every address in two snapshots
+    pub fn new(before: &Snapshot, after: &Snapshot) -> Self {
+        let mut diff = SnapshotDiff::default();
+        let ips: BTreeSet<&IpAddr> = before.entries.keys().chain(after.entries.keys()).collect();
+        for &ip in ips {
+            let (old, new) = match (before.entries.get(&ip), after.entries.get(&ip)) {
+                (Some(None), _) | (_, Some(None)) => {
+                    diff.failed += 1;
+                    continue;
+                }
+                (old, new) => (old.and_then(Option::as_ref), new.and_then(Option::as_ref)),
+            };
+            let (status, changed) = match (old, new) {
+                (Some(old), Some(new)) => {
+                    let changed: Vec<Field> = Field::ALL
+                        .into_iter()
+                        .filter(|field| field.values(old) != field.values(new))
+                        .collect();
+                    if changed.is_empty() {
+                        diff.unchanged += 1;
+                        continue;
+                    }
+                    (Status::Changed, changed)
+                }
+                (Some(_), None) => (Status::Removed, Vec::new()),
+                (None, Some(_)) => (Status::Added, Vec::new()),
+                (None, None) => continue,
+            };
+            diff.entries.push(EntryDiff {
+                ip,
+                status,
+                changed,
+                before: old.cloned(),
+                after: new.cloned(),
+            });
+        }
+        diff
+    }
+
+    /// Counts the added, removed and changed addresses
+    pub fn counts(&self) -> (usize, usize, usize) {
+        let count = |status| self.entries.iter().filter(|e| e.status == status).count();
+        (
+            count(Status::Added),
+            count(Status::Removed),
+            count(Status::Changed),
+        )
+    }
+
+    /// Renders one line per address: "+" for added, "-" for removed and "~"
+    /// for changed, listing the old and new values of each changed field
+    pub fn render(&self) -> String {
+        let describe = |results: &[AsnInfo]| -> String {
+            let values: Vec<String> = Field::ALL
+                .into_iter()
+                .map(|field| field.values(results).join(", "))
+                .filter(|values| !values.is_empty())
+                .collect();
+            if values.is_empty() {
+                "(no results)".to_string()
+            } else {
+                values.join(" ")
+            }
+        };
+
+        let mut output = String::new();
+        for entry in &self.entries {
+            let before = entry.before.as_deref().unwrap_or_default();
+            let after = entry.after.as_deref().unwrap_or_default();
+            let _ = match entry.status {
+                Status::Added => writeln!(output, "+ {}  {}", entry.ip, describe(after)),
+                Status::Removed => writeln!(output, "- {}  {}", entry.ip, describe(before)),
+                Status::Changed => {
+                    let changes: Vec<String> = entry
+                        .changed
+                        .iter()
+                        .map(|field| {
+                            let show = |results: &[AsnInfo]| {
+                                let values = field.values(results);
+                                if values.is_empty() {
+                                    "(none)".to_string()
+                                } else {
+                                    values.join(", ")
+                                }
+                            };
+                            format!("{}: {} -> {}", field.name(), show(before), show(after))
+                        })
+                        .collect();
+                    writeln!(output, "~ {}  {}", entry.ip, changes.join("; "))
+                }
+            };
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(asn: &str, holder: &str, prefix: &str) -> AsnInfo {
+        AsnInfo {
+            prefix: Some(prefix.to_string()),
+            ..AsnInfo::new(asn, holder)
+        }
+    }
+
+    fn snapshot(data: &str) -> Snapshot {
+        Snapshot::read(data.as_bytes()).unwrap()
+    }
+
+    const BEFORE: &str = r#"{"ip":"192.0.2.1","results":[{"asn":"64496","holder":"Example Net","prefix":"192.0.2.0/24"}]}
+{"ip":"192.0.2.2","results":[{"asn":"64496","holder":"Example Net","prefix":"192.0.2.0/24"}]}
+{"ip":"198.51.100.1","results":[{"asn":"64497","holder":"Other Net","prefix":"198.51.100.0/24"}]}
+{"ip":"203.0.113.1","error":"timed out"}
+"#;
+
+    const AFTER: &str = r#"[
+  {"ip":"192.0.2.1","results":[{"asn":"AS64511","holder":"New Net","prefix":"192.0.2.0/24"}]},
+  {"ip":"192.0.2.2","results":[{"asn":"AS64496","holder":"Example Net","prefix":"192.0.2.0/24","country":"US"}]},
+  {"ip":"2001:db8::1","results":[]},
+  {"ip":"203.0.113.1","results":[{"asn":"64500","holder":"Doc Net","prefix":"203.0.113.0/24"}]}
+]"#;
+
+    #[test]
+    fn test_read() {
+        let before = snapshot(BEFORE);
+        assert_eq!(before.entries.len(), 4);
+        assert_eq!(
+            before.entries[&"192.0.2.1".parse::<IpAddr>().unwrap()],
+            Some(vec![info("64496", "Example Net", "192.0.2.0/24")])
+        );
+        assert_eq!(
+            before.entries[&"203.0.113.1".parse::<IpAddr>().unwrap()],
+            None
+        );
+        assert_eq!(snapshot(AFTER).entries.len(), 4);
+
+        let err = Snapshot::read("\n{\"ip\":\"192.0.2.1\"}\n".as_bytes()).unwrap_err();
+        assert_eq!(err.to_string(), "line 2: missing 'results' or 'error'");
+        let err = Snapshot::read("{\"ip\":\"bogus\",\"results\":[]}".as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_record_line_round_trips() {
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        let found = record_line(ip, &Ok(vec![info("64496", "Example Net", "192.0.2.0/24")]));
+        let failed = record_line(
+            "2001:db8::1".parse().unwrap(),
+            &Err(Error::new(ErrorKind::TimedOut, "timed out")),
+        );
+        assert_eq!(failed, r#"{"ip":"2001:db8::1","error":"timed out"}"#);
+
+        let read = snapshot(&format!("{}\n{}\n", found, failed));
+        assert_eq!(
+            read.entries[&ip],
+            Some(vec![info("64496", "Example Net", "192.0.2.0/24")])
+        );
+        assert_eq!(
+            read.entries[&"2001:db8::1".parse::<IpAddr>().unwrap()],
+            None
+        );
+    }
+
+    #[test]
+    fn test_diff() {
+        let diff = SnapshotDiff::new(&snapshot(BEFORE), &snapshot(AFTER));
+        let summary: Vec<(String, Status, Vec<Field>)> = diff
+            .entries
+            .iter()
+            .map(|e| (e.ip.to_string(), e.status, e.changed.clone()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    "192.0.2.1".to_string(),
+                    Status::Changed,
+                    vec![Field::Asn, Field::Holder]
+                ),
+                ("198.51.100.1".to_string(), Status::Removed, vec![]),
+                ("2001:db8::1".to_string(), Status::Added, vec![]),
+            ]
+        );
+        // "64496" and "AS64496" are the same ASN, and other fields are ignored
+        assert_eq!(diff.unchanged, 1);
+        assert_eq!(diff.failed, 1);
+        assert_eq!(diff.counts(), (1, 1, 1));
+
+        assert_eq!(
+            diff.render(),
+            "~ 192.0.2.1  asn: AS64496 -> AS64511; holder: Example Net -> New Net\n\
+             - 198.51.100.1  AS64497 Other Net 198.51.100.0/24\n\
+             + 2001:db8::1  (no results)\n"
+        );
+
+        let json = serde_json::to_value(&diff.entries[0]).unwrap();
+        assert_eq!(json["status"], "changed");
+        assert_eq!(json["changed"], serde_json::json!(["asn", "holder"]));
+        assert_eq!(json["after"][0]["holder"], "New Net");
+        let json = serde_json::to_value(&diff.entries[2]).unwrap();
+        assert!(json.get("changed").is_none());
+        assert!(json["before"].is_null());
+    }
+}
@@ -0,0 +1,286 @@
+// Annotating traceroute, mtr and tracepath output with the ASNs along the path
+
+pub mod parse;
+
+pub use parse::parse_trace;
+
+use crate::asn::AsnInfo;
+use crate::enrich::label;
+use crate::network::{is_bogon, is_private};
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+use std::net::IpAddr;
+
+/// The tool whose output is being read
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TraceFormat {
+    /// Detect the format from the output itself
+    #[default]
+    Auto,
+    /// `traceroute` or `traceroute6`
+    Traceroute,
+    /// `mtr --json`
+    MtrJson,
+    /// `mtr --report` or `mtr --report-wide`
+    MtrReport,
+    /// `tracepath`
+    Tracepath,
+}
+
+/// One TTL of a trace and the routers that answered at it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hop {
+    /// TTL of the probes, starting from 1
+    pub ttl: u32,
+    /// Addresses that answered, in the order first seen
+    pub addresses: Vec<IpAddr>,
+    /// Routers shown only by hostname, when the trace was run without addresses
+    pub names: Vec<String>,
+}
+
+impl Hop {
+    /// Creates a hop with no replies
+    pub fn new(ttl: u32) -> Self {
+        Hop {
+            ttl,
+            addresses: Vec::new(),
+            names: Vec::new(),
+        }
+    }
+
+    /// Returns true if nothing answered at this TTL
+    pub fn is_silent(&self) -> bool {
+        self.addresses.is_empty() && self.names.is_empty()
+    }
+
+    fn add_address(&mut self, ip: IpAddr) {
+        if !self.addresses.contains(&ip) {
+            self.addresses.push(ip);
+        }
+    }
+
+    fn add_name(&mut self, name: &str) {
+        if !self.names.iter().any(|n| n == name) {
+            self.names.push(name.to_string());
+        }
+    }
+}
+
+/// One step of the AS-level path
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathSegment {
+    Asn(u32),
+    /// Hops in private address space
+    Private,
+    /// Hops in other address space that is never routed publicly
+    Bogon,
+}
+
+impl fmt::Display for PathSegment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathSegment::Asn(asn) => write!(f, "AS{}", asn),
+            PathSegment::Private => write!(f, "private"),
+            PathSegment::Bogon => write!(f, "bogon"),
+        }
+    }
+}
+
+/// Returns the addresses worth looking up: every publicly routable hop address
+pub fn public_addresses(hops: &[Hop]) -> Vec<IpAddr> {
+    let mut addresses: Vec<IpAddr> = hops
+        .iter()
+        .flat_map(|hop| hop.addresses.iter().copied())
+        .filter(|&ip| !is_bogon(ip))
+        .collect();
+    addresses.sort();
+    addresses.dedup();
+    addresses
+}
+
+fn segment(ip: IpAddr, origins: &HashMap<IpAddr, AsnInfo>) -> Option<PathSegment> {
+    if is_private(ip) {
+        Some(PathSegment::Private)
+    } else if is_bogon(ip) {
+        Some(PathSegment::Bogon)
+    } else {
+        origins
+            .get(&ip)
+            .and_then(AsnInfo::asn_number)
+            .map(PathSegment::Asn)
+    }
+}
+
+/// Collapses the hops into the sequence of networks the trace passed through
+///
+/// Repeated segments are merged, as are private or bogon hops inside a single
+/// AS, while hops without a known origin and silent hops are left out.
+pub fn as_path(hops: &[Hop], origins: &HashMap<IpAddr, AsnInfo>) -> Vec<PathSegment> {
+    let mut path: Vec<PathSegment> = Vec::new();
+    for ip in hops.iter().flat_map(|hop| hop.addresses.iter().copied()) {
+        let Some(segment) = segment(ip, origins) else {
+            continue;
+        };
+        if path.last() == Some(&segment) {
+            continue;
+        }
+        if let (PathSegment::Asn(_), [.., before, PathSegment::Private | PathSegment::Bogon]) =
+            (segment, path.as_slice())
+        {
+            if *before == segment {
+                path.pop();
+                continue;
+            }
+        }
+        path.push(segment);
+    }
+    path
+}
+
+/// Renders each hop with the origin of every address that answered, followed
+/// by the collapsed AS path
+pub fn render(hops: &[Hop], origins: &HashMap<IpAddr, AsnInfo>) -> String {
+    let responders = |hop: &Hop| -> Vec<(String, String)> {
+        let mut rows: Vec<(String, String)> = hop
+            .addresses
+            .iter()
+            .map(|&ip| {
+                let note = if is_private(ip) {
+                    "private".to_string()
+                } else if is_bogon(ip) {
+                    "bogon".to_string()
+                } else {
+                    origins
+                        .get(&ip)
+                        .and_then(|info| label(std::slice::from_ref(info)))
+                        .unwrap_or_else(|| "(no ASN)".to_string())
+                };
+                (ip.to_string(), note)
+            })
+            .collect();
+        rows.extend(
+            hop.names
+                .iter()
+                .map(|name| (name.clone(), "(no address)".to_string())),
+        );
+        rows
+    };
+    let width = hops
+        .iter()
+        .flat_map(|hop| responders(hop).into_iter().map(|(host, _)| host.len()))
+        .max()
+        .unwrap_or(0);
+
+    let mut output = String::new();
+    for hop in hops {
+        let rows = responders(hop);
+        if rows.is_empty() {
+            let _ = writeln!(output, "{:>2}  *", hop.ttl);
+        }
+        for (i, (host, note)) in rows.iter().enumerate() {
+            let ttl = if i == 0 {
+                hop.ttl.to_string()
+            } else {
+                String::new()
+            };
+            let _ = writeln!(output, "{:>2}  {:<width$}  {}", ttl, host, note);
+        }
+    }
+
+    let path: Vec<String> = as_path(hops, origins)
+        .iter()
+        .map(PathSegment::to_string)
+        .collect();
+    let path = if path.is_empty() {
+        "(none)".to_string()
+    } else {
+        path.join(" ")
+    };
+    let _ = writeln!(output, "AS path: {}", path);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hop(ttl: u32, addresses: &[&str]) -> Hop {
+        Hop {
+            ttl,
+            addresses: addresses.iter().map(|a| a.parse().unwrap()).collect(),
+            names: Vec::new(),
+        }
+    }
+
+    fn origins() -> HashMap<IpAddr, AsnInfo> {
+        [
+            ("203.0.114.1", AsnInfo::new("64496", "Access Net")),
+            ("203.0.114.2", AsnInfo::new("64496", "Access Net")),
+            ("198.51.101.1", AsnInfo::new("64497", "Transit Net")),
+            ("8.8.8.8", AsnInfo::new("15169", "Google LLC")),
+        ]
+        .into_iter()
+        .map(|(ip, info)| (ip.parse().unwrap(), info))
+        .collect()
+    }
+
+    #[test]
+    fn test_public_addresses() {
+        let hops = vec![
+            hop(1, &["192.168.1.1"]),
+            hop(2, &["203.0.114.1", "203.0.114.2"]),
+            hop(3, &["203.0.114.1"]),
+        ];
+        assert_eq!(
+            public_addresses(&hops),
+            vec![
+                "203.0.114.1".parse::<IpAddr>().unwrap(),
+                "203.0.114.2".parse().unwrap()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_as_path() {
+        let hops = vec![
+            hop(1, &["192.168.1.1"]),
+            hop(2, &["100.64.0.1"]),
+            hop(3, &["203.0.114.1"]),
+            hop(4, &["10.10.0.1"]),
+            hop(5, &["203.0.114.2"]),
+            hop(6, &[]),
+            hop(7, &["198.51.101.1", "198.51.101.9"]),
+            hop(8, &["127.0.0.1"]),
+            hop(9, &["8.8.8.8"]),
+        ];
+        let path: Vec<String> = as_path(&hops, &origins())
+            .iter()
+            .map(PathSegment::to_string)
+            .collect();
+        assert_eq!(path, ["private", "AS64496", "AS64497", "bogon", "AS15169"]);
+    }
+
+    #[test]
+    fn test_render() {
+        let mut named = Hop::new(4);
+        named.add_name("edge.example.net");
+        let hops = vec![
+            hop(1, &["192.168.1.1"]),
+            hop(2, &["203.0.114.1", "203.0.114.9"]),
+            hop(3, &[]),
+            named,
+            hop(5, &["8.8.8.8"]),
+        ];
+        assert_eq!(
+            render(&hops, &origins()),
+            " 1  192.168.1.1       private\n\
+             \x202  203.0.114.1       AS64496 Access Net\n\
+             \x20   203.0.114.9       (no ASN)\n\
+             \x203  *\n\
+             \x204  edge.example.net  (no address)\n\
+             \x205  8.8.8.8           AS15169 Google LLC\n\
+             AS path: private AS64496 AS15169\n"
+        );
+        assert_eq!(render(&[], &origins()), "AS path: (none)\n");
+    }
+}
@@ -0,0 +1,358 @@
+// Parsers for the output of traceroute, mtr and tracepath
+
+use super::{Hop, TraceFormat};
+use serde_json::Value;
+use std::io::{Error, ErrorKind};
+use std::net::IpAddr;
+
+/// Parses captured trace output into its hops, in TTL order
+///
+/// # Errors
+///
+/// Returns an error if the output holds no hops, or if mtr JSON is malformed
+pub fn parse_trace(output: &str, format: TraceFormat) -> Result<Vec<Hop>, Error> {
+    let format = match format {
+        TraceFormat::Auto => detect(output),
+        format => format,
+    };
+    let hops = match format {
+        TraceFormat::MtrJson => parse_mtr_json(output)?,
+        TraceFormat::MtrReport => parse_mtr_report(output),
+        TraceFormat::Tracepath => parse_tracepath(output),
+        TraceFormat::Traceroute | TraceFormat::Auto => parse_traceroute(output),
+    };
+    if hops.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("no hops found in {:?} output", format),
+        ));
+    }
+    Ok(hops)
+}
+
+/// Guesses which tool produced the output
+fn detect(output: &str) -> TraceFormat {
+    if output.trim_start().starts_with('{') {
+        TraceFormat::MtrJson
+    } else if output.contains(".|--") {
+        TraceFormat::MtrReport
+    } else if output
+        .lines()
+        .any(|line| tracepath_ttl(line.split_whitespace().next().unwrap_or("")).is_some())
+    {
+        TraceFormat::Tracepath
+    } else {
+        TraceFormat::Traceroute
+    }
+}
+
+/// Returns true for tokens naming a router, as opposed to timings, flags and annotations
+fn is_name(token: &str) -> bool {
+    let starts_well = token
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_');
+    let is_as_annotation = token
+        .strip_prefix("AS")
+        .is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit() || c == '?'));
+    starts_well
+        && !is_as_annotation
+        && token != "ms"
+        && token.parse::<f64>().is_err()
+        && token
+            .strip_suffix("ms")
+            .map_or(true, |n| n.parse::<f64>().is_err())
+}
+
+/// Adds the routers named by `tokens` to the hop
+///
+/// A router appears as an address, as "hostname (address)", or as a bare
+/// hostname when the tool was not asked to print addresses.
+fn add_responders<'a>(hop: &mut Hop, tokens: impl IntoIterator<Item = &'a str>) {
+    let mut pending: Option<&str> = None;
+    for token in tokens {
+        let inner = token.strip_prefix('(').and_then(|t| t.strip_suffix(')'));
+        if let Some(ip) = inner.and_then(|t| t.parse::<IpAddr>().ok()) {
+            hop.add_address(ip);
+            pending = None;
+            continue;
+        }
+        if let Some(name) = pending.take() {
+            hop.add_name(name);
+        }
+        if let Ok(ip) = token.parse::<IpAddr>() {
+            hop.add_address(ip);
+        } else if is_name(token) {
+            pending = Some(token);
+        }
+    }
+    if let Some(name) = pending {
+        hop.add_name(name);
+    }
+}
+
+/// Returns the hop for `ttl`, reusing the last one when a tool prints a TTL over several lines
+fn hop_for(hops: &mut Vec<Hop>, ttl: u32) -> &mut Hop {
+    if hops.last().map_or(true, |hop| hop.ttl != ttl) {
+        hops.push(Hop::new(ttl));
+    }
+    hops.last_mut().expect("a hop was just pushed")
+}
+
+/// Parses `traceroute` output, where each line starts with the TTL followed by
+/// the routers and timings, e.g. " 1  _gateway (192.168.1.1)  0.512 ms"
+///
+/// Indented lines without a TTL list further routers for the previous hop.
+fn parse_traceroute(output: &str) -> Vec<Hop> {
+    let mut hops: Vec<Hop> = Vec::new();
+    for line in output.lines() {
+        let mut tokens = line.split_whitespace().peekable();
+        let Some(&first) = tokens.peek() else {
+            continue;
+        };
+        if let Ok(ttl) = first.parse::<u32>() {
+            tokens.next();
+            add_responders(hop_for(&mut hops, ttl), tokens);
+        } else if line.starts_with(char::is_whitespace) {
+            if let Some(hop) = hops.last_mut() {
+                add_responders(hop, tokens);
+            }
+        }
+    }
+    hops
+}
+
+/// Returns the TTL of a tracepath hop token such as "3:" or "1?:"
+fn tracepath_ttl(token: &str) -> Option<u32> {
+    let ttl = token.strip_suffix(':')?;
+    ttl.strip_suffix('?').unwrap_or(ttl).parse().ok()
+}
+
+/// Parses `tracepath` output, e.g. " 1:  _gateway (192.168.1.1)  0.555ms"
+///
+/// The `[LOCALHOST]` lines reporting the local path MTU are skipped.
+fn parse_tracepath(output: &str) -> Vec<Hop> {
+    let mut hops: Vec<Hop> = Vec::new();
+    for line in output.lines() {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let Some(ttl) = tokens.first().and_then(|token| tracepath_ttl(token)) else {
+            continue;
+        };
+        let router = &tokens[1..];
+        if router.first().is_some_and(|t| t.starts_with('[')) {
+            continue;
+        }
+        let hop = hop_for(&mut hops, ttl);
+        if router.starts_with(&["no", "reply"]) {
+            continue;
+        }
+        // Only the first column names the router; the rest are timings and
+        // notes such as "pmtu 1492", "asymm 3" and "reached"
+        let end = match router.get(1) {
+            Some(token) if token.starts_with('(') => 2,
+            _ => 1,
+        };
+        add_responders(hop, router.iter().take(end).copied());
+    }
+    hops
+}
+
+/// Parses `mtr --report` output, e.g. "  1.|-- 192.168.1.1  0.0%  10  0.5 ..."
+///
+/// Silent hops are shown as "???", and further routers for a hop appear on
+/// "|  `|--" lines below it.
+fn parse_mtr_report(output: &str) -> Vec<Hop> {
+    let mut hops: Vec<Hop> = Vec::new();
+    for line in output.lines() {
+        let Some((prefix, rest)) = line.split_once("|--") else {
+            continue;
+        };
+        let ttl = prefix.trim().strip_suffix('.').and_then(|t| t.parse().ok());
+        let hop = match ttl {
+            Some(ttl) => hop_for(&mut hops, ttl),
+            None => match hops.last_mut() {
+                Some(hop) => hop,
+                None => continue,
+            },
+        };
+        // The router is followed by Loss%, Snt, Last, Avg, Best, Wrst and StDev
+        let tokens: Vec<&str> = rest.split_whitespace().collect();
+        let end = if tokens.len() > 7 {
+            tokens.len() - 7
+        } else {
+            tokens.len()
+        };
+        let router = &tokens[..end];
+        add_responders(hop, router.iter().copied().filter(|&t| t != "???"));
+    }
+    hops
+}
+
+/// Parses `mtr --json` output, whose report lists one "hub" per router
+fn parse_mtr_json(output: &str) -> Result<Vec<Hop>, Error> {
+    let report: Value = serde_json::from_str(output).map_err(|e| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("invalid mtr JSON output: {}", e),
+        )
+    })?;
+    let hubs = report
+        .pointer("/report/hubs")
+        .and_then(Value::as_array)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "mtr JSON output has no hubs"))?;
+
+    let mut hops: Vec<Hop> = Vec::new();
+    for hub in hubs {
+        // Older mtr releases give the count as a string
+        let ttl = match hub.get("count") {
+            Some(Value::Number(n)) => n.as_u64().and_then(|n| u32::try_from(n).ok()),
+            Some(Value::String(s)) => s.parse().ok(),
+            _ => None,
+        };
+        let Some(ttl) = ttl else {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "mtr JSON hub without a count",
+            ));
+        };
+        let host = hub.get("host").and_then(Value::as_str).unwrap_or("???");
+        add_responders(
+            hop_for(&mut hops, ttl),
+            host.split_whitespace().filter(|&t| t != "???"),
+        );
+    }
+    Ok(hops)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(hops: &[Hop]) -> Vec<(u32, Vec<String>)> {
+        hops.iter()
+            .map(|hop| {
+                let mut routers: Vec<String> =
+                    hop.addresses.iter().map(IpAddr::to_string).collect();
+                routers.extend(hop.names.iter().cloned());
+                (hop.ttl, routers)
+            })
+            .collect()
+    }
+
+    fn expected(hops: &[(u32, &[&str])]) -> Vec<(u32, Vec<String>)> {
+        hops.iter()
+            .map(|(ttl, routers)| (*ttl, routers.iter().map(|r| r.to_string()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn test_traceroute() {
+        let output = "\
+traceroute to dns.google (8.8.8.8), 30 hops max, 60 byte packets
+ 1  _gateway (192.168.1.1)  0.512 ms  0.470 ms  0.451 ms
+ 2  * * *
+ 3  100.64.0.1 (100.64.0.1)  8.120 ms !H  edge.example.net (203.0.114.2)  9.001 ms *
+    core.example.net (203.0.114.3)  9.5 ms
+ 4  [AS15169] 8.8.8.8  11.2 ms  11.0 ms  10.9 ms
+";
+        let hops = parse_trace(output, TraceFormat::Auto).unwrap();
+        assert_eq!(
+            summary(&hops),
+            expected(&[
+                (1, &["192.168.1.1"]),
+                (2, &[]),
+                (3, &["100.64.0.1", "203.0.114.2", "203.0.114.3"]),
+                (4, &["8.8.8.8"]),
+            ])
+        );
+        assert!(hops[1].is_silent());
+
+        let ipv6 = " 1  2001:db8::1  1.0 ms\n 2  2606:4700::1111  5.1 ms\n";
+        assert_eq!(
+            summary(&parse_trace(ipv6, TraceFormat::Traceroute).unwrap()),
+            expected(&[(1, &["2001:db8::1"]), (2, &["2606:4700::1111"])])
+        );
+    }
+
+    #[test]
+    fn test_tracepath() {
+        let output = "\
+ 1?: [LOCALHOST]                      pmtu 1500
+ 1:  _gateway (192.168.1.1)                                0.555ms
+ 1:  _gateway (192.168.1.1)                                0.480ms
+ 2:  no reply
+ 3:  203.0.114.2                                           9.512ms asymm  4
+ 4:  edge.example.net                                     10.100ms pmtu 1492
+ 5:  8.8.8.8                                              11.002ms reached
+     Resume: pmtu 1492 hops 5 back 5
+";
+        let hops = parse_trace(output, TraceFormat::Auto).unwrap();
+        assert_eq!(
+            summary(&hops),
+            expected(&[
+                (1, &["192.168.1.1"]),
+                (2, &[]),
+                (3, &["203.0.114.2"]),
+                (4, &["edge.example.net"]),
+                (5, &["8.8.8.8"]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_mtr_report() {
+        let output = "\
+Start: 2024-05-01T10:00:00+0000
+HOST: probe                       Loss%   Snt   Last   Avg  Best  Wrst StDev
+  1.|-- _gateway (192.168.1.1)     0.0%    10    0.5   0.6   0.4   0.9   0.1
+  2.|-- ???                       100.0    10    0.0   0.0   0.0   0.0   0.0
+  3.|-- AS64496  203.0.114.2       0.0%    10    9.1   9.3   8.9   9.9   0.3
+    |  `|-- 203.0.114.3
+  4.|-- 8.8.8.8                    0.0%    10   11.0  11.1  10.9  11.4   0.1
+";
+        let hops = parse_trace(output, TraceFormat::Auto).unwrap();
+        assert_eq!(
+            summary(&hops),
+            expected(&[
+                (1, &["192.168.1.1"]),
+                (2, &[]),
+                (3, &["203.0.114.2", "203.0.114.3"]),
+                (4, &["8.8.8.8"]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_mtr_json() {
+        let output = r#"{
+  "report": {
+    "mtr": {"src": "probe", "dst": "8.8.8.8", "tests": 10},
+    "hubs": [
+      {"count": "1", "host": "192.168.1.1", "Loss%": 0.0, "Avg": 0.6},
+      {"count": 2, "host": "???", "Loss%": 100.0, "Avg": 0.0},
+      {"count": 3, "host": "edge.example.net (203.0.114.2)", "Loss%": 0.0, "Avg": 9.3},
+      {"count": 4, "host": "dns.google", "Loss%": 0.0, "Avg": 11.1}
+    ]
+  }
+}"#;
+        let hops = parse_trace(output, TraceFormat::Auto).unwrap();
+        assert_eq!(
+            summary(&hops),
+            expected(&[
+                (1, &["192.168.1.1"]),
+                (2, &[]),
+                (3, &["203.0.114.2"]),
+                (4, &["dns.google"]),
+            ])
+        );
+
+        assert!(parse_trace("{\"report\": {}}", TraceFormat::MtrJson).is_err());
+        assert!(parse_trace("{", TraceFormat::Auto).is_err());
+    }
+
+    #[test]
+    fn test_no_hops() {
+        let err = parse_trace("traceroute: unknown host\n", TraceFormat::Auto).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
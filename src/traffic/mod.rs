@@ -0,0 +1,304 @@
+// Summarizing captured traffic and flow exports by the ASNs of their endpoints
+
+pub mod netflow;
+pub mod pcap;
+
+pub use netflow::{split_messages, FlowDecoder};
+pub use pcap::{parse_packet, udp_payload, PcapReader, RawPacket};
+
+use crate::asn::AsnInfo;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::net::IpAddr;
+
+/// The addresses, transport protocol and ports identifying a unidirectional flow
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FlowKey {
+    pub src: IpAddr,
+    pub dst: IpAddr,
+    /// IP protocol number (6 for TCP, 17 for UDP)
+    pub protocol: u8,
+    /// Source port, or 0 for protocols without ports
+    pub src_port: u16,
+    /// Destination port, or 0 for protocols without ports
+    pub dst_port: u16,
+}
+
+impl FlowKey {
+    /// Returns the key with its endpoints ordered, so both directions of a
+    /// conversation share it
+    pub fn bidirectional(self) -> FlowKey {
+        if (self.src, self.src_port) <= (self.dst, self.dst_port) {
+            self
+        } else {
+            FlowKey {
+                src: self.dst,
+                dst: self.src,
+                protocol: self.protocol,
+                src_port: self.dst_port,
+                dst_port: self.src_port,
+            }
+        }
+    }
+}
+
+/// Traffic seen in one direction of a flow, from a packet or a flow record
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Traffic {
+    pub flow: FlowKey,
+    pub packets: u64,
+    /// Bytes at the IP layer, headers included
+    pub bytes: u64,
+}
+
+/// Which endpoints of a flow its traffic is attributed to
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Side {
+    /// Both the source and the destination
+    #[default]
+    Either,
+    /// The source, for the networks sending the most traffic
+    Source,
+    /// The destination, for the networks receiving the most traffic
+    Destination,
+}
+
+/// Totals for one origin AS
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsnTotals {
+    /// The origin AS, or None for addresses without a lookup result
+    pub asn: Option<u32>,
+    pub holder: String,
+    pub packets: u64,
+    pub bytes: u64,
+    /// Conversations with an endpoint in the AS, counting both directions as one
+    pub flows: u64,
+    /// Distinct addresses seen in the AS
+    pub addresses: usize,
+}
+
+/// Accumulates traffic per flow for a summary by ASN
+#[derive(Debug, Default)]
+pub struct TrafficSummary {
+    flows: HashMap<FlowKey, (u64, u64)>,
+}
+
+impl TrafficSummary {
+    /// Creates an empty summary
+    pub fn new() -> Self {
+        TrafficSummary::default()
+    }
+
+    /// Adds the packets and bytes of some traffic to its flow
+    pub fn add(&mut self, traffic: Traffic) {
+        let counters = self.flows.entry(traffic.flow).or_default();
+        counters.0 += traffic.packets;
+        counters.1 += traffic.bytes;
+    }
+
+    /// Returns the number of unidirectional flows seen
+    pub fn len(&self) -> usize {
+        self.flows.len()
+    }
+
+    /// Returns true if no traffic has been added
+    pub fn is_empty(&self) -> bool {
+        self.flows.is_empty()
+    }
+
+    /// Returns every distinct endpoint address
+    pub fn addresses(&self) -> Vec<IpAddr> {
+        let addresses: HashSet<IpAddr> = self
+            .flows
+            .keys()
+            .flat_map(|flow| [flow.src, flow.dst])
+            .collect();
+        addresses.into_iter().collect()
+    }
+
+    /// Totals the traffic of each origin AS, largest byte count first
+    ///
+    /// With `Side::Either` traffic counts towards the AS of each of its
+    /// endpoints, but only once when both are in the same AS. Addresses
+    /// missing from `origins` are totalled under an `asn` of None.
+    pub fn by_asn(&self, origins: &HashMap<IpAddr, AsnInfo>, side: Side) -> Vec<AsnTotals> {
+        let origin = |ip: IpAddr| (origins.get(&ip).and_then(AsnInfo::asn_number), ip);
+        let mut totals: HashMap<Option<u32>, AsnTotals> = HashMap::new();
+        let mut conversations: HashSet<(Option<u32>, FlowKey)> = HashSet::new();
+        let mut addresses: HashSet<(Option<u32>, IpAddr)> = HashSet::new();
+
+        for (flow, &(packets, bytes)) in &self.flows {
+            let endpoints = match side {
+                Side::Source => vec![origin(flow.src)],
+                Side::Destination => vec![origin(flow.dst)],
+                Side::Either => vec![origin(flow.src), origin(flow.dst)],
+            };
+            let mut asns: Vec<Option<u32>> = Vec::with_capacity(2);
+            for (asn, ip) in endpoints {
+                addresses.insert((asn, ip));
+                if !asns.contains(&asn) {
+                    asns.push(asn);
+                }
+            }
+            for asn in asns {
+                let entry = totals.entry(asn).or_insert_with(|| AsnTotals {
+                    asn,
+                    holder: String::new(),
+                    packets: 0,
+                    bytes: 0,
+                    flows: 0,
+                    addresses: 0,
+                });
+                entry.packets += packets;
+                entry.bytes += bytes;
+                if conversations.insert((asn, flow.bidirectional())) {
+                    entry.flows += 1;
+                }
+            }
+        }
+        for (asn, ip) in addresses {
+            let entry = totals
+                .get_mut(&asn)
+                .expect("every address belongs to a flow");
+            entry.addresses += 1;
+            if entry.holder.is_empty() {
+                if let Some(info) = origins.get(&ip) {
+                    entry.holder = info.holder.clone();
+                }
+            }
+        }
+
+        let mut totals: Vec<AsnTotals> = totals.into_values().collect();
+        totals.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(a.asn.cmp(&b.asn)));
+        totals
+    }
+}
+
+/// Renders totals as an aligned table with a header row
+pub fn render_table(totals: &[AsnTotals]) -> String {
+    let mut table = format!(
+        "{:<12} {:>10} {:>14} {:>8} {:>9}  {}\n",
+        "ASN", "PACKETS", "BYTES", "FLOWS", "ADDRESSES", "HOLDER"
+    );
+    for row in totals {
+        let (asn, holder) = match row.asn {
+            Some(asn) => (format!("AS{}", asn), row.holder.as_str()),
+            None => ("-".to_string(), "(no ASN)"),
+        };
+        let _ = writeln!(
+            table,
+            "{:<12} {:>10} {:>14} {:>8} {:>9}  {}",
+            asn, row.packets, row.bytes, row.flows, row.addresses, holder
+        );
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn traffic(src: &str, sport: u16, dst: &str, dport: u16, bytes: u64) -> Traffic {
+        Traffic {
+            flow: FlowKey {
+                src: src.parse().unwrap(),
+                dst: dst.parse().unwrap(),
+                protocol: 6,
+                src_port: sport,
+                dst_port: dport,
+            },
+            packets: 1,
+            bytes,
+        }
+    }
+
+    #[test]
+    fn test_bidirectional_key() {
+        let forward = traffic("192.0.2.1", 40000, "198.51.100.1", 443, 0).flow;
+        let reverse = traffic("198.51.100.1", 443, "192.0.2.1", 40000, 0).flow;
+        assert_ne!(forward, reverse);
+        assert_eq!(forward.bidirectional(), reverse.bidirectional());
+    }
+
+    #[test]
+    fn test_by_asn() {
+        let mut summary = TrafficSummary::new();
+        summary.add(traffic("192.0.2.1", 40000, "198.51.100.1", 443, 100));
+        summary.add(traffic("198.51.100.1", 443, "192.0.2.1", 40000, 1500));
+        summary.add(traffic("192.0.2.1", 40001, "198.51.100.2", 443, 60));
+        summary.add(traffic("192.0.2.1", 40002, "192.0.2.2", 22, 40));
+        summary.add(traffic("192.0.2.1", 40003, "10.0.0.1", 53, 70));
+        assert_eq!(summary.len(), 5);
+        assert_eq!(summary.addresses().len(), 5);
+
+        let origins: HashMap<IpAddr, AsnInfo> = [
+            ("192.0.2.1", AsnInfo::new("64496", "Example Net")),
+            ("192.0.2.2", AsnInfo::new("64496", "Example Net")),
+            ("198.51.100.1", AsnInfo::new("AS64497", "Other Net")),
+            ("198.51.100.2", AsnInfo::new("AS64497", "Other Net")),
+        ]
+        .into_iter()
+        .map(|(ip, info)| (ip.parse().unwrap(), info))
+        .collect();
+
+        let totals = summary.by_asn(&origins, Side::Either);
+        assert_eq!(
+            totals,
+            vec![
+                AsnTotals {
+                    asn: Some(64496),
+                    holder: "Example Net".to_string(),
+                    packets: 5,
+                    bytes: 1770,
+                    flows: 4,
+                    addresses: 2,
+                },
+                AsnTotals {
+                    asn: Some(64497),
+                    holder: "Other Net".to_string(),
+                    packets: 3,
+                    bytes: 1660,
+                    flows: 2,
+                    addresses: 2,
+                },
+                AsnTotals {
+                    asn: None,
+                    holder: String::new(),
+                    packets: 1,
+                    bytes: 70,
+                    flows: 1,
+                    addresses: 1,
+                },
+            ]
+        );
+
+        let sources = summary.by_asn(&origins, Side::Source);
+        let summarize = |totals: &[AsnTotals]| -> Vec<(Option<u32>, u64, u64, usize)> {
+            totals
+                .iter()
+                .map(|t| (t.asn, t.bytes, t.flows, t.addresses))
+                .collect()
+        };
+        assert_eq!(
+            summarize(&sources),
+            vec![(Some(64497), 1500, 1, 1), (Some(64496), 270, 4, 1)]
+        );
+        assert_eq!(
+            summarize(&summary.by_asn(&origins, Side::Destination)),
+            vec![
+                (Some(64496), 1540, 2, 2),
+                (Some(64497), 160, 2, 2),
+                (None, 70, 1, 1)
+            ]
+        );
+
+        let table = render_table(&totals);
+        let lines: Vec<&str> = table.lines().collect();
+        assert!(lines[0].starts_with("ASN "));
+        assert_eq!(
+            lines[1],
+            "AS64496               5           1770        4         2  Example Net"
+        );
+        assert!(lines[3].starts_with("- ") && lines[3].ends_with("(no ASN)"));
+    }
+}
@@ -0,0 +1,570 @@
+// Decoding NetFlow v5, NetFlow v9 and IPFIX export packets
+
+use super::{FlowKey, Traffic};
+use std::collections::HashMap;
+use std::io::{self, Error, ErrorKind};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+const V5_HEADER: usize = 24;
+const V5_RECORD: usize = 48;
+const V9_HEADER: usize = 20;
+const IPFIX_HEADER: usize = 16;
+
+/// Field length marking an IPFIX variable-length field
+const VARIABLE_LENGTH: u16 = 65535;
+
+// Information elements shared by NetFlow v9 and IPFIX
+const OCTET_DELTA_COUNT: u16 = 1;
+const PACKET_DELTA_COUNT: u16 = 2;
+const PROTOCOL_IDENTIFIER: u16 = 4;
+const SOURCE_TRANSPORT_PORT: u16 = 7;
+const SOURCE_IPV4_ADDRESS: u16 = 8;
+const DESTINATION_TRANSPORT_PORT: u16 = 11;
+const DESTINATION_IPV4_ADDRESS: u16 = 12;
+const SOURCE_IPV6_ADDRESS: u16 = 27;
+const DESTINATION_IPV6_ADDRESS: u16 = 28;
+const OCTET_TOTAL_COUNT: u16 = 85;
+const PACKET_TOTAL_COUNT: u16 = 86;
+
+/// A template announced by an exporter
+struct Template {
+    /// Element id and length of each field; None for enterprise-specific elements
+    fields: Vec<(Option<u16>, u16)>,
+    /// Options templates describe the exporter rather than flows
+    options: bool,
+}
+
+/// Exporter, observation domain (v9 source id) and template id
+type TemplateKey = (Option<SocketAddr>, u32, u16);
+
+/// Decodes flow export packets, remembering the templates each exporter announces
+#[derive(Default)]
+pub struct FlowDecoder {
+    templates: HashMap<TemplateKey, Template>,
+    dropped: u64,
+}
+
+impl FlowDecoder {
+    /// Creates a decoder that has not seen any templates
+    pub fn new() -> Self {
+        FlowDecoder::default()
+    }
+
+    /// Returns the number of data sets skipped because their template had not been seen
+    ///
+    /// Exporters resend templates periodically, so a few are expected at the
+    /// start of a capture.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Decodes the flow records of one export packet
+    ///
+    /// Templates are scoped to the `exporter` the packet came from (None for
+    /// files without that information). Records without both addresses, such
+    /// as options data, are skipped. Counters are returned as exported,
+    /// without scaling for sampling.
+    pub fn decode(
+        &mut self,
+        exporter: Option<SocketAddr>,
+        message: &[u8],
+    ) -> io::Result<Vec<Traffic>> {
+        match u16_at(message, 0)? {
+            5 => decode_v5(message),
+            9 => self.decode_v9(exporter, message),
+            10 => self.decode_ipfix(exporter, message),
+            version => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("unsupported flow export version {}", version),
+            )),
+        }
+    }
+
+    fn decode_v9(
+        &mut self,
+        exporter: Option<SocketAddr>,
+        message: &[u8],
+    ) -> io::Result<Vec<Traffic>> {
+        let source_id = u32_at(message, 16)?;
+        let mut flows = Vec::new();
+        for (id, body) in sets(message, V9_HEADER)? {
+            match id {
+                0 => self.read_templates(exporter, source_id, body, false, false)?,
+                1 => self.read_templates(exporter, source_id, body, false, true)?,
+                2..=255 => {}
+                id => self.read_data(&(exporter, source_id, id), body, &mut flows),
+            }
+        }
+        Ok(flows)
+    }
+
+    fn decode_ipfix(
+        &mut self,
+        exporter: Option<SocketAddr>,
+        message: &[u8],
+    ) -> io::Result<Vec<Traffic>> {
+        let length = u16_at(message, 2)? as usize;
+        let message = message
+            .get(..length)
+            .filter(|_| length >= IPFIX_HEADER)
+            .ok_or_else(|| invalid("IPFIX message length does not match the packet"))?;
+        let domain = u32_at(message, 12)?;
+        let mut flows = Vec::new();
+        for (id, body) in sets(message, IPFIX_HEADER)? {
+            match id {
+                2 => self.read_templates(exporter, domain, body, true, false)?,
+                3 => self.read_templates(exporter, domain, body, true, true)?,
+                0..=255 => {}
+                id => self.read_data(&(exporter, domain, id), body, &mut flows),
+            }
+        }
+        Ok(flows)
+    }
+
+    /// Stores the templates of a (options) template set
+    fn read_templates(
+        &mut self,
+        exporter: Option<SocketAddr>,
+        domain: u32,
+        body: &[u8],
+        ipfix: bool,
+        options: bool,
+    ) -> io::Result<()> {
+        let mut offset = 0;
+        // Anything shorter than a template header is padding
+        while offset + 4 <= body.len() {
+            let id = u16_at(body, offset)?;
+            let count = u16_at(body, offset + 2)? as usize;
+            offset += 4;
+            if id < 256 {
+                break;
+            }
+            let key = (exporter, domain, id);
+            let field_count = match (ipfix, options) {
+                // IPFIX withdraws a template by resending it without fields
+                (true, false) if count == 0 => {
+                    self.templates.remove(&key);
+                    continue;
+                }
+                (true, false) => count,
+                // Followed by the scope field count, which is included in count
+                (true, true) => {
+                    offset += 2;
+                    count
+                }
+                (false, false) => count,
+                // Scope and option lengths in bytes, four per field
+                (false, true) => {
+                    let option_length = u16_at(body, offset)? as usize;
+                    offset += 2;
+                    (count + option_length) / 4
+                }
+            };
+            let mut fields = Vec::with_capacity(field_count);
+            for _ in 0..field_count {
+                let element = u16_at(body, offset)?;
+                let length = u16_at(body, offset + 2)?;
+                offset += 4;
+                if ipfix && element & 0x8000 != 0 {
+                    // Enterprise number
+                    offset += 4;
+                    fields.push((None, length));
+                } else {
+                    fields.push((Some(element), length));
+                }
+            }
+            if offset > body.len() {
+                return Err(truncated());
+            }
+            self.templates.insert(key, Template { fields, options });
+        }
+        Ok(())
+    }
+
+    /// Decodes the records of a data set with the template they refer to
+    fn read_data(&mut self, key: &TemplateKey, body: &[u8], flows: &mut Vec<Traffic>) {
+        let Some(template) = self.templates.get(key) else {
+            self.dropped += 1;
+            return;
+        };
+        if template.options {
+            return;
+        }
+        let mut offset = 0;
+        while let Some((traffic, length)) = read_record(template, &body[offset..]) {
+            flows.extend(traffic);
+            offset += length;
+        }
+    }
+}
+
+/// Decodes a NetFlow v5 packet, whose records have a fixed layout
+fn decode_v5(message: &[u8]) -> io::Result<Vec<Traffic>> {
+    let count = u16_at(message, 2)? as usize;
+    let records = message
+        .get(V5_HEADER..V5_HEADER + count * V5_RECORD)
+        .ok_or_else(truncated)?;
+    Ok(records
+        .chunks_exact(V5_RECORD)
+        .map(|record| Traffic {
+            flow: FlowKey {
+                src: IpAddr::V4(Ipv4Addr::new(record[0], record[1], record[2], record[3])),
+                dst: IpAddr::V4(Ipv4Addr::new(record[4], record[5], record[6], record[7])),
+                protocol: record[38],
+                src_port: u16::from_be_bytes([record[32], record[33]]),
+                dst_port: u16::from_be_bytes([record[34], record[35]]),
+            },
+            packets: uint(&record[16..20]),
+            bytes: uint(&record[20..24]),
+        })
+        .collect())
+}
+
+/// Splits the flowsets or sets following a header into their ids and bodies
+fn sets(message: &[u8], header: usize) -> io::Result<Vec<(u16, &[u8])>> {
+    let mut sets = Vec::new();
+    let mut offset = header;
+    while offset + 4 <= message.len() {
+        let id = u16_at(message, offset)?;
+        let length = u16_at(message, offset + 2)? as usize;
+        if length < 4 || offset + length > message.len() {
+            return Err(invalid("malformed flow export set length"));
+        }
+        sets.push((id, &message[offset + 4..offset + length]));
+        offset += length;
+    }
+    Ok(sets)
+}
+
+/// Decodes one data record, returning its traffic (if it describes a flow)
+/// and its length, or None when the rest of the set is too short to hold it
+fn read_record(template: &Template, data: &[u8]) -> Option<(Option<Traffic>, usize)> {
+    let mut offset = 0;
+    let (mut src, mut dst) = (None, None);
+    let (mut protocol, mut src_port, mut dst_port) = (0, 0, 0);
+    let (mut bytes, mut packets, mut total_bytes, mut total_packets) = (None, None, None, None);
+    for &(element, length) in &template.fields {
+        let length = if length == VARIABLE_LENGTH {
+            let short = *data.get(offset)?;
+            offset += 1;
+            if short == 255 {
+                offset += 2;
+                u16::from_be_bytes([*data.get(offset - 2)?, *data.get(offset - 1)?]) as usize
+            } else {
+                short as usize
+            }
+        } else {
+            length as usize
+        };
+        let value = data.get(offset..offset + length)?;
+        offset += length;
+        match element {
+            Some(OCTET_DELTA_COUNT) => bytes = Some(uint(value)),
+            Some(PACKET_DELTA_COUNT) => packets = Some(uint(value)),
+            Some(OCTET_TOTAL_COUNT) => total_bytes = Some(uint(value)),
+            Some(PACKET_TOTAL_COUNT) => total_packets = Some(uint(value)),
+            Some(PROTOCOL_IDENTIFIER) => protocol = uint(value) as u8,
+            Some(SOURCE_TRANSPORT_PORT) => src_port = uint(value) as u16,
+            Some(DESTINATION_TRANSPORT_PORT) => dst_port = uint(value) as u16,
+            Some(SOURCE_IPV4_ADDRESS | SOURCE_IPV6_ADDRESS) => src = address(value),
+            Some(DESTINATION_IPV4_ADDRESS | DESTINATION_IPV6_ADDRESS) => dst = address(value),
+            _ => {}
+        }
+    }
+    if offset == 0 {
+        return None;
+    }
+    let traffic = match (src, dst) {
+        (Some(src), Some(dst)) => Some(Traffic {
+            flow: FlowKey {
+                src,
+                dst,
+                protocol,
+                src_port,
+                dst_port,
+            },
+            packets: packets.or(total_packets).unwrap_or(0),
+            bytes: bytes.or(total_bytes).unwrap_or(0),
+        }),
+        _ => None,
+    };
+    Some((traffic, offset))
+}
+
+/// Splits a file of back-to-back NetFlow v5 or IPFIX messages, such as an
+/// RFC 5655 IPFIX file
+///
+/// NetFlow v9 messages do not record their length, so v9 exports must be
+/// read from a packet capture instead.
+pub fn split_messages(data: &[u8]) -> io::Result<Vec<&[u8]>> {
+    let mut messages = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let rest = &data[offset..];
+        let (length, header) = match u16_at(rest, 0)? {
+            5 => (V5_HEADER + u16_at(rest, 2)? as usize * V5_RECORD, V5_HEADER),
+            10 => (u16_at(rest, 2)? as usize, IPFIX_HEADER),
+            9 => {
+                return Err(invalid(
+                    "NetFlow v9 exports have no message lengths; read them from a pcap capture",
+                ))
+            }
+            _ => return Err(invalid("not a NetFlow v5 or IPFIX export file")),
+        };
+        if length < header || length > rest.len() {
+            return Err(truncated());
+        }
+        messages.push(&rest[..length]);
+        offset += length;
+    }
+    Ok(messages)
+}
+
+/// Reads a big-endian unsigned integer of up to eight bytes
+fn uint(value: &[u8]) -> u64 {
+    value.iter().fold(0, |acc, &b| (acc << 8) | b as u64)
+}
+
+fn address(value: &[u8]) -> Option<IpAddr> {
+    match value.len() {
+        4 => Some(IpAddr::V4(Ipv4Addr::new(
+            value[0], value[1], value[2], value[3],
+        ))),
+        16 => Some(IpAddr::V6(Ipv6Addr::from(
+            <[u8; 16]>::try_from(value).ok()?,
+        ))),
+        _ => None,
+    }
+}
+
+fn u16_at(data: &[u8], offset: usize) -> io::Result<u16> {
+    match data.get(offset..offset + 2) {
+        Some(bytes) => Ok(u16::from_be_bytes([bytes[0], bytes[1]])),
+        None => Err(truncated()),
+    }
+}
+
+fn u32_at(data: &[u8], offset: usize) -> io::Result<u32> {
+    match data.get(offset..offset + 4) {
+        Some(bytes) => Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+        None => Err(truncated()),
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+fn truncated() -> Error {
+    invalid("truncated flow export packet")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Source, destination, ports, packets and bytes of a v5 record
+    type V5Record = ([u8; 4], [u8; 4], u16, u16, u32, u32);
+
+    fn v5_packet(records: &[V5Record]) -> Vec<u8> {
+        let mut packet = vec![0, 5];
+        packet.extend_from_slice(&(records.len() as u16).to_be_bytes());
+        packet.resize(V5_HEADER, 0);
+        for &(src, dst, sport, dport, packets, bytes) in records {
+            let mut record = [0u8; V5_RECORD];
+            record[0..4].copy_from_slice(&src);
+            record[4..8].copy_from_slice(&dst);
+            record[16..20].copy_from_slice(&packets.to_be_bytes());
+            record[20..24].copy_from_slice(&bytes.to_be_bytes());
+            record[32..34].copy_from_slice(&sport.to_be_bytes());
+            record[34..36].copy_from_slice(&dport.to_be_bytes());
+            record[38] = 6;
+            packet.extend_from_slice(&record);
+        }
+        packet
+    }
+
+    fn set(id: u16, body: &[u8]) -> Vec<u8> {
+        let mut set = id.to_be_bytes().to_vec();
+        set.extend_from_slice(&((body.len() + 4) as u16).to_be_bytes());
+        set.extend_from_slice(body);
+        set
+    }
+
+    fn fields(fields: &[(u16, u16)]) -> Vec<u8> {
+        fields
+            .iter()
+            .flat_map(|(id, len)| [id.to_be_bytes(), len.to_be_bytes()].concat())
+            .collect()
+    }
+
+    fn v9_packet(sets: &[Vec<u8>]) -> Vec<u8> {
+        let mut packet = vec![0, 9, 0, 1];
+        packet.extend_from_slice(&[0; 12]);
+        packet.extend_from_slice(&7u32.to_be_bytes());
+        for set in sets {
+            packet.extend_from_slice(set);
+        }
+        packet
+    }
+
+    fn ipfix_packet(sets: &[Vec<u8>]) -> Vec<u8> {
+        let body: Vec<u8> = sets.concat();
+        let mut packet = vec![0, 10];
+        packet.extend_from_slice(&((body.len() + IPFIX_HEADER) as u16).to_be_bytes());
+        packet.extend_from_slice(&[0; 8]);
+        packet.extend_from_slice(&1u32.to_be_bytes());
+        packet.extend_from_slice(&body);
+        packet
+    }
+
+    fn flow(src: &str, dst: &str, sport: u16, dport: u16, packets: u64, bytes: u64) -> Traffic {
+        Traffic {
+            flow: FlowKey {
+                src: src.parse().unwrap(),
+                dst: dst.parse().unwrap(),
+                protocol: 6,
+                src_port: sport,
+                dst_port: dport,
+            },
+            packets,
+            bytes,
+        }
+    }
+
+    #[test]
+    fn test_v5() {
+        let packet = v5_packet(&[
+            ([192, 0, 2, 1], [198, 51, 100, 1], 40000, 443, 10, 5000),
+            ([198, 51, 100, 1], [192, 0, 2, 1], 443, 40000, 8, 12000),
+        ]);
+        let flows = FlowDecoder::new().decode(None, &packet).unwrap();
+        assert_eq!(
+            flows,
+            vec![
+                flow("192.0.2.1", "198.51.100.1", 40000, 443, 10, 5000),
+                flow("198.51.100.1", "192.0.2.1", 443, 40000, 8, 12000),
+            ]
+        );
+        assert!(FlowDecoder::new().decode(None, &packet[..60]).is_err());
+    }
+
+    #[test]
+    fn test_v9_templates() {
+        let template = {
+            let mut body = 256u16.to_be_bytes().to_vec();
+            body.extend_from_slice(&6u16.to_be_bytes());
+            body.extend(fields(&[(8, 4), (12, 4), (7, 2), (11, 2), (4, 1), (1, 4)]));
+            body.extend_from_slice(&[0, 0]);
+            set(0, &body)
+        };
+        let options = {
+            let mut body = 257u16.to_be_bytes().to_vec();
+            body.extend_from_slice(&4u16.to_be_bytes());
+            body.extend_from_slice(&4u16.to_be_bytes());
+            body.extend(fields(&[(1, 4), (34, 4)]));
+            set(1, &body)
+        };
+        let mut record = vec![192, 0, 2, 1, 198, 51, 100, 1];
+        record.extend_from_slice(&40000u16.to_be_bytes());
+        record.extend_from_slice(&443u16.to_be_bytes());
+        record.push(6);
+        record.extend_from_slice(&1500u32.to_be_bytes());
+        let mut data = [record.clone(), record].concat();
+        data.extend_from_slice(&[0, 0]);
+        let data = set(256, &data);
+        let options_data = set(257, &[0; 8]);
+
+        let exporter = Some("192.0.2.254:2055".parse().unwrap());
+        let mut decoder = FlowDecoder::new();
+        assert!(decoder
+            .decode(exporter, &v9_packet(std::slice::from_ref(&data)))
+            .unwrap()
+            .is_empty());
+        assert_eq!(decoder.dropped(), 1);
+
+        let flows = decoder
+            .decode(
+                exporter,
+                &v9_packet(&[template, options, data.clone(), options_data]),
+            )
+            .unwrap();
+        assert_eq!(
+            flows,
+            vec![flow("192.0.2.1", "198.51.100.1", 40000, 443, 0, 1500); 2]
+        );
+
+        // Templates belong to the exporter that sent them
+        assert!(decoder
+            .decode(None, &v9_packet(&[data]))
+            .unwrap()
+            .is_empty());
+        assert_eq!(decoder.dropped(), 2);
+    }
+
+    #[test]
+    fn test_ipfix_variable_length_and_enterprise_fields() {
+        let template = {
+            let mut body = 300u16.to_be_bytes().to_vec();
+            body.extend_from_slice(&6u16.to_be_bytes());
+            body.extend(fields(&[(27, 16), (28, 16), (86, 8)]));
+            // Enterprise-specific variable-length element
+            body.extend(fields(&[(0x8000 | 1, VARIABLE_LENGTH)]));
+            body.extend_from_slice(&9u32.to_be_bytes());
+            body.extend(fields(&[(2, 2), (85, 8)]));
+            set(2, &body)
+        };
+        let mut record = Vec::new();
+        record.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        record.extend_from_slice(&"2001:db8::2".parse::<Ipv6Addr>().unwrap().octets());
+        record.extend_from_slice(&99u64.to_be_bytes());
+        record.extend_from_slice(&[3, b'a', b'b', b'c']);
+        record.extend_from_slice(&5u16.to_be_bytes());
+        record.extend_from_slice(&700u64.to_be_bytes());
+        let packet = ipfix_packet(&[template, set(300, &record)]);
+
+        let flows = FlowDecoder::new().decode(None, &packet).unwrap();
+        assert_eq!(flows.len(), 1);
+        assert_eq!(flows[0].flow.src, "2001:db8::1".parse::<IpAddr>().unwrap());
+        assert_eq!(flows[0].flow.protocol, 0);
+        // Delta counters win over totals
+        assert_eq!((flows[0].packets, flows[0].bytes), (5, 700));
+    }
+
+    #[test]
+    fn test_ipfix_template_withdrawal() {
+        let template = {
+            let mut body = 256u16.to_be_bytes().to_vec();
+            body.extend_from_slice(&2u16.to_be_bytes());
+            body.extend(fields(&[(8, 4), (12, 4)]));
+            set(2, &body)
+        };
+        let data = set(256, &[192, 0, 2, 1, 192, 0, 2, 2]);
+        let mut decoder = FlowDecoder::new();
+        let flows = decoder
+            .decode(None, &ipfix_packet(&[template, data.clone()]))
+            .unwrap();
+        assert_eq!(flows.len(), 1);
+
+        let withdrawal = set(2, &[1, 0, 0, 0]);
+        let flows = decoder
+            .decode(None, &ipfix_packet(&[withdrawal, data]))
+            .unwrap();
+        assert!(flows.is_empty());
+        assert_eq!(decoder.dropped(), 1);
+    }
+
+    #[test]
+    fn test_split_messages() {
+        let v5 = v5_packet(&[([192, 0, 2, 1], [198, 51, 100, 1], 1, 2, 1, 40)]);
+        let ipfix = ipfix_packet(&[]);
+        let file = [v5.clone(), ipfix.clone(), v5.clone()].concat();
+        assert_eq!(
+            split_messages(&file).unwrap(),
+            vec![&v5[..], &ipfix[..], &v5[..]]
+        );
+        assert!(split_messages(&file[..file.len() - 1]).is_err());
+        assert!(split_messages(&v9_packet(&[])).is_err());
+        assert!(FlowDecoder::new().decode(None, &[0, 7, 0, 0]).is_err());
+    }
+}
@@ -0,0 +1,673 @@
+// Reading pcap and pcapng capture files
+
+use super::{FlowKey, Traffic};
+use std::fs::File;
+use std::io::{self, BufReader, Error, ErrorKind, Read};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+
+const PCAP_MICROS: u32 = 0xa1b2_c3d4;
+const PCAP_NANOS: u32 = 0xa1b2_3c4d;
+const PCAPNG_SECTION: u32 = 0x0a0d_0d0a;
+const PCAPNG_BYTE_ORDER: u32 = 0x1a2b_3c4d;
+
+const PCAPNG_INTERFACE: u32 = 1;
+const PCAPNG_OBSOLETE_PACKET: u32 = 2;
+const PCAPNG_SIMPLE_PACKET: u32 = 3;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+
+/// Largest packet or block accepted, to reject corrupt length fields
+const MAX_RECORD: usize = 256 * 1024 * 1024;
+
+const LINKTYPE_NULL: u32 = 0;
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LOOP: u32 = 108;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_LINUX_SLL2: u32 = 276;
+
+/// A captured packet and the link-layer type of its data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawPacket {
+    /// LINKTYPE_* value of the capturing interface (1 for Ethernet)
+    pub linktype: u32,
+    /// The captured bytes, possibly cut short by the snapshot length
+    pub data: Vec<u8>,
+}
+
+enum Format {
+    Pcap { linktype: u32 },
+    PcapNg { interfaces: Vec<u32> },
+}
+
+/// Reads packets from a pcap or pcapng capture, detecting the format and byte order
+pub struct PcapReader<R> {
+    input: R,
+    format: Format,
+    big_endian: bool,
+    truncated: bool,
+}
+
+impl PcapReader<BufReader<File>> {
+    /// Opens a capture file
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        PcapReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> PcapReader<R> {
+    /// Reads the file header from `input`
+    pub fn new(mut input: R) -> io::Result<Self> {
+        let mut magic = [0u8; 4];
+        input.read_exact(&mut magic)?;
+        if u32::from_le_bytes(magic) == PCAPNG_SECTION {
+            let mut reader = PcapReader {
+                input,
+                format: Format::PcapNg {
+                    interfaces: Vec::new(),
+                },
+                big_endian: false,
+                truncated: false,
+            };
+            reader.read_section_header()?;
+            return Ok(reader);
+        }
+
+        let big_endian = match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+            (PCAP_MICROS | PCAP_NANOS, _) => false,
+            (_, PCAP_MICROS | PCAP_NANOS) => true,
+            _ => return Err(invalid("not a pcap or pcapng file")),
+        };
+        let mut header = [0u8; 20];
+        input.read_exact(&mut header)?;
+        let mut reader = PcapReader {
+            input,
+            format: Format::Pcap { linktype: 0 },
+            big_endian,
+            truncated: false,
+        };
+        // The link type's upper bits carry FCS information
+        let linktype = reader.u32_at(&header, 16) & 0x0fff_ffff;
+        reader.format = Format::Pcap { linktype };
+        Ok(reader)
+    }
+
+    /// Returns true if the capture ended part way through a packet
+    pub fn truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Returns the next packet, or None at the end of the capture
+    pub fn next_packet(&mut self) -> io::Result<Option<RawPacket>> {
+        loop {
+            let packet = match self.format {
+                Format::Pcap { linktype } => self.next_pcap_packet(linktype),
+                Format::PcapNg { .. } => self.next_pcapng_packet(),
+            };
+            match packet {
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                    self.truncated = true;
+                    return Ok(None);
+                }
+                Ok(Some(None)) => continue,
+                other => return other.map(Option::flatten),
+            }
+        }
+    }
+
+    /// Reads a pcap record; the outer None marks the end of the file
+    fn next_pcap_packet(&mut self, linktype: u32) -> io::Result<Option<Option<RawPacket>>> {
+        let mut header = [0u8; 16];
+        if !self.read_or_eof(&mut header)? {
+            return Ok(None);
+        }
+        let length = self.u32_at(&header, 8) as usize;
+        let data = self.read_vec(length)?;
+        Ok(Some(Some(RawPacket { linktype, data })))
+    }
+
+    /// Reads a pcapng block, returning Some(None) for blocks other than packets
+    fn next_pcapng_packet(&mut self) -> io::Result<Option<Option<RawPacket>>> {
+        let mut header = [0u8; 8];
+        if !self.read_or_eof(&mut header)? {
+            return Ok(None);
+        }
+        let block_type = self.u32_at(&header, 0);
+        if block_type == PCAPNG_SECTION {
+            // A new section may switch byte order, so its length is read with it
+            self.read_section_body([header[4], header[5], header[6], header[7]])?;
+            return Ok(Some(None));
+        }
+        let length = self.u32_at(&header, 4) as usize;
+        if length < 12 {
+            return Err(invalid("pcapng block is too short"));
+        }
+        let body = self.read_vec(length - 8)?;
+        let body = &body[..body.len() - 4];
+
+        let packet = match block_type {
+            PCAPNG_INTERFACE if body.len() >= 2 => {
+                let linktype = self.u16_at(body, 0) as u32;
+                if let Format::PcapNg { interfaces } = &mut self.format {
+                    interfaces.push(linktype);
+                }
+                None
+            }
+            PCAPNG_ENHANCED_PACKET | PCAPNG_OBSOLETE_PACKET if body.len() >= 20 => {
+                let interface = if block_type == PCAPNG_ENHANCED_PACKET {
+                    self.u32_at(body, 0) as usize
+                } else {
+                    self.u16_at(body, 0) as usize
+                };
+                let length = (self.u32_at(body, 12) as usize).min(body.len() - 20);
+                Some(RawPacket {
+                    linktype: self.interface_linktype(interface)?,
+                    data: body[20..20 + length].to_vec(),
+                })
+            }
+            PCAPNG_SIMPLE_PACKET if body.len() >= 4 => {
+                let length = (self.u32_at(body, 0) as usize).min(body.len() - 4);
+                Some(RawPacket {
+                    linktype: self.interface_linktype(0)?,
+                    data: body[4..4 + length].to_vec(),
+                })
+            }
+            _ => None,
+        };
+        Ok(Some(packet))
+    }
+
+    /// Returns the link type of a pcapng interface declared in the current section
+    fn interface_linktype(&self, interface: usize) -> io::Result<u32> {
+        match &self.format {
+            Format::PcapNg { interfaces } => interfaces.get(interface).copied(),
+            Format::Pcap { .. } => None,
+        }
+        .ok_or_else(|| invalid("pcapng packet from an undeclared interface"))
+    }
+
+    /// Reads the rest of a section header block, whose type has been read
+    fn read_section_header(&mut self) -> io::Result<()> {
+        let mut length = [0u8; 4];
+        self.input.read_exact(&mut length)?;
+        self.read_section_body(length)
+    }
+
+    /// Reads a section header block from its byte-order magic on, given the
+    /// raw bytes of its length, which are in the byte order the magic sets
+    fn read_section_body(&mut self, length: [u8; 4]) -> io::Result<()> {
+        let mut order = [0u8; 4];
+        self.input.read_exact(&mut order)?;
+        self.big_endian = if u32::from_le_bytes(order) == PCAPNG_BYTE_ORDER {
+            false
+        } else if u32::from_be_bytes(order) == PCAPNG_BYTE_ORDER {
+            true
+        } else {
+            return Err(invalid("invalid pcapng byte-order magic"));
+        };
+        let length = self.u32_at(&length, 0) as usize;
+        if length < 12 {
+            return Err(invalid("pcapng block is too short"));
+        }
+        self.read_vec(length - 12)?;
+        self.format = Format::PcapNg {
+            interfaces: Vec::new(),
+        };
+        Ok(())
+    }
+
+    /// Fills `buf`, returning false if the input ended before its first byte
+    fn read_or_eof(&mut self, buf: &mut [u8]) -> io::Result<bool> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.input.read(&mut buf[filled..]) {
+                Ok(0) if filled == 0 => return Ok(false),
+                Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(true)
+    }
+
+    fn read_vec(&mut self, length: usize) -> io::Result<Vec<u8>> {
+        if length > MAX_RECORD {
+            return Err(invalid("capture record is too large"));
+        }
+        let mut data = vec![0u8; length];
+        self.input.read_exact(&mut data)?;
+        Ok(data)
+    }
+
+    fn u32_at(&self, data: &[u8], offset: usize) -> u32 {
+        let bytes = [
+            data[offset],
+            data[offset + 1],
+            data[offset + 2],
+            data[offset + 3],
+        ];
+        if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    }
+
+    fn u16_at(&self, data: &[u8], offset: usize) -> u16 {
+        let bytes = [data[offset], data[offset + 1]];
+        if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        }
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+fn be16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([
+        *data.get(offset)?,
+        *data.get(offset + 1)?,
+    ]))
+}
+
+/// Extracts the IP flow and size of a captured packet
+///
+/// Supports Ethernet (with VLAN tags), Linux cooked captures (SLL and SLL2),
+/// raw IP and BSD loopback link types. Returns None for non-IP packets and
+/// packets too short to hold their IP header.
+pub fn parse_packet(linktype: u32, data: &[u8]) -> Option<Traffic> {
+    let ip = parse_ip(ip_layer(linktype, data)?)?;
+    // TCP, UDP and SCTP all start with the source and destination ports
+    let (src_port, dst_port) = match (ip.protocol, ip.transport) {
+        (6 | 17 | 132, Some(header)) if header.len() >= 4 => (be16(header, 0)?, be16(header, 2)?),
+        _ => (0, 0),
+    };
+    Some(Traffic {
+        flow: FlowKey {
+            src: ip.src,
+            dst: ip.dst,
+            protocol: ip.protocol,
+            src_port,
+            dst_port,
+        },
+        packets: 1,
+        bytes: ip.bytes as u64,
+    })
+}
+
+/// Returns the sender and payload of a captured UDP datagram
+pub fn udp_payload(linktype: u32, data: &[u8]) -> Option<(SocketAddr, &[u8])> {
+    let ip = parse_ip(ip_layer(linktype, data)?)?;
+    let header = ip
+        .transport
+        .filter(|header| ip.protocol == 17 && header.len() >= 8)?;
+    let length = (be16(header, 4)? as usize).clamp(8, header.len());
+    let sender = SocketAddr::new(ip.src, be16(header, 0)?);
+    Some((sender, &header[8..length]))
+}
+
+/// Returns the IP packet inside a link-layer frame
+fn ip_layer(linktype: u32, data: &[u8]) -> Option<&[u8]> {
+    let (ethertype, payload) = match linktype {
+        LINKTYPE_ETHERNET => {
+            let mut offset = 12;
+            let mut ethertype = be16(data, offset)?;
+            // 802.1Q and 802.1ad tags
+            while matches!(ethertype, 0x8100 | 0x88a8 | 0x9100) {
+                offset += 4;
+                ethertype = be16(data, offset)?;
+            }
+            (ethertype, data.get(offset + 2..)?)
+        }
+        LINKTYPE_LINUX_SLL => (be16(data, 14)?, data.get(16..)?),
+        LINKTYPE_LINUX_SLL2 => (be16(data, 0)?, data.get(20..)?),
+        LINKTYPE_NULL | LINKTYPE_LOOP => return data.get(4..),
+        // DLT_RAW is 12 or 14 depending on the platform that wrote the file
+        LINKTYPE_RAW | 12 | 14 => return Some(data),
+        _ => return None,
+    };
+    matches!(ethertype, 0x0800 | 0x86dd).then_some(payload)
+}
+
+/// The parts of an IPv4 or IPv6 header needed to identify its flow
+struct IpHeader<'a> {
+    src: IpAddr,
+    dst: IpAddr,
+    protocol: u8,
+    /// Length of the packet on the wire, headers included
+    bytes: usize,
+    /// The transport header and payload, unless this is a non-first fragment
+    transport: Option<&'a [u8]>,
+}
+
+/// Parses an IPv4 or IPv6 header, skipping IPv6 extension headers
+fn parse_ip(data: &[u8]) -> Option<IpHeader<'_>> {
+    match data.first()? >> 4 {
+        4 => {
+            let header_len = (data[0] & 0x0f) as usize * 4;
+            if header_len < 20 || data.len() < 20 {
+                return None;
+            }
+            let total_len = be16(data, 2)? as usize;
+            let first_fragment = be16(data, 6)? & 0x1fff == 0;
+            Some(IpHeader {
+                src: IpAddr::V4(Ipv4Addr::new(data[12], data[13], data[14], data[15])),
+                dst: IpAddr::V4(Ipv4Addr::new(data[16], data[17], data[18], data[19])),
+                protocol: data[9],
+                // Offloaded segments are captured with a total length of 0
+                bytes: if total_len == 0 {
+                    data.len()
+                } else {
+                    total_len
+                },
+                transport: data.get(header_len..).filter(|_| first_fragment),
+            })
+        }
+        6 => {
+            if data.len() < 40 {
+                return None;
+            }
+            let src: [u8; 16] = data[8..24].try_into().ok()?;
+            let dst: [u8; 16] = data[24..40].try_into().ok()?;
+            let payload_len = be16(data, 4)? as usize;
+            let mut next = data[6];
+            let mut offset = 40;
+            let mut first_fragment = true;
+            loop {
+                match next {
+                    // Hop-by-hop, routing and destination options
+                    0 | 43 | 60 => {
+                        next = *data.get(offset)?;
+                        offset += (*data.get(offset + 1)? as usize + 1) * 8;
+                    }
+                    // Fragment
+                    44 => {
+                        next = *data.get(offset)?;
+                        first_fragment = be16(data, offset + 2)? >> 3 == 0;
+                        offset += 8;
+                    }
+                    // Authentication header
+                    51 => {
+                        next = *data.get(offset)?;
+                        offset += (*data.get(offset + 1)? as usize + 2) * 4;
+                    }
+                    _ => break,
+                }
+            }
+            Some(IpHeader {
+                src: IpAddr::V6(Ipv6Addr::from(src)),
+                dst: IpAddr::V6(Ipv6Addr::from(dst)),
+                protocol: next,
+                // Jumbograms carry their length in a hop-by-hop option instead
+                bytes: if payload_len == 0 {
+                    data.len()
+                } else {
+                    40 + payload_len
+                },
+                transport: data.get(offset..).filter(|_| first_fragment),
+            })
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn ipv4_udp(src: [u8; 4], dst: [u8; 4], sport: u16, dport: u16, payload: usize) -> Vec<u8> {
+        let total = 28 + payload;
+        let mut packet = vec![
+            0x45,
+            0,
+            (total >> 8) as u8,
+            total as u8,
+            0,
+            0,
+            0x40,
+            0,
+            64,
+            17,
+            0,
+            0,
+        ];
+        packet.extend_from_slice(&src);
+        packet.extend_from_slice(&dst);
+        packet.extend_from_slice(&sport.to_be_bytes());
+        packet.extend_from_slice(&dport.to_be_bytes());
+        packet.extend_from_slice(&((8 + payload) as u16).to_be_bytes());
+        packet.extend_from_slice(&[0, 0]);
+        packet.resize(total, 0);
+        packet
+    }
+
+    fn ethernet(ethertype: u16, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0u8; 12];
+        frame.extend_from_slice(&ethertype.to_be_bytes());
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    /// Builds a little- or big-endian pcap file
+    fn pcap(big_endian: bool, linktype: u32, packets: &[Vec<u8>]) -> Vec<u8> {
+        let u32b = |v: u32| {
+            if big_endian {
+                v.to_be_bytes()
+            } else {
+                v.to_le_bytes()
+            }
+        };
+        let mut file = u32b(PCAP_MICROS).to_vec();
+        let u16b = |v: u16| {
+            if big_endian {
+                v.to_be_bytes()
+            } else {
+                v.to_le_bytes()
+            }
+        };
+        file.extend_from_slice(&u16b(2));
+        file.extend_from_slice(&u16b(4));
+        file.extend_from_slice(&[0; 8]);
+        file.extend_from_slice(&u32b(65535));
+        file.extend_from_slice(&u32b(linktype));
+        for packet in packets {
+            file.extend_from_slice(&u32b(1_700_000_000));
+            file.extend_from_slice(&u32b(0));
+            file.extend_from_slice(&u32b(packet.len() as u32));
+            file.extend_from_slice(&u32b(packet.len() as u32));
+            file.extend_from_slice(packet);
+        }
+        file
+    }
+
+    fn block(block_type: u32, body: &[u8]) -> Vec<u8> {
+        let mut body = body.to_vec();
+        body.resize((body.len() + 3) / 4 * 4, 0);
+        let length = (body.len() + 12) as u32;
+        let mut block = block_type.to_le_bytes().to_vec();
+        block.extend_from_slice(&length.to_le_bytes());
+        block.extend_from_slice(&body);
+        block.extend_from_slice(&length.to_le_bytes());
+        block
+    }
+
+    fn read_all(file: Vec<u8>) -> (Vec<RawPacket>, bool) {
+        let mut reader = PcapReader::new(Cursor::new(file)).unwrap();
+        let mut packets = Vec::new();
+        while let Some(packet) = reader.next_packet().unwrap() {
+            packets.push(packet);
+        }
+        (packets, reader.truncated())
+    }
+
+    #[test]
+    fn test_pcap_both_byte_orders() {
+        let packet = ethernet(
+            0x0800,
+            &ipv4_udp([192, 0, 2, 1], [198, 51, 100, 1], 5353, 53, 4),
+        );
+        for big_endian in [false, true] {
+            let (packets, truncated) =
+                read_all(pcap(big_endian, 1, &[packet.clone(), packet.clone()]));
+            assert!(!truncated);
+            assert_eq!(packets.len(), 2);
+            assert_eq!(packets[0].linktype, 1);
+            assert_eq!(packets[0].data, packet);
+        }
+    }
+
+    #[test]
+    fn test_truncated_pcap() {
+        let packet = ethernet(
+            0x0800,
+            &ipv4_udp([192, 0, 2, 1], [198, 51, 100, 1], 1, 2, 0),
+        );
+        let mut file = pcap(false, 1, &[packet.clone(), packet]);
+        file.truncate(file.len() - 5);
+        let (packets, truncated) = read_all(file);
+        assert_eq!(packets.len(), 1);
+        assert!(truncated);
+    }
+
+    #[test]
+    fn test_pcapng() {
+        let ip = ipv4_udp([192, 0, 2, 1], [198, 51, 100, 1], 5353, 53, 3);
+        let mut section = PCAPNG_BYTE_ORDER.to_le_bytes().to_vec();
+        section.extend_from_slice(&[1, 0, 0, 0]);
+        section.extend_from_slice(&u64::MAX.to_le_bytes());
+        let mut file = block(PCAPNG_SECTION, &section);
+
+        let mut interface = (LINKTYPE_RAW as u16).to_le_bytes().to_vec();
+        interface.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+        file.extend(block(PCAPNG_INTERFACE, &interface));
+        file.extend(block(5, &[0; 8]));
+
+        let mut enhanced = vec![0u8; 12];
+        enhanced.extend_from_slice(&(ip.len() as u32).to_le_bytes());
+        enhanced.extend_from_slice(&(ip.len() as u32).to_le_bytes());
+        enhanced.extend_from_slice(&ip);
+        file.extend(block(PCAPNG_ENHANCED_PACKET, &enhanced));
+
+        let mut simple = (ip.len() as u32).to_le_bytes().to_vec();
+        simple.extend_from_slice(&ip);
+        file.extend(block(PCAPNG_SIMPLE_PACKET, &simple));
+
+        // A second section, as left by concatenating captures
+        let second = file.clone();
+        file.extend(second);
+
+        let (packets, truncated) = read_all(file);
+        assert!(!truncated);
+        assert_eq!(packets.len(), 4);
+        for packet in packets {
+            assert_eq!(packet.linktype, LINKTYPE_RAW);
+            assert_eq!(packet.data, ip);
+        }
+    }
+
+    #[test]
+    fn test_rejects_other_files() {
+        assert!(PcapReader::new(Cursor::new(b"GIF89a".to_vec())).is_err());
+    }
+
+    #[test]
+    fn test_parse_ipv4_link_types() {
+        let ip = ipv4_udp([192, 0, 2, 1], [198, 51, 100, 1], 5353, 53, 10);
+        let expected = Traffic {
+            flow: FlowKey {
+                src: "192.0.2.1".parse().unwrap(),
+                dst: "198.51.100.1".parse().unwrap(),
+                protocol: 17,
+                src_port: 5353,
+                dst_port: 53,
+            },
+            packets: 1,
+            bytes: 38,
+        };
+
+        let mut vlan = vec![0u8; 12];
+        vlan.extend_from_slice(&[0x81, 0x00, 0x00, 0x64]);
+        vlan.extend_from_slice(&ethernet(0x0800, &ip)[12..]);
+        let mut sll = vec![0u8; 14];
+        sll.extend_from_slice(&[0x08, 0x00]);
+        sll.extend_from_slice(&ip);
+        let mut sll2 = vec![0x08, 0x00];
+        sll2.extend_from_slice(&[0; 18]);
+        sll2.extend_from_slice(&ip);
+        let mut null = 2u32.to_le_bytes().to_vec();
+        null.extend_from_slice(&ip);
+
+        for (linktype, data) in [
+            (LINKTYPE_ETHERNET, ethernet(0x0800, &ip)),
+            (LINKTYPE_ETHERNET, vlan),
+            (LINKTYPE_LINUX_SLL, sll),
+            (LINKTYPE_LINUX_SLL2, sll2),
+            (LINKTYPE_NULL, null),
+            (LINKTYPE_RAW, ip.clone()),
+        ] {
+            assert_eq!(
+                parse_packet(linktype, &data),
+                Some(expected),
+                "{}",
+                linktype
+            );
+        }
+        assert_eq!(
+            parse_packet(LINKTYPE_ETHERNET, &ethernet(0x0806, &[0; 28])),
+            None
+        );
+        assert_eq!(parse_packet(LINKTYPE_ETHERNET, &[0; 10]), None);
+    }
+
+    #[test]
+    fn test_parse_ipv6_with_extension_headers() {
+        let mut ip = vec![0x60, 0, 0, 0, 0, 24, 0, 64];
+        ip.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        ip.extend_from_slice(&"2001:db8::2".parse::<Ipv6Addr>().unwrap().octets());
+        // Hop-by-hop options followed by TCP
+        ip.extend_from_slice(&[6, 0, 0, 0, 0, 0, 0, 0]);
+        ip.extend_from_slice(&443u16.to_be_bytes());
+        ip.extend_from_slice(&50000u16.to_be_bytes());
+        ip.extend_from_slice(&[0; 12]);
+
+        let traffic = parse_packet(LINKTYPE_ETHERNET, &ethernet(0x86dd, &ip)).unwrap();
+        assert_eq!(traffic.flow.src, "2001:db8::1".parse::<IpAddr>().unwrap());
+        assert_eq!(traffic.flow.protocol, 6);
+        assert_eq!((traffic.flow.src_port, traffic.flow.dst_port), (443, 50000));
+        assert_eq!(traffic.bytes, 64);
+    }
+
+    #[test]
+    fn test_udp_payload() {
+        let ip = ipv4_udp([192, 0, 2, 1], [198, 51, 100, 1], 2055, 9995, 5);
+        let mut frame = ethernet(0x0800, &ip);
+        frame.extend_from_slice(&[0; 6]);
+        let (sender, payload) = udp_payload(LINKTYPE_ETHERNET, &frame).unwrap();
+        assert_eq!(sender, "192.0.2.1:2055".parse().unwrap());
+        assert_eq!(payload, [0; 5]);
+
+        let mut tcp = ip.clone();
+        tcp[9] = 6;
+        assert_eq!(udp_payload(LINKTYPE_RAW, &tcp), None);
+
+        // Snaplen cut the capture inside the UDP header
+        for captured in [20 + 6, 20 + 7] {
+            assert_eq!(udp_payload(LINKTYPE_RAW, &ip[..captured]), None);
+        }
+    }
+
+    #[test]
+    fn test_non_first_fragment_has_no_ports() {
+        let mut ip = ipv4_udp([192, 0, 2, 1], [198, 51, 100, 1], 5353, 53, 10);
+        ip[6] = 0x00;
+        ip[7] = 0x10;
+        let traffic = parse_packet(LINKTYPE_RAW, &ip).unwrap();
+        assert_eq!((traffic.flow.src_port, traffic.flow.dst_port), (0, 0));
+    }
+}
@@ -0,0 +1,604 @@
+// Watching addresses and prefixes for changes in their origin ASNs
+
+pub mod webhook;
+
+pub use webhook::Webhook;
+
+use crate::asn::Asn;
+use crate::enrich::{lookup_all, DEFAULT_WORKERS};
+use crate::network::Prefix;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{BufRead, Error, ErrorKind};
+use std::net::IpAddr;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How long to wait between checks, written as a number of seconds or with
+/// an s, m, h or d suffix (e.g. "90s", "15m", "1h")
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interval(pub Duration);
+
+impl FromStr for Interval {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+        let scale = match unit {
+            "" | "s" => 1,
+            "m" => 60,
+            "h" => 3600,
+            "d" => 86400,
+            _ => 0,
+        };
+        match number
+            .parse::<u64>()
+            .ok()
+            .and_then(|n| n.checked_mul(scale))
+        {
+            Some(secs) if secs > 0 => Ok(Interval(Duration::from_secs(secs))),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid interval '{}', expected e.g. 30s, 15m or 1h", s),
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.0.as_secs();
+        match [(86400, "d"), (3600, "h"), (60, "m")]
+            .iter()
+            .find(|(scale, _)| secs % scale == 0)
+        {
+            Some((scale, unit)) if secs > 0 => write!(f, "{}{}", secs / scale, unit),
+            _ => write!(f, "{}s", secs),
+        }
+    }
+}
+
+/// Reads targets, one address or prefix per line, ignoring blank lines and "#" comments
+///
+/// # Errors
+///
+/// Returns an error naming the line of the first target that cannot be parsed
+pub fn read_targets(reader: impl BufRead) -> Result<Vec<Prefix>, Error> {
+    let mut targets = Vec::new();
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let target = line.split('#').next().unwrap_or_default().trim();
+        if target.is_empty() {
+            continue;
+        }
+        let prefix: Prefix = target.parse().map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("line {}: invalid target '{}': {}", number + 1, target, e),
+            )
+        })?;
+        if !targets.contains(&prefix) {
+            targets.push(prefix);
+        }
+    }
+    Ok(targets)
+}
+
+/// The name a target is reported and stored under: its address for single
+/// addresses, otherwise the prefix
+pub fn target_name(target: &Prefix) -> String {
+    if target.prefix_len() == target.max_len() {
+        target.addr().to_string()
+    } else {
+        target.to_string()
+    }
+}
+
+/// The origins last seen for a target
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Observation {
+    /// Origin ASNs, sorted
+    pub origins: Vec<u32>,
+    /// Routed prefixes covering the target, as reported by the lookup
+    pub prefixes: Vec<String>,
+    /// Unix time of the lookup
+    pub checked: u64,
+}
+
+/// The last known origins of every target, persisted between runs
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WatchState {
+    pub targets: BTreeMap<String, Observation>,
+}
+
+impl WatchState {
+    /// Loads the state saved at `path`, or an empty state if the file does not exist yet
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not valid state JSON
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        match fs::read(path) {
+            Ok(data) => serde_json::from_slice(&data).map_err(|e| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("invalid state file {}: {}", path.display(), e),
+                )
+            }),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(WatchState::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Saves the state to `path`, replacing the previous file only once the new one is written
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        let data = serde_json::to_vec_pretty(self)?;
+        fs::write(&temp, data)?;
+        fs::rename(&temp, path)
+    }
+}
+
+/// What changed about a target's origins
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EventKind {
+    /// An ASN started originating the target
+    NewOrigin,
+    /// An ASN stopped originating the target
+    LostOrigin,
+    /// The target became originated by more than one ASN
+    Moas,
+    /// A route more specific than one seen before appeared inside the target
+    MoreSpecific,
+}
+
+/// A change in a target's origins, serialized as one JSON object per event
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Event {
+    /// Unix time of the lookup that saw the change
+    pub time: u64,
+    pub event: EventKind,
+    pub target: String,
+    /// The ASN gained or lost; None for MOAS and more-specific events
+    pub asn: Option<u32>,
+    pub origins: Vec<u32>,
+    pub previous: Vec<u32>,
+    /// A one-line description, suitable for chat webhooks
+    pub message: String,
+}
+
+fn origin_list(origins: &[u32]) -> String {
+    if origins.is_empty() {
+        return "none".to_string();
+    }
+    let asns: Vec<String> = origins.iter().map(|asn| format!("AS{}", asn)).collect();
+    asns.join(", ")
+}
+
+/// Lists the events between the previous and current observation of a target
+///
+/// A target seen for the first time only raises a MOAS event, since there is
+/// nothing to compare its origins with. A routed prefix that is more specific
+/// than a previously seen one raises a more-specific event, even when it is
+/// originated by a known ASN.
+pub fn compare(target: &str, previous: Option<&Observation>, current: &Observation) -> Vec<Event> {
+    let event = |event: EventKind, asn: Option<u32>, message: String| Event {
+        time: current.checked,
+        event,
+        target: target.to_string(),
+        asn,
+        origins: current.origins.clone(),
+        previous: previous.map(|p| p.origins.clone()).unwrap_or_default(),
+        message,
+    };
+
+    let mut events = Vec::new();
+    if let Some(previous) = previous {
+        for &asn in &current.origins {
+            if !previous.origins.contains(&asn) {
+                let message = format!(
+                    "{} is now originated by AS{} (was {})",
+                    target,
+                    asn,
+                    origin_list(&previous.origins)
+                );
+                events.push(event(EventKind::NewOrigin, Some(asn), message));
+            }
+        }
+        for &asn in &previous.origins {
+            if !current.origins.contains(&asn) {
+                let message = format!(
+                    "{} is no longer originated by AS{} (now {})",
+                    target,
+                    asn,
+                    origin_list(&current.origins)
+                );
+                events.push(event(EventKind::LostOrigin, Some(asn), message));
+            }
+        }
+    }
+    if let Some(previous) = previous {
+        let parse = |prefixes: &[String]| -> Vec<Prefix> {
+            prefixes.iter().filter_map(|p| p.parse().ok()).collect()
+        };
+        let known = parse(&previous.prefixes);
+        for prefix in parse(&current.prefixes) {
+            if known.contains(&prefix) {
+                continue;
+            }
+            let covering = known.iter().find(|covering| {
+                covering.contains_prefix(&prefix) && covering.prefix_len() < prefix.prefix_len()
+            });
+            if let Some(covering) = covering {
+                let message = format!(
+                    "{} has a new more-specific route {} inside {} (origins {})",
+                    target,
+                    prefix,
+                    covering,
+                    origin_list(&current.origins)
+                );
+                events.push(event(EventKind::MoreSpecific, None, message));
+            }
+        }
+    }
+    let was_moas = previous.is_some_and(|p| p.origins.len() > 1);
+    if current.origins.len() > 1 && !was_moas {
+        let message = format!(
+            "{} is originated by multiple ASNs: {}",
+            target,
+            origin_list(&current.origins)
+        );
+        events.push(event(EventKind::Moas, None, message));
+    }
+    events
+}
+
+/// Looks up a set of targets and compares their origins with the last known state
+pub struct Watcher<'a> {
+    lookup: &'a dyn Asn,
+    targets: Vec<Prefix>,
+    workers: usize,
+}
+
+impl<'a> Watcher<'a> {
+    /// Most addresses looked up inside each prefix target
+    pub const SAMPLES: usize = 16;
+
+    /// Creates a watcher for the given targets
+    ///
+    /// Prefixes are looked up at `SAMPLES` evenly spaced addresses, so a
+    /// more-specific route covering none of them goes unnoticed.
+    pub fn new(lookup: &'a dyn Asn, targets: Vec<Prefix>) -> Self {
+        Watcher {
+            lookup,
+            targets,
+            workers: DEFAULT_WORKERS,
+        }
+    }
+
+    /// Sets the number of lookups run concurrently
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers;
+        self
+    }
+
+    /// Looks up every target once, updating `state` and returning the changes
+    ///
+    /// Targets whose lookup fails are logged as warnings and keep their
+    /// previous state, so an outage is not mistaken for lost origins.
+    pub fn check(&self, state: &mut WatchState) -> Vec<Event> {
+        let samples: Vec<Vec<IpAddr>> = self.targets.iter().map(sample_addresses).collect();
+        let addresses: Vec<IpAddr> = samples.iter().flatten().copied().collect();
+        let results = lookup_all(self.lookup, &addresses, self.workers);
+        let checked = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        let mut events = Vec::new();
+        'targets: for (target, samples) in self.targets.iter().zip(&samples) {
+            let name = target_name(target);
+            let mut current = Observation {
+                checked,
+                ..Observation::default()
+            };
+            // Several targets may share an address, e.g. a prefix and its first host
+            for ip in samples {
+                let infos = match results.get(ip) {
+                    Some(Ok(infos)) => infos,
+                    Some(Err(e)) => {
+                        tracing::warn!(prefix = %name, %ip, error = %e, "lookup failed");
+                        continue 'targets;
+                    }
+                    None => continue,
+                };
+                current
+                    .origins
+                    .extend(infos.iter().filter_map(|info| info.asn_number()));
+                current
+                    .prefixes
+                    .extend(infos.iter().filter_map(|info| info.prefix.clone()));
+            }
+            current.origins.sort_unstable();
+            current.origins.dedup();
+            current.prefixes.sort();
+            current.prefixes.dedup();
+
+            events.extend(compare(&name, state.targets.get(&name), &current));
+            state.targets.insert(name, current);
+        }
+        events
+    }
+}
+
+/// The addresses a target is looked up at: the first address of each of up
+/// to `Watcher::SAMPLES` equal parts of the prefix
+fn sample_addresses(target: &Prefix) -> Vec<IpAddr> {
+    let bits = Watcher::SAMPLES.ilog2() as u8;
+    let len = (target.prefix_len() + bits).min(target.max_len());
+    match target.subnets(len) {
+        Ok(subnets) => subnets.map(|subnet| subnet.addr()).collect(),
+        Err(_) => vec![target.addr()],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asn::AsnInfo;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    /// Answers lookups from a table that tests can change between checks
+    #[derive(Default)]
+    struct Table {
+        results: Mutex<HashMap<IpAddr, Result<Vec<u32>, ErrorKind>>>,
+        routes: Mutex<HashMap<IpAddr, String>>,
+    }
+
+    impl Table {
+        fn set(&self, ip: &str, result: Result<Vec<u32>, ErrorKind>) {
+            self.results
+                .lock()
+                .unwrap()
+                .insert(ip.parse().unwrap(), result);
+        }
+
+        /// Sets the routed prefix reported for an address
+        fn route(&self, ip: &str, prefix: &str) {
+            self.routes
+                .lock()
+                .unwrap()
+                .insert(ip.parse().unwrap(), prefix.to_string());
+        }
+    }
+
+    impl Asn for Table {
+        fn lookup_asn(&self, ip: IpAddr) -> Result<Vec<AsnInfo>, Error> {
+            let prefix = self.routes.lock().unwrap().get(&ip).cloned();
+            match self.results.lock().unwrap().get(&ip).cloned() {
+                Some(Ok(asns)) => Ok(asns
+                    .iter()
+                    .map(|asn| AsnInfo {
+                        prefix: prefix.clone(),
+                        ..AsnInfo::new(asn.to_string(), "Example")
+                    })
+                    .collect()),
+                Some(Err(kind)) => Err(Error::new(kind, "lookup failed")),
+                None => Ok(Vec::new()),
+            }
+        }
+    }
+
+    fn kinds(events: &[Event]) -> Vec<(EventKind, &str, Option<u32>)> {
+        events
+            .iter()
+            .map(|e| (e.event, e.target.as_str(), e.asn))
+            .collect()
+    }
+
+    #[test]
+    fn test_interval() {
+        let secs = |s: &str| s.parse::<Interval>().unwrap().0.as_secs();
+        assert_eq!(secs("90"), 90);
+        assert_eq!(secs("90s"), 90);
+        assert_eq!(secs("15m"), 900);
+        assert_eq!(secs("1h"), 3600);
+        assert_eq!(secs("2d"), 172800);
+        for invalid in ["", "0", "1w", "h", "-5m", "1.5h", "99999999999999999d"] {
+            assert!(invalid.parse::<Interval>().is_err(), "{}", invalid);
+        }
+        assert_eq!(Interval(Duration::from_secs(7200)).to_string(), "2h");
+        assert_eq!(Interval(Duration::from_secs(90)).to_string(), "90s");
+    }
+
+    #[test]
+    fn test_read_targets() {
+        let input = "# our space\n192.0.2.0/24\n\n198.51.100.7  # resolver\n192.0.2.0/24\n";
+        let targets = read_targets(input.as_bytes()).unwrap();
+        let names: Vec<String> = targets.iter().map(target_name).collect();
+        assert_eq!(names, ["192.0.2.0/24", "198.51.100.7"]);
+
+        let err = read_targets("192.0.2.0/24\nnot-an-ip\n".as_bytes()).unwrap_err();
+        assert!(err.to_string().starts_with("line 2:"));
+    }
+
+    #[test]
+    fn test_compare() {
+        let observation = |origins: &[u32]| Observation {
+            origins: origins.to_vec(),
+            prefixes: Vec::new(),
+            checked: 1,
+        };
+        assert!(compare("t", None, &observation(&[64496])).is_empty());
+        assert!(compare("t", Some(&observation(&[64496])), &observation(&[64496])).is_empty());
+
+        let events = compare("t", Some(&observation(&[64496])), &observation(&[64497]));
+        assert_eq!(
+            kinds(&events),
+            [
+                (EventKind::NewOrigin, "t", Some(64497)),
+                (EventKind::LostOrigin, "t", Some(64496))
+            ]
+        );
+        assert_eq!(
+            events[0].message,
+            "t is now originated by AS64497 (was AS64496)"
+        );
+        assert_eq!(events[1].previous, [64496]);
+
+        let events = compare(
+            "t",
+            Some(&observation(&[64496])),
+            &observation(&[64496, 64497]),
+        );
+        assert_eq!(
+            kinds(&events),
+            [
+                (EventKind::NewOrigin, "t", Some(64497)),
+                (EventKind::Moas, "t", None)
+            ]
+        );
+        assert_eq!(
+            kinds(&compare("t", None, &observation(&[64496, 64497]))),
+            [(EventKind::Moas, "t", None)]
+        );
+
+        let events = compare("t", Some(&observation(&[64496])), &observation(&[]));
+        assert_eq!(
+            events[0].message,
+            "t is no longer originated by AS64496 (now none)"
+        );
+
+        let json = serde_json::to_value(&events[0]).unwrap();
+        assert_eq!(json["event"], "lost-origin");
+        assert_eq!(json["asn"], 64496);
+
+        let routed = |prefixes: &[&str]| Observation {
+            prefixes: prefixes.iter().map(|p| p.to_string()).collect(),
+            ..observation(&[64496])
+        };
+        let events = compare(
+            "t",
+            Some(&routed(&["192.0.2.0/24"])),
+            &routed(&["192.0.2.0/24", "192.0.2.128/25"]),
+        );
+        assert_eq!(kinds(&events), [(EventKind::MoreSpecific, "t", None)]);
+        assert_eq!(
+            events[0].message,
+            "t has a new more-specific route 192.0.2.128/25 inside 192.0.2.0/24 (origins AS64496)"
+        );
+        // A replacement route that is not inside a known one is not more specific
+        assert!(compare(
+            "t",
+            Some(&routed(&["192.0.2.0/25"])),
+            &routed(&["192.0.2.0/24"])
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_watcher_check() {
+        let table = Table::default();
+        table.set("192.0.2.0", Ok(vec![64496]));
+        table.set("198.51.100.7", Ok(vec![64497]));
+        let targets = read_targets("192.0.2.0/24\n192.0.2.0\n198.51.100.7\n".as_bytes()).unwrap();
+        let watcher = Watcher::new(&table, targets).workers(2);
+
+        let mut state = WatchState::default();
+        assert!(watcher.check(&mut state).is_empty());
+        assert_eq!(state.targets["192.0.2.0/24"].origins, [64496]);
+        // Targets sharing an address each get the result
+        assert_eq!(state.targets["192.0.2.0"].origins, [64496]);
+
+        table.set("192.0.2.0", Ok(vec![64496, 64511]));
+        table.set("198.51.100.7", Err(ErrorKind::TimedOut));
+        assert_eq!(
+            kinds(&watcher.check(&mut state)),
+            [
+                (EventKind::NewOrigin, "192.0.2.0/24", Some(64511)),
+                (EventKind::Moas, "192.0.2.0/24", None),
+                (EventKind::NewOrigin, "192.0.2.0", Some(64511)),
+                (EventKind::Moas, "192.0.2.0", None)
+            ]
+        );
+        // A failed lookup keeps the last known origins
+        assert_eq!(state.targets["198.51.100.7"].origins, [64497]);
+    }
+
+    #[test]
+    fn test_watcher_check_samples_prefix() {
+        let table = Table::default();
+        table.set("192.0.2.0", Ok(vec![64496]));
+        table.route("192.0.2.0", "192.0.2.0/24");
+        let targets = read_targets(
+            "192.0.2.0/24
+"
+            .as_bytes(),
+        )
+        .unwrap();
+        let watcher = Watcher::new(&table, targets);
+        assert_eq!(
+            sample_addresses(&watcher.targets[0]).len(),
+            Watcher::SAMPLES
+        );
+
+        let mut state = WatchState::default();
+        assert!(watcher.check(&mut state).is_empty());
+        assert_eq!(state.targets["192.0.2.0/24"].prefixes, ["192.0.2.0/24"]);
+
+        // A hijack of the upper half is seen although the first address is unchanged
+        table.set("192.0.2.128", Ok(vec![64511]));
+        table.route("192.0.2.128", "192.0.2.128/25");
+        assert_eq!(
+            kinds(&watcher.check(&mut state)),
+            [
+                (EventKind::NewOrigin, "192.0.2.0/24", Some(64511)),
+                (EventKind::MoreSpecific, "192.0.2.0/24", None),
+                (EventKind::Moas, "192.0.2.0/24", None)
+            ]
+        );
+
+        let single = "198.51.100.7".parse().unwrap();
+        assert_eq!(sample_addresses(&single), [single.addr()]);
+    }
+
+    #[test]
+    fn test_state_round_trip() {
+        let path =
+            std::env::temp_dir().join(format!("asn-fetcher-watch-{}.json", std::process::id()));
+        assert_eq!(WatchState::load(&path).unwrap(), WatchState::default());
+
+        let mut state = WatchState::default();
+        state.targets.insert(
+            "192.0.2.0/24".to_string(),
+            Observation {
+                origins: vec![64496],
+                prefixes: vec!["192.0.2.0/24".to_string()],
+                checked: 1700000000,
+            },
+        );
+        state.save(&path).unwrap();
+        assert_eq!(WatchState::load(&path).unwrap(), state);
+
+        fs::write(&path, "not json").unwrap();
+        assert_eq!(
+            WatchState::load(&path).unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
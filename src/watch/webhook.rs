@@ -0,0 +1,128 @@
+// Delivering watch events to an HTTP webhook
+
+use super::Event;
+use crate::asn::client::map_reqwest_error;
+use reqwest::blocking::{Client, ClientBuilder};
+use std::io::{Error, ErrorKind};
+use std::time::Duration;
+
+/// Posts each event as a JSON object to a URL
+pub struct Webhook {
+    client: Client,
+    url: String,
+}
+
+impl Webhook {
+    const TIMEOUT_SECS: u64 = 10;
+
+    /// Creates a webhook posting to `url`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the HTTP client cannot be created
+    pub fn new(url: impl Into<String>) -> Result<Self, reqwest::Error> {
+        let client = ClientBuilder::new()
+            .timeout(Duration::from_secs(Self::TIMEOUT_SECS))
+            .build()?;
+        Ok(Webhook {
+            client,
+            url: url.into(),
+        })
+    }
+
+    /// Posts one event
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the server does not answer with a success status
+    pub fn send(&self, event: &Event) -> Result<(), Error> {
+        let response = self
+            .client
+            .post(&self.url)
+            .json(event)
+            .send()
+            .map_err(map_reqwest_error)?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!("webhook {} answered {}", self.url, status),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::watch::EventKind;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Accepts one request, answers with `status` and returns the request body
+    fn serve_once(status: &'static str) -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            let mut stream = stream;
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                status
+            )
+            .unwrap();
+            String::from_utf8(body).unwrap()
+        });
+        (url, handle)
+    }
+
+    fn event() -> Event {
+        Event {
+            time: 1700000000,
+            event: EventKind::NewOrigin,
+            target: "192.0.2.0/24".to_string(),
+            asn: Some(64511),
+            origins: vec![64496, 64511],
+            previous: vec![64496],
+            message: "192.0.2.0/24 is now originated by AS64511 (was AS64496)".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_send() {
+        let (url, handle) = serve_once("204 No Content");
+        Webhook::new(url).unwrap().send(&event()).unwrap();
+        let body: serde_json::Value = serde_json::from_str(&handle.join().unwrap()).unwrap();
+        assert_eq!(body["event"], "new-origin");
+        assert_eq!(body["target"], "192.0.2.0/24");
+        assert_eq!(body["origins"], serde_json::json!([64496, 64511]));
+    }
+
+    #[test]
+    fn test_send_rejected() {
+        let (url, handle) = serve_once("500 Internal Server Error");
+        let err = Webhook::new(url).unwrap().send(&event()).unwrap_err();
+        assert!(err.to_string().contains("500"));
+        handle.join().unwrap();
+    }
+}
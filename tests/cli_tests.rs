@@ -5,7 +5,8 @@ use asn_fetcher::cli::Args;
 use assert_cmd::prelude::*;
 use clap::Parser;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
-use std::process::Command;
+use std::path::PathBuf;
+use std::process::{Child, Command};
 
 /// Integration test for IPv4 ASN lookup
 ///
@@ -50,6 +51,61 @@ fn test_ripe_client_creation() {
     assert!(result.is_ok(), "Should be able to create a Ripe client");
 }
 
+/// A pfx2as table routing 192.0.2.0/24 from AS64496, removed when dropped
+struct Pfx2asFixture(PathBuf);
+
+impl Pfx2asFixture {
+    /// The `--source` value reading the table
+    fn source(&self) -> String {
+        format!("pfx2as:{}", self.0.display())
+    }
+}
+
+impl Drop for Pfx2asFixture {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Writes the pfx2as table to a temporary file unique to the test `name`
+fn pfx2as_fixture(name: &str) -> Pfx2asFixture {
+    let path = std::env::temp_dir().join(format!(
+        "asn-fetcher-{}-{}.pfx2as",
+        name,
+        std::process::id()
+    ));
+    std::fs::write(&path, "192.0.2.0\t24\t64496\n").unwrap();
+    Pfx2asFixture(path)
+}
+
+/// Starts a server subcommand on an ephemeral port over the pfx2as table and
+/// returns it with the address it announces once ready
+fn spawn_server(pfx2as: &Pfx2asFixture, subcommand: &[&str]) -> (Child, String) {
+    use std::io::{BufRead, BufReader};
+
+    let mut child = cli_command()
+        .arg("--source")
+        .arg(pfx2as.source())
+        .args(subcommand)
+        .args(["--listen", "127.0.0.1:0"])
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stderr = BufReader::new(child.stderr.take().unwrap());
+    let addr = loop {
+        let mut line = String::new();
+        assert!(stderr.read_line(&mut line).unwrap() > 0, "server exited");
+        let announced = line.split_once("Listening on ");
+        if let Some((_, url)) = announced.and_then(|(_, rest)| rest.split_once("://")) {
+            break url.split_whitespace().next().unwrap().to_string();
+        }
+    };
+    // Keep the pipe open so later log lines don't fail to write
+    child.stderr = Some(stderr.into_inner());
+    (child, addr)
+}
+
 fn cli_command() -> Command {
     Command::new(assert_cmd::cargo::cargo_bin!("asn-fetcher"))
 }
@@ -57,20 +113,20 @@ fn cli_command() -> Command {
 #[test]
 fn test_valid_ipv4() {
     let args = Args::try_parse_from(["asn-fetcher", "127.0.0.1"]).expect("Valid IPv4 should parse");
-    assert_eq!(args.ip, IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)));
+    assert_eq!(args.ip, Some(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))));
 }
 
 #[test]
 fn test_valid_ipv6() {
     let args = Args::try_parse_from(["asn-fetcher", "::1"]).expect("Valid IPv6 should parse");
-    assert_eq!(args.ip, IpAddr::V6(Ipv6Addr::LOCALHOST));
+    assert_eq!(args.ip, Some(IpAddr::V6(Ipv6Addr::LOCALHOST)));
 }
 
 #[test]
 fn test_valid_ipv4_with_source() {
     let args = Args::try_parse_from(["asn-fetcher", "127.0.0.1", "--source", "ripe"])
         .expect("Valid IPv4 and source should parse");
-    assert_eq!(args.ip, IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)));
+    assert_eq!(args.ip, Some(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))));
     assert_eq!(args.source, "ripe");
 }
 
@@ -80,3 +136,933 @@ fn test_invalid_ip() {
     cmd.arg("not-an-ip");
     cmd.assert().failure();
 }
+
+#[test]
+fn test_delegated_files_parse() {
+    let args = Args::try_parse_from([
+        "asn-fetcher",
+        "8.8.8.8",
+        "--source",
+        "ripe,cymru-whois",
+        "--delegated",
+        "delegated-arin-extended-latest",
+        "--delegated",
+        "delegated-ripencc-extended-latest",
+    ])
+    .expect("Repeated --delegated should parse");
+    assert_eq!(args.source, "ripe,cymru-whois");
+    assert_eq!(args.delegated.len(), 2);
+}
+
+#[test]
+fn test_missing_delegated_file() {
+    let mut cmd = cli_command();
+    cmd.args(["8.8.8.8", "--delegated", "/nonexistent/delegated-stats"]);
+    cmd.assert().failure();
+}
+
+#[test]
+fn test_pfx2as_source() {
+    let pfx2as = pfx2as_fixture("cli");
+
+    let mut cmd = cli_command();
+    cmd.args(["192.0.2.10", "--source"]).arg(pfx2as.source());
+    let output = cmd.output().unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("64496"));
+    assert!(stdout.contains("192.0.2.0/24"));
+}
+
+#[test]
+fn test_metrics_file() {
+    let dir = std::env::temp_dir();
+    let id = std::process::id();
+    let pfx2as = pfx2as_fixture("metrics");
+    let metrics = dir.join(format!("asn-fetcher-metrics-{}.prom", id));
+
+    let mut cmd = cli_command();
+    cmd.args(["192.0.2.10", "--source"])
+        .arg(pfx2as.source())
+        .arg("--metrics-file")
+        .arg(&metrics);
+    let output = cmd.output().unwrap();
+
+    assert!(output.status.success());
+    let text = std::fs::read_to_string(&metrics).unwrap();
+    std::fs::remove_file(&metrics).unwrap();
+    assert!(text.contains("asn_fetcher_lookups_total{provider=\"pfx2as\"} 1\n"));
+    assert!(text.contains("# TYPE asn_fetcher_lookup_duration_seconds histogram\n"));
+}
+
+#[test]
+fn test_log_levels_and_json() {
+    let pfx2as = pfx2as_fixture("log");
+    let run = |flags: &[&str]| {
+        let output = cli_command()
+            .args(["192.0.2.10", "--source"])
+            .arg(pfx2as.source())
+            .args(flags)
+            .env_remove("RUST_LOG")
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stderr).into_owned()
+    };
+
+    assert_eq!(run(&[]).trim(), "INFO Using provider: pfx2as");
+    assert_eq!(run(&["-q"]), "");
+
+    let json = run(&["-v", "--log-format", "json"]);
+    let events: Vec<serde_json::Value> = json
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(events[0]["fields"]["message"], "Using provider: pfx2as");
+    let found = events
+        .iter()
+        .find(|event| event["fields"]["message"] == "found")
+        .unwrap();
+    assert_eq!(found["level"], "DEBUG");
+    assert_eq!(found["spans"][0]["provider"], "pfx2as");
+    assert_eq!(found["spans"][0]["ip"], "192.0.2.10");
+}
+
+#[test]
+fn test_unknown_dataset_format() {
+    let mut cmd = cli_command();
+    cmd.args(["192.0.2.10", "--source", "bogus:/tmp/file"]);
+    cmd.assert().failure();
+}
+
+#[test]
+fn test_db_build_and_localdb_source() {
+    let dir = std::env::temp_dir();
+    let id = std::process::id();
+    let pfx2as = pfx2as_fixture("build");
+    let delegated = dir.join(format!("asn-fetcher-build-{}.delegated", id));
+    let db = dir.join(format!("asn-fetcher-build-{}.asndb", id));
+    std::fs::write(&delegated, "ripencc|NL|asn|64496|1|20200101|assigned|x\n").unwrap();
+
+    let build = cli_command()
+        .args(["db", "build", "--output"])
+        .arg(&db)
+        .arg(pfx2as.source())
+        .arg(format!("delegated:{}", delegated.display()))
+        .output()
+        .unwrap();
+    assert!(
+        build.status.success(),
+        "{}",
+        String::from_utf8_lossy(&build.stderr)
+    );
+
+    let lookup = cli_command()
+        .args(["192.0.2.10", "--source"])
+        .arg(format!("localdb:{}", db.display()))
+        .output()
+        .unwrap();
+    for path in [&delegated, &db] {
+        std::fs::remove_file(path).unwrap();
+    }
+
+    assert!(lookup.status.success());
+    let stdout = String::from_utf8_lossy(&lookup.stdout);
+    assert!(stdout.contains("64496"));
+    assert!(stdout.contains("NL"));
+}
+
+#[test]
+fn test_db_build_requires_inputs() {
+    let mut cmd = cli_command();
+    cmd.args(["db", "build", "--output", "/tmp/unused.asndb"]);
+    cmd.assert().failure();
+}
+
+#[test]
+fn test_missing_ip_without_subcommand() {
+    let mut cmd = cli_command();
+    cmd.assert().failure();
+}
+
+#[test]
+fn test_db_export_mmdb() {
+    let dir = std::env::temp_dir();
+    let id = std::process::id();
+    let tsv = dir.join(format!("asn-fetcher-export-{}.tsv", id));
+    let mmdb = dir.join(format!("asn-fetcher-export-{}.mmdb", id));
+    std::fs::write(&tsv, "192.0.2.0\t192.0.2.255\t64496\tNL\tEXAMPLE-AS\n").unwrap();
+
+    let export = cli_command()
+        .args(["db", "export", "--format", "mmdb", "--output"])
+        .arg(&mmdb)
+        .arg(format!("iptoasn:{}", tsv.display()))
+        .output()
+        .unwrap();
+    assert!(
+        export.status.success(),
+        "{}",
+        String::from_utf8_lossy(&export.stderr)
+    );
+
+    let lookup = cli_command()
+        .args(["192.0.2.10", "--source"])
+        .arg(format!("mmdb:{}", mmdb.display()))
+        .output()
+        .unwrap();
+    std::fs::remove_file(&tsv).unwrap();
+    std::fs::remove_file(&mmdb).unwrap();
+
+    assert!(lookup.status.success());
+    let stdout = String::from_utf8_lossy(&lookup.stdout);
+    assert!(stdout.contains("64496"));
+    assert!(stdout.contains("EXAMPLE-AS"));
+}
+
+#[test]
+fn test_vrps_annotate_results() {
+    let dir = std::env::temp_dir();
+    let id = std::process::id();
+    let pfx2as = dir.join(format!("asn-fetcher-rov-{}.pfx2as", id));
+    let vrps = dir.join(format!("asn-fetcher-rov-{}.json", id));
+    std::fs::write(&pfx2as, "192.0.2.0\t24\t64496\n198.51.100.0\t24\t64497\n").unwrap();
+    std::fs::write(
+        &vrps,
+        r#"{"roas": [{"asn": "AS64496", "prefix": "192.0.2.0/24", "maxLength": 24, "ta": "test"},
+                     {"asn": "AS64500", "prefix": "198.51.100.0/22", "maxLength": 24, "ta": "test"}]}"#,
+    )
+    .unwrap();
+
+    let lookup = |ip: &str| {
+        cli_command()
+            .args([ip, "--source"])
+            .arg(format!("pfx2as:{}", pfx2as.display()))
+            .arg("--vrps")
+            .arg(&vrps)
+            .output()
+            .unwrap()
+    };
+    let valid = lookup("192.0.2.1");
+    let invalid = lookup("198.51.100.1");
+    std::fs::remove_file(&pfx2as).unwrap();
+    std::fs::remove_file(&vrps).unwrap();
+
+    assert!(valid.status.success());
+    assert!(String::from_utf8_lossy(&valid.stdout).contains("rpki: Some(Valid)"));
+    assert!(invalid.status.success());
+    assert!(String::from_utf8_lossy(&invalid.stdout).contains("rpki: Some(InvalidAsn)"));
+}
+
+#[test]
+fn test_rtr_source_validates_results() {
+    use asn_fetcher::rpki::{rtr::Pdu, Vrp};
+    use std::io::Write;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let cache = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        assert_eq!(Pdu::read(&mut stream).unwrap().1, Pdu::ResetQuery);
+        let vrp = Vrp {
+            prefix: "192.0.2.0/24".parse().unwrap(),
+            max_length: 24,
+            asn: 64500,
+        };
+        for pdu in [
+            Pdu::CacheResponse { session: 1 },
+            Pdu::Prefix {
+                announce: true,
+                vrp,
+            },
+            Pdu::EndOfData {
+                session: 1,
+                serial: 1,
+                timers: None,
+            },
+        ] {
+            stream.write_all(&pdu.encode(1)).unwrap();
+        }
+    });
+
+    let pfx2as = pfx2as_fixture("rtr");
+    let output = cli_command()
+        .args(["192.0.2.1", "--rtr", &addr.to_string(), "--source"])
+        .arg(pfx2as.source())
+        .output()
+        .unwrap();
+    cache.join().unwrap();
+
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("rpki: Some(InvalidAsn)"));
+}
+
+/// Serves one canned whois reply on a local port, standing in for an IRR server
+fn whois_stand_in(reply: &'static str) -> (String, std::thread::JoinHandle<()>) {
+    use std::io::{BufRead, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let handle = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut query = String::new();
+        std::io::BufReader::new(&stream)
+            .read_line(&mut query)
+            .unwrap();
+        stream.write_all(reply.as_bytes()).unwrap();
+    });
+    (addr, handle)
+}
+
+#[test]
+fn test_irr_routes_compare() {
+    let (addr, server) = whois_stand_in("route: 192.0.2.0/24\norigin: AS64500\nsource: TEST\n\n");
+    let pfx2as = pfx2as_fixture("irr");
+
+    let output = cli_command()
+        .arg("--source")
+        .arg(pfx2as.source())
+        .args(["irr", "--server", &addr, "routes", "192.0.2.1", "--compare"])
+        .output()
+        .unwrap();
+    server.join().unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("origin: 64500"));
+    assert!(stdout.contains("BGP origin AS64496: not registered in IRR"));
+}
+
+#[test]
+fn test_irr_not_found() {
+    let (addr, server) = whois_stand_in("%  No entries found for the selected source(s).\n");
+    let output = cli_command()
+        .args(["-qq", "irr", "--server", &addr, "as-set", "AS-MISSING"])
+        .output()
+        .unwrap();
+    server.join().unwrap();
+
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        format!("No as-set AS-MISSING found on {}\n", addr)
+    );
+}
+
+#[test]
+fn test_irr_expand_from_dump() {
+    let path = std::env::temp_dir().join(format!("asn-fetcher-irr-{}.db", std::process::id()));
+    std::fs::write(
+        &path,
+        "as-set: AS-EXAMPLE\nmembers: AS64496, AS-NESTED\n\n\
+         as-set: AS-NESTED\nmembers: AS64497, AS-EXAMPLE\n\n\
+         route: 192.0.2.0/24\norigin: AS64497\n",
+    )
+    .unwrap();
+
+    let output = cli_command()
+        .args(["irr", "expand", "AS-EXAMPLE", "--db"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "AS64496\nAS64497\n192.0.2.0/24\n"
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains("cyclic reference to AS-EXAMPLE"));
+}
+
+#[test]
+fn test_filter_from_dump() {
+    let path = std::env::temp_dir().join(format!("asn-fetcher-filter-{}.db", std::process::id()));
+    std::fs::write(
+        &path,
+        "as-set: AS-EXAMPLE\nmembers: AS64496, AS64497\n\n\
+         route: 192.0.2.0/25\norigin: AS64496\n\n\
+         route: 192.0.2.128/25\norigin: AS64497\n\n\
+         route6: 2001:db8::/32\norigin: AS64497\n",
+    )
+    .unwrap();
+
+    let output = cli_command()
+        .args(["filter", "AS-EXAMPLE", "--target", "iosxr", "-A", "--db"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "prefix-set AS-EXAMPLE\n 192.0.2.0/24 ge 25 le 25,\n 2001:db8::/32\nend-set\n"
+    );
+}
+
+#[test]
+fn test_serve_http_api() {
+    use std::io::{Read, Write};
+
+    let pfx2as = pfx2as_fixture("serve");
+
+    let (mut child, addr) = spawn_server(&pfx2as, &["serve"]);
+
+    let request = |raw: &str| {
+        let mut stream = std::net::TcpStream::connect(&addr).unwrap();
+        stream.write_all(raw.as_bytes()).unwrap();
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).unwrap();
+        reply
+    };
+    let single = request("GET /ip/192.0.2.10 HTTP/1.1\r\n\r\n");
+    let body = r#"["192.0.2.20", "198.51.100.1"]"#;
+    let bulk = request(&format!(
+        "POST /bulk HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}",
+        body.len(),
+        body
+    ));
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    assert!(single.starts_with("HTTP/1.1 200 OK"));
+    assert!(single.contains(r#""asn":"64496""#));
+    assert!(single.contains(r#""prefix":"192.0.2.0/24""#));
+    assert!(bulk.starts_with("HTTP/1.1 200 OK"));
+    assert!(bulk.contains(r#"{"ip":"198.51.100.1","results":[]}"#));
+}
+
+#[test]
+fn test_serve_whois_cymru_format() {
+    use std::io::{Read, Write};
+
+    let pfx2as = pfx2as_fixture("whois");
+
+    let (mut child, addr) = spawn_server(&pfx2as, &["serve-whois"]);
+
+    let mut stream = std::net::TcpStream::connect(&addr).unwrap();
+    stream
+        .write_all(b"begin\nverbose\n192.0.2.7\n198.51.100.1\nend\n")
+        .unwrap();
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply).unwrap();
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    let lines: Vec<&str> = reply.lines().collect();
+    assert!(lines[0].starts_with("Bulk mode;"));
+    assert_eq!(
+        lines[1],
+        "64496   | 192.0.2.7        | 192.0.2.0/24        |    |          |            | Unknown"
+    );
+    assert!(lines[2].starts_with("NA      | 198.51.100.1     | NA"));
+}
+
+#[test]
+fn test_serve_dns_origin_txt() {
+    let pfx2as = pfx2as_fixture("dns");
+
+    let (mut child, addr) = spawn_server(&pfx2as, &["serve-dns", "--zone", "asn.test"]);
+
+    // TXT query for 7.2.0.192.origin.asn.test
+    let mut query = vec![0xab, 0xcd, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+    for label in ["7", "2", "0", "192", "origin", "asn", "test"] {
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.extend_from_slice(&[0, 0, 16, 0, 1]);
+
+    let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket
+        .set_read_timeout(Some(std::time::Duration::from_secs(10)))
+        .unwrap();
+    socket.send_to(&query, &addr).unwrap();
+    let mut buf = [0u8; 512];
+    let received = socket.recv(&mut buf);
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    let response = &buf[..received.unwrap()];
+    assert_eq!(&response[..2], [0xab, 0xcd]);
+    assert_eq!(response[3] & 0x0f, 0, "NOERROR");
+    assert_eq!(&response[6..8], [0, 1], "one answer");
+    let text = String::from_utf8_lossy(response);
+    assert!(text.ends_with("64496 | 192.0.2.0/24 |  |  | "));
+}
+
+#[test]
+fn test_enrich_log_lines() {
+    use std::io::Write;
+
+    let pfx2as = pfx2as_fixture("enrich");
+
+    let mut child = cli_command()
+        .args(["--source"])
+        .arg(pfx2as.source())
+        .args(["enrich", "--inline"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"Failed password from 192.0.2.7 port 22\nidle\n[2001:db8::1]:443 ok\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Failed password from 192.0.2.7 [AS64496] port 22\nidle\n[2001:db8::1]:443 ok\n"
+    );
+}
+
+#[test]
+fn test_enrich_json_lines() {
+    use std::io::Write;
+
+    let pfx2as = pfx2as_fixture("enrich-json");
+
+    let mut child = cli_command()
+        .args(["--source"])
+        .arg(pfx2as.source())
+        .args(["enrich-json", "--field", "source.ip", "-f", "/id.resp_h"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"{\"source\":{\"ip\":\"192.0.2.7\"},\"id.resp_h\":\"10.0.0.1\"}\nplain text\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "{\"source\":{\"ip\":\"192.0.2.7\",\"as\":{\"number\":64496}},\"id.resp_h\":\"10.0.0.1\"}\nplain text\n"
+    );
+}
+
+#[test]
+fn test_enrich_csv_column() {
+    use std::io::Write;
+
+    let pfx2as = pfx2as_fixture("enrich-csv");
+
+    let mut child = cli_command()
+        .args(["--source"])
+        .arg(pfx2as.source())
+        .args(["enrich-csv", "--column", "src_ip", "-d", ";"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"action;src_ip\n\"deny; logged\";192.0.2.7\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "action;src_ip;asn;holder;prefix;country;registry;allocated\n\
+         \"deny; logged\";192.0.2.7;64496;;192.0.2.0/24;;;\n"
+    );
+}
+
+#[test]
+fn test_pcap_summary() {
+    let dir = std::env::temp_dir();
+    let id = std::process::id();
+    let pfx2as = dir.join(format!("asn-fetcher-pcap-{}.pfx2as", id));
+    let capture = dir.join(format!("asn-fetcher-pcap-{}.pcap", id));
+    std::fs::write(&pfx2as, "192.0.2.0\t24\t64496\n198.51.100.0\t24\t64497\n").unwrap();
+
+    // Little-endian pcap of Ethernet frames carrying IPv4/UDP packets
+    let mut file = vec![0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0];
+    file.extend_from_slice(&[0; 8]);
+    file.extend_from_slice(&65535u32.to_le_bytes());
+    file.extend_from_slice(&1u32.to_le_bytes());
+    for (src, dst, sport, dport) in [
+        ([192, 0, 2, 7], [198, 51, 100, 1], 40000u16, 53u16),
+        ([198, 51, 100, 1], [192, 0, 2, 7], 53, 40000),
+        ([192, 0, 2, 7], [203, 0, 113, 1], 40001, 123),
+    ] {
+        let mut frame = vec![0u8; 12];
+        frame.extend_from_slice(&[0x08, 0x00, 0x45, 0, 0, 28, 0, 0, 0, 0, 64, 17, 0, 0]);
+        frame.extend_from_slice(&src);
+        frame.extend_from_slice(&dst);
+        frame.extend_from_slice(&sport.to_be_bytes());
+        frame.extend_from_slice(&dport.to_be_bytes());
+        frame.extend_from_slice(&[0, 8, 0, 0]);
+        file.extend_from_slice(&[0; 8]);
+        file.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        file.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        file.extend_from_slice(&frame);
+    }
+    std::fs::write(&capture, file).unwrap();
+
+    let output = cli_command()
+        .args(["--source"])
+        .arg(format!("pfx2as:{}", pfx2as.display()))
+        .arg("pcap")
+        .arg(&capture)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&pfx2as).unwrap();
+    std::fs::remove_file(&capture).unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 4, "{}", stdout);
+    assert!(lines[0].starts_with("ASN"));
+    assert_eq!(
+        lines[1].split_whitespace().collect::<Vec<_>>(),
+        ["AS64496", "3", "84", "2", "1", "Unknown"]
+    );
+    assert_eq!(
+        lines[2].split_whitespace().collect::<Vec<_>>(),
+        ["AS64497", "2", "56", "1", "1", "Unknown"]
+    );
+    assert!(lines[3].starts_with("- ") && lines[3].ends_with("(no ASN)"));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Read 3 packets (0 not IP)"));
+}
+
+/// Builds a NetFlow v5 export packet with TCP records of (src, dst, packets, bytes)
+fn netflow_v5(records: &[([u8; 4], [u8; 4], u32, u32)]) -> Vec<u8> {
+    let mut packet = vec![0, 5, 0, records.len() as u8];
+    packet.resize(24, 0);
+    for (src, dst, packets, bytes) in records {
+        let mut record = [0u8; 48];
+        record[0..4].copy_from_slice(src);
+        record[4..8].copy_from_slice(dst);
+        record[16..20].copy_from_slice(&packets.to_be_bytes());
+        record[20..24].copy_from_slice(&bytes.to_be_bytes());
+        record[38] = 6;
+        packet.extend_from_slice(&record);
+    }
+    packet
+}
+
+#[test]
+fn test_flows_from_file() {
+    let dir = std::env::temp_dir();
+    let id = std::process::id();
+    let pfx2as = dir.join(format!("asn-fetcher-flows-{}.pfx2as", id));
+    let exports = dir.join(format!("asn-fetcher-flows-{}.nf5", id));
+    std::fs::write(&pfx2as, "192.0.2.0\t24\t64496\n198.51.100.0\t24\t64497\n").unwrap();
+    let mut file = netflow_v5(&[
+        ([192, 0, 2, 7], [198, 51, 100, 1], 10, 1000),
+        ([198, 51, 100, 1], [192, 0, 2, 7], 20, 30000),
+    ]);
+    file.extend(netflow_v5(&[([192, 0, 2, 8], [203, 0, 113, 1], 1, 60)]));
+    std::fs::write(&exports, file).unwrap();
+
+    let output = cli_command()
+        .args(["--source"])
+        .arg(format!("pfx2as:{}", pfx2as.display()))
+        .args(["flows", "--by", "source", "--top", "1"])
+        .arg(&exports)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&pfx2as).unwrap();
+    std::fs::remove_file(&exports).unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 2, "{}", stdout);
+    assert_eq!(
+        lines[1].split_whitespace().collect::<Vec<_>>(),
+        ["AS64497", "20", "30000", "1", "1", "Unknown"]
+    );
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("Decoded 3 flow records from 2 export packets"));
+}
+
+#[test]
+fn test_flows_udp_listener() {
+    use std::io::{BufRead, BufReader};
+
+    let pfx2as = pfx2as_fixture("collector");
+
+    let mut child = cli_command()
+        .args(["--source"])
+        .arg(pfx2as.source())
+        .args(["flows", "--listen", "127.0.0.1:0", "--duration", "2"])
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stderr = BufReader::new(child.stderr.take().unwrap());
+    let addr = loop {
+        let mut line = String::new();
+        assert!(stderr.read_line(&mut line).unwrap() > 0, "collector exited");
+        if let Some((_, rest)) = line.trim().split_once("Listening on udp://") {
+            break rest.to_string();
+        }
+    };
+
+    let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket
+        .send_to(
+            &netflow_v5(&[([192, 0, 2, 7], [10, 0, 0, 1], 3, 180)]),
+            &addr,
+        )
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 3, "{}", stdout);
+    assert!(lines
+        .iter()
+        .any(|line| line.split_whitespace().collect::<Vec<_>>()
+            == ["AS64496", "3", "180", "1", "1", "Unknown"]));
+    assert!(lines.iter().any(|line| line.ends_with("(no ASN)")));
+}
+
+#[test]
+fn test_hops_traceroute() {
+    let dir = std::env::temp_dir();
+    let id = std::process::id();
+    let pfx2as = dir.join(format!("asn-fetcher-hops-{}.pfx2as", id));
+    let trace = dir.join(format!("asn-fetcher-hops-{}.txt", id));
+    std::fs::write(&pfx2as, "203.0.114.0\t24\t64496\n8.8.8.0\t24\t15169\n").unwrap();
+    std::fs::write(
+        &trace,
+        "traceroute to 8.8.8.8 (8.8.8.8), 30 hops max, 60 byte packets\n\
+         \x201  _gateway (192.168.1.1)  0.512 ms  0.470 ms  0.451 ms\n\
+         \x202  * * *\n\
+         \x203  edge.example.net (203.0.114.2)  9.001 ms  9.1 ms  9.2 ms\n\
+         \x204  8.8.8.8 (8.8.8.8)  11.2 ms  11.0 ms  10.9 ms\n",
+    )
+    .unwrap();
+
+    let output = cli_command()
+        .args(["--source"])
+        .arg(format!("pfx2as:{}", pfx2as.display()))
+        .arg("hops")
+        .arg(&trace)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&pfx2as).unwrap();
+    std::fs::remove_file(&trace).unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<Vec<&str>> = stdout
+        .lines()
+        .map(|line| line.split_whitespace().collect())
+        .collect();
+    assert_eq!(
+        lines,
+        vec![
+            vec!["1", "192.168.1.1", "private"],
+            vec!["2", "*"],
+            vec!["3", "203.0.114.2", "AS64496"],
+            vec!["4", "8.8.8.8", "AS15169"],
+            vec!["AS", "path:", "private", "AS64496", "AS15169"],
+        ]
+    );
+}
+
+#[test]
+fn test_watch_reports_origin_changes() {
+    let dir = std::env::temp_dir();
+    let id = std::process::id();
+    let pfx2as = dir.join(format!("asn-fetcher-watch-{}.pfx2as", id));
+    let targets = dir.join(format!("asn-fetcher-watch-{}.txt", id));
+    let state = dir.join(format!("asn-fetcher-watch-{}.json", id));
+    std::fs::write(&targets, "# our space\n192.0.2.0/24\n198.51.100.7\n").unwrap();
+
+    let watch = || {
+        cli_command()
+            .args(["--source"])
+            .arg(format!("pfx2as:{}", pfx2as.display()))
+            .args(["watch", "--once", "--file"])
+            .arg(&targets)
+            .arg("--state")
+            .arg(&state)
+            .output()
+            .unwrap()
+    };
+
+    std::fs::write(&pfx2as, "192.0.2.0\t24\t64496\n198.51.100.0\t24\t64497\n").unwrap();
+    let first = watch();
+    assert!(first.status.success());
+    assert!(first.stdout.is_empty());
+
+    std::fs::write(&pfx2as, "192.0.2.0\t24\t64511\n198.51.100.0\t24\t64497\n").unwrap();
+    let second = watch();
+    let saved = std::fs::read_to_string(&state).unwrap();
+    for path in [&pfx2as, &targets, &state] {
+        std::fs::remove_file(path).unwrap();
+    }
+
+    assert!(second.status.success());
+    let events: Vec<serde_json::Value> = String::from_utf8_lossy(&second.stdout)
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let summary: Vec<(&str, &str, u64)> = events
+        .iter()
+        .map(|e| {
+            (
+                e["event"].as_str().unwrap(),
+                e["target"].as_str().unwrap(),
+                e["asn"].as_u64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            ("new-origin", "192.0.2.0/24", 64511),
+            ("lost-origin", "192.0.2.0/24", 64496)
+        ]
+    );
+    assert!(String::from_utf8_lossy(&second.stderr).contains("Checked 2 targets, 2 changes"));
+    let saved: serde_json::Value = serde_json::from_str(&saved).unwrap();
+    assert_eq!(
+        saved["targets"]["198.51.100.7"]["origins"],
+        serde_json::json!([64497])
+    );
+}
+
+#[test]
+fn test_diff_snapshots() {
+    let dir = std::env::temp_dir();
+    let id = std::process::id();
+    let before = dir.join(format!("asn-fetcher-before-{}.ndjson", id));
+    let after = dir.join(format!("asn-fetcher-after-{}.ndjson", id));
+    std::fs::write(
+        &before,
+        r#"{"ip":"192.0.2.1","results":[{"asn":"64496","holder":"Example Net","prefix":"192.0.2.0/24"}]}
+{"ip":"198.51.100.1","results":[{"asn":"64497","holder":"Other Net","prefix":"198.51.100.0/24"}]}
+"#,
+    )
+    .unwrap();
+    std::fs::write(
+        &after,
+        r#"{"ip":"192.0.2.1","results":[{"asn":"64496","holder":"Example Net","prefix":"192.0.2.0/23"}]}
+{"ip":"203.0.113.1","results":[{"asn":"64500","holder":"Doc Net","prefix":"203.0.113.0/24"}]}
+"#,
+    )
+    .unwrap();
+
+    let text = cli_command()
+        .arg("diff")
+        .args([&before, &after])
+        .output()
+        .unwrap();
+    let json = cli_command()
+        .arg("diff")
+        .args([&before, &after])
+        .arg("--json")
+        .output()
+        .unwrap();
+    std::fs::remove_file(&before).unwrap();
+    std::fs::remove_file(&after).unwrap();
+
+    assert!(text.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&text.stdout),
+        "~ 192.0.2.1  prefix: 192.0.2.0/24 -> 192.0.2.0/23\n\
+         - 198.51.100.1  AS64497 Other Net 198.51.100.0/24\n\
+         + 203.0.113.1  AS64500 Doc Net 203.0.113.0/24\n"
+    );
+    assert!(String::from_utf8_lossy(&text.stderr)
+        .contains("1 changed, 1 added, 1 removed, 0 unchanged"));
+
+    assert!(json.status.success());
+    let entries: Vec<serde_json::Value> = String::from_utf8_lossy(&json.stdout)
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[0]["status"], "changed");
+    assert_eq!(entries[0]["changed"], serde_json::json!(["prefix"]));
+    assert_eq!(entries[1]["status"], "removed");
+    assert!(entries[1]["after"].is_null());
+    assert_eq!(entries[2]["after"][0]["asn"], "64500");
+}
+
+#[test]
+fn test_json_lookups_diff() {
+    let dir = std::env::temp_dir();
+    let id = std::process::id();
+    let addresses = dir.join(format!("asn-fetcher-addresses-{}.txt", id));
+    std::fs::write(
+        &addresses,
+        "# vendor addresses\n192.0.2.1\n198.51.100.1\n\n203.0.113.1  # unrouted at first\n",
+    )
+    .unwrap();
+
+    let snapshot = |name: &str, table: &str| {
+        let pfx2as = dir.join(format!("asn-fetcher-{}-{}.pfx2as", name, id));
+        std::fs::write(&pfx2as, table).unwrap();
+        let output = cli_command()
+            .arg("--source")
+            .arg(format!("pfx2as:{}", pfx2as.display()))
+            .arg("--file")
+            .arg(&addresses)
+            .arg("--json")
+            .output()
+            .unwrap();
+        std::fs::remove_file(&pfx2as).unwrap();
+        assert!(output.status.success());
+        let path = dir.join(format!("asn-fetcher-{}-{}.ndjson", name, id));
+        std::fs::write(&path, &output.stdout).unwrap();
+        path
+    };
+    let before = snapshot(
+        "json-before",
+        "192.0.2.0\t24\t64496\n198.51.100.0\t24\t64497\n",
+    );
+    let after = snapshot(
+        "json-after",
+        "192.0.2.0\t24\t64511\n203.0.113.0\t24\t64500\n",
+    );
+
+    let lines = std::fs::read_to_string(&before).unwrap();
+    let records: Vec<serde_json::Value> = lines
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(records.len(), 3);
+    assert_eq!(records[0]["ip"], "192.0.2.1");
+    assert_eq!(records[0]["results"][0]["asn"], "64496");
+    assert_eq!(records[2]["results"], serde_json::json!([]));
+
+    let diff = cli_command()
+        .arg("diff")
+        .args([&before, &after])
+        .output()
+        .unwrap();
+    std::fs::remove_file(&addresses).unwrap();
+    std::fs::remove_file(&before).unwrap();
+    std::fs::remove_file(&after).unwrap();
+
+    assert!(diff.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&diff.stdout),
+        "~ 192.0.2.1  asn: AS64496 -> AS64511\n\
+         ~ 198.51.100.1  asn: AS64497 -> (none); holder: Unknown -> (none); \
+         prefix: 198.51.100.0/24 -> (none)\n\
+         ~ 203.0.113.1  asn: (none) -> AS64500; holder: (none) -> Unknown; \
+         prefix: (none) -> 203.0.113.0/24\n"
+    );
+}